@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+// Abbreviations and what they expand to
+#[derive(Default)]
+pub struct AbbrevTable {
+    abbrevs: HashMap<String, String>,
+}
+
+impl AbbrevTable {
+    pub fn new(abbrevs: &[(&str, &str)]) -> AbbrevTable {
+        let mut table = AbbrevTable::default();
+        for (abbrev, expansion) in abbrevs {
+            table.define(abbrev, expansion);
+        }
+        table
+    }
+
+    pub fn define(&mut self, abbrev: &str, expansion: &str) {
+        self.abbrevs.insert(abbrev.to_string(), expansion.to_string());
+    }
+
+    // The expansion of `word`, capitalized when the word is but its abbrev isn't
+    pub fn expansion(&self, word: &str) -> Option<String> {
+        if let Some(expansion) = self.abbrevs.get(word) {
+            return Some(expansion.clone());
+        }
+
+        let mut chars = word.chars();
+        let first = chars.next().filter(|c| c.is_uppercase())?;
+        let lowered: String = first.to_lowercase().chain(chars).collect();
+        let expansion = self.abbrevs.get(&lowered)?;
+
+        let mut chars = expansion.chars();
+        let first = chars.next()?;
+        Some(first.to_uppercase().chain(chars).collect())
+    }
+}
+
+// Expands the abbrev before the cursor when whitespace is typed after it,
+// checking the buffer's own abbrevs before the global ones
+pub struct AbbrevMode {
+    local: AbbrevTable,
+}
+
+impl AbbrevMode {
+    pub fn new(local: AbbrevTable) -> AbbrevMode {
+        AbbrevMode {
+            local,
+        }
+    }
+}
+
+impl TextMinorMode for AbbrevMode {
+    fn name(&self) -> &'static str {
+        "abbrev"
+    }
+
+    fn self_insert(&mut self, global: &mut Global, content: &mut TextContent, text: &str) -> bool {
+        if !text.starts_with(char::is_whitespace) {
+            return false;
+        }
+
+        let Position { row, col } = content.cursor;
+        let line = &content.line(row)[..col];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &line[start..];
+        if word.is_empty() {
+            return false;
+        }
+
+        let expansion = self.local.expansion(word).or_else(|| global.abbrevs.expansion(word));
+        if let Some(expansion) = expansion {
+            content.replace_range(Position::new(row, start), content.cursor, &expansion);
+        }
+        // The whitespace still gets typed after the expansion
+        false
+    }
+}
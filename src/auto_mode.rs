@@ -0,0 +1,308 @@
+use std::path::Path;
+
+use crate::abbrev_mode::AbbrevMode;
+use crate::abbrev_mode::AbbrevTable;
+use crate::diff_mode;
+use crate::electric_pair_mode::ElectricPairMode;
+use crate::fold_mode::FoldMode;
+use crate::go_mode;
+use crate::grammars;
+use crate::javascript_mode;
+use crate::json_mode;
+use crate::markdown_mode;
+use crate::outline_mode::OutlineMode;
+use crate::python_mode;
+use crate::rust_mode;
+use crate::text_mode::TextMinorMode;
+use crate::tree_sitter_mode::Language;
+use crate::tree_sitter_mode::TreeSitterMode;
+use crate::vim_mode;
+use crate::vim_mode::VimMode;
+use crate::whitespace_cleanup_mode::WhitespaceCleanupMode;
+
+// A major mode and the files it's picked for
+pub struct AutoMode {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+    // Whole file names, for files like Makefile without an extension
+    pub filenames: &'static [&'static str],
+    // Programs named on a "#!" first line, without any version number
+    pub interpreters: &'static [&'static str],
+    pub comment_start: Option<&'static str>,
+    // The grammar, for languages highlighted with tree-sitter
+    pub language: Option<&'static Language>,
+    // Minor modes that go with the language, like its snippets. Languages
+    // without a grammar can have their major mode here too.
+    pub extra_modes: fn() -> Vec<Box<dyn TextMinorMode>>,
+}
+
+fn no_extra_modes() -> Vec<Box<dyn TextMinorMode>> {
+    vec!()
+}
+
+fn outline_extra_modes() -> Vec<Box<dyn TextMinorMode>> {
+    vec!(Box::new(OutlineMode {}))
+}
+
+// File names are checked first, then extensions, then the "#!" line
+pub const AUTO_MODES: &[AutoMode] = &[
+    AutoMode {
+        name: "rust",
+        extensions: &["rs"],
+        filenames: &[],
+        interpreters: &[],
+        comment_start: Some("//"),
+        language: Some(&rust_mode::RUST),
+        extra_modes: rust_mode::extra_modes,
+    },
+    AutoMode {
+        name: "python",
+        extensions: &["py", "pyw"],
+        filenames: &["SConstruct"],
+        interpreters: &["python", "pypy"],
+        comment_start: Some("#"),
+        language: Some(&python_mode::PYTHON),
+        extra_modes: no_extra_modes,
+    },
+    AutoMode {
+        name: "shell",
+        extensions: &["sh", "bash", "zsh"],
+        filenames: &[".bashrc", ".bash_profile", ".profile", ".zshrc"],
+        interpreters: &["sh", "bash", "zsh", "dash"],
+        comment_start: Some("#"),
+        language: None,
+        extra_modes: no_extra_modes,
+    },
+    AutoMode {
+        name: "makefile",
+        extensions: &["mk"],
+        filenames: &["Makefile", "makefile", "GNUmakefile"],
+        interpreters: &["make"],
+        comment_start: Some("#"),
+        language: None,
+        extra_modes: no_extra_modes,
+    },
+    AutoMode {
+        name: "toml",
+        extensions: &["toml"],
+        filenames: &["Cargo.lock"],
+        interpreters: &[],
+        comment_start: Some("#"),
+        language: None,
+        extra_modes: no_extra_modes,
+    },
+    AutoMode {
+        name: "conf",
+        extensions: &["yaml", "yml", "conf", "ini"],
+        filenames: &[".gitignore", ".gitattributes"],
+        interpreters: &[],
+        comment_start: Some("#"),
+        language: None,
+        extra_modes: no_extra_modes,
+    },
+    AutoMode {
+        name: "javascript",
+        extensions: &["js", "mjs", "cjs", "jsx"],
+        filenames: &[],
+        interpreters: &["node"],
+        comment_start: Some("//"),
+        language: Some(&javascript_mode::JAVASCRIPT),
+        extra_modes: no_extra_modes,
+    },
+    AutoMode {
+        name: "typescript",
+        extensions: &["ts", "mts", "cts"],
+        filenames: &[],
+        interpreters: &["ts-node", "deno"],
+        comment_start: Some("//"),
+        language: Some(&javascript_mode::TYPESCRIPT),
+        extra_modes: no_extra_modes,
+    },
+    AutoMode {
+        name: "tsx",
+        extensions: &["tsx"],
+        filenames: &[],
+        interpreters: &[],
+        comment_start: Some("//"),
+        language: Some(&javascript_mode::TSX),
+        extra_modes: no_extra_modes,
+    },
+    AutoMode {
+        name: "go",
+        extensions: &["go"],
+        filenames: &[],
+        interpreters: &[],
+        comment_start: Some("//"),
+        language: Some(&go_mode::GO),
+        extra_modes: no_extra_modes,
+    },
+    AutoMode {
+        name: "json",
+        extensions: &["json"],
+        filenames: &[".eslintrc", ".babelrc"],
+        interpreters: &[],
+        comment_start: None,
+        language: Some(&json_mode::JSON),
+        extra_modes: no_extra_modes,
+    },
+    AutoMode {
+        name: "markdown",
+        extensions: &["md", "markdown"],
+        filenames: &[],
+        interpreters: &[],
+        comment_start: None,
+        language: Some(&markdown_mode::MARKDOWN),
+        extra_modes: markdown_mode::extra_modes,
+    },
+    AutoMode {
+        name: "outline",
+        extensions: &["org", "outline"],
+        filenames: &[],
+        interpreters: &[],
+        comment_start: Some("#"),
+        language: None,
+        extra_modes: outline_extra_modes,
+    },
+    AutoMode {
+        name: "c",
+        extensions: &["c", "h", "cpp", "hpp", "cc", "java"],
+        filenames: &[],
+        interpreters: &[],
+        comment_start: Some("//"),
+        language: None,
+        extra_modes: no_extra_modes,
+    },
+    AutoMode {
+        name: "lua",
+        extensions: &["lua", "sql", "hs"],
+        filenames: &[],
+        interpreters: &["lua"],
+        comment_start: Some("--"),
+        language: None,
+        extra_modes: no_extra_modes,
+    },
+    AutoMode {
+        name: "diff",
+        extensions: &["diff", "patch"],
+        filenames: &[],
+        interpreters: &[],
+        comment_start: None,
+        language: None,
+        extra_modes: diff_mode::extra_modes,
+    },
+    AutoMode {
+        name: "lisp",
+        extensions: &["el", "lisp", "scm"],
+        filenames: &[],
+        interpreters: &["guile", "sbcl"],
+        comment_start: Some(";"),
+        language: None,
+        extra_modes: no_extra_modes,
+    },
+];
+
+// The program a "#!" line runs, looking past env, e.g. "python" for
+// "#!/usr/bin/env python3"
+fn interpreter(first_line: &str) -> Option<&str> {
+    let mut words = first_line.strip_prefix("#!")?.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+    Some(program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.'))
+}
+
+// The built-in modes after the ones for grammars loaded at runtime
+fn all_modes() -> impl Iterator<Item = &'static AutoMode> + Clone {
+    grammars::loaded().iter().chain(AUTO_MODES)
+}
+
+// The mode for the file at `path` starting with `first_line`
+pub fn for_file(path: &Path, first_line: &str) -> Option<&'static AutoMode> {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    all_modes().find(|mode| mode.filenames.contains(&name))
+        .or_else(|| all_modes().find(|mode| mode.extensions.contains(&extension)))
+        .or_else(|| {
+            let program = interpreter(first_line)?;
+            all_modes().find(|mode| mode.interpreters.contains(&program))
+        })
+}
+
+// The grammar of the language called `name`, which can also be given by
+// its extension like "py"
+pub fn language_named(name: &str) -> Option<&'static Language> {
+    let name = name.to_lowercase();
+    all_modes()
+        .find(|mode| mode.name == name || mode.extensions.contains(&name.as_str()))
+        .and_then(|mode| mode.language)
+}
+
+// The modes for editing the file at `path` starting with `first_line`
+pub fn modes_for_file(path: &Path, first_line: &str) -> Vec<Box<dyn TextMinorMode>> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let mut modes: Vec<Box<dyn TextMinorMode>> = vec!();
+    if let Some(auto_mode) = for_file(path, first_line) {
+        let extra_modes = (auto_mode.extra_modes)();
+        match auto_mode.language {
+            Some(language) => {
+                modes.push(Box::new(TreeSitterMode::new(language)));
+                modes.push(Box::new(FoldMode {}));
+            },
+            None if extra_modes.iter().any(|mode| mode.is_major()) => {},
+            None => modes.push(Box::new(LanguageMode { name: auto_mode.name, comment_start: auto_mode.comment_start })),
+        }
+        modes.extend(extra_modes);
+    } else {
+        modes.push(Box::new(FundamentalMode {}));
+    }
+    if !modes.iter().any(|mode| mode.name() == "abbrev") {
+        modes.push(Box::new(AbbrevMode::new(AbbrevTable::default())));
+    }
+    modes.push(Box::new(ElectricPairMode {}));
+    modes.push(Box::new(WhitespaceCleanupMode::for_extension(extension)));
+    if vim_mode::enabled_from_env() {
+        modes.push(Box::new(VimMode {}));
+    }
+    modes
+}
+
+// The major mode of languages without a grammar, which still have a name in
+// the modeline, a keymap section and comment syntax
+pub struct LanguageMode {
+    name: &'static str,
+    comment_start: Option<&'static str>,
+}
+
+impl TextMinorMode for LanguageMode {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn comment_syntax(&self) -> Option<&'static str> {
+        self.comment_start
+    }
+
+    fn is_major(&self) -> bool {
+        true
+    }
+}
+
+// The major mode of files no other mode is for, like notes in .txt files.
+// It parses nothing and wraps long lines at spaces.
+pub struct FundamentalMode {
+}
+
+impl TextMinorMode for FundamentalMode {
+    fn name(&self) -> &'static str {
+        "fundamental"
+    }
+
+    fn is_major(&self) -> bool {
+        true
+    }
+
+    fn wraps_lines(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,54 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::buffer::Buffer;
+use crate::line_ending::LineEnding;
+use crate::minibuffer::Minibuffer;
+use crate::text_content::Position;
+use crate::text_mode::Global;
+
+// How often buffers with unsaved changes get copied to their auto-save files
+pub const AUTO_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+// Where the unsaved changes to `path` are kept, e.g. "src/#main.rs#"
+pub fn auto_save_path(path: &Path) -> Option<PathBuf> {
+    let file_name = path.file_name()?.to_string_lossy();
+    Some(path.with_file_name(format!("#{}#", file_name)))
+}
+
+// There are changes to `path` left behind by a session that never saved them
+pub fn has_newer_auto_save(path: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    match auto_save_path(path).and_then(|auto_save| modified(&auto_save)) {
+        Some(auto_saved) => modified(path).is_none_or(|saved| auto_saved > saved),
+        None => false,
+    }
+}
+
+// Asks whether to bring back the auto-saved changes to the current buffer
+pub fn offer_recovery(global: &mut Global) {
+    global.minibuffer = Some(Minibuffer::new("Auto-save file is newer, recover it? (y or n) ", "", None, recover));
+}
+
+fn recover(global: &mut Global, buffer: &mut Buffer, answer: &str) {
+    if !answer.trim_start().starts_with(['y', 'Y']) {
+        return;
+    }
+    let auto_save = match buffer.path.as_deref().and_then(auto_save_path) {
+        Some(auto_save) => auto_save,
+        None => return,
+    };
+
+    match fs::read_to_string(&auto_save) {
+        Ok(text) => {
+            // Replaced as an edit so it can be undone and still needs saving
+            let (line_ending, _) = LineEnding::detect(&text);
+            let end = buffer.content.end_of_buffer();
+            buffer.content.replace_range(Position::default(), end, &line_ending.decode(&text));
+            buffer.content.cursor = Position::default();
+        },
+        Err(e) => global.message(format!("Recovering failed: {}: {}", auto_save.display(), e)),
+    }
+}
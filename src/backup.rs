@@ -0,0 +1,70 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+// Where the original of a file goes before it is first overwritten
+pub enum Backups {
+    Off,
+    // "file~" next to the file
+    Beside,
+    // Numbered copies in one directory, e.g. "!src!main.rs.~3~", so every
+    // session's original is kept
+    Directory(PathBuf),
+}
+
+impl Backups {
+    // From EDIT0R_BACKUPS, which is "off", "beside" (the default) or the
+    // directory to keep numbered backups in
+    pub fn from_env() -> Backups {
+        match std::env::var("EDIT0R_BACKUPS") {
+            Ok(setting) if setting == "off" => Backups::Off,
+            Ok(setting) if setting.is_empty() || setting == "beside" => Backups::Beside,
+            Ok(directory) => Backups::Directory(PathBuf::from(directory)),
+            Err(_) => Backups::Beside,
+        }
+    }
+}
+
+// Copies the file at `path` to its backup, unless it doesn't exist yet
+pub fn back_up(backups: &Backups, path: &Path) -> Result<(), String> {
+    if !path.is_file() {
+        return Ok(());
+    }
+    let backup = match backups {
+        Backups::Off => return Ok(()),
+        Backups::Beside => {
+            let file_name = path.file_name().ok_or("Not a file path")?.to_string_lossy();
+            path.with_file_name(format!("{}~", file_name))
+        },
+        Backups::Directory(directory) => {
+            fs::create_dir_all(directory).map_err(|e| format!("{}: {}", directory.display(), e))?;
+            numbered_backup(directory, path)
+        },
+    };
+
+    fs::copy(path, &backup)
+        .map(|_| ())
+        .map_err(|e| format!("{}: {}", backup.display(), e))
+}
+
+// The next free numbered backup of `path` in `directory`, named after the
+// whole path so files with the same name don't mix
+fn numbered_backup(directory: &Path, path: &Path) -> PathBuf {
+    let full_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let stem = full_path.to_string_lossy().replace(['/', '\\'], "!");
+
+    let latest = fs::read_dir(directory)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|name| {
+            name.strip_prefix(&stem)?
+                .strip_prefix(".~")?
+                .strip_suffix('~')?
+                .parse::<usize>()
+                .ok()
+        })
+        .max()
+        .unwrap_or(0);
+    directory.join(format!("{}.~{}~", stem, latest + 1))
+}
@@ -0,0 +1,329 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::buffer::Buffer;
+use crate::buffer::write_atomically;
+use crate::marker::Marker;
+use crate::minibuffer::Minibuffer;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+// Named places in files to come back to, as in Emacs' bookmarks. They're
+// kept between sessions in EDIT0R_BOOKMARKS, else ~/.config/edit0r/bookmarks,
+// one to a line as its name, file, row, column and the text of its line,
+// separated by tabs. The text finds the line again when something else
+// changed the file in between.
+
+#[derive(Clone)]
+pub struct Bookmark {
+    pub name: String,
+    pub path: PathBuf,
+    pub position: Position,
+    // The line it's on, as of the last time it was seen
+    line: String,
+    // Following it in the buffer visiting the file, once there's one
+    marker: Option<Marker>,
+}
+
+pub struct Bookmarks {
+    bookmarks: Vec<Bookmark>,
+    // Where they're kept, if anywhere
+    path: Option<PathBuf>,
+    // Whether they changed since they were last saved
+    changed: bool,
+}
+
+// The same file always has the same path, however it was opened
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+// Tabs and newlines in names and lines would split them
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => {},
+        }
+    }
+    unescaped
+}
+
+fn parse(line: &str) -> Option<Bookmark> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    match fields.as_slice() {
+        [name, path, row, col, text] => Some(Bookmark {
+            name: unescape(name),
+            path: PathBuf::from(unescape(path)),
+            position: Position::new(row.parse().ok()?, col.parse().ok()?),
+            line: unescape(text),
+            marker: None,
+        }),
+        _ => None,
+    }
+}
+
+impl Bookmarks {
+    // From the file named by EDIT0R_BOOKMARKS, or the one in the config
+    // directory, leaving out lines that aren't bookmarks
+    pub fn from_env() -> Bookmarks {
+        let path = match std::env::var_os("EDIT0R_BOOKMARKS") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/edit0r/bookmarks")),
+        };
+        let text = path.as_ref().and_then(|path| fs::read_to_string(path).ok()).unwrap_or_default();
+        Bookmarks {
+            bookmarks: text.lines().filter_map(parse).collect(),
+            path,
+            changed: false,
+        }
+    }
+
+    // Writes them back if they changed, including by being moved along
+    // with the text around them
+    pub fn save(&mut self) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) if self.changed => path,
+            _ => return Ok(()),
+        };
+        if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+            fs::create_dir_all(directory).map_err(|e| format!("{}: {}", directory.display(), e))?;
+        }
+        let text: String = self.bookmarks.iter().map(|bookmark| {
+            let path = escape(&bookmark.path.to_string_lossy());
+            format!("{}\t{}\t{}\t{}\t{}\n", escape(&bookmark.name), path, bookmark.position.row, bookmark.position.col, escape(&bookmark.line))
+        }).collect();
+        write_atomically(path, &text)?;
+        self.changed = false;
+        Ok(())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.bookmarks.iter().map(|bookmark| bookmark.name.clone()).collect()
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Bookmark> {
+        self.bookmarks.iter().find(|bookmark| bookmark.name == name)
+    }
+
+    // Adds `bookmark`, in place of one by the same name
+    pub fn set(&mut self, bookmark: Bookmark) {
+        match self.bookmarks.iter_mut().find(|old| old.name == bookmark.name) {
+            Some(old) => *old = bookmark,
+            None => self.bookmarks.push(bookmark),
+        }
+        self.changed = true;
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Bookmark> {
+        let index = self.bookmarks.iter().position(|bookmark| bookmark.name == name)?;
+        self.changed = true;
+        Some(self.bookmarks.remove(index))
+    }
+
+    // Whether any are in the file at `path`
+    pub fn in_file(&self, path: &Path) -> bool {
+        let path = canonical(path);
+        self.bookmarks.iter().any(|bookmark| bookmark.path == path)
+    }
+}
+
+// Where `bookmark` is in `content`: on its row while the line there is still
+// the one it was on, else on the nearest line that is
+fn locate(bookmark: &Bookmark, content: &TextContent) -> Position {
+    let last = content.line_count() - 1;
+    let row = bookmark.position.row.min(last);
+    let found = (content.line(row) == bookmark.line).then_some(row).or_else(|| {
+        (1..=last)
+            .flat_map(|distance| [row.checked_sub(distance), Some(row + distance).filter(|&row| row <= last)])
+            .flatten()
+            .find(|&row| content.line(row) == bookmark.line)
+    });
+    let row = found.unwrap_or(row);
+    let line = content.line(row);
+    let mut col = if found.is_some() { bookmark.position.col.min(line.len()) } else { 0 };
+    while !line.is_char_boundary(col) {
+        col -= 1;
+    }
+    Position::new(row, col)
+}
+
+// Keeps the bookmarks in a file on the text they were set on as it's
+// edited, with a marker for each
+pub struct BookmarkMode {
+    path: PathBuf,
+    // The edits the bookmarks were last looked at after, or None before
+    // they were first found in the text
+    seen_changes: Option<usize>,
+}
+
+impl BookmarkMode {
+    pub fn new(path: &Path) -> BookmarkMode {
+        BookmarkMode { path: canonical(path), seen_changes: None }
+    }
+}
+
+impl TextMinorMode for BookmarkMode {
+    fn name(&self) -> &'static str {
+        "bookmark"
+    }
+
+    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
+        let change_count = content.change_count();
+        if self.seen_changes == Some(change_count) {
+            return;
+        }
+        let bookmarks = &mut global.bookmarks;
+        for bookmark in bookmarks.bookmarks.iter_mut().filter(|bookmark| bookmark.path == self.path) {
+            let position = match bookmark.marker.and_then(|marker| content.markers.get(marker)) {
+                Some(position) => position,
+                None => {
+                    let position = locate(bookmark, content);
+                    bookmark.marker = Some(content.markers.add(position, false));
+                    position
+                },
+            };
+            let line = content.line(position.row);
+            if position != bookmark.position || line != bookmark.line {
+                bookmark.position = position;
+                bookmark.line = line.into_owned();
+                bookmarks.changed = true;
+            }
+        }
+        self.seen_changes = Some(change_count);
+    }
+}
+
+// Removes `marker` from whichever buffer it's in, since the others don't
+// have it
+fn remove_marker(global: &mut Global, buffer: &mut Buffer, marker: Marker) {
+    buffer.content.markers.remove(marker);
+    for other in global.buffers.iter_mut() {
+        other.content.markers.remove(marker);
+    }
+}
+
+// Sets a bookmark at the cursor, prompting for its name
+pub fn bookmark_set(global: &mut Global, buffer: &mut Buffer) {
+    if buffer.path.is_none() {
+        global.message("Buffer has no file".to_string());
+        return;
+    }
+    let names = global.bookmarks.names();
+    global.minibuffer = Some(Minibuffer::with_list("Set bookmark: ", &buffer.name, names, |global, buffer, input| {
+        let (name, path) = match (input.trim(), &buffer.path) {
+            ("", _) | (_, None) => return,
+            (name, Some(path)) => (name.to_string(), canonical(path)),
+        };
+        // The bookmark this one takes over from needs following no longer
+        if let Some(marker) = global.bookmarks.find(&name).and_then(|old| old.marker) {
+            remove_marker(global, buffer, marker);
+        }
+        let position = buffer.content.cursor;
+        let line = buffer.content.line(position.row).into_owned();
+        let marker = Some(buffer.content.markers.add(position, false));
+        global.bookmarks.set(Bookmark { name: name.clone(), path: path.clone(), position, line, marker });
+        if !buffer.has_minor_mode("bookmark") {
+            let seen_changes = Some(buffer.content.change_count());
+            buffer.minor_modes.push(Box::new(BookmarkMode { path, seen_changes }));
+        }
+        match global.bookmarks.save() {
+            Ok(()) => global.message(format!("Set bookmark {}", name)),
+            Err(e) => global.message(format!("Saving bookmarks failed: {}", e)),
+        }
+    }));
+}
+
+fn go_to(content: &mut TextContent, bookmark: &Bookmark) {
+    content.cursor = bookmark.marker
+        .and_then(|marker| content.markers.get(marker))
+        .unwrap_or_else(|| locate(bookmark, content));
+    content.deactivate_mark();
+    content.clamp_to_narrowing();
+    content.reveal_cursor();
+}
+
+// Goes to a bookmark, prompting for it, opening its file if need be
+pub fn bookmark_jump(global: &mut Global, _: &mut Buffer) {
+    let names = global.bookmarks.names();
+    if names.is_empty() {
+        global.message("No bookmarks".to_string());
+        return;
+    }
+    global.minibuffer = Some(Minibuffer::with_list("Jump to bookmark: ", "", names, |global, buffer, input| {
+        let bookmark = match global.bookmarks.find(input) {
+            Some(bookmark) => bookmark.clone(),
+            None => {
+                global.message(format!("No bookmark named {}", input));
+                return;
+            },
+        };
+        let visits = |buffer: &Buffer| buffer.path.as_deref().map(canonical).as_ref() == Some(&bookmark.path);
+        if visits(buffer) {
+            go_to(&mut buffer.content, &bookmark);
+            return;
+        }
+        let visiting = global.buffers.iter_mut().find(|buffer| visits(buffer)).map(|buffer| buffer.name.clone());
+        let name = match visiting {
+            Some(name) => name,
+            None if !bookmark.path.exists() => {
+                global.message(format!("{} no longer exists", bookmark.path.display()));
+                return;
+            },
+            None => match text_mode::visit_file(&bookmark.path, global) {
+                Ok(visited) => global.buffers.add(visited),
+                Err(e) => {
+                    global.message(format!("Opening failed: {}", e));
+                    return;
+                },
+            },
+        };
+        if let Some(visiting) = global.buffers.find_mut(&name) {
+            go_to(&mut visiting.content, &bookmark);
+        }
+        global.buffers.switch_to(&name);
+    }));
+}
+
+// Deletes a bookmark, prompting for it
+pub fn bookmark_delete(global: &mut Global, _: &mut Buffer) {
+    let names = global.bookmarks.names();
+    if names.is_empty() {
+        global.message("No bookmarks".to_string());
+        return;
+    }
+    global.minibuffer = Some(Minibuffer::with_list("Delete bookmark: ", "", names, |global, buffer, input| {
+        let marker = match global.bookmarks.remove(input) {
+            Some(bookmark) => bookmark.marker,
+            None => {
+                global.message(format!("No bookmark named {}", input));
+                return;
+            },
+        };
+        if let Some(marker) = marker {
+            remove_marker(global, buffer, marker);
+        }
+        match global.bookmarks.save() {
+            Ok(()) => global.message(format!("Deleted bookmark {}", input)),
+            Err(e) => global.message(format!("Saving bookmarks failed: {}", e)),
+        }
+    }));
+}
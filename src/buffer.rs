@@ -0,0 +1,361 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use tree_sitter::Tree;
+
+use crate::auto_save;
+use crate::backup;
+use crate::buffer_local::COMMENT_START;
+use crate::line_ending::LineEnding;
+use crate::show_paren;
+use crate::show_paren::BracketMatch;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+use crate::tree_sitter_mode::Definition;
+use crate::undo_file;
+use crate::view::View;
+
+// The text being edited together with the minor modes working on it
+pub struct Buffer {
+    // Unique among the open buffers
+    pub name: String,
+    pub content: TextContent,
+    pub view: View,
+    pub minor_modes: Vec<Box<dyn TextMinorMode>>,
+    // The file the content is saved to
+    pub path: Option<PathBuf>,
+    // What lines end with in the file
+    pub line_ending: LineEnding,
+    // The file had more than one kind of line ending when it was loaded
+    pub mixed_line_endings: bool,
+    // TextContent::change_count() when the file was last written
+    saved_at: usize,
+    saved_line_ending: LineEnding,
+    // TextContent::change_count() when the auto-save file was last written
+    auto_saved_at: usize,
+    // The original of the file has been backed up this session
+    backed_up: bool,
+    // When the file was last changed, as of loading or saving it
+    disk_modified: Option<SystemTime>,
+    // Something else has changed the file since
+    pub changed_on_disk: bool,
+    // Edits are taken back instead of made
+    pub read_only: bool,
+    // The bracket at the cursor and its match, as of refresh_brackets
+    pub brackets: Option<BracketMatch>,
+    // TextContent::change_count() and the cursor when the brackets were found
+    brackets_found_at: Option<(usize, Position)>,
+}
+
+impl Buffer {
+    pub fn new(name: &str, content: TextContent, minor_modes: Vec<Box<dyn TextMinorMode>>) -> Buffer {
+        let mut view = View::default();
+        view.wrap = minor_modes.iter().any(|mode| mode.wraps_lines());
+        Buffer {
+            name: name.to_string(),
+            content,
+            view,
+            minor_modes,
+            path: None,
+            line_ending: LineEnding::Lf,
+            mixed_line_endings: false,
+            saved_at: 0,
+            saved_line_ending: LineEnding::Lf,
+            auto_saved_at: 0,
+            backed_up: false,
+            disk_modified: None,
+            changed_on_disk: false,
+            read_only: false,
+            brackets: None,
+            brackets_found_at: None,
+        }
+    }
+
+    // Inserts typed text, letting the minor modes take over if they want
+    pub fn self_insert(&mut self, global: &mut Global, text: &str) {
+        for minor_mode in &mut self.minor_modes {
+            if minor_mode.self_insert(global, &mut self.content, text) {
+                return;
+            }
+        }
+        self.content.self_insert(text);
+    }
+
+    // Offers Tab (or Shift-Tab when `backward`) to the minor modes, returns
+    // false when none of them used it
+    pub fn tab(&mut self, backward: bool) -> bool {
+        let content = &mut self.content;
+        self.minor_modes
+            .iter_mut()
+            .any(|mode| mode.tab(content, backward))
+    }
+
+    pub fn has_minor_mode(&self, name: &str) -> bool {
+        self.minor_modes.iter().any(|mode| mode.name() == name)
+    }
+
+    // Turns the named minor mode off, or on using `make` when it isn't enabled
+    pub fn toggle_minor_mode(&mut self, name: &str, make: fn() -> Box<dyn TextMinorMode>) {
+        if self.has_minor_mode(name) {
+            self.minor_modes.retain(|mode| mode.name() != name);
+        } else {
+            self.minor_modes.push(make());
+        }
+        self.content.modified = true;
+    }
+
+    // Reruns the minor modes after any edit, including undo and redo
+    pub fn refresh_faces(&mut self, global: &mut Global) {
+        // Half done transactions would leave the modes looking at a mess
+        if self.content.in_transaction() {
+            return;
+        }
+        for minor_mode in &mut self.minor_modes {
+            minor_mode.poll(global, &mut self.content);
+        }
+        if self.minor_modes.iter().any(|mode| mode.has_results()) {
+            self.content.modified = true;
+        }
+        if !self.content.modified {
+            return;
+        }
+
+        self.content.clear_faces();
+        for minor_mode in &mut self.minor_modes {
+            minor_mode.modify(global, &mut self.content);
+        }
+        // Modes working from the syntax tree go on top of the language's faces
+        if self.minor_modes.iter().any(|mode| mode.uses_syntax_tree()) {
+            if let Some(tree) = self.syntax_tree() {
+                for minor_mode in &mut self.minor_modes {
+                    minor_mode.modify_syntax(global, &mut self.content, &tree);
+                }
+            }
+        }
+        self.content.modified = false;
+        self.content.trim_changes();
+    }
+
+    // Whether the faces are still to be brought up to date by work done off
+    // the main thread
+    pub fn is_busy(&self) -> bool {
+        self.minor_modes.iter().any(|mode| mode.is_busy())
+    }
+
+    // Edited since it was last saved or loaded
+    pub fn is_dirty(&self) -> bool {
+        self.content.change_count() != self.saved_at || self.line_ending != self.saved_line_ending
+    }
+
+    // Sets the line ending a file was loaded with
+    pub fn set_file_line_ending(&mut self, line_ending: LineEnding, mixed: bool) {
+        self.line_ending = line_ending;
+        self.saved_line_ending = line_ending;
+        self.mixed_line_endings = mixed;
+    }
+
+    // Saves to the buffer's file if there is anything new to save
+    pub fn save(&mut self, global: &Global) -> Result<(), String> {
+        if !self.is_dirty() {
+            return Ok(());
+        }
+        let path = self.path.clone().ok_or("Buffer has no file to save to")?;
+        self.write_to(&path, global)
+    }
+
+    // Lets the minor modes tidy up, then writes the content to `path`,
+    // backing up what was there first if it is the first save to it
+    pub fn write_to(&mut self, path: &Path, global: &Global) -> Result<(), String> {
+        if !self.backed_up || self.path.as_deref() != Some(path) {
+            backup::back_up(&global.backups, path)?;
+            self.backed_up = true;
+        }
+
+        self.content.begin_transaction();
+        for minor_mode in &mut self.minor_modes {
+            minor_mode.before_save(&mut self.content);
+        }
+        self.content.commit_transaction();
+
+        write_atomically(path, &self.line_ending.encode(&self.content.text()))?;
+        self.saved_at = self.content.change_count();
+        self.saved_line_ending = self.line_ending;
+        // Everything was written with the one line ending
+        self.mixed_line_endings = false;
+
+        // The changes it held are safe now
+        if let Some(auto_save) = auto_save::auto_save_path(path) {
+            let _ = fs::remove_file(auto_save);
+        }
+        self.auto_saved_at = self.saved_at;
+        self.disk_modified = modified_time(path);
+        self.changed_on_disk = false;
+
+        // Losing the history isn't worth failing the save over
+        if global.undo_files {
+            let _ = undo_file::write(path, &self.content);
+        }
+        for minor_mode in &mut self.minor_modes {
+            minor_mode.after_save(&mut self.content, path);
+        }
+        Ok(())
+    }
+
+    // Remembers the state of the file as the one the buffer matches
+    pub fn record_disk_state(&mut self) {
+        self.disk_modified = self.path.as_deref().and_then(modified_time);
+        self.changed_on_disk = false;
+    }
+
+    // Looks for changes made to the file by other programs, returning true
+    // the first time they turn up
+    pub fn check_disk(&mut self) -> bool {
+        if self.changed_on_disk {
+            return false;
+        }
+        let path = match &self.path {
+            Some(path) => path,
+            None => return false,
+        };
+        self.changed_on_disk = modified_time(path) != self.disk_modified;
+        self.changed_on_disk
+    }
+
+    // Replaces the content with the file as it is now, throwing away any
+    // unsaved changes. It goes in as an edit so it can still be undone.
+    pub fn revert(&mut self) -> Result<(), String> {
+        let path = self.path.clone().ok_or("Buffer has no file to revert to")?;
+        let text = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let (line_ending, mixed) = LineEnding::detect(&text);
+
+        self.content.widen();
+        let cursor = self.content.cursor;
+        let end = self.content.end_of_buffer();
+        self.content.replace_range(Position::default(), end, &line_ending.decode(&text));
+        let row = cursor.row.min(self.content.line_count() - 1);
+        self.content.cursor = Position::new(row, self.content.clamp_col(row, cursor.col));
+        self.content.deactivate_mark();
+
+        self.set_file_line_ending(line_ending, mixed);
+        self.saved_at = self.content.change_count();
+        self.auto_saved_at = self.saved_at;
+        self.record_disk_state();
+        Ok(())
+    }
+
+    // Copies unsaved changes made since the last auto-save next to the file,
+    // so they can be recovered after a crash
+    pub fn auto_save(&mut self) -> Result<(), String> {
+        let change_count = self.content.change_count();
+        if !self.is_dirty() || change_count == self.auto_saved_at {
+            return Ok(());
+        }
+        let auto_save = match self.path.as_deref().and_then(auto_save::auto_save_path) {
+            Some(auto_save) => auto_save,
+            None => return Ok(()),
+        };
+
+        write_atomically(&auto_save, &self.line_ending.encode(&self.content.text()))?;
+        self.auto_saved_at = change_count;
+        Ok(())
+    }
+
+    // Finds the bracket at the cursor again once it moved or the text changed
+    pub fn refresh_brackets(&mut self) {
+        let found_at = (self.content.change_count(), self.content.cursor);
+        if self.brackets_found_at == Some(found_at) {
+            return;
+        }
+        self.brackets_found_at = Some(found_at);
+
+        let (content, minor_modes) = (&self.content, &mut self.minor_modes);
+        self.brackets = show_paren::find(content, || minor_modes.iter_mut().find_map(|mode| mode.syntax_tree(content)));
+    }
+
+    pub fn syntax_tree(&mut self) -> Option<Tree> {
+        let content = &self.content;
+        self.minor_modes.iter_mut().find_map(|mode| mode.syntax_tree(content))
+    }
+
+    // The parts of the content that can be folded, from the first mode that
+    // knows, ordered by where they start
+    pub fn fold_ranges(&mut self) -> Vec<(Position, Position)> {
+        let content = &self.content;
+        self.minor_modes.iter_mut().find_map(|mode| mode.fold_ranges(content)).unwrap_or_default()
+    }
+
+    // What the content defines, from the first mode that can tell
+    pub fn definitions(&mut self) -> Vec<Definition> {
+        let content = &self.content;
+        self.minor_modes.iter_mut().find_map(|mode| mode.definitions(content)).unwrap_or_default()
+    }
+
+    pub fn comment_syntax(&self) -> Option<&'static str> {
+        self.minor_modes
+            .iter()
+            .find_map(|mode| mode.comment_syntax())
+            .or_else(|| self.content.locals.get(&COMMENT_START))
+    }
+
+    // The indentation line `row` should have according to the minor modes,
+    // falling back to copying the line above
+    pub fn indentation_for(&mut self, row: usize) -> String {
+        for minor_mode in &mut self.minor_modes {
+            if let Some(columns) = minor_mode.indent_level(&self.content, row) {
+                return " ".repeat(columns);
+            }
+        }
+
+        match row {
+            0 => String::new(),
+            _ => leading_whitespace(&self.content.line(row - 1)).to_string(),
+        }
+    }
+
+    // Replaces the leading whitespace of line `row` with its proper indentation
+    pub fn indent_line(&mut self, row: usize) {
+        let indentation = self.indentation_for(row);
+        let current_len = leading_whitespace(&self.content.line(row)).len();
+        if self.content.line(row)[..current_len] == indentation {
+            return;
+        }
+
+        self.content.delete_range(Position::new(row, 0), Position::new(row, current_len));
+        self.content.insert(Position::new(row, 0), &indentation);
+    }
+}
+
+// Writes to a temporary file next to `path` and renames it over the top, so
+// a crash halfway through never leaves a truncated file behind
+pub fn write_atomically(path: &Path, text: &str) -> Result<(), String> {
+    let file_name = path.file_name().ok_or("Not a file path")?.to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{}.edit0r-save", file_name));
+
+    let write = || -> std::io::Result<()> {
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&temp_path, metadata.permissions())?;
+        }
+        fs::rename(&temp_path, path)
+    };
+
+    write().map_err(|e| {
+        let _ = fs::remove_file(&temp_path);
+        format!("{}: {}", path.display(), e)
+    })
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+pub fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
@@ -0,0 +1,120 @@
+use std::path::Path;
+
+use crate::buffer::Buffer;
+
+// What a command asked to do with the buffers once it has finished
+enum Request {
+    SwitchTo(String),
+    Next,
+    Previous,
+    Kill,
+}
+
+// The buffers that aren't being shown, most recently shown first. The current
+// buffer is held by the main loop and only swapped with one of these after a
+// command asks for it, since commands get it alongside Global rather than
+// through it.
+#[derive(Default)]
+pub struct BufferList {
+    buffers: Vec<Buffer>,
+    request: Option<Request>,
+}
+
+impl BufferList {
+    // Adds a buffer, renaming it if another one already has its name
+    pub fn add(&mut self, mut buffer: Buffer) -> String {
+        buffer.name = self.unique_name(&buffer.name);
+        let name = buffer.name.clone();
+        self.buffers.push(buffer);
+        name
+    }
+
+    pub fn unique_name(&self, name: &str) -> String {
+        let mut unique = name.to_string();
+        let mut n = 2;
+        while self.find(&unique).is_some() {
+            unique = format!("{}<{}>", name, n);
+            n += 1;
+        }
+        unique
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Buffer> {
+        self.buffers.iter().find(|buffer| buffer.name == name)
+    }
+
+    pub fn find_mut(&mut self, name: &str) -> Option<&mut Buffer> {
+        self.buffers.iter_mut().find(|buffer| buffer.name == name)
+    }
+
+    // Takes the buffer out of the list, for one about to be replaced
+    pub fn remove(&mut self, name: &str) -> Option<Buffer> {
+        let index = self.buffers.iter().position(|buffer| buffer.name == name)?;
+        Some(self.buffers.remove(index))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.buffers.iter().map(|buffer| buffer.name.as_str())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Buffer> {
+        self.buffers.iter_mut()
+    }
+
+    // The buffer already open on the file at `path`
+    pub fn find_visiting(&self, path: &Path) -> Option<&Buffer> {
+        self.buffers.iter().find(|buffer| buffer.path.as_deref() == Some(path))
+    }
+
+    // The buffer shown before the current one
+    pub fn most_recent(&self) -> Option<&Buffer> {
+        self.buffers.first()
+    }
+
+    pub fn switch_to(&mut self, name: &str) {
+        self.request = Some(Request::SwitchTo(name.to_string()));
+    }
+
+    pub fn next(&mut self) {
+        self.request = Some(Request::Next);
+    }
+
+    pub fn previous(&mut self) {
+        self.request = Some(Request::Previous);
+    }
+
+    // Kills the current buffer, unless it is the only one
+    pub fn kill_current(&mut self) {
+        self.request = Some(Request::Kill);
+    }
+
+    // Carries out the last request, swapping `current` for the buffer to show
+    // from now on. Next and previous go round the buffers in opposite directions.
+    pub fn apply_request(&mut self, current: &mut Buffer) {
+        let request = match self.request.take() {
+            Some(request) if !self.buffers.is_empty() => request,
+            _ => return,
+        };
+
+        match request {
+            Request::SwitchTo(name) => {
+                if let Some(index) = self.buffers.iter().position(|buffer| buffer.name == name) {
+                    let mut next = self.buffers.remove(index);
+                    std::mem::swap(current, &mut next);
+                    self.buffers.insert(0, next);
+                }
+            },
+            Request::Next => {
+                let mut next = self.buffers.pop().unwrap();
+                std::mem::swap(current, &mut next);
+                self.buffers.insert(0, next);
+            },
+            Request::Previous => {
+                let mut next = self.buffers.remove(0);
+                std::mem::swap(current, &mut next);
+                self.buffers.push(next);
+            },
+            Request::Kill => *current = self.buffers.remove(0),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::any::Any;
+use std::collections::HashMap;
+
+// A setting each buffer can have its own value for, with the value buffers
+// get until they set one
+pub struct Variable<T> {
+    name: &'static str,
+    default: T,
+}
+
+impl<T> Variable<T> {
+    pub const fn new(name: &'static str, default: T) -> Variable<T> {
+        Variable { name, default }
+    }
+}
+
+// Columns per indentation level
+pub const INDENT_WIDTH: Variable<usize> = Variable::new("indent-width", 4);
+
+// The column lines should stay within, shown by the fill column indicator
+pub const FILL_COLUMN: Variable<usize> = Variable::new("fill-column", 100);
+
+// Whether the language mode underlines text the parser couldn't make sense of
+pub const SYNTAX_ERRORS: Variable<bool> = Variable::new("syntax-errors", true);
+
+// Line comment prefix, for files without a language mode to say
+pub const COMMENT_START: Variable<Option<&'static str>> = Variable::new("comment-start", None);
+
+// The values a buffer has set, so modes can keep per-buffer settings without
+// going through Global
+#[derive(Default)]
+pub struct BufferLocals {
+    values: HashMap<&'static str, Box<dyn Any>>,
+}
+
+impl BufferLocals {
+    pub fn get<T: Clone + 'static>(&self, variable: &Variable<T>) -> T {
+        self.values
+            .get(variable.name)
+            .and_then(|value| value.downcast_ref::<T>())
+            .cloned()
+            .unwrap_or_else(|| variable.default.clone())
+    }
+
+    pub fn set<T: 'static>(&mut self, variable: &Variable<T>, value: T) {
+        self.values.insert(variable.name, Box::new(value));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::buffer::Buffer;
+use crate::motion::is_word_char;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Face;
+use crate::text_mode::FaceColor;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+// Colors written in the text, like #1e90ff or rgb(30, 144, 255), shown on a
+// background of that color, as in Emacs' rainbow-mode. Handy in themes and
+// stylesheets.
+pub struct ColorPreviewMode {
+}
+
+impl TextMinorMode for ColorPreviewMode {
+    fn name(&self) -> &'static str {
+        "color-preview"
+    }
+
+    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
+        let mut spans = vec!();
+        let (start, end) = content.visible_range();
+        for row in start.row..=end.row {
+            let line = content.line(row);
+            let mut skip_to = 0;
+            for (i, c) in line.char_indices() {
+                if i < skip_to || !(c == '#' || c == 'r') {
+                    continue;
+                }
+                if line[..i].chars().next_back().is_some_and(is_word_char) {
+                    continue;
+                }
+                if let Some((color, len)) = color_at(&line[i..]) {
+                    spans.push((row, i, i + len, global.faces.anonymous_face_id(swatch(color))));
+                    skip_to = i + len;
+                }
+            }
+        }
+        for (row, start, end, face_id) in spans {
+            content.faces.add(Position::new(row, start), Position::new(row, end), face_id);
+        }
+    }
+}
+
+// A face with `color` behind text that stands out against it
+fn swatch(color: FaceColor) -> Face {
+    let (FaceColor::Rgb(r, g, b) | FaceColor::Rgba(r, g, b, _)) = color;
+    let luma = 299 * r as u32 + 587 * g as u32 + 114 * b as u32;
+    let fg = if luma > 140_000 { FaceColor::Rgb(0, 0, 0) } else { FaceColor::Rgb(255, 255, 255) };
+    Face { bg: Some(color), fg: Some(fg), bold: false, underline: false }
+}
+
+// The color `text` starts with, and how long it's written
+fn color_at(text: &str) -> Option<(FaceColor, usize)> {
+    if let Some(digits) = text.strip_prefix('#') {
+        let len = digits.len() - digits.trim_start_matches(|c: char| c.is_ascii_hexdigit()).len();
+        if digits[len..].chars().next().is_some_and(is_word_char) {
+            return None;
+        }
+        return hex_color(&digits[..len]).map(|color| (color, len + 1));
+    }
+    let arguments = text.strip_prefix("rgba(").or_else(|| text.strip_prefix("rgb("))?;
+    let close = arguments.find(')')?;
+    let len = text.len() - arguments.len() + close + 1;
+    rgb_function(&arguments[..close]).map(|color| (color, len))
+}
+
+// Colors like #fff, #ffffff and the same with alpha, #ffff and #ffffffff
+fn hex_color(digits: &str) -> Option<FaceColor> {
+    let channel = |i: usize, width: usize| -> Option<u8> {
+        let value = u8::from_str_radix(&digits[i * width..(i + 1) * width], 16).ok()?;
+        // #abc is short for #aabbcc
+        Some(if width == 1 { value * 17 } else { value })
+    };
+    let width = match digits.len() {
+        3 | 4 => 1,
+        6 | 8 => 2,
+        _ => return None,
+    };
+    let (r, g, b) = (channel(0, width)?, channel(1, width)?, channel(2, width)?);
+    match digits.len() / width {
+        4 => Some(FaceColor::Rgba(r, g, b, channel(3, width)?)),
+        _ => Some(FaceColor::Rgb(r, g, b)),
+    }
+}
+
+// The arguments of CSS's rgb() and rgba(), by commas or spaces and a slash
+// before the alpha, e.g. "255, 0, 0" or "100% 0% 0% / 0.5"
+fn rgb_function(arguments: &str) -> Option<FaceColor> {
+    let values: Vec<&str> = arguments.split([',', ' ', '/']).filter(|value| !value.is_empty()).collect();
+    // Channels go to 255 and alpha to 1, either as a percentage
+    let parse = |value: &str, full: f32| -> Option<u8> {
+        let number = match value.strip_suffix('%') {
+            Some(percent) => percent.parse::<f32>().ok()? / 100.0,
+            None => value.parse::<f32>().ok()? / full,
+        };
+        Some((number.clamp(0.0, 1.0) * 255.0).round() as u8)
+    };
+    match values.as_slice() {
+        [r, g, b] => Some(FaceColor::Rgb(parse(r, 255.0)?, parse(g, 255.0)?, parse(b, 255.0)?)),
+        [r, g, b, a] => Some(FaceColor::Rgba(parse(r, 255.0)?, parse(g, 255.0)?, parse(b, 255.0)?, parse(a, 1.0)?)),
+        _ => None,
+    }
+}
+
+// Turns showing colors written in the text in those colors on or off
+pub fn color_preview_mode(_: &mut Global, buffer: &mut Buffer) {
+    buffer.toggle_minor_mode("color-preview", || Box::new(ColorPreviewMode {}));
+}
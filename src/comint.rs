@@ -0,0 +1,485 @@
+use std::io::Read;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+
+use crate::buffer::Buffer;
+use crate::buffer_local::Variable;
+use crate::dired;
+use crate::minibuffer::Minibuffer;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+use crate::undo::end_of;
+
+// Buffers talking to an interactive program, like a python or psql prompt,
+// in the style of Emacs' comint. What the program prints goes in above the
+// line being typed, and Return sends that line to it:
+//
+//     >>> 1 + 1
+//     2
+//     >>> |
+//
+// The program runs on pipes rather than a terminal, so ones that check
+// should be asked to be interactive, e.g. "python3 -i".
+
+// Faces for the eight colors of ANSI escapes, bright ones included
+const ANSI_FACES: [&str; 8] = [
+    "ansi-black", "ansi-red", "ansi-green", "ansi-yellow",
+    "ansi-blue", "ansi-magenta", "ansi-cyan", "ansi-white",
+];
+
+// What the commands need to work on the input, kept with the buffer
+#[derive(Clone)]
+struct Comint {
+    // Where the program's output ends and the input being typed starts
+    mark: Position,
+    // TextContent::change_count() as of the mark
+    marked_at: usize,
+    // Lines sent to the program, oldest first
+    history: Vec<String>,
+    // How far back in the history M-p has gone, None for what's being typed
+    history_index: Option<usize>,
+    draft: String,
+    // Lines for the program's stdin, None once it's closed
+    input: Option<Sender<String>>,
+}
+
+const COMINT: Variable<Comint> = Variable::new("comint", Comint {
+    mark: Position { row: 0, col: 0 },
+    marked_at: 0,
+    history: Vec::new(),
+    history_index: None,
+    draft: String::new(),
+    input: None,
+});
+
+// The buffer's state, with the mark moved along with the edits since it was
+// set. Text typed right at the mark is input, so the mark stays before it.
+fn state(content: &TextContent) -> Comint {
+    let mut comint = content.locals.get(&COMINT);
+    for edit in content.changes_since(comint.marked_at) {
+        comint.mark = edit.shift(comint.mark, true);
+    }
+    comint.mark = comint.mark.min(content.end_of_buffer());
+    comint
+}
+
+fn set_state(content: &mut TextContent, mut comint: Comint) {
+    comint.marked_at = content.change_count();
+    content.locals.set(&COMINT, comint);
+}
+
+pub struct ComintMode {
+    // The mode's name, for buffers built on comint to have keys of their own
+    mode_name: &'static str,
+    // Who to say finished, e.g. "Process python3"
+    label: String,
+    child: Child,
+    output: Receiver<String>,
+    // The start of an escape sequence cut off at the end of the last output
+    unfinished: String,
+    // The ANSI color output is in, by its index in ANSI_FACES
+    color: Option<usize>,
+    // Output colored by escapes, moved along with the edits since followed_at
+    colored: Vec<(Position, Position, usize)>,
+    followed_at: usize,
+    // Set once the program has exited and said so in the buffer
+    finished: bool,
+}
+
+impl ComintMode {
+    // Starts `program` with `args` in `directory`, reading what it prints on
+    // threads of its own
+    fn spawn(program: &str, args: &[&str], directory: Option<PathBuf>) -> Result<(ComintMode, Sender<String>), String> {
+        let mut command = Command::new(program);
+        command.args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            // Programs that page or draw for a terminal shouldn't here
+            .env("TERM", "dumb")
+            .env("PAGER", "cat")
+            .env("PYTHONUNBUFFERED", "1");
+        if let Some(directory) = directory {
+            command.current_dir(directory);
+        }
+        let mut child = command.spawn().map_err(|e| format!("{}: {}", program, e))?;
+
+        let (output_sender, output) = mpsc::channel();
+        let stdout = child.stdout.take().ok_or("No stdout")?;
+        let stderr = child.stderr.take().ok_or("No stderr")?;
+        let stderr_sender = output_sender.clone();
+        thread::spawn(move || read_output(stdout, output_sender));
+        thread::spawn(move || read_output(stderr, stderr_sender));
+
+        let (input, lines) = mpsc::channel();
+        let stdin = child.stdin.take().ok_or("No stdin")?;
+        thread::spawn(move || write_input(stdin, lines));
+
+        let mode = ComintMode {
+            mode_name: "comint",
+            label: format!("Process {}", program),
+            child,
+            output,
+            unfinished: String::new(),
+            color: None,
+            colored: vec!(),
+            followed_at: 0,
+            finished: false,
+        };
+        Ok((mode, input))
+    }
+
+    // Takes the escape sequences out of `output`, noting which parts they colored
+    fn strip_escapes(&mut self, output: &str) -> Vec<(String, Option<usize>)> {
+        let mut text = std::mem::take(&mut self.unfinished);
+        text.push_str(output);
+
+        let mut runs = vec!((String::new(), self.color));
+        let mut chars = text.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\x1b' => {
+                    let sequence = match escape_sequence(&text[i..]) {
+                        Some(sequence) => sequence,
+                        None => {
+                            self.unfinished = text[i..].to_string();
+                            break;
+                        },
+                    };
+                    if let Some(params) = sequence.strip_prefix("\x1b[").and_then(|rest| rest.strip_suffix('m')) {
+                        self.color = sgr_color(params, self.color);
+                        runs.push((String::new(), self.color));
+                    }
+                    while chars.peek().is_some_and(|&(j, _)| j < i + sequence.len()) {
+                        chars.next();
+                    }
+                },
+                '\r' | '\x07' => {},
+                c => runs.last_mut().unwrap().0.push(c),
+            }
+        }
+        runs.retain(|(text, _)| !text.is_empty());
+        runs
+    }
+
+    // Puts the output in at the mark, before whatever is being typed
+    fn insert_output(&mut self, global: &mut Global, content: &mut TextContent, output: &str) {
+        let runs = self.strip_escapes(output);
+        if runs.is_empty() {
+            return;
+        }
+        let text: String = runs.iter().map(|(text, _)| text.as_str()).collect();
+        let start = insert_before_input(content, &text);
+
+        let mut at = start;
+        for (text, color) in runs {
+            let end = end_of(at, &text);
+            if let Some(face_id) = color.and_then(|color| global.faces.get_face_id(ANSI_FACES[color])) {
+                self.colored.push((at, end, face_id));
+            }
+            at = end;
+        }
+    }
+
+    // Notes that the program exited at the end of the buffer, as Emacs does
+    fn finish(&mut self, content: &mut TextContent) {
+        let status = match self.child.try_wait() {
+            Ok(Some(status)) => status,
+            Ok(None) => return,
+            Err(_) => {
+                self.finished = true;
+                return;
+            },
+        };
+        let message = match status.code() {
+            Some(0) => format!("\n{} finished\n", self.label),
+            Some(code) => format!("\n{} exited abnormally with code {}\n", self.label, code),
+            None => format!("\n{} killed\n", self.label),
+        };
+        let end = content.end_of_buffer();
+        let inserted = content.insert(end, &message);
+        let mut comint = state(content);
+        if let Some(end) = inserted {
+            comint.mark = end;
+        }
+        comint.input = None;
+        set_state(content, comint);
+        self.finished = true;
+    }
+}
+
+impl Drop for ComintMode {
+    // Killing the buffer stops the program
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+impl TextMinorMode for ComintMode {
+    fn name(&self) -> &'static str {
+        self.mode_name
+    }
+
+    fn is_major(&self) -> bool {
+        true
+    }
+
+    fn wraps_lines(&self) -> bool {
+        true
+    }
+
+    fn poll(&mut self, global: &mut Global, content: &mut TextContent) {
+        for edit in content.changes_since(self.followed_at) {
+            for span in &mut self.colored {
+                span.0 = edit.shift(span.0, false);
+                span.1 = edit.shift(span.1, true);
+            }
+        }
+        self.colored.retain(|&(start, end, _)| start < end);
+
+        let mut output = String::new();
+        let mut closed = false;
+        loop {
+            match self.output.try_recv() {
+                Ok(text) => output.push_str(&text),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    closed = true;
+                    break;
+                },
+            }
+        }
+        if !output.is_empty() {
+            self.insert_output(global, content, &output);
+        }
+        if closed && !self.finished {
+            self.finish(content);
+        }
+        self.followed_at = content.change_count();
+    }
+
+    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
+        for &(start, end, face_id) in &self.colored {
+            content.faces.add(start, end, face_id);
+        }
+        // Stored as moved along now, since the edits it was moved by are
+        // dropped once the modes have run
+        let comint = state(content);
+        let mark = comint.mark;
+        set_state(content, comint);
+        // The prompt is what was printed last, on the line being typed
+        if let (true, Some(face_id)) = (mark.col > 0, global.faces.get_face_id("comint-prompt")) {
+            content.faces.add(Position::new(mark.row, 0), mark, face_id);
+        }
+    }
+}
+
+// The escape sequence `text` starts with, or None if it's cut off. Control
+// sequences end in a letter or the like, others are one character long.
+fn escape_sequence(text: &str) -> Option<&str> {
+    let mut chars = text.char_indices().skip(1);
+    match chars.next()? {
+        (_, '[') => chars
+            .find(|&(_, c)| ('@'..='~').contains(&c))
+            .map(|(i, c)| &text[..i + c.len_utf8()]),
+        (i, c) => Some(&text[..i + c.len_utf8()]),
+    }
+}
+
+// The color after the "select graphic rendition" escape with `params`
+fn sgr_color(params: &str, mut color: Option<usize>) -> Option<usize> {
+    for param in params.split(';') {
+        match param.parse::<usize>().unwrap_or(0) {
+            0 | 39 => color = None,
+            n @ 30..=37 => color = Some(n - 30),
+            n @ 90..=97 => color = Some(n - 90),
+            // Colors by number or RGB, which there are no faces for
+            38 => return None,
+            _ => {},
+        }
+    }
+    color
+}
+
+// Sends what the program prints on, whole characters at a time
+fn read_output(mut reader: impl Read, output: Sender<String>) {
+    let mut buffer = [0; 4096];
+    let mut bytes: Vec<u8> = vec!();
+    while let Ok(read) = reader.read(&mut buffer) {
+        if read == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&buffer[..read]);
+        let valid = match std::str::from_utf8(&bytes) {
+            Ok(text) => text.len(),
+            // Invalid bytes are let through, a character cut off is kept for later
+            Err(e) if e.error_len().is_some() => bytes.len(),
+            Err(e) => e.valid_up_to(),
+        };
+        let text = String::from_utf8_lossy(&bytes[..valid]).into_owned();
+        bytes.drain(..valid);
+        if output.send(text).is_err() {
+            break;
+        }
+    }
+}
+
+fn write_input(mut writer: impl Write, lines: Receiver<String>) {
+    for line in lines {
+        if writer.write_all(line.as_bytes()).and_then(|_| writer.flush()).is_err() {
+            break;
+        }
+    }
+}
+
+// Inserts `text` at the mark, before the input, and moves the mark past it.
+// Returns where the text went.
+pub fn insert_before_input(content: &mut TextContent, text: &str) -> Position {
+    let mut comint = state(content);
+    let start = comint.mark;
+    if let Some(end) = content.insert(start, text) {
+        comint.mark = end;
+    }
+    set_state(content, comint);
+    start
+}
+
+// The directory programs are run from, the one of the current file or listing
+pub fn default_directory(buffer: &Buffer) -> Option<PathBuf> {
+    dired::directory(buffer)
+        .or_else(|| buffer.path.as_ref().and_then(|path| path.parent()).map(PathBuf::from))
+        .filter(|directory| directory.is_dir())
+}
+
+// Starts `program` with `args` in a new buffer, whose major mode goes by
+// `mode_name` and says `label` finished when the program exits
+pub fn start(mode_name: &'static str, label: &str, program: &str, args: &[&str], directory: Option<PathBuf>) -> Result<Buffer, String> {
+    let (mut mode, input) = ComintMode::spawn(program, args, directory)?;
+    mode.mode_name = mode_name;
+    mode.label = label.to_string();
+    let mut buffer = Buffer::new(&format!("*{}*", program), TextContent::new(""), vec!(Box::new(mode)));
+    let comint = Comint { input: Some(input), ..state(&buffer.content) };
+    set_state(&mut buffer.content, comint);
+    Ok(buffer)
+}
+
+// Runs a program in a new buffer, asking for its command line, split at spaces
+pub fn comint_run(global: &mut Global, _: &mut Buffer) {
+    global.minibuffer = Some(Minibuffer::new("Run program: ", "", None, run_program));
+}
+
+fn run_program(global: &mut Global, buffer: &mut Buffer, input: &str) {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let (program, args) = match words.split_first() {
+        Some(words) => words,
+        None => return,
+    };
+    let label = format!("Process {}", program);
+    match start("comint", &label, program, args, default_directory(buffer)) {
+        Ok(started) => {
+            let name = global.buffers.add(started);
+            global.buffers.switch_to(&name);
+        },
+        Err(e) => global.message(format!("Running failed: {}", e)),
+    }
+}
+
+// Sends the input after the prompt to the program, and starts a new line
+pub fn comint_send_input(global: &mut Global, buffer: &mut Buffer) {
+    let content = &mut buffer.content;
+    let mut comint = state(content);
+    let sender = match &comint.input {
+        Some(sender) => sender.clone(),
+        None => {
+            global.message("No program is running in this buffer".to_string());
+            return;
+        },
+    };
+
+    let end = content.end_of_buffer();
+    let input = content.text_range(comint.mark, end);
+    content.cursor = match content.insert(end, "\n") {
+        Some(end) => end,
+        None => return,
+    };
+    content.deactivate_mark();
+    comint.mark = content.cursor;
+    if !input.trim().is_empty() && comint.history.last() != Some(&input) {
+        comint.history.push(input.clone());
+    }
+    comint.history_index = None;
+    if sender.send(input + "\n").is_err() {
+        comint.input = None;
+    }
+    set_state(content, comint);
+}
+
+// Swaps the input for an older line sent, or a newer one and finally what
+// was being typed, like the minibuffer's history
+fn step_history(global: &mut Global, buffer: &mut Buffer, older: bool) {
+    let content = &mut buffer.content;
+    let mut comint = state(content);
+    let end = content.end_of_buffer();
+    let index = match (comint.history_index, older) {
+        (None, true) if !comint.history.is_empty() => {
+            comint.draft = content.text_range(comint.mark, end);
+            Some(0)
+        },
+        (Some(index), true) if index + 1 < comint.history.len() => Some(index + 1),
+        (Some(0), false) => None,
+        (Some(index), false) => Some(index - 1),
+        _ => {
+            global.message(if older { "No older input" } else { "No newer input" }.to_string());
+            return;
+        },
+    };
+
+    let text = match index {
+        Some(index) => comint.history[comint.history.len() - 1 - index].clone(),
+        None => std::mem::take(&mut comint.draft),
+    };
+    content.cursor = match content.replace_range(comint.mark, end, &text) {
+        Some(end) => end,
+        None => return,
+    };
+    comint.history_index = index;
+    set_state(content, comint);
+}
+
+pub fn comint_previous_input(global: &mut Global, buffer: &mut Buffer) {
+    step_history(global, buffer, true);
+}
+
+pub fn comint_next_input(global: &mut Global, buffer: &mut Buffer) {
+    step_history(global, buffer, false);
+}
+
+// Goes to the start of the input on the prompt's line, else the line's start
+pub fn comint_beginning_of_line(_: &mut Global, buffer: &mut Buffer) {
+    let content = &mut buffer.content;
+    let mark = state(content).mark;
+    let row = content.cursor.row;
+    content.cursor = if row == mark.row && content.cursor.col > mark.col { mark } else { Position::new(row, 0) };
+}
+
+// Closes the program's input, which most take as the signal to exit
+pub fn comint_send_eof(global: &mut Global, buffer: &mut Buffer) {
+    let mut comint = state(&buffer.content);
+    if comint.input.take().is_none() {
+        global.message("No program is running in this buffer".to_string());
+    }
+    set_state(&mut buffer.content, comint);
+}
@@ -0,0 +1,296 @@
+use crate::bookmark;
+use crate::buffer::Buffer;
+use crate::color_preview_mode;
+use crate::comint;
+use crate::commands;
+use crate::compilation;
+use crate::completion;
+use crate::diff_mode;
+use crate::dired;
+use crate::fold_mode;
+use crate::git_blame;
+use crate::git_gutter;
+use crate::git_status;
+use crate::goto_address_mode;
+use crate::hl_todo;
+use crate::keymap;
+use crate::markdown_mode;
+use crate::commands::Command;
+use crate::commands::CommandContext;
+use crate::commands::Repeatable;
+use crate::minibuffer;
+use crate::minibuffer::Minibuffer;
+use crate::outline_mode;
+use crate::popup;
+use crate::search;
+use crate::spell_mode;
+use crate::text_mode::Global;
+use crate::vim_mode;
+
+// A command as M-x and key bindings know it
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub doc: &'static str,
+    pub command: Command,
+}
+
+macro_rules! command(
+    ($name:expr, $command:path, $doc:expr) => (
+        CommandInfo { name: $name, doc: $doc, command: $command }
+    )
+);
+
+// Every command there is, by name
+pub const COMMANDS: &[CommandInfo] = &[
+    command!("newline", commands::newline, "Breaks the line at the cursor"),
+    command!("newline-and-indent", commands::newline_and_indent, "Breaks the line at the cursor and indents the new one"),
+    command!("indent-for-tab", commands::indent_for_tab, "Indents the line, after the minor modes have had a go at Tab"),
+    command!("backtab", commands::backtab, "Takes the line back an indentation level"),
+    command!("delete-char", commands::delete_char, "Deletes the character at the cursor"),
+    command!("delete-backward-char", commands::delete_backward_char, "Deletes the character before the cursor"),
+    command!("kill-line", commands::kill_line, "Kills to the end of the line, or the line break at the end"),
+    command!("save-buffer", commands::save_buffer, "Saves the buffer to its file"),
+    command!("write-file", commands::write_file, "Saves the buffer to another file, which it visits from then on"),
+    command!("revert-buffer", commands::revert_buffer, "Reloads the file, throwing away unsaved changes"),
+    command!("narrow-to-region", commands::narrow_to_region, "Hides everything but the region"),
+    command!("widen", commands::widen, "Shows the whole buffer again after narrowing"),
+    command!("set-indent-width", commands::set_indent_width, "Sets how many columns an indentation level is in this buffer"),
+    command!("load-theme", commands::load_theme, "Switches every buffer to the faces of a theme file"),
+    command!("set-font", commands::set_font, "Sets the fonts to draw with"),
+    command!("set-fill-column", commands::set_fill_column, "Sets the column lines are kept within in this buffer"),
+    command!("toggle-read-only", commands::toggle_read_only, "Makes the buffer read-only, or editable again"),
+    command!("find-file", commands::find_file, "Opens a file in a buffer of its own"),
+    command!("cycle-line-ending", commands::cycle_line_ending, "Switches the file to the next of LF, CRLF and CR line endings"),
+    command!("insert-char", commands::insert_char, "Inserts a character by its code point or name"),
+    command!("switch-to-buffer", commands::switch_to_buffer, "Prompts for a buffer and switches to it"),
+    command!("next-buffer", commands::next_buffer, "Switches to the next buffer"),
+    command!("previous-buffer", commands::previous_buffer, "Switches to the previous buffer"),
+    command!("kill-buffer", commands::kill_buffer, "Closes the current buffer"),
+    command!("toggle-tab-bar", commands::toggle_tab_bar, "Shows or hides the tab bar"),
+    command!("tab-next", commands::tab_next, "Switches to the buffer of the next tab"),
+    command!("tab-previous", commands::tab_previous, "Switches to the buffer of the previous tab"),
+    command!("tab-move", commands::tab_move, "Moves the current tab one place to the right"),
+    command!("tab-move-back", commands::tab_move_back, "Moves the current tab one place to the left"),
+    command!("split-window-below", commands::split_window_below, "Splits the selected window into one above the other"),
+    command!("split-window-right", commands::split_window_right, "Splits the selected window into two side by side"),
+    command!("other-window", commands::other_window, "Moves the focus to the next window"),
+    command!("delete-window", commands::delete_window, "Closes the selected window"),
+    command!("delete-other-windows", commands::delete_other_windows, "Makes the selected window fill the frame"),
+    command!("balance-windows", commands::balance_windows, "Gives every window the same share of the frame"),
+    command!("scratch-buffer", commands::scratch_buffer, "Opens a new buffer for notes that isn't saved anywhere"),
+    command!("undo", commands::undo, "Undoes the last change"),
+    command!("redo", commands::redo, "Redoes the last change undone"),
+    command!("backward-char", commands::backward_char, "Moves back a character"),
+    command!("forward-char", commands::forward_char, "Moves forward a character"),
+    command!("previous-line", commands::previous_line, "Moves up a line"),
+    command!("next-line", commands::next_line, "Moves down a line"),
+    command!("scroll-up", commands::scroll_up, "Pages down"),
+    command!("scroll-down", commands::scroll_down, "Pages up"),
+    command!("toggle-truncate-lines", commands::toggle_truncate_lines, "Switches between wrapping long lines and cutting them off"),
+    command!("cycle-line-numbers", commands::cycle_line_numbers, "Goes from absolute line numbers to relative ones to none"),
+    command!("cycle-cursor-style", commands::cycle_cursor_style, "Goes from a block cursor to a bar to an underline"),
+    command!("toggle-cursor-blink", commands::toggle_cursor_blink, "Starts or stops the cursor blinking"),
+    command!("toggle-smooth-scroll", commands::toggle_smooth_scroll, "Switches between sliding and jumping when scrolling"),
+    command!("scroll-bar-mode", commands::scroll_bar_mode, "Shows or hides the scroll bars"),
+    command!("text-scale-increase", commands::text_scale_increase, "Makes the text bigger"),
+    command!("text-scale-decrease", commands::text_scale_decrease, "Makes the text smaller"),
+    command!("text-scale-reset", commands::text_scale_reset, "Puts the text back to its first size"),
+    command!("forward-word", commands::forward_word, "Moves to the end of the word"),
+    command!("backward-word", commands::backward_word, "Moves to the start of the word"),
+    command!("beginning-of-line", commands::beginning_of_line, "Moves to the start of the line"),
+    command!("end-of-line", commands::end_of_line, "Moves to the end of the line"),
+    command!("forward-paragraph", commands::forward_paragraph, "Moves to the next blank line"),
+    command!("backward-paragraph", commands::backward_paragraph, "Moves to the previous blank line"),
+    command!("beginning-of-buffer", commands::beginning_of_buffer, "Moves to the start of the buffer"),
+    command!("end-of-buffer", commands::end_of_buffer, "Moves to the end of the buffer"),
+    command!("kill-word", commands::kill_word, "Kills to the end of the word"),
+    command!("backward-kill-word", commands::backward_kill_word, "Kills to the start of the word"),
+    command!("delete-word", commands::delete_word, "Deletes to the end of the word, leaving the kill ring alone"),
+    command!("backward-delete-word", commands::backward_delete_word, "Deletes to the start of the word, leaving the kill ring alone"),
+    command!("set-mark", commands::set_mark, "Starts a region at the cursor"),
+    command!("mark-whole-buffer", commands::mark_whole_buffer, "Selects the whole buffer"),
+    command!("keyboard-quit", commands::keyboard_quit, "Gets rid of the region"),
+    command!("kill-editor", commands::kill_editor, "Closes the editor"),
+    command!("reload-keys", keymap::reload_keys, "Reads the keys file again"),
+    command!("kill-region", commands::kill_region, "Kills the region"),
+    command!("copy-region", commands::copy_region, "Copies the region to the kill ring"),
+    command!("yank", commands::yank, "Inserts the last kill"),
+    command!("transpose-chars", commands::transpose_chars, "Swaps the characters around the cursor"),
+    command!("transpose-words", commands::transpose_words, "Swaps the words around the cursor"),
+    command!("increment-number", commands::increment_number, "Adds one to the number at the cursor"),
+    command!("decrement-number", commands::decrement_number, "Takes one off the number at the cursor"),
+    command!("upcase-word", commands::upcase_word, "Upper cases to the end of the word"),
+    command!("downcase-word", commands::downcase_word, "Lower cases to the end of the word"),
+    command!("capitalize-word", commands::capitalize_word, "Capitalizes the word"),
+    command!("kill-rectangle", commands::kill_rectangle, "Kills the rectangle between the mark and the cursor"),
+    command!("delete-rectangle", commands::delete_rectangle, "Deletes the rectangle between the mark and the cursor"),
+    command!("yank-rectangle", commands::yank_rectangle, "Inserts the last killed rectangle"),
+    command!("string-rectangle", commands::string_rectangle, "Fills each line of the rectangle with a string"),
+    command!("search-forward-regexp", search::search_forward_regexp, "Goes to the end of the next match of a regexp"),
+    command!("search-backward-regexp", search::search_backward_regexp, "Goes to the start of the previous match of a regexp"),
+    command!("query-replace", search::query_replace, "Replaces a string one match at a time, asking about each"),
+    command!("query-replace-regexp", search::query_replace_regexp, "Replaces a regexp one match at a time, asking about each"),
+    command!("query-replace-yes", search::query_replace_yes, "Replaces this match and goes to the next"),
+    command!("query-replace-no", search::query_replace_no, "Leaves this match and goes to the next"),
+    command!("query-replace-all", search::query_replace_all, "Replaces this match and all the rest"),
+    command!("query-replace-last", search::query_replace_last, "Replaces this match and stops"),
+    command!("query-replace-quit", search::query_replace_quit, "Stops replacing"),
+    command!("bookmark-set", bookmark::bookmark_set, "Sets a bookmark at the cursor, prompting for its name"),
+    command!("bookmark-jump", bookmark::bookmark_jump, "Goes to a bookmark, opening its file if need be"),
+    command!("bookmark-delete", bookmark::bookmark_delete, "Deletes a bookmark, prompting for it"),
+    command!("move-lines-up", commands::move_lines_up, "Moves the line or the region's lines up"),
+    command!("move-lines-down", commands::move_lines_down, "Moves the line or the region's lines down"),
+    command!("duplicate-lines", commands::duplicate_lines, "Copies the line or the region's lines below themselves"),
+    command!("sort-lines", commands::sort_lines, "Sorts the region's lines"),
+    command!("sort-lines-numerically", commands::sort_lines_numerically, "Sorts the region's lines by the number each starts with"),
+    command!("reverse-lines", commands::reverse_lines, "Reverses the order of the region's lines"),
+    command!("delete-duplicate-lines", commands::delete_duplicate_lines, "Keeps only the first of each set of identical lines"),
+    command!("join-line", commands::join_line, "Joins the next line onto this one"),
+    command!("toggle-electric-pair-mode", commands::toggle_electric_pair_mode, "Turns closing brackets and quotes as they open on or off"),
+    command!("toggle-minimap", commands::toggle_minimap, "Shows or hides the minimap"),
+    command!("hl-line-mode", commands::hl_line_mode, "Turns highlighting the cursor's line on or off"),
+    command!("hl-todo-mode", hl_todo::hl_todo_mode, "Turns highlighting TODO, FIXME, HACK and NOTE in comments on or off"),
+    command!("todo-list", hl_todo::todo_list, "Lists the TODOs and the like in the comments of the project's files"),
+    command!("fill-column-indicator-mode", commands::fill_column_indicator_mode, "Turns the line at the fill column on or off"),
+    command!("indent-guide-mode", commands::indent_guide_mode, "Turns the lines marking indentation levels on or off"),
+    command!("toggle-syntax-errors", commands::toggle_syntax_errors, "Underlines where the parser found syntax errors, or stops"),
+    command!("rainbow-delimiters-mode", commands::rainbow_delimiters_mode, "Turns coloring brackets by their depth on or off"),
+    command!("color-preview-mode", color_preview_mode::color_preview_mode, "Turns showing colors like #1e90ff in those colors on or off"),
+    command!("goto-address-mode", goto_address_mode::goto_address_mode, "Turns underlining URLs and places like src/main.rs:10 as links on or off"),
+    command!("follow-link", goto_address_mode::follow_link, "Opens the URL at the cursor in the browser, or goes to the place in a file it's on"),
+    command!("overwrite-mode", commands::overwrite_mode, "Turns typing over the text on or off"),
+    command!("vim-mode", vim_mode::vim_mode, "Turns vim's modal editing on or off in this buffer"),
+    command!("vim-normal-state", vim_mode::vim_normal_state, "Goes back to vim's normal state"),
+    command!("toggle-comment", commands::toggle_comment, "Comments out the line or the region's lines, or uncomments them"),
+    command!("select-enclosing-node", commands::select_enclosing_node, "Selects the syntax node around the cursor"),
+    command!("expand-selection", commands::expand_selection, "Grows the region to the enclosing syntax node"),
+    command!("shrink-selection", commands::shrink_selection, "Shrinks the region back to the node it grew from"),
+    command!("forward-sibling", commands::forward_sibling, "Moves to the next syntax node at the same level"),
+    command!("backward-sibling", commands::backward_sibling, "Moves to the previous syntax node at the same level"),
+    command!("next-definition", commands::next_definition, "Moves to the start of the next function, type or other definition"),
+    command!("previous-definition", commands::previous_definition, "Moves to the start of the previous definition"),
+    command!("imenu", commands::imenu, "Prompts for a definition in the buffer and goes to it"),
+    command!("jump-to-matching-bracket", commands::jump_to_matching_bracket, "Moves onto the bracket matching the one at the cursor"),
+    command!("raise-node", commands::raise_node, "Replaces the parent syntax node with the one at the cursor"),
+    command!("splice-node", commands::splice_node, "Removes the brackets around the cursor, keeping what's inside"),
+    command!("completion-at-point", commands::completion_at_point, "Offers the words starting like the one before the cursor in a menu"),
+    command!("fold-mode", fold_mode::fold_mode, "Turns the keys for folding blocks on or off, showing everything when off"),
+    command!("fold-block", fold_mode::fold_block, "Folds away the block around the cursor"),
+    command!("unfold-block", fold_mode::unfold_block, "Shows the folded block at the cursor again"),
+    command!("toggle-fold", fold_mode::toggle_fold, "Folds or unfolds the block at the cursor"),
+    command!("fold-all", fold_mode::fold_all, "Folds away every outermost block"),
+    command!("unfold-all", fold_mode::unfold_all, "Shows every folded block again"),
+    command!("comint-run", comint::comint_run, "Runs a program in a buffer to type its input in"),
+    command!("comint-send-input", comint::comint_send_input, "Sends the input after the prompt to the program"),
+    command!("comint-previous-input", comint::comint_previous_input, "Swaps the input for the line sent before it"),
+    command!("comint-next-input", comint::comint_next_input, "Swaps the input for the line sent after it"),
+    command!("comint-beginning-of-line", comint::comint_beginning_of_line, "Goes to the start of the input, after the prompt"),
+    command!("comint-send-eof", comint::comint_send_eof, "Closes the program's input"),
+    command!("compile", compilation::compile, "Runs a build command, showing its output in the compilation buffer"),
+    command!("recompile", compilation::recompile, "Runs the last build command again"),
+    command!("next-error", compilation::next_error, "Goes to the next place the compilation mentions"),
+    command!("previous-error", compilation::previous_error, "Goes to the previous place the compilation mentions"),
+    command!("compile-goto-error", compilation::compile_goto_error, "Goes to the place mentioned on this line of the compilation"),
+    command!("compilation-next-error", compilation::compilation_next_error, "Moves to the next place mentioned in the compilation"),
+    command!("compilation-previous-error", compilation::compilation_previous_error, "Moves to the previous place mentioned in the compilation"),
+    command!("compilation-shell-minor-mode", compilation::compilation_shell_minor_mode, "Underlines places mentioned in a program's output"),
+    command!("git-blame-mode", git_blame::git_blame_mode, "Turns saying who last changed the cursor's line on or off"),
+    command!("git-blame-toggle-every-line", git_blame::git_blame_toggle_every_line, "Switches between blaming the cursor's line and every line"),
+    command!("git-gutter-mode", git_gutter::git_gutter_mode, "Turns marking lines changed since the last commit on or off"),
+    command!("git-gutter-refresh", git_gutter::git_gutter_refresh, "Diffs the file against the last commit again"),
+    command!("git-gutter-next-hunk", git_gutter::git_gutter_next_hunk, "Goes to the next lines changed since the last commit"),
+    command!("git-gutter-previous-hunk", git_gutter::git_gutter_previous_hunk, "Goes to the previous lines changed since the last commit"),
+    command!("git-status", git_status::git_status, "Shows what's changed in the repository of the current file"),
+    command!("git-status-refresh", git_status::git_status_refresh, "Shows the repository's status again"),
+    command!("git-status-visit", git_status::git_status_visit, "Opens the file on this line of the status, at its hunk"),
+    command!("git-status-stage", git_status::git_status_stage, "Stages the file or hunk on this line"),
+    command!("git-status-unstage", git_status::git_status_unstage, "Unstages the file or hunk on this line"),
+    command!("git-status-discard", git_status::git_status_discard, "Throws away the changes on this line, after asking"),
+    command!("git-status-commit", git_status::git_status_commit, "Commits what's staged, prompting for the message"),
+    command!("git-status-push", git_status::git_status_push, "Pushes the current branch"),
+    command!("git-status-next", git_status::git_status_next, "Goes to the next file or hunk"),
+    command!("git-status-previous", git_status::git_status_previous, "Goes to the previous file or hunk"),
+    command!("spell-mode", spell_mode::spell_mode, "Turns underlining misspelled words on or off"),
+    command!("spell-correct-word", spell_mode::spell_correct_word, "Offers corrections for the word at the cursor"),
+    command!("diff-buffers", diff_mode::diff_buffers, "Compares the buffer side by side with another, prompting for it"),
+    command!("diff-buffer-with-file", diff_mode::diff_buffer_with_file, "Compares the buffer side by side with its file as saved"),
+    command!("diff-next-change", diff_mode::diff_next_change, "Goes to the next hunk or change of a diff"),
+    command!("diff-previous-change", diff_mode::diff_previous_change, "Goes to the previous hunk or change of a diff"),
+    command!("dired-find-file", dired::dired_find_file, "Opens the file on this line of a directory listing"),
+    command!("dired-up-directory", dired::dired_up_directory, "Lists the directory above the one listed"),
+    command!("dired-next-line", dired::dired_next_line, "Goes to the name of the next file listed"),
+    command!("dired-previous-line", dired::dired_previous_line, "Goes to the name of the previous file listed"),
+    command!("dired-revert", dired::dired_revert, "Lists the directory again"),
+    command!("dired-do-rename", dired::dired_do_rename, "Renames or moves the file on this line"),
+    command!("dired-do-copy", dired::dired_do_copy, "Copies the file on this line"),
+    command!("dired-do-delete", dired::dired_do_delete, "Deletes the file on this line, after asking"),
+    command!("dired-create-directory", dired::dired_create_directory, "Creates a directory, prompting for its name"),
+    command!("dired-create-file", dired::dired_create_file, "Creates an empty file, prompting for its name"),
+    command!("outline-promote", outline_mode::outline_promote, "Takes the heading the cursor is under up a level"),
+    command!("outline-demote", outline_mode::outline_demote, "Takes the heading the cursor is under down a level"),
+    command!("outline-promote-subtree", outline_mode::outline_promote_subtree, "Takes the heading the cursor is under and the ones under it up a level"),
+    command!("outline-demote-subtree", outline_mode::outline_demote_subtree, "Takes the heading the cursor is under and the ones under it down a level"),
+    command!("markdown-toggle-markup-hiding", markdown_mode::toggle_markup_hiding, "Hides or shows Markdown markup, leaving its faces to show it"),
+    command!("describe-syntax-at-point", commands::describe_syntax_at_point, "Shows the syntax nodes around the cursor"),
+    command!("popup-next", popup::popup_next, "Picks out the next line of the menu"),
+    command!("popup-previous", popup::popup_previous, "Picks out the previous line of the menu"),
+    command!("popup-choose", popup::popup_choose, "Chooses the line picked out in the menu"),
+    command!("popup-close", popup::popup_close, "Closes the popups"),
+    command!("execute-extended-command", execute_extended_command, "Prompts for a command by name and runs it"),
+    command!("describe-command", describe_command, "Prompts for a command by name and shows what it does"),
+    command!("exit-minibuffer", minibuffer::exit_minibuffer, "Finishes with the minibuffer, giving it the input"),
+    command!("previous-history-element", minibuffer::previous_history_element, "Goes back to an older input to the prompt"),
+    command!("next-history-element", minibuffer::next_history_element, "Goes forward to a newer input to the prompt"),
+    command!("minibuffer-previous-completion", minibuffer::minibuffer_previous_completion, "Picks out the previous candidate"),
+    command!("minibuffer-next-completion", minibuffer::minibuffer_next_completion, "Picks out the next candidate"),
+    command!("minibuffer-complete", minibuffer::minibuffer_complete, "Replaces the input with the candidate picked out"),
+    command!("abort-minibuffer", minibuffer::abort_minibuffer, "Closes the minibuffer without giving it the input"),
+];
+
+pub fn find(name: &str) -> Option<&'static CommandInfo> {
+    COMMANDS.iter().find(|info| info.name == name)
+}
+
+// The command named `name`, for running from a key binding
+pub fn command(name: &str) -> Result<Command, String> {
+    find(name).map(|info| info.command).ok_or_else(|| format!("{} is not a command", name))
+}
+
+// Every command's name, for completing
+fn command_names(_: &str) -> Vec<String> {
+    COMMANDS.iter().map(|info| info.name.to_string()).collect()
+}
+
+// The command named `input`, or else the one it matches best
+fn best_match(input: &str) -> Option<&'static CommandInfo> {
+    let input = input.trim();
+    if input.is_empty() {
+        return None;
+    }
+    find(input).or_else(|| {
+        let best = completion::filter(input, &command_names(input)).into_iter().next()?;
+        find(&best.text)
+    })
+}
+
+pub fn execute_extended_command(global: &mut Global, _: &mut Buffer) {
+    global.minibuffer = Some(Minibuffer::new("M-x ", "", Some(command_names), run_named_command));
+}
+
+fn run_named_command(global: &mut Global, buffer: &mut Buffer, input: &str) {
+    match best_match(input) {
+        Some(info) => commands::execute(global, buffer, Repeatable::Command(info.command), CommandContext::default()),
+        None => global.message(format!("No command matches {}", input)),
+    }
+}
+
+pub fn describe_command(global: &mut Global, _: &mut Buffer) {
+    global.minibuffer = Some(Minibuffer::new("Describe command: ", "", Some(command_names), show_description));
+}
+
+fn show_description(global: &mut Global, _: &mut Buffer, input: &str) {
+    match best_match(input) {
+        Some(info) => global.message(format!("{}: {}", info.name, info.doc)),
+        None => global.message(format!("No command matches {}", input)),
+    }
+}
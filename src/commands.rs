@@ -0,0 +1,1244 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use tree_sitter::Tree;
+
+use crate::auto_mode;
+use crate::auto_save;
+use crate::buffer::Buffer;
+use crate::buffer_local::FILL_COLUMN;
+use crate::buffer_local::INDENT_WIDTH;
+use crate::buffer_local::SYNTAX_ERRORS;
+use crate::buffer::leading_whitespace;
+use crate::dired;
+use crate::electric_pair_mode::ElectricPairMode;
+use crate::hl_line_mode::FillColumnIndicatorMode;
+use crate::hl_line_mode::HlLineMode;
+use crate::indent_guide_mode::IndentGuideMode;
+use crate::font::FontSettings;
+use crate::minibuffer::Minibuffer;
+use crate::minibuffer::complete_path;
+use crate::minimap_mode::MinimapMode;
+use crate::motion;
+use crate::motion::Motion;
+use crate::motion::is_word_char;
+use crate::overwrite_mode::OverwriteMode;
+use crate::popup::Popup;
+use crate::rainbow_delimiters_mode::RainbowDelimitersMode;
+use crate::rectangle;
+use crate::rectangle::Rectangle;
+use crate::text_content::EditGuard;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode;
+use crate::text_mode::Global;
+use crate::theme;
+use crate::tree_sitter_mode;
+use crate::unicode_names;
+
+// Everything bindable to a key is a Command
+pub type Command = fn(&mut Global, &mut Buffer);
+
+// What C-x z can run again
+#[derive(Clone)]
+pub enum Repeatable {
+    Command(Command),
+    SelfInsert(String),
+    // Typing that skips the minor modes, from C-q
+    QuotedInsert(String),
+}
+
+// The prefix argument typed before a command
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PrefixArg {
+    // C-u on its own, pressed this many times
+    Universal(u32),
+    // Digits after C-u or M-, negative after a -
+    Number(i64),
+    // C-u - or M-- on its own
+    Minus,
+}
+
+// What the keys before a command asked of it, in Global::command while it
+// runs. Commands that read the argument themselves aren't repeated for it.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommandContext {
+    arg: Option<PrefixArg>,
+    used: bool,
+}
+
+impl CommandContext {
+    pub fn new(arg: Option<PrefixArg>) -> CommandContext {
+        CommandContext { arg, used: false }
+    }
+
+    // Runs a command `count` times, like typing the number first
+    pub fn count(count: usize) -> CommandContext {
+        CommandContext::new((count != 1).then_some(PrefixArg::Number(count as i64)))
+    }
+
+    // How many times the command runs: 4 for each C-u, or the number typed
+    pub fn repeat_count(&self) -> usize {
+        match self.arg {
+            None | Some(PrefixArg::Minus) => 1,
+            Some(PrefixArg::Universal(presses)) => 4usize.saturating_pow(presses),
+            Some(PrefixArg::Number(number)) => number.unsigned_abs() as usize,
+        }
+    }
+
+    // The argument as a number, for commands taking it themselves
+    pub fn take_number(&mut self) -> Option<i64> {
+        let number = match self.arg? {
+            PrefixArg::Universal(presses) => 4i64.saturating_pow(presses),
+            PrefixArg::Number(number) => number,
+            PrefixArg::Minus => -1,
+        };
+        self.used = true;
+        Some(number)
+    }
+
+    // Whether there was an argument, for commands using it as a switch
+    pub fn take_flag(&mut self) -> bool {
+        self.used = true;
+        self.arg.is_some()
+    }
+}
+
+// Runs a command (or typing) with the prefix argument in `context` and
+// remembers it for repeating, then switches buffers if it asked to. Edits to
+// read-only buffers, or outside the narrowed part of a buffer, are refused.
+pub fn execute(global: &mut Global, buffer: &mut Buffer, repeatable: Repeatable, context: CommandContext) {
+    // Repeated commands undo as one, typing keeps amalgamating as usual
+    if let Repeatable::Command(_) = repeatable {
+        buffer.content.undo_boundary();
+    }
+
+    let outer = std::mem::replace(&mut global.command, context);
+    run_guarded(global, buffer, |global, buffer| {
+        for _ in 0..context.repeat_count() {
+            match &repeatable {
+                Repeatable::Command(command) => command(global, buffer),
+                Repeatable::SelfInsert(text) => buffer.self_insert(global, text),
+                Repeatable::QuotedInsert(text) => buffer.content.self_insert(text),
+            }
+            if global.command.used {
+                break;
+            }
+        }
+    });
+    global.command = outer;
+
+    buffer.content.clamp_to_narrowing();
+    global.last_command = Some((repeatable, context));
+    global.buffers.apply_request(buffer);
+}
+
+// Runs `run` with edits to a read-only buffer, or outside the narrowed part
+// of a buffer, refused, saying so when any were
+pub fn run_guarded(global: &mut Global, buffer: &mut Buffer, run: impl FnOnce(&mut Global, &mut Buffer)) {
+    let guard = if buffer.read_only {
+        EditGuard::ReadOnly
+    } else if buffer.content.is_narrowed() {
+        EditGuard::Narrowing
+    } else {
+        EditGuard::Unguarded
+    };
+    buffer.content.guard_edits(guard);
+    run(global, buffer);
+    if buffer.content.guard_edits(EditGuard::Unguarded) {
+        match guard {
+            EditGuard::ReadOnly => global.message(format!("Buffer is read-only: {}", buffer.name)),
+            EditGuard::Narrowing => global.message("Edit outside the narrowed region".to_string()),
+            EditGuard::Unguarded => {},
+        }
+    }
+}
+
+// Runs a structural edit when one of the modes has a syntax tree
+fn with_syntax_tree(buffer: &mut Buffer, edit: fn(&mut TextContent, &Tree)) {
+    if let Some(tree) = buffer.syntax_tree() {
+        edit(&mut buffer.content, &tree);
+    }
+}
+
+fn move_by(content: &mut TextContent, motion: Motion) {
+    content.cursor = motion(content, content.cursor);
+}
+
+// Deletes from the cursor to wherever `motion` would move it
+fn delete_by(content: &mut TextContent, motion: Motion) -> Option<String> {
+    let target = motion(content, content.cursor);
+    content.delete_range(content.cursor, target)
+}
+
+pub fn newline(_: &mut Global, buffer: &mut Buffer) {
+    buffer.content.insert_str("\n");
+}
+
+pub fn newline_and_indent(_: &mut Global, buffer: &mut Buffer) {
+    buffer.content.insert_str("\n");
+    buffer.indent_line(buffer.content.cursor.row);
+}
+
+// Tab lets the minor modes (e.g. snippets) have a go before indenting
+pub fn indent_for_tab(_: &mut Global, buffer: &mut Buffer) {
+    if !buffer.tab(false) {
+        buffer.indent_line(buffer.content.cursor.row);
+    }
+}
+
+pub fn backtab(_: &mut Global, buffer: &mut Buffer) {
+    buffer.tab(true);
+}
+
+pub fn delete_char(_: &mut Global, buffer: &mut Buffer) {
+    buffer.content.delete_char();
+}
+
+pub fn delete_backward_char(_: &mut Global, buffer: &mut Buffer) {
+    buffer.content.delete_backward_char();
+}
+
+// Kills to the end of the line, or with an argument that many whole lines
+pub fn kill_line(global: &mut Global, buffer: &mut Buffer) {
+    let content = &mut buffer.content;
+    match global.command.take_number() {
+        Some(lines) if lines > 0 => {
+            let end_row = content.cursor.row + lines as usize;
+            let end = if end_row < content.line_count() {
+                Position::new(end_row, 0)
+            } else {
+                let last = content.line_count() - 1;
+                Position::new(last, content.line_len(last))
+            };
+            if let Some(killed) = content.delete_range(content.cursor, end) {
+                global.kill_new(killed);
+            }
+        },
+        _ => global.kill_new(content.kill_line().unwrap_or_default()),
+    }
+}
+
+pub fn save_buffer(global: &mut Global, buffer: &mut Buffer) {
+    if !buffer.is_dirty() {
+        global.message("(No changes need to be saved)".to_string());
+        return;
+    }
+    match buffer.save(global) {
+        Ok(()) => {
+            if let Some(path) = &buffer.path {
+                global.message(format!("Wrote {}", path.display()));
+            }
+        },
+        Err(e) => global.message(format!("Saving failed: {}", e)),
+    }
+}
+
+// Saves to a new file, prompting for it, which the buffer then visits
+pub fn write_file(global: &mut Global, buffer: &mut Buffer) {
+    let directory = file_directory(buffer);
+    global.minibuffer = Some(Minibuffer::new("Write file: ", &directory, Some(complete_path), |global, buffer, input| {
+        let path = match input.trim() {
+            "" => return,
+            path => PathBuf::from(path),
+        };
+        if path.is_dir() {
+            global.message(format!("{} is a directory", path.display()));
+            return;
+        }
+        if let Err(e) = buffer.write_to(&path, global) {
+            global.message(format!("Saving failed: {}", e));
+            return;
+        }
+        if let Some(file_name) = path.file_name() {
+            buffer.name = global.buffers.unique_name(&file_name.to_string_lossy());
+        }
+        buffer.path = Some(path);
+    }));
+}
+
+// Reloads the file, throwing away unsaved changes
+pub fn revert_buffer(global: &mut Global, buffer: &mut Buffer) {
+    // Read-only buffers can still be brought up to date
+    buffer.content.inhibit_read_only();
+    if let Err(e) = buffer.revert() {
+        global.message(format!("Reverting failed: {}", e));
+    }
+}
+
+// Hides everything but the region, which is all that can be edited then
+pub fn narrow_to_region(_: &mut Global, buffer: &mut Buffer) {
+    if let Some((start, end)) = buffer.content.region() {
+        buffer.content.narrow(start, end);
+        buffer.content.deactivate_mark();
+    }
+}
+
+pub fn widen(_: &mut Global, buffer: &mut Buffer) {
+    buffer.content.widen();
+}
+
+// Sets how many columns each indentation level is in this buffer
+pub fn set_indent_width(global: &mut Global, buffer: &mut Buffer) {
+    let current = buffer.content.locals.get(&INDENT_WIDTH).to_string();
+    global.minibuffer = Some(Minibuffer::new("Indent width: ", &current, None, |global, buffer, input| {
+        match input.trim().parse() {
+            Ok(width) => buffer.content.locals.set(&INDENT_WIDTH, width),
+            Err(_) => global.message(format!("Not a width: {}", input)),
+        }
+    }));
+}
+
+// Prompts for a theme file and switches every buffer over to it
+pub fn load_theme(global: &mut Global, _: &mut Buffer) {
+    let prompt = Minibuffer::new("Load theme: ", theme::THEME_DIRECTORY, Some(complete_path), |global, buffer, input| {
+        match theme::read(Path::new(input)) {
+            Ok(loaded) => {
+                theme::apply(&mut global.faces, loaded);
+                // Syntax faces get looked up by name again
+                buffer.content.modified = true;
+                for other in global.buffers.iter_mut() {
+                    other.content.modified = true;
+                }
+            },
+            Err(e) => global.message(format!("Loading theme failed: {}", e)),
+        }
+    });
+    global.minibuffer = Some(prompt);
+}
+
+// Prompts for fonts, like "DejaVu Sans Mono-14, Noto Sans CJK JP", to draw
+// with from now on
+pub fn set_font(global: &mut Global, _: &mut Buffer) {
+    let current = global.font.spec();
+    global.minibuffer = Some(Minibuffer::new("Font: ", &current, None, |global, _, input| {
+        match FontSettings::parse(input) {
+            Some(settings) => global.font = settings,
+            None => global.message("No font given".to_string()),
+        }
+    }));
+}
+
+// Prompts for the fill column, or sets it to the argument when there is one
+pub fn set_fill_column(global: &mut Global, buffer: &mut Buffer) {
+    if let Some(column) = global.command.take_number() {
+        buffer.content.locals.set(&FILL_COLUMN, column.max(0) as usize);
+        global.message(format!("Fill column set to {}", column.max(0)));
+        return;
+    }
+    let current = buffer.content.locals.get(&FILL_COLUMN).to_string();
+    global.minibuffer = Some(Minibuffer::new("Fill column: ", &current, None, |global, buffer, input| {
+        match input.trim().parse() {
+            Ok(column) => buffer.content.locals.set(&FILL_COLUMN, column),
+            Err(_) => global.message(format!("Not a column: {}", input)),
+        }
+    }));
+}
+
+pub fn toggle_read_only(_: &mut Global, buffer: &mut Buffer) {
+    buffer.read_only = !buffer.read_only;
+}
+
+// Asks whether to reload the current buffer's file after it changed on disk
+pub fn offer_revert(global: &mut Global) {
+    global.minibuffer = Some(Minibuffer::new("File changed on disk, revert? (y or n) ", "", None, |global, buffer, answer| {
+        if answer.trim_start().starts_with(['y', 'Y']) {
+            revert_buffer(global, buffer);
+        }
+    }));
+}
+
+// The directory of the buffer's file with a slash, to start paths typed in
+// from, or nothing for the working directory
+fn file_directory(buffer: &Buffer) -> String {
+    buffer.path
+        .as_ref()
+        .and_then(|path| path.parent())
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .filter(|dir| !dir.is_empty())
+        .map_or_else(String::new, |dir| format!("{}/", dir))
+}
+
+// Prompts for a file and opens it in a buffer of its own, or a new empty one
+// when it doesn't exist yet
+pub fn find_file(global: &mut Global, buffer: &mut Buffer) {
+    let directory = file_directory(buffer);
+    global.minibuffer = Some(Minibuffer::new("Find file: ", &directory, Some(complete_path), visit_path));
+}
+
+// Opens the file at `input`, or lists it when it's a directory, switching to
+// the buffer already showing it if there is one
+pub fn visit_path(global: &mut Global, buffer: &mut Buffer, input: &str) {
+    if input.is_empty() {
+        return;
+    }
+    let path = PathBuf::from(input);
+    let listed = fs::canonicalize(&path).ok();
+    if buffer.path.as_ref() == Some(&path) || (listed.is_some() && dired::directory(buffer) == listed) {
+        return;
+    }
+
+    let visiting = global.buffers.find_visiting(&path).map(|buffer| buffer.name.clone());
+    if let Some(name) = visiting.or_else(|| dired::find_listing(global, &path)) {
+        global.buffers.switch_to(&name);
+        return;
+    }
+
+    match text_mode::visit_file(&path, global) {
+        Ok(visited) => {
+            let name = global.buffers.add(visited);
+            global.buffers.switch_to(&name);
+            if auto_save::has_newer_auto_save(&path) {
+                auto_save::offer_recovery(global);
+            }
+        },
+        Err(e) => global.message(format!("Opening failed: {}", e)),
+    }
+}
+
+// Switches the file's line ending to the next of LF, CRLF and CR, taking
+// effect on the next save
+pub fn cycle_line_ending(_: &mut Global, buffer: &mut Buffer) {
+    buffer.line_ending = buffer.line_ending.next();
+}
+
+// A character given by its code point in hex, e.g. "U+00E9" or "e9"
+fn char_from_code_point(input: &str) -> Option<char> {
+    let input = input.trim();
+    let digits = ["U+", "u+", "0x", "0X"]
+        .iter()
+        .find_map(|prefix| input.strip_prefix(prefix))
+        .unwrap_or(input);
+    u32::from_str_radix(digits, 16).ok().and_then(char::from_u32)
+}
+
+// Inserts a character given by its code point or its name, prompting for it
+pub fn insert_char(global: &mut Global, _: &mut Buffer) {
+    let prompt = "Insert character (code point or name): ";
+    global.minibuffer = Some(Minibuffer::new(prompt, "", Some(unicode_names::complete), |global, buffer, input| {
+        match char_from_code_point(input).or_else(|| unicode_names::lookup(input)) {
+            Some(c) => buffer.content.insert_str(&c.to_string()),
+            None => global.message(format!("No character {}", input.trim())),
+        }
+    }));
+}
+
+// The start of the word the cursor is at the end of
+fn word_before_cursor(content: &TextContent) -> Position {
+    let cursor = content.cursor;
+    let line = content.line(cursor.row);
+    let word_len: usize = line[..cursor.col]
+        .chars()
+        .rev()
+        .take_while(|&c| is_word_char(c))
+        .map(char::len_utf8)
+        .sum();
+    Position::new(cursor.row, cursor.col - word_len)
+}
+
+// Offers the words in the buffer starting like the one before the cursor in
+// a menu, the one chosen taking its place
+pub fn completion_at_point(global: &mut Global, buffer: &mut Buffer) {
+    let content = &buffer.content;
+    let start = word_before_cursor(content);
+    let prefix = content.text_range(start, content.cursor);
+    if prefix.is_empty() {
+        global.message("Nothing to complete".to_string());
+        return;
+    }
+
+    let mut words: Vec<String> = vec!();
+    for row in 0..content.line_count() {
+        for word in content.line(row).split(|c: char| !is_word_char(c)) {
+            if word.len() > prefix.len() && word.starts_with(&prefix) && !words.iter().any(|known| known == word) {
+                words.push(word.to_string());
+            }
+        }
+    }
+    if words.is_empty() {
+        global.message(format!("No completions for {}", prefix));
+        return;
+    }
+    global.popups.open(Popup::menu(start, words, |_, buffer, word| {
+        let start = word_before_cursor(&buffer.content);
+        buffer.content.replace_range(start, buffer.content.cursor, word);
+    }));
+}
+
+// Prompts for a buffer to switch to, the last one shown when none is given
+pub fn switch_to_buffer(global: &mut Global, _: &mut Buffer) {
+    let names: Vec<String> = global.buffers.names().map(str::to_string).collect();
+    global.minibuffer = Some(Minibuffer::with_list("Switch to buffer: ", "", names, |global, buffer, input| {
+        let name = match input {
+            "" => global.buffers.most_recent().map(|buffer| buffer.name.clone()),
+            name => Some(name.to_string()),
+        };
+        match name {
+            Some(name) if global.buffers.find(&name).is_some() => global.buffers.switch_to(&name),
+            Some(name) if name == buffer.name => {},
+            Some(name) => global.message(format!("No buffer named {}", name)),
+            None => {},
+        }
+    }));
+}
+
+pub fn next_buffer(global: &mut Global, _: &mut Buffer) {
+    global.buffers.next();
+}
+
+pub fn previous_buffer(global: &mut Global, _: &mut Buffer) {
+    global.buffers.previous();
+}
+
+// Buffers without a file offer to save their changes somewhere first
+pub fn kill_buffer(global: &mut Global, buffer: &mut Buffer) {
+    if buffer.path.is_none() && buffer.is_dirty() {
+        let prompt = format!("Save {} to file (empty to discard): ", buffer.name);
+        global.minibuffer = Some(Minibuffer::new(&prompt, "", Some(complete_path), save_and_kill));
+        return;
+    }
+    global.buffers.kill_current();
+}
+
+fn save_and_kill(global: &mut Global, buffer: &mut Buffer, input: &str) {
+    if !input.is_empty() {
+        let path = PathBuf::from(input);
+        if let Err(e) = buffer.write_to(&path, global) {
+            global.message(format!("Saving failed: {}", e));
+            return;
+        }
+    }
+    global.buffers.kill_current();
+}
+
+pub fn toggle_tab_bar(global: &mut Global, _: &mut Buffer) {
+    global.tab_bar.enabled = !global.tab_bar.enabled;
+}
+
+// Switches to the buffer of the next tab, going round to the first after the
+// last
+pub fn tab_next(global: &mut Global, buffer: &mut Buffer) {
+    if let Some(name) = global.tab_bar.neighbour(&buffer.name, 1).map(str::to_string) {
+        global.buffers.switch_to(&name);
+    }
+}
+
+pub fn tab_previous(global: &mut Global, buffer: &mut Buffer) {
+    if let Some(name) = global.tab_bar.neighbour(&buffer.name, -1).map(str::to_string) {
+        global.buffers.switch_to(&name);
+    }
+}
+
+// Moves the current buffer's tab one place to the right
+pub fn tab_move(global: &mut Global, buffer: &mut Buffer) {
+    global.tab_bar.move_tab(&buffer.name, 1);
+}
+
+pub fn tab_move_back(global: &mut Global, buffer: &mut Buffer) {
+    global.tab_bar.move_tab(&buffer.name, -1);
+}
+
+// Splits the selected window into one above the other, both showing the
+// current buffer
+pub fn split_window_below(global: &mut Global, buffer: &mut Buffer) {
+    global.windows.split(buffer, true);
+}
+
+// Splits the selected window into two side by side
+pub fn split_window_right(global: &mut Global, buffer: &mut Buffer) {
+    global.windows.split(buffer, false);
+}
+
+// Moves the focus to the next window, going round to the first after the last
+pub fn other_window(global: &mut Global, buffer: &mut Buffer) {
+    let next = (global.windows.selected() + 1) % global.windows.leaves().len();
+    global.windows.select(next, buffer, &mut global.buffers);
+}
+
+pub fn delete_window(global: &mut Global, buffer: &mut Buffer) {
+    if let Err(e) = global.windows.delete_selected(buffer, &mut global.buffers) {
+        global.message(e);
+    }
+}
+
+pub fn delete_other_windows(global: &mut Global, _: &mut Buffer) {
+    global.windows.delete_others();
+}
+
+pub fn balance_windows(global: &mut Global, _: &mut Buffer) {
+    global.windows.balance();
+}
+
+// Opens a new buffer for notes that isn't saved anywhere
+pub fn scratch_buffer(global: &mut Global, _: &mut Buffer) {
+    let scratch = Buffer::new("*scratch*", TextContent::new(""), auto_mode::modes_for_file(Path::new(""), ""));
+    let name = global.buffers.add(scratch);
+    global.buffers.switch_to(&name);
+}
+
+pub fn undo(_: &mut Global, buffer: &mut Buffer) {
+    buffer.content.undo();
+}
+
+pub fn redo(_: &mut Global, buffer: &mut Buffer) {
+    buffer.content.redo();
+}
+
+pub fn backward_char(_: &mut Global, buffer: &mut Buffer) {
+    buffer.content.move_left();
+}
+
+pub fn forward_char(_: &mut Global, buffer: &mut Buffer) {
+    buffer.content.move_right();
+}
+
+pub fn previous_line(_: &mut Global, buffer: &mut Buffer) {
+    buffer.content.move_up();
+}
+
+pub fn next_line(_: &mut Global, buffer: &mut Buffer) {
+    buffer.content.move_down();
+}
+
+// Pages down, like Emacs' scroll-up-command
+pub fn scroll_up(_: &mut Global, buffer: &mut Buffer) {
+    let page = buffer.view.page() as isize;
+    buffer.view.scroll_by(&mut buffer.content, page);
+}
+
+pub fn scroll_down(_: &mut Global, buffer: &mut Buffer) {
+    let page = buffer.view.page() as isize;
+    buffer.view.scroll_by(&mut buffer.content, -page);
+}
+
+// Switches between wrapping long lines and cutting them off at the window edge
+pub fn toggle_truncate_lines(_: &mut Global, buffer: &mut Buffer) {
+    buffer.view.wrap = !buffer.view.wrap;
+}
+
+// Goes from absolute line numbers to relative ones to none
+pub fn cycle_line_numbers(_: &mut Global, buffer: &mut Buffer) {
+    buffer.view.line_numbers = buffer.view.line_numbers.next();
+}
+
+// Goes from a block cursor to a bar to an underline
+pub fn cycle_cursor_style(global: &mut Global, _: &mut Buffer) {
+    global.cursor.style = global.cursor.style.next();
+}
+
+pub fn toggle_cursor_blink(global: &mut Global, _: &mut Buffer) {
+    global.cursor.blink = !global.cursor.blink;
+}
+
+pub fn scroll_bar_mode(global: &mut Global, _: &mut Buffer) {
+    global.scroll_bar = !global.scroll_bar;
+}
+
+pub fn toggle_smooth_scroll(global: &mut Global, _: &mut Buffer) {
+    global.smooth_scroll = !global.smooth_scroll;
+}
+
+pub fn text_scale_increase(global: &mut Global, _: &mut Buffer) {
+    global.text_scale += 1;
+}
+
+pub fn text_scale_decrease(global: &mut Global, _: &mut Buffer) {
+    global.text_scale -= 1;
+}
+
+pub fn text_scale_reset(global: &mut Global, _: &mut Buffer) {
+    global.text_scale = 0;
+}
+
+pub fn forward_word(_: &mut Global, buffer: &mut Buffer) {
+    move_by(&mut buffer.content, motion::forward_word);
+}
+
+pub fn backward_word(_: &mut Global, buffer: &mut Buffer) {
+    move_by(&mut buffer.content, motion::backward_word);
+}
+
+pub fn beginning_of_line(_: &mut Global, buffer: &mut Buffer) {
+    move_by(&mut buffer.content, motion::beginning_of_line);
+}
+
+pub fn end_of_line(_: &mut Global, buffer: &mut Buffer) {
+    move_by(&mut buffer.content, motion::end_of_line);
+}
+
+pub fn forward_paragraph(_: &mut Global, buffer: &mut Buffer) {
+    move_by(&mut buffer.content, motion::forward_paragraph);
+}
+
+pub fn backward_paragraph(_: &mut Global, buffer: &mut Buffer) {
+    move_by(&mut buffer.content, motion::backward_paragraph);
+}
+
+pub fn beginning_of_buffer(_: &mut Global, buffer: &mut Buffer) {
+    move_by(&mut buffer.content, motion::beginning_of_buffer);
+}
+
+pub fn end_of_buffer(_: &mut Global, buffer: &mut Buffer) {
+    move_by(&mut buffer.content, motion::end_of_buffer);
+}
+
+pub fn kill_word(global: &mut Global, buffer: &mut Buffer) {
+    global.kill_new(delete_by(&mut buffer.content, motion::forward_word).unwrap_or_default());
+}
+
+pub fn backward_kill_word(global: &mut Global, buffer: &mut Buffer) {
+    global.kill_new(delete_by(&mut buffer.content, motion::backward_word).unwrap_or_default());
+}
+
+// Like kill_word but leaves the kill ring alone
+pub fn delete_word(_: &mut Global, buffer: &mut Buffer) {
+    delete_by(&mut buffer.content, motion::forward_word);
+}
+
+pub fn backward_delete_word(_: &mut Global, buffer: &mut Buffer) {
+    delete_by(&mut buffer.content, motion::backward_word);
+}
+
+pub fn set_mark(_: &mut Global, buffer: &mut Buffer) {
+    buffer.content.set_mark();
+}
+
+// Selects everything, or the narrowed part
+pub fn mark_whole_buffer(_: &mut Global, buffer: &mut Buffer) {
+    let (start, end) = buffer.content.visible_range();
+    buffer.content.mark = Some(start);
+    buffer.content.cursor = end;
+}
+
+pub fn keyboard_quit(_: &mut Global, buffer: &mut Buffer) {
+    buffer.content.deactivate_mark();
+}
+
+pub fn kill_editor(global: &mut Global, _: &mut Buffer) {
+    global.quit = true;
+}
+
+pub fn kill_region(global: &mut Global, buffer: &mut Buffer) {
+    if let Some(text) = buffer.content.delete_region() {
+        global.kill_new(text);
+    }
+}
+
+pub fn copy_region(global: &mut Global, buffer: &mut Buffer) {
+    if let Some(text) = buffer.content.region_text() {
+        global.kill_new(text);
+    }
+    buffer.content.deactivate_mark();
+}
+
+// Inserts the last kill, leaving the cursor before it with an argument
+pub fn yank(global: &mut Global, buffer: &mut Buffer) {
+    let before = global.command.take_flag();
+    let start = buffer.content.cursor;
+    if let Some(text) = global.current_kill() {
+        buffer.content.insert_str(text);
+    }
+    if before {
+        buffer.content.mark = Some(buffer.content.cursor);
+        buffer.content.cursor = start;
+    }
+}
+
+// Swaps the characters around the cursor, or the two before it at the end of a line
+pub fn transpose_chars(_: &mut Global, buffer: &mut Buffer) {
+    let content = &mut buffer.content;
+    let Position { row, col } = content.cursor;
+    let pos = if col == content.line_len(row) {
+        match content.prev_position(content.cursor) {
+            Some(pos) if pos.row == row => pos,
+            _ => return,
+        }
+    } else {
+        content.cursor
+    };
+    let (before, after) = match (content.prev_position(pos), content.next_position(pos)) {
+        (Some(before), Some(after)) if before.row == row && after.row == row => (before, after),
+        _ => return,
+    };
+
+    let first = content.text_range(before, pos);
+    let second = content.text_range(pos, after);
+    if let Some(end) = content.replace_range(before, after, &(second + &first)) {
+        content.cursor = end;
+    }
+}
+
+// Swaps the word before the cursor with the one after it
+pub fn transpose_words(_: &mut Global, buffer: &mut Buffer) {
+    let content = &mut buffer.content;
+    let end2 = motion::forward_word(content, content.cursor);
+    let start2 = motion::backward_word(content, end2);
+    let start1 = motion::backward_word(content, start2);
+    let end1 = motion::forward_word(content, start1);
+    if start1 >= start2 || end1 > start2 {
+        return;
+    }
+
+    let word1 = content.text_range(start1, end1);
+    let between = content.text_range(end1, start2);
+    let word2 = content.text_range(start2, end2);
+    if let Some(end) = content.replace_range(start1, end2, &(word2 + &between + &word1)) {
+        content.cursor = end;
+    }
+}
+
+// Rewrites the text from the cursor to the end of the next word
+fn change_word_case(content: &mut TextContent, change: fn(&str) -> String) {
+    let end = motion::forward_word(content, content.cursor);
+    let text = content.text_range(content.cursor, end);
+    let changed = change(&text);
+    if changed != text {
+        content.replace_range(content.cursor, end, &changed);
+    } else {
+        content.cursor = end;
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut in_word = false;
+    let mut capitalized = String::with_capacity(text.len());
+    for c in text.chars() {
+        match (is_word_char(c), in_word) {
+            (true, false) => capitalized.extend(c.to_uppercase()),
+            (true, true)  => capitalized.extend(c.to_lowercase()),
+            (false, _)    => capitalized.push(c),
+        }
+        in_word = is_word_char(c);
+    }
+    capitalized
+}
+
+// The byte range of the first number on `line` ending after `col`, and
+// whether it is hexadecimal. A minus sign only counts when it isn't
+// straight after a word, so `a-1` is a subtraction.
+fn number_at_or_after(line: &str, col: usize) -> Option<(usize, usize, bool)> {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let prefixed = bytes[i..].starts_with(b"0x") || bytes[i..].starts_with(b"0X");
+        let hex = prefixed && bytes.get(i + 2).is_some_and(u8::is_ascii_hexdigit);
+        let (digits_start, is_digit): (usize, fn(&u8) -> bool) = if hex {
+            (i + 2, u8::is_ascii_hexdigit)
+        } else if bytes[i].is_ascii_digit() {
+            (i, u8::is_ascii_digit)
+        } else {
+            i += 1;
+            continue;
+        };
+
+        let end = digits_start + bytes[digits_start..].iter().take_while(|b| is_digit(b)).count();
+        if end > col {
+            let negative = !hex && i > 0 && bytes[i - 1] == b'-'
+                && (i < 2 || !(bytes[i - 2] as char).is_alphanumeric());
+            let start = if negative { i - 1 } else { i };
+            return Some((start, end, hex));
+        }
+        i = end;
+    }
+    None
+}
+
+fn add_to_number(content: &mut TextContent, delta: i64) {
+    let row = content.cursor.row;
+    let line = content.line(row);
+    let (start, end, hex) = match number_at_or_after(&line, content.cursor.col) {
+        Some(number) => number,
+        None => return,
+    };
+
+    let text = &line[start..end];
+    let changed = if hex {
+        // Keeps the width and letter case of the digits
+        let digits = &text[2..];
+        let value = match u64::from_str_radix(digits, 16) {
+            Ok(value) => value.wrapping_add(delta as u64),
+            Err(_) => return,
+        };
+        let width = digits.len();
+        if digits.chars().any(|c| c.is_ascii_uppercase()) {
+            format!("{}{:0width$X}", &text[..2], value, width = width)
+        } else {
+            format!("{}{:0width$x}", &text[..2], value, width = width)
+        }
+    } else {
+        match text.parse::<i64>() {
+            Ok(value) => value.wrapping_add(delta).to_string(),
+            Err(_) => return,
+        }
+    };
+
+    // On the last digit, so doing it again changes the same number
+    if let Some(end) = content.replace_range(Position::new(row, start), Position::new(row, end), &changed) {
+        content.cursor = Position::new(row, end.col - 1);
+    }
+}
+
+pub fn increment_number(_: &mut Global, buffer: &mut Buffer) {
+    add_to_number(&mut buffer.content, 1);
+}
+
+pub fn decrement_number(_: &mut Global, buffer: &mut Buffer) {
+    add_to_number(&mut buffer.content, -1);
+}
+
+pub fn upcase_word(_: &mut Global, buffer: &mut Buffer) {
+    change_word_case(&mut buffer.content, |text| text.to_uppercase());
+}
+
+pub fn downcase_word(_: &mut Global, buffer: &mut Buffer) {
+    change_word_case(&mut buffer.content, |text| text.to_lowercase());
+}
+
+pub fn capitalize_word(_: &mut Global, buffer: &mut Buffer) {
+    change_word_case(&mut buffer.content, capitalize);
+}
+
+pub fn kill_rectangle(global: &mut Global, buffer: &mut Buffer) {
+    if let Some(rect) = Rectangle::from_region(&buffer.content) {
+        global.killed_rectangle = rectangle::delete_rectangle(&mut buffer.content, &rect);
+        buffer.content.deactivate_mark();
+    }
+}
+
+pub fn delete_rectangle(_: &mut Global, buffer: &mut Buffer) {
+    if let Some(rect) = Rectangle::from_region(&buffer.content) {
+        rectangle::delete_rectangle(&mut buffer.content, &rect);
+        buffer.content.deactivate_mark();
+    }
+}
+
+pub fn yank_rectangle(global: &mut Global, buffer: &mut Buffer) {
+    rectangle::insert_rectangle(&mut buffer.content, &global.killed_rectangle);
+}
+
+// Fills each line of the rectangle with a string, prompting for it
+pub fn string_rectangle(global: &mut Global, buffer: &mut Buffer) {
+    if Rectangle::from_region(&buffer.content).is_none() {
+        return;
+    }
+    global.minibuffer = Some(Minibuffer::new("String rectangle: ", "", None, |_, buffer, input| {
+        if let Some(rect) = Rectangle::from_region(&buffer.content) {
+            rectangle::string_rectangle(&mut buffer.content, &rect, input);
+            buffer.content.deactivate_mark();
+        }
+    }));
+}
+
+// The rows covered by the region, or just the cursor's row. A region ending
+// at the very start of a line doesn't include that line.
+fn selected_rows(content: &TextContent) -> (usize, usize) {
+    match content.region() {
+        Some((start, end)) if end.row > start.row && end.col == 0 => (start.row, end.row - 1),
+        Some((start, end)) => (start.row, end.row),
+        None => (content.cursor.row, content.cursor.row),
+    }
+}
+
+// Shifts the cursor and mark by `rows` after their lines were moved
+fn shift_rows(content: &mut TextContent, rows: isize) {
+    let row = content.cursor.row as isize + rows;
+    content.cursor.row = row as usize;
+    if let Some(mark) = &mut content.mark {
+        mark.row = (mark.row as isize + rows) as usize;
+    }
+}
+
+fn move_lines(content: &mut TextContent, up: bool) {
+    let (top, bottom) = selected_rows(content);
+    if (up && top == 0) || (!up && bottom + 1 >= content.line_count()) {
+        return;
+    }
+
+    let (first, last) = if up { (top - 1, bottom) } else { (top, bottom + 1) };
+    let mut lines: Vec<String> = content.line_range(first..=last);
+    if up {
+        lines.rotate_left(1);
+    } else {
+        lines.rotate_right(1);
+    }
+
+    let (cursor, mark) = (content.cursor, content.mark);
+    let end = Position::new(last, content.line_len(last));
+    if content.replace_range(Position::new(first, 0), end, &lines.join("\n")).is_none() {
+        return;
+    }
+    content.cursor = cursor;
+    content.mark = mark;
+    shift_rows(content, if up { -1 } else { 1 });
+}
+
+pub fn move_lines_up(_: &mut Global, buffer: &mut Buffer) {
+    move_lines(&mut buffer.content, true);
+}
+
+pub fn move_lines_down(_: &mut Global, buffer: &mut Buffer) {
+    move_lines(&mut buffer.content, false);
+}
+
+// Copies the current line (or the lines of the region) below itself
+pub fn duplicate_lines(_: &mut Global, buffer: &mut Buffer) {
+    let content = &mut buffer.content;
+    let (top, bottom) = selected_rows(content);
+    let text = content.line_range(top..=bottom).join("\n");
+
+    let (cursor, mark) = (content.cursor, content.mark);
+    let end = Position::new(bottom, content.line_len(bottom));
+    if content.insert(end, &format!("\n{}", text)).is_none() {
+        return;
+    }
+    content.cursor = cursor;
+    content.mark = mark;
+    shift_rows(content, (bottom - top + 1) as isize);
+}
+
+// Replaces the lines of the region with `rewrite` of them, keeping them selected
+fn rewrite_lines(content: &mut TextContent, rewrite: fn(&mut Vec<String>)) {
+    if content.mark.is_none() {
+        return;
+    }
+
+    let (top, bottom) = selected_rows(content);
+    let mut lines = content.line_range(top..=bottom);
+    rewrite(&mut lines);
+
+    let end = Position::new(bottom, content.line_len(bottom));
+    let end = match content.replace_range(Position::new(top, 0), end, &lines.join("\n")) {
+        Some(end) => end,
+        None => return,
+    };
+    content.mark = Some(Position::new(top, 0));
+    content.cursor = end;
+}
+
+// The number a line starts with, ignoring indentation
+fn leading_number(line: &str) -> Option<f64> {
+    let line = line.trim_start();
+    let len = line
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && c == '-')))
+        .map_or(line.len(), |(i, _)| i);
+    line[..len].parse().ok()
+}
+
+pub fn sort_lines(_: &mut Global, buffer: &mut Buffer) {
+    rewrite_lines(&mut buffer.content, |lines| lines.sort());
+}
+
+// Sorts by the number each line starts with, lines without one going first
+pub fn sort_lines_numerically(_: &mut Global, buffer: &mut Buffer) {
+    rewrite_lines(&mut buffer.content, |lines| {
+        lines.sort_by(|a, b| leading_number(a).partial_cmp(&leading_number(b)).unwrap_or(Ordering::Equal));
+    });
+}
+
+pub fn reverse_lines(_: &mut Global, buffer: &mut Buffer) {
+    rewrite_lines(&mut buffer.content, |lines| lines.reverse());
+}
+
+// Keeps only the first of each set of identical lines
+pub fn delete_duplicate_lines(_: &mut Global, buffer: &mut Buffer) {
+    rewrite_lines(&mut buffer.content, |lines| {
+        let mut seen = HashSet::new();
+        lines.retain(|line| seen.insert(line.clone()));
+    });
+}
+
+// Joins the next line onto this one, leaving a single space between them
+pub fn join_line(_: &mut Global, buffer: &mut Buffer) {
+    let content = &mut buffer.content;
+    let row = content.cursor.row;
+    if row + 1 >= content.line_count() {
+        return;
+    }
+
+    let kept = content.line(row).trim_end().len();
+    let next = &content.line(row + 1);
+    let skipped = next.len() - next.trim_start().len();
+    let separator = if next.trim_start().is_empty() || kept == 0 { "" } else { " " };
+
+    let start = Position::new(row, kept);
+    let end = Position::new(row + 1, skipped);
+    content.replace_range(start, end, separator);
+    content.cursor = start;
+}
+
+pub fn toggle_electric_pair_mode(_: &mut Global, buffer: &mut Buffer) {
+    buffer.toggle_minor_mode("electric-pair", || Box::new(ElectricPairMode {}));
+}
+
+pub fn toggle_minimap(_: &mut Global, buffer: &mut Buffer) {
+    buffer.toggle_minor_mode("minimap", || Box::new(MinimapMode {}));
+}
+
+pub fn hl_line_mode(_: &mut Global, buffer: &mut Buffer) {
+    buffer.toggle_minor_mode("hl-line", || Box::new(HlLineMode {}));
+}
+
+pub fn fill_column_indicator_mode(_: &mut Global, buffer: &mut Buffer) {
+    buffer.toggle_minor_mode("fill-column-indicator", || Box::new(FillColumnIndicatorMode {}));
+}
+
+pub fn indent_guide_mode(_: &mut Global, buffer: &mut Buffer) {
+    buffer.toggle_minor_mode("indent-guide", || Box::new(IndentGuideMode {}));
+}
+
+pub fn rainbow_delimiters_mode(_: &mut Global, buffer: &mut Buffer) {
+    buffer.toggle_minor_mode("rainbow-delimiters", || Box::new(RainbowDelimitersMode {}));
+}
+
+// Shows or stops showing where the parser found syntax errors
+pub fn toggle_syntax_errors(global: &mut Global, buffer: &mut Buffer) {
+    let shown = !buffer.content.locals.get(&SYNTAX_ERRORS);
+    buffer.content.locals.set(&SYNTAX_ERRORS, shown);
+    buffer.content.modified = true;
+    global.message(if shown { "Showing syntax errors" } else { "Hiding syntax errors" }.to_string());
+}
+
+pub fn overwrite_mode(_: &mut Global, buffer: &mut Buffer) {
+    buffer.toggle_minor_mode("overwrite", || Box::new(OverwriteMode {}));
+}
+
+// Comments out the current line or the region's lines, or uncomments them
+// when every non-blank one is already commented
+pub fn toggle_comment(_: &mut Global, buffer: &mut Buffer) {
+    let prefix = match buffer.comment_syntax() {
+        Some(prefix) => prefix,
+        None => return,
+    };
+
+    let content = &mut buffer.content;
+    let (top, bottom) = selected_rows(content);
+    let rows: Vec<usize> = (top..=bottom)
+        .filter(|&row| !content.line(row).trim().is_empty())
+        .collect();
+
+    let commented = rows.iter().all(|&row| content.line(row).trim_start().starts_with(prefix));
+    content.begin_transaction();
+    if commented {
+        for &row in &rows {
+            let line = content.line(row);
+            let start = leading_whitespace(&line).len();
+            let mut end = start + prefix.len();
+            if line[end..].starts_with(' ') {
+                end += 1;
+            }
+            content.delete_range(Position::new(row, start), Position::new(row, end));
+        }
+    } else {
+        // Line the comment markers up at the least indented line
+        let column = rows.iter()
+            .map(|&row| leading_whitespace(&content.line(row)).len())
+            .min()
+            .unwrap_or(0);
+        let marker = format!("{} ", prefix);
+        for &row in &rows {
+            content.insert(Position::new(row, column), &marker);
+        }
+    }
+    content.commit_transaction();
+}
+
+pub fn select_enclosing_node(_: &mut Global, buffer: &mut Buffer) {
+    with_syntax_tree(buffer, tree_sitter_mode::select_enclosing_node);
+}
+
+pub fn expand_selection(_: &mut Global, buffer: &mut Buffer) {
+    with_syntax_tree(buffer, tree_sitter_mode::expand_selection);
+}
+
+pub fn shrink_selection(_: &mut Global, buffer: &mut Buffer) {
+    with_syntax_tree(buffer, tree_sitter_mode::shrink_selection);
+}
+
+pub fn forward_sibling(_: &mut Global, buffer: &mut Buffer) {
+    with_syntax_tree(buffer, tree_sitter_mode::forward_sibling);
+}
+
+pub fn backward_sibling(_: &mut Global, buffer: &mut Buffer) {
+    with_syntax_tree(buffer, tree_sitter_mode::backward_sibling);
+}
+
+// Moves to the start of the next function, type or other definition
+pub fn next_definition(global: &mut Global, buffer: &mut Buffer) {
+    let cursor = buffer.content.cursor;
+    match buffer.definitions().into_iter().find(|definition| definition.start > cursor) {
+        Some(definition) => buffer.content.cursor = definition.start,
+        None => global.message("No more definitions".to_string()),
+    }
+}
+
+pub fn previous_definition(global: &mut Global, buffer: &mut Buffer) {
+    let cursor = buffer.content.cursor;
+    match buffer.definitions().into_iter().rev().find(|definition| definition.start < cursor) {
+        Some(definition) => buffer.content.cursor = definition.start,
+        None => global.message("No definitions before this".to_string()),
+    }
+}
+
+// The definitions of `buffer` as imenu lists them, like "Point.new
+// (function)", where ones listed the same get a number like "<2>"
+fn definition_labels(buffer: &mut Buffer) -> Vec<(String, Position)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut labels = vec!();
+    for definition in buffer.definitions() {
+        let label = format!("{} ({})", definition.name, definition.kind);
+        let count = counts.entry(label.clone()).or_insert(0);
+        *count += 1;
+        let label = match *count {
+            1 => label,
+            count => format!("{}<{}>", label, count),
+        };
+        labels.push((label, definition.start));
+    }
+    labels
+}
+
+// Prompts for a definition in the buffer to go to, like Emacs' imenu
+pub fn imenu(global: &mut Global, buffer: &mut Buffer) {
+    let labels = definition_labels(buffer);
+    if labels.is_empty() {
+        global.message("No definitions in this buffer".to_string());
+        return;
+    }
+    let names = labels.into_iter().map(|(label, _)| label).collect();
+    global.minibuffer = Some(Minibuffer::with_list("Go to definition: ", "", names, |global, buffer, input| {
+        match definition_labels(buffer).into_iter().find(|(label, _)| label == input) {
+            Some((_, start)) => buffer.content.cursor = start,
+            None => global.message(format!("No definition named {}", input)),
+        }
+    }));
+}
+
+// Moves onto the bracket matching the one at or right before the cursor
+pub fn jump_to_matching_bracket(_: &mut Global, buffer: &mut Buffer) {
+    buffer.refresh_brackets();
+    if let Some(matching) = buffer.brackets.and_then(|found| found.matching) {
+        buffer.content.cursor = matching;
+    }
+}
+
+// Shows the syntax nodes around the cursor, innermost first, under it
+pub fn describe_syntax_at_point(global: &mut Global, buffer: &mut Buffer) {
+    let cursor = buffer.content.cursor;
+    match buffer.syntax_tree() {
+        Some(tree) => global.popups.open(Popup::info(cursor, tree_sitter_mode::node_path(&tree, cursor))),
+        None => global.message("No syntax tree in this buffer".to_string()),
+    }
+}
+
+pub fn raise_node(_: &mut Global, buffer: &mut Buffer) {
+    with_syntax_tree(buffer, tree_sitter_mode::raise_node);
+}
+
+pub fn splice_node(_: &mut Global, buffer: &mut Buffer) {
+    with_syntax_tree(buffer, tree_sitter_mode::splice_node);
+}
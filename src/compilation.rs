@@ -0,0 +1,374 @@
+use std::fs;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use crate::buffer::Buffer;
+use crate::buffer::leading_whitespace;
+use crate::buffer_local::Variable;
+use crate::comint;
+use crate::minibuffer::Minibuffer;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+// Builds run like Emacs' M-x compile: the command's output fills the
+// *compilation* buffer, where the places it mentions, like
+//
+//     src/main.rs:10:5: error: ...
+//       --> src/main.rs:10:5
+//     File "script.py", line 3, in <module>
+//
+// are underlined, and next-error goes to each in turn from any buffer.
+
+const BUFFER_NAME: &str = "*compilation*";
+
+// The command the buffer ran, and where, for g to run it again
+const COMMAND: Variable<String> = Variable::new("compile-command", String::new());
+const DIRECTORY: Variable<Option<PathBuf>> = Variable::new("compilation-directory", None);
+
+// The row of the last place next-error went to
+const ERROR_ROW: Variable<Option<usize>> = Variable::new("compilation-error-row", None);
+
+// A place in a file that a line of output mentions
+pub struct Location {
+    // Where it's mentioned on the line
+    pub cols: Range<usize>,
+    pub file: String,
+    // Counting from 1, as compilers do
+    pub line: usize,
+    pub column: Option<usize>,
+}
+
+// Underlines the places mentioned in the output of a program, in the
+// compilation buffer or any comint buffer it's turned on in
+pub struct CompilationShellMode {
+}
+
+impl TextMinorMode for CompilationShellMode {
+    fn name(&self) -> &'static str {
+        "compilation-shell"
+    }
+
+    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
+        let mut spans = vec!();
+        let (start, end) = content.visible_range();
+        for row in start.row..=end.row {
+            let line = content.line(row);
+            let location = match find_location(&line) {
+                Some(location) => location,
+                None => continue,
+            };
+            // rustc says what's wrong on the line above the place
+            let above = if row > 0 { content.line(row - 1).into_owned() } else { String::new() };
+            let described = if line.trim_start().starts_with("-->") { above.as_str() } else { &line };
+            let face = severity_face(described);
+            if let Some(face_id) = global.faces.get_face_id(face) {
+                spans.push((row, location.cols, face_id));
+            }
+        }
+        for (row, cols, face_id) in spans {
+            content.faces.add(Position::new(row, cols.start), Position::new(row, cols.end), face_id);
+        }
+    }
+}
+
+fn severity_face(line: &str) -> &'static str {
+    let line = line.trim_start();
+    if line.starts_with("warning") || line.contains(": warning") {
+        "compilation-warning"
+    } else if line.starts_with("note") || line.contains(": note") || line.starts_with("help") {
+        "compilation-info"
+    } else {
+        "compilation-error"
+    }
+}
+
+// The first place mentioned on `line`
+fn find_location(line: &str) -> Option<Location> {
+    find_locations(line).into_iter().next()
+}
+
+// The places mentioned on `line`, either as file:line or file:line:column
+// like most compilers and grep, or the way Python's tracebacks put it
+pub fn find_locations(line: &str) -> Vec<Location> {
+    let mut locations = vec!();
+    if let Some(location) = python_location(line) {
+        locations.push(location);
+        return locations;
+    }
+
+    let mut offset = 0;
+    for word in line.split(' ') {
+        let start = offset + word.len() - word.trim_start_matches(['(', '\'', '"']).len();
+        offset += word.len() + 1;
+        let word = line[start..offset - 1].trim_end_matches([',', ')']);
+        let mut parts = word.split(':');
+        let file = parts.next().unwrap_or("");
+        if file.is_empty() || file.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        let number = match parts.next().and_then(|part| part.parse().ok()) {
+            Some(number) => number,
+            None => continue,
+        };
+        let column = parts.next().and_then(|part| part.parse().ok());
+        let digits = |n: Option<usize>| n.map_or(0, |n: usize| n.to_string().len() + 1);
+        let len = file.len() + digits(Some(number)) + digits(column);
+        locations.push(Location {
+            cols: start..start + len,
+            file: file.to_string(),
+            line: number,
+            column,
+        });
+    }
+    locations
+}
+
+// Like File "script.py", line 3
+fn python_location(line: &str) -> Option<Location> {
+    let start = line.find("File \"")?;
+    let file_start = start + "File \"".len();
+    let file_end = file_start + line[file_start..].find('"')?;
+    let rest = line[file_end..].strip_prefix("\", line ")?;
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let number = rest[..digits].parse().ok()?;
+    Some(Location {
+        cols: start..file_end + "\", line ".len() + digits,
+        file: line[file_start..file_end].to_string(),
+        line: number,
+        column: None,
+    })
+}
+
+// The command to offer first, the last one run or else what builds the
+// project the current file is in
+fn default_command(global: &Global, buffer: &Buffer) -> String {
+    let last = if buffer.has_minor_mode("compilation") {
+        Some(buffer.content.locals.get(&COMMAND))
+    } else {
+        global.buffers.find(BUFFER_NAME).map(|compilation| compilation.content.locals.get(&COMMAND))
+    };
+    if let Some(command) = last.filter(|command| !command.is_empty()) {
+        return command;
+    }
+    let directory = directory(buffer).unwrap_or_else(|| PathBuf::from("."));
+    if directory.ancestors().any(|directory| directory.join("Cargo.toml").exists()) {
+        "cargo build".to_string()
+    } else {
+        "make -k".to_string()
+    }
+}
+
+// Asks for a command to build with, then runs it in the compilation buffer
+pub fn compile(global: &mut Global, buffer: &mut Buffer) {
+    let initial = default_command(global, buffer);
+    global.minibuffer = Some(Minibuffer::new("Compile command: ", &initial, None, run_compile));
+}
+
+fn run_compile(global: &mut Global, buffer: &mut Buffer, input: &str) {
+    if !input.trim().is_empty() {
+        let directory = directory(buffer);
+        start(global, buffer, input, directory);
+    }
+}
+
+// Runs the compilation buffer's command again
+pub fn recompile(global: &mut Global, buffer: &mut Buffer) {
+    let command = default_command(global, buffer);
+    let directory = directory(buffer);
+    start(global, buffer, &command, directory);
+}
+
+// Where to build, where the last build ran when in the compilation buffer
+fn directory(buffer: &Buffer) -> Option<PathBuf> {
+    if buffer.has_minor_mode("compilation") {
+        buffer.content.locals.get(&DIRECTORY)
+    } else {
+        comint::default_directory(buffer)
+    }
+}
+
+// Runs `command` in a fresh compilation buffer, stopping the last build if
+// it's still going
+fn start(global: &mut Global, buffer: &mut Buffer, command: &str, directory: Option<PathBuf>) {
+    let mut compilation = match comint::start("compilation", "Compilation", "sh", &["-c", command], directory.clone()) {
+        Ok(compilation) => compilation,
+        Err(e) => {
+            global.message(format!("Compiling failed: {}", e));
+            return;
+        },
+    };
+    compilation.name = BUFFER_NAME.to_string();
+    compilation.read_only = true;
+    compilation.minor_modes.push(Box::new(CompilationShellMode {}));
+    let shown_directory = directory.as_ref().map_or_else(|| ".".to_string(), |directory| directory.display().to_string());
+    comint::insert_before_input(&mut compilation.content, &format!("Compilation started in {}\n\n{}\n", shown_directory, command));
+    compilation.content.locals.set(&COMMAND, command.to_string());
+    compilation.content.locals.set(&DIRECTORY, directory);
+    // Builds get no input
+    comint::comint_send_eof(global, &mut compilation);
+
+    if buffer.has_minor_mode("compilation") {
+        *buffer = compilation;
+    } else {
+        global.buffers.remove(BUFFER_NAME);
+        global.buffers.add(compilation);
+        global.buffers.switch_to(BUFFER_NAME);
+    }
+}
+
+// A read-only buffer of places in the files under `directory`, one a line
+// like the output of grep -n, which the compilation keys go through when
+// `major_mode` gives them its keymap section
+pub fn location_list(name: &str, text: &str, directory: PathBuf, major_mode: Box<dyn TextMinorMode>) -> Buffer {
+    let mut buffer = Buffer::new(name, TextContent::new(text), vec!(major_mode, Box::new(CompilationShellMode {})));
+    buffer.read_only = true;
+    buffer.content.locals.set(&DIRECTORY, Some(directory));
+    buffer
+}
+
+// The directory the places in a location list are in
+pub fn listed_directory(buffer: &Buffer) -> Option<PathBuf> {
+    buffer.content.locals.get(&DIRECTORY)
+}
+
+// The next or previous place mentioned after `row` in the compilation buffer
+fn step_location(content: &TextContent, row: Option<usize>, forward: bool) -> Option<(usize, Location)> {
+    let line_count = content.line_count();
+    let mut rows: Box<dyn Iterator<Item = usize>> = match (row, forward) {
+        (None, true) => Box::new(0..line_count),
+        (Some(row), true) => Box::new(row + 1..line_count),
+        (None, false) => Box::new((0..line_count).rev()),
+        (Some(row), false) => Box::new((0..row).rev()),
+    };
+    rows.find_map(|row| find_location(&content.line(row)).map(|location| (row, location)))
+}
+
+// Moves on to the next or previous place in `compilation`, returning it
+// along with where it's relative to
+fn next_location(compilation: &mut Buffer, forward: bool) -> Option<(Option<PathBuf>, Location)> {
+    let content = &mut compilation.content;
+    let row = content.locals.get(&ERROR_ROW);
+    let (row, location) = step_location(content, row, forward)?;
+    content.locals.set(&ERROR_ROW, Some(row));
+    content.cursor = Position::new(row, location.cols.start);
+    content.deactivate_mark();
+    Some((content.locals.get(&DIRECTORY), location))
+}
+
+// Puts the cursor on `location` in the file it's in, opening it if need be
+pub fn visit_location(global: &mut Global, buffer: &mut Buffer, directory: Option<PathBuf>, location: &Location) {
+    let path = match directory {
+        Some(directory) => directory.join(&location.file),
+        None => PathBuf::from(&location.file),
+    };
+    let target = match fs::canonicalize(&path) {
+        Ok(target) => target,
+        Err(e) => {
+            global.message(format!("{}: {}", path.display(), e));
+            return;
+        },
+    };
+    let visits = |buffer: &Buffer| buffer.path.as_ref().and_then(|path| fs::canonicalize(path).ok()).as_ref() == Some(&target);
+
+    if visits(buffer) {
+        go_to(&mut buffer.content, location);
+        return;
+    }
+    let visiting = global.buffers.iter_mut().find(|buffer| visits(buffer)).map(|buffer| buffer.name.clone());
+    let name = match visiting {
+        Some(name) => name,
+        None => match text_mode::visit_file(&target, global) {
+            Ok(visited) => global.buffers.add(visited),
+            Err(e) => {
+                global.message(format!("Opening failed: {}", e));
+                return;
+            },
+        },
+    };
+    if let Some(visiting) = global.buffers.find_mut(&name) {
+        go_to(&mut visiting.content, location);
+    }
+    global.buffers.switch_to(&name);
+}
+
+fn go_to(content: &mut TextContent, location: &Location) {
+    let row = location.line.saturating_sub(1).min(content.line_count() - 1);
+    let line = content.line(row);
+    let col = match location.column {
+        Some(column) => line.char_indices().nth(column.saturating_sub(1)).map_or(line.len(), |(i, _)| i),
+        None => leading_whitespace(&line).len(),
+    };
+    content.cursor = Position::new(row, col);
+    content.deactivate_mark();
+}
+
+fn step_error(global: &mut Global, buffer: &mut Buffer, forward: bool) {
+    let found = if buffer.has_minor_mode("compilation") {
+        next_location(buffer, forward)
+    } else {
+        match global.buffers.find_mut(BUFFER_NAME) {
+            Some(compilation) => next_location(compilation, forward),
+            None => {
+                global.message("No compilation has been run".to_string());
+                return;
+            },
+        }
+    };
+    match found {
+        Some((directory, location)) => visit_location(global, buffer, directory, &location),
+        None => global.message(if forward { "No more errors" } else { "No earlier errors" }.to_string()),
+    }
+}
+
+// Goes to the next place the compilation mentions, from any buffer
+pub fn next_error(global: &mut Global, buffer: &mut Buffer) {
+    step_error(global, buffer, true);
+}
+
+pub fn previous_error(global: &mut Global, buffer: &mut Buffer) {
+    step_error(global, buffer, false);
+}
+
+// Goes to the place mentioned on the cursor's line of the compilation buffer
+pub fn compile_goto_error(global: &mut Global, buffer: &mut Buffer) {
+    let row = buffer.content.cursor.row;
+    let location = match find_location(&buffer.content.line(row)) {
+        Some(location) => location,
+        None => {
+            global.message("No place mentioned on this line".to_string());
+            return;
+        },
+    };
+    buffer.content.locals.set(&ERROR_ROW, Some(row));
+    let directory = buffer.content.locals.get(&DIRECTORY);
+    visit_location(global, buffer, directory, &location);
+}
+
+// Moves between the places mentioned in the compilation buffer, without
+// going to them
+fn step_in_buffer(global: &mut Global, buffer: &mut Buffer, forward: bool) {
+    let content = &mut buffer.content;
+    match step_location(content, Some(content.cursor.row), forward) {
+        Some((row, location)) => {
+            content.cursor = Position::new(row, location.cols.start);
+            content.deactivate_mark();
+        },
+        None => global.message(if forward { "No more errors" } else { "No earlier errors" }.to_string()),
+    }
+}
+
+pub fn compilation_next_error(global: &mut Global, buffer: &mut Buffer) {
+    step_in_buffer(global, buffer, true);
+}
+
+pub fn compilation_previous_error(global: &mut Global, buffer: &mut Buffer) {
+    step_in_buffer(global, buffer, false);
+}
+
+// Underlines the places a comint buffer's program mentions, e.g. a shell's
+pub fn compilation_shell_minor_mode(_: &mut Global, buffer: &mut Buffer) {
+    buffer.toggle_minor_mode("compilation-shell", || Box::new(CompilationShellMode {}));
+}
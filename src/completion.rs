@@ -0,0 +1,59 @@
+// Narrows candidates down to the ones matching what was typed, which are the
+// ones with its characters in order, others allowed between them. E.g. "svbf"
+// matches "save-buffer".
+
+// A candidate that matched, with where
+pub struct Candidate {
+    pub text: String,
+    // Byte offsets of the characters the input matched, to highlight them
+    pub matched: Vec<usize>,
+    score: usize,
+}
+
+// Whether `text` has a new word starting at byte offset `i`
+fn is_word_start(text: &str, i: usize) -> bool {
+    text[..i].chars().next_back().is_none_or(|c| matches!(c, '-' | '_' | '/' | '.' | ' '))
+}
+
+// Case is ignored, and a space matches dashes and underscores too
+fn char_matches(wanted: char, c: char) -> bool {
+    match wanted {
+        ' ' => matches!(c, ' ' | '-' | '_'),
+        _ => c.to_lowercase().eq(wanted.to_lowercase()),
+    }
+}
+
+// Where the characters of `query` are in `text`, and how well it matches
+// with lower being better. Every character skipped counts against it, unless
+// it skips to the start of a word.
+fn fuzzy_match(query: &str, text: &str) -> Option<(usize, Vec<usize>)> {
+    let mut matched = vec!();
+    let mut score = 0;
+    let mut chars = text.char_indices();
+    let mut at = 0;
+    for wanted in query.chars() {
+        let (i, c) = chars.find(|&(_, c)| char_matches(wanted, c))?;
+        if i > at && !is_word_start(text, i) {
+            score += text[at..i].chars().count();
+        }
+        matched.push(i);
+        at = i + c.len_utf8();
+    }
+    Some((score, matched))
+}
+
+// The candidates matching `query`, best and then shortest first. They stay in
+// the order given while nothing has been typed.
+pub fn filter(query: &str, candidates: &[String]) -> Vec<Candidate> {
+    let mut matches: Vec<Candidate> = candidates
+        .iter()
+        .filter_map(|text| {
+            let (score, matched) = fuzzy_match(query, text)?;
+            Some(Candidate { text: text.clone(), matched, score })
+        })
+        .collect();
+    if !query.is_empty() {
+        matches.sort_by_key(|candidate| (candidate.score, candidate.text.len()));
+    }
+    matches
+}
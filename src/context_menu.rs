@@ -0,0 +1,53 @@
+use crate::buffer::Buffer;
+use crate::command_registry;
+use crate::commands;
+use crate::commands::CommandContext;
+use crate::commands::Repeatable;
+use crate::popup::Popup;
+use crate::text_content::Position;
+use crate::text_mode::Global;
+
+// A line of a context menu, running a command from the registry
+#[derive(Clone, Copy)]
+pub struct MenuItem {
+    pub label: &'static str,
+    pub command: &'static str,
+}
+
+// What every buffer's context menu starts with
+const EDIT_ITEMS: &[MenuItem] = &[
+    MenuItem { label: "Cut", command: "kill-region" },
+    MenuItem { label: "Copy", command: "copy-region" },
+    MenuItem { label: "Paste", command: "yank" },
+    MenuItem { label: "Select All", command: "mark-whole-buffer" },
+];
+
+// The editing items followed by the ones the buffer's modes add, first
+// label winning when two are the same
+fn items(buffer: &Buffer) -> Vec<MenuItem> {
+    let mut items: Vec<MenuItem> = vec!();
+    let from_modes = buffer.minor_modes.iter().flat_map(|mode| mode.context_menu().iter());
+    for &item in EDIT_ITEMS.iter().chain(from_modes) {
+        if !items.iter().any(|known| known.label == item.label) {
+            items.push(item);
+        }
+    }
+    items
+}
+
+// Pops up the context menu of `buffer` under `anchor`
+pub fn open(global: &mut Global, buffer: &Buffer, anchor: Position) {
+    let labels = items(buffer).iter().map(|item| item.label.to_string()).collect();
+    global.popups.open(Popup::menu(anchor, labels, choose));
+}
+
+fn choose(global: &mut Global, buffer: &mut Buffer, label: &str) {
+    let item = match items(buffer).into_iter().find(|item| item.label == label) {
+        Some(item) => item,
+        None => return,
+    };
+    match command_registry::command(item.command) {
+        Ok(command) => commands::execute(global, buffer, Repeatable::Command(command), CommandContext::default()),
+        Err(e) => global.message(e),
+    }
+}
@@ -0,0 +1,70 @@
+use std::time::Duration;
+use std::time::Instant;
+
+// How long the cursor stays on, and then off, while blinking
+const BLINK_INTERVAL: Duration = Duration::from_millis(500);
+
+// Blinks before the cursor stays on, so an idle editor stops redrawing
+const BLINKS: u32 = 10;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CursorStyle {
+    // Covers the character it is on, which shows through inverted
+    Block,
+    // A thin line on the left edge of the character
+    Bar,
+    // A thin line under the character
+    Underline,
+}
+
+impl CursorStyle {
+    // Block, bar, underline and round again
+    pub fn next(self) -> CursorStyle {
+        match self {
+            CursorStyle::Block => CursorStyle::Bar,
+            CursorStyle::Bar => CursorStyle::Underline,
+            CursorStyle::Underline => CursorStyle::Block,
+        }
+    }
+}
+
+pub struct Cursor {
+    pub style: CursorStyle,
+    pub blink: bool,
+    // When the cursor last moved or a key was pressed, which starts the
+    // blinking over with the cursor on
+    blink_start: Instant,
+}
+
+impl Cursor {
+    // From EDIT0R_CURSOR, which is "block", "bar" (the default) or
+    // "underline", optionally followed by ",steady" to stop it blinking
+    pub fn from_env() -> Cursor {
+        let setting = std::env::var("EDIT0R_CURSOR").unwrap_or_default();
+        let mut parts = setting.split(',').map(str::trim);
+        let style = match parts.next() {
+            Some("block") => CursorStyle::Block,
+            Some("underline") => CursorStyle::Underline,
+            _ => CursorStyle::Bar,
+        };
+        Cursor {
+            style,
+            blink: !parts.any(|part| part == "steady"),
+            blink_start: Instant::now(),
+        }
+    }
+
+    // Shows the cursor for a whole blink after something happened
+    pub fn reset_blink(&mut self) {
+        self.blink_start = Instant::now();
+    }
+
+    // Whether the cursor is in the on half of its blink
+    pub fn is_on(&self) -> bool {
+        if !self.blink {
+            return true;
+        }
+        let phases = (self.blink_start.elapsed().as_millis() / BLINK_INTERVAL.as_millis()) as u32;
+        phases >= BLINKS * 2 || phases.is_multiple_of(2)
+    }
+}
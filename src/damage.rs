@@ -0,0 +1,75 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+// What went on each screen row last frame, so rows that come out the same can
+// be left alone on the frame texture and idle frames draw nothing at all
+#[derive(Default)]
+pub struct Damage {
+    rows: Vec<Option<u64>>,
+    // Everything that moves or restyles every row, like the window size
+    layout: Option<u64>,
+    // Parts of the screen drawn as a whole rather than by row, like the
+    // minimap
+    parts: HashMap<&'static str, u64>,
+    drawn: bool,
+}
+
+fn hash_of(value: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl Damage {
+    // Starts a frame with `rows` screen rows, returning true when everything
+    // has to be drawn again since `layout` changed
+    pub fn begin(&mut self, layout: impl Hash, rows: usize) -> bool {
+        self.drawn = false;
+        let layout = Some(hash_of(layout));
+        if self.layout == layout && self.rows.len() == rows {
+            return false;
+        }
+        self.layout = layout;
+        self.rows = vec!(None; rows);
+        self.parts.clear();
+        true
+    }
+
+    // Has everything drawn again next frame, e.g. once the window was
+    // uncovered or resized
+    pub fn invalidate(&mut self) {
+        self.layout = None;
+    }
+
+    // Whether screen row `row` has to be drawn to show what `key` describes
+    pub fn needs_drawing(&mut self, row: usize, key: impl Hash) -> bool {
+        let key = Some(hash_of(key));
+        match self.rows.get_mut(row) {
+            Some(old) if *old == key => false,
+            Some(old) => {
+                *old = key;
+                self.drawn = true;
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Whether the part of the screen called `part` has to be drawn to show
+    // what `key` describes
+    pub fn needs_drawing_part(&mut self, part: &'static str, key: impl Hash) -> bool {
+        let key = hash_of(key);
+        if self.parts.insert(part, key) == Some(key) {
+            return false;
+        }
+        self.drawn = true;
+        true
+    }
+
+    // Something was drawn this frame, so it needs presenting
+    pub fn any(&self) -> bool {
+        self.drawn
+    }
+}
@@ -0,0 +1,332 @@
+use std::ops::Range;
+
+use crate::buffer::Buffer;
+use crate::buffer_local::Variable;
+use crate::line_ending::LineEnding;
+use crate::minibuffer::Minibuffer;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+// Unified diffs, like .patch files, with their file headers, hunk ranges
+// and added and removed lines in faces of their own. Two buffers can also
+// be compared side by side, each in a read-only copy where blank rows are
+// put in so their changed lines line up across the windows.
+
+pub struct DiffMode {
+}
+
+impl TextMinorMode for DiffMode {
+    fn name(&self) -> &'static str {
+        "diff"
+    }
+
+    fn is_major(&self) -> bool {
+        true
+    }
+
+    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
+        let file_face_id = global.faces.get_face_id("diff-file-header");
+        let hunk_face_id = global.faces.get_face_id("diff-hunk-header");
+        let added_face_id = global.faces.get_face_id("diff-added");
+        let removed_face_id = global.faces.get_face_id("diff-removed");
+
+        let mut spans = vec!();
+        let (start, end) = content.visible_range();
+        for row in start.row..=end.row {
+            let line = content.line(row);
+            let face_id = if is_file_header(content, row, &line) {
+                file_face_id
+            } else if line.starts_with("@@") {
+                hunk_face_id
+            } else if line.starts_with('+') {
+                added_face_id
+            } else if line.starts_with('-') {
+                removed_face_id
+            } else {
+                None
+            };
+            if let Some(face_id) = face_id {
+                spans.push((row, line.len(), face_id));
+            }
+        }
+        for (row, len, face_id) in spans {
+            content.faces.add(Position::new(row, 0), Position::new(row, len), face_id);
+        }
+    }
+}
+
+pub fn extra_modes() -> Vec<Box<dyn TextMinorMode>> {
+    vec!(Box::new(DiffMode {}))
+}
+
+// Whether `line` on `row` names the files compared rather than being a
+// changed line, which "--- " and "+++ " lines could also be
+fn is_file_header(content: &TextContent, row: usize, line: &str) -> bool {
+    const HEADERS: &[&str] = &["diff ", "index ", "new file mode", "deleted file mode", "similarity index", "rename from", "rename to"];
+    if HEADERS.iter().any(|header| line.starts_with(header)) {
+        return true;
+    }
+    let next = || (row + 1 < content.line_count()).then(|| content.line(row + 1));
+    let previous = || (row > 0).then(|| content.line(row - 1));
+    (line.starts_with("--- ") && next().is_some_and(|next| next.starts_with("+++ ")))
+        || (line.starts_with("+++ ") && previous().is_some_and(|previous| previous.starts_with("--- ")))
+}
+
+// Changed lines on `rows` of one side, the first `lines` of them its own
+// and the rest blank to match the other side's
+#[derive(Clone)]
+struct Region {
+    rows: Range<usize>,
+    lines: usize,
+}
+
+// One side of a comparison, and the buffer with the other
+#[derive(Clone)]
+struct Side {
+    partner: String,
+    regions: Vec<Region>,
+}
+
+const SIDE: Variable<Option<Side>> = Variable::new("diff-side", None);
+
+// Colors the lines one side of a comparison has that the other doesn't
+pub struct DiffSideMode {
+    regions: Vec<Region>,
+    // The side with the older text, whose lines were removed
+    old: bool,
+}
+
+impl DiffSideMode {
+    fn changed_region(&self, row: usize) -> Option<&Region> {
+        self.regions.iter().find(|region| region.rows.start <= row && row < region.rows.start + region.lines)
+    }
+}
+
+impl TextMinorMode for DiffSideMode {
+    fn name(&self) -> &'static str {
+        "diff-side"
+    }
+
+    fn is_major(&self) -> bool {
+        true
+    }
+
+    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
+        let face_id = match global.faces.get_face_id(if self.old { "diff-removed" } else { "diff-added" }) {
+            Some(face_id) => face_id,
+            None => return,
+        };
+        for region in &self.regions {
+            for row in region.rows.start..region.rows.start + region.lines {
+                let len = content.line_len(row);
+                content.faces.add(Position::new(row, 0), Position::new(row, len), face_id);
+            }
+        }
+    }
+
+    fn line_mark(&self, row: usize) -> Option<&'static str> {
+        self.changed_region(row)?;
+        Some(if self.old { "diff-removed" } else { "diff-added" })
+    }
+}
+
+// Whether step `d` reached diagonal `k` by a line added rather than one
+// removed, with v indexed by diagonal + `offset`
+fn from_above(v: &[isize], k: isize, d: isize, offset: isize) -> bool {
+    k == -d || (k != d && v[(k - 1 + offset) as usize] < v[(k + 1 + offset) as usize])
+}
+
+// The lines `old` and `new` have in common as pairs of their indexes, by
+// Myers' algorithm
+fn common_lines(old: &[&str], new: &[&str]) -> Vec<(usize, usize)> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let offset = n + m + 1;
+    // How far along `old` each diagonal k = x - y got, by k + offset
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    // Diagonals -d..=d of v before each step d
+    let mut trace = vec!();
+    'search: for d in 0..=n + m {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let at = |k: isize| (k + offset) as usize;
+            let mut x = if from_above(&v, k, d, offset) { v[at(k + 1)] } else { v[at(k - 1)] + 1 };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[at(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Back from the end, taking the diagonal runs each step ended with
+    let mut pairs = vec!();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let previous_k = if from_above(v, k, d, d) { k + 1 } else { k - 1 };
+        let previous_x = if d == 0 { 0 } else { v[(previous_k + d) as usize] };
+        let previous_y = if d == 0 { 0 } else { previous_x - previous_k };
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            pairs.push((x as usize, y as usize));
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    pairs.reverse();
+    pairs
+}
+
+// The two texts with blank lines put in so they line up, and where they
+// differ on each side
+fn align(old: &str, new: &str) -> ((String, Vec<Region>), (String, Vec<Region>)) {
+    let old: Vec<&str> = old.split('\n').collect();
+    let new: Vec<&str> = new.split('\n').collect();
+    let (mut old_lines, mut new_lines): (Vec<&str>, Vec<&str>) = (vec!(), vec!());
+    let (mut old_regions, mut new_regions) = (vec!(), vec!());
+    let (mut x, mut y) = (0, 0);
+    // Past the last lines, so the changes after the last line in common are seen
+    let end = std::iter::once((old.len(), new.len()));
+    for (next_x, next_y) in common_lines(&old, &new).into_iter().chain(end) {
+        let (removed, added) = (next_x - x, next_y - y);
+        if removed + added > 0 {
+            let row = old_lines.len();
+            let rows = row..row + removed.max(added);
+            old_lines.extend(&old[x..next_x]);
+            old_lines.extend(std::iter::repeat_n("", rows.len() - removed));
+            new_lines.extend(&new[y..next_y]);
+            new_lines.extend(std::iter::repeat_n("", rows.len() - added));
+            old_regions.push(Region { rows: rows.clone(), lines: removed });
+            new_regions.push(Region { rows, lines: added });
+        }
+        if next_x < old.len() {
+            old_lines.push(old[next_x]);
+            new_lines.push(new[next_y]);
+        }
+        x = next_x + 1;
+        y = next_y + 1;
+    }
+    ((old_lines.join("\n"), old_regions), (new_lines.join("\n"), new_regions))
+}
+
+// Shows `old_name`'s `old` text and `new_name`'s `new` text side by side,
+// in the selected window and one split off to the right
+fn compare(global: &mut Global, old_name: &str, old: &str, new_name: &str, new: &str) {
+    let ((old_text, old_regions), (new_text, new_regions)) = align(old, new);
+    if old_regions.is_empty() {
+        global.message(format!("{} and {} are the same", old_name, new_name));
+        return;
+    }
+    let old_side = global.buffers.unique_name(&format!("*diff {}*", old_name));
+    let new_side = global.buffers.unique_name(&format!("*diff {}*", new_name));
+    let sides = [
+        (&old_side, &new_side, old_text, old_regions, true),
+        (&new_side, &old_side, new_text, new_regions, false),
+    ];
+    for (name, partner, text, regions, old) in sides {
+        let mode = DiffSideMode { regions: regions.clone(), old };
+        let mut side = Buffer::new(name, TextContent::new(&text), vec!(Box::new(mode)));
+        side.read_only = true;
+        side.view.line_marks = true;
+        side.content.locals.set(&SIDE, Some(Side { partner: partner.clone(), regions }));
+        global.buffers.add(side);
+    }
+    global.buffers.switch_to(&old_side);
+    global.windows.delete_others();
+    if let Some(new_buffer) = global.buffers.find(&new_side) {
+        global.windows.split_showing(new_buffer, false);
+    }
+}
+
+// Compares the current buffer side by side with another, prompting for it
+pub fn diff_buffers(global: &mut Global, _: &mut Buffer) {
+    let names: Vec<String> = global.buffers.names().map(str::to_string).collect();
+    let initial = global.buffers.most_recent().map_or_else(String::new, |other| other.name.clone());
+    global.minibuffer = Some(Minibuffer::with_list("Diff with buffer: ", &initial, names, |global, buffer, input| {
+        let new = match global.buffers.find(input) {
+            Some(other) => other.content.text(),
+            None => {
+                global.message(format!("No buffer named {}", input));
+                return;
+            },
+        };
+        compare(global, &buffer.name, &buffer.content.text(), input, &new);
+    }));
+}
+
+// Compares the file as saved with what the buffer has now
+pub fn diff_buffer_with_file(global: &mut Global, buffer: &mut Buffer) {
+    let path = match &buffer.path {
+        Some(path) => path.clone(),
+        None => {
+            global.message("Buffer has no file".to_string());
+            return;
+        },
+    };
+    let saved = match std::fs::read_to_string(&path) {
+        Ok(text) => {
+            let (line_ending, _) = LineEnding::detect(&text);
+            line_ending.decode(&text)
+        },
+        Err(e) => {
+            global.message(format!("{}: {}", path.display(), e));
+            return;
+        },
+    };
+    let saved_name = format!("{} (saved)", buffer.name);
+    compare(global, &saved_name, &saved, &buffer.name, &buffer.content.text());
+}
+
+fn step_change(global: &mut Global, buffer: &mut Buffer, forward: bool) {
+    let row = buffer.content.cursor.row;
+    let side = buffer.content.locals.get(&SIDE);
+    let starts: Vec<usize> = match &side {
+        Some(side) => side.regions.iter().map(|region| region.rows.start).collect(),
+        None if buffer.has_minor_mode("diff") => {
+            (0..buffer.content.line_count()).filter(|&row| buffer.content.line(row).starts_with("@@")).collect()
+        },
+        None => {
+            global.message("Not a diff".to_string());
+            return;
+        },
+    };
+    let found = if forward {
+        starts.into_iter().find(|&start| start > row)
+    } else {
+        starts.into_iter().rev().find(|&start| start < row)
+    };
+    let row = match found {
+        Some(row) => row,
+        None => {
+            global.message(if forward { "No more changes" } else { "No earlier changes" }.to_string());
+            return;
+        },
+    };
+    let cursor = Position::new(row, 0);
+    buffer.content.cursor = cursor;
+    buffer.content.deactivate_mark();
+    // The other side goes to the same change, on the same row
+    if let Some(Side { partner, .. }) = side {
+        if let Some(other) = global.buffers.find_mut(&partner) {
+            other.content.cursor = cursor;
+        }
+        global.windows.move_cursors(&partner, cursor);
+    }
+}
+
+// Goes to the next hunk of a diff, or the next change compared side by side
+pub fn diff_next_change(global: &mut Global, buffer: &mut Buffer) {
+    step_change(global, buffer, true);
+}
+
+pub fn diff_previous_change(global: &mut Global, buffer: &mut Buffer) {
+    step_change(global, buffer, false);
+}
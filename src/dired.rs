@@ -0,0 +1,394 @@
+use std::fs;
+use std::fs::Metadata;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::buffer::Buffer;
+use crate::buffer_local::Variable;
+use crate::commands;
+use crate::minibuffer::Minibuffer;
+use crate::minibuffer::complete_path;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+// Listing a directory like Emacs' dired, one file a line after a header
+// naming the directory:
+//
+//       /home/me/project:
+//       drwxr-xr-x       4096 2024-03-01 12:30 ..
+//       -rw-r--r--        312 2024-03-02 09:15 Cargo.toml
+//
+// The listing is read-only, keys in the dired keymap section open and work
+// on the file on the cursor's line.
+
+// The directory a buffer lists, None in other buffers
+const DIRECTORY: Variable<Option<PathBuf>> = Variable::new("dired-directory", None);
+
+// Where the names start, after the permissions, size and time changed
+const NAME_COL: usize = 41;
+
+pub struct DiredMode {
+}
+
+impl TextMinorMode for DiredMode {
+    fn name(&self) -> &'static str {
+        "dired"
+    }
+
+    fn is_major(&self) -> bool {
+        true
+    }
+
+    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
+        let header_face_id = global.faces.get_face_id("dired-header");
+        let directory_face_id = global.faces.get_face_id("dired-directory");
+        let symlink_face_id = global.faces.get_face_id("dired-symlink");
+
+        let mut spans = vec!();
+        for row in 0..content.line_count() {
+            let line = content.line(row);
+            let face_id = match (row, line.chars().nth(2)) {
+                (0, _) => header_face_id,
+                (_, Some('d')) => directory_face_id,
+                (_, Some('l')) => symlink_face_id,
+                _ => None,
+            };
+            if let (Some(face_id), true) = (face_id, line.len() > NAME_COL || row == 0) {
+                let start = if row == 0 { 0 } else { NAME_COL };
+                spans.push((row, start, line.len(), face_id));
+            }
+        }
+        for (row, start, end, face_id) in spans {
+            content.faces.add(Position::new(row, start), Position::new(row, end), face_id);
+        }
+    }
+}
+
+// The directory `buffer` lists
+pub fn directory(buffer: &Buffer) -> Option<PathBuf> {
+    buffer.content.locals.get(&DIRECTORY)
+}
+
+// The name of the buffer already listing `directory`
+pub fn find_listing(global: &mut Global, directory: &Path) -> Option<String> {
+    let directory = fs::canonicalize(directory).ok()?;
+    global.buffers
+        .iter_mut()
+        .find(|buffer| buffer.content.locals.get(&DIRECTORY).as_ref() == Some(&directory))
+        .map(|buffer| buffer.name.clone())
+}
+
+// A buffer listing `directory`, named after it with a slash
+pub fn open(directory: &Path) -> Result<Buffer, String> {
+    let directory = fs::canonicalize(directory).map_err(|e| format!("{}: {}", directory.display(), e))?;
+    let name = directory.file_name().map_or_else(|| "/".to_string(), |name| format!("{}/", name.to_string_lossy()));
+    let mut buffer = Buffer::new(&name, TextContent::new(""), vec!(Box::new(DiredMode {})));
+    buffer.read_only = true;
+    buffer.content = listing_content(&directory)?;
+    // On the first file rather than ".."
+    let row = if buffer.content.line_count() > 2 { 2 } else { 1 };
+    move_to_name(&mut buffer.content, row);
+    Ok(buffer)
+}
+
+fn listing_content(directory: &Path) -> Result<TextContent, String> {
+    let mut entries: Vec<(String, Metadata)> = fs::read_dir(directory)
+        .map_err(|e| format!("{}: {}", directory.display(), e))?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = fs::symlink_metadata(entry.path()).ok()?;
+            Some((entry.file_name().to_string_lossy().into_owned(), metadata))
+        })
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    if let Some(metadata) = directory.parent().and_then(|parent| fs::metadata(parent).ok()) {
+        entries.insert(0, ("..".to_string(), metadata));
+    }
+
+    let mut text = format!("  {}:", directory.display());
+    for (name, metadata) in entries {
+        let name = match fs::read_link(directory.join(&name)) {
+            Ok(target) if metadata.file_type().is_symlink() => format!("{} -> {}", name, target.display()),
+            _ => name,
+        };
+        let time = metadata.modified().map_or_else(|_| " ".repeat(16), format_time);
+        text.push_str(&format!("\n  {} {:>10} {} {}", permissions(&metadata), metadata.len(), time, name));
+    }
+
+    let mut content = TextContent::new(&text);
+    content.locals.set(&DIRECTORY, Some(directory.to_path_buf()));
+    Ok(content)
+}
+
+// Like ls shows them, e.g. "drwxr-xr-x"
+fn permissions(metadata: &Metadata) -> String {
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_symlink() { 'l' } else if file_type.is_dir() { 'd' } else { '-' };
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode()
+    };
+    #[cfg(not(unix))]
+    let mode = if metadata.permissions().readonly() { 0o444 } else { 0o666 };
+
+    let mut shown = kind.to_string();
+    for (bit, c) in (0..9).rev().zip("rwxrwxrwx".chars()) {
+        shown.push(if mode & (1 << bit) != 0 { c } else { '-' });
+    }
+    shown
+}
+
+// As "YYYY-MM-DD HH:MM" in UTC
+fn format_time(time: SystemTime) -> String {
+    let seconds = time.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |since| since.as_secs()) as i64;
+    let (days, seconds) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    // Howard Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, seconds / 3600, seconds % 3600 / 60)
+}
+
+fn move_to_name(content: &mut TextContent, row: usize) {
+    let row = row.clamp(1.min(content.line_count() - 1), content.line_count() - 1);
+    content.cursor = Position::new(row, NAME_COL.min(content.line_len(row)));
+    content.deactivate_mark();
+}
+
+// The name on `row` of the listing, without where a link points
+fn name_at(content: &TextContent, row: usize) -> Option<String> {
+    let line = content.line(row);
+    if row == 0 || line.len() <= NAME_COL {
+        return None;
+    }
+    let name = &line[NAME_COL..];
+    let name = match line.chars().nth(2) {
+        Some('l') => name.split(" -> ").next().unwrap_or(name),
+        _ => name,
+    };
+    Some(name.to_string())
+}
+
+// The directory `buffer` lists, saying so when it isn't a listing
+fn listed_directory(global: &mut Global, buffer: &Buffer) -> Option<PathBuf> {
+    let directory = directory(buffer);
+    if directory.is_none() {
+        global.message("Not a directory listing".to_string());
+    }
+    directory
+}
+
+// The directory listed and the path of the file on the cursor's line
+fn file_at_cursor(global: &mut Global, buffer: &Buffer) -> Option<(PathBuf, PathBuf)> {
+    let directory = listed_directory(global, buffer)?;
+    match name_at(&buffer.content, buffer.content.cursor.row) {
+        Some(name) if name == ".." => {
+            let parent = directory.parent().unwrap_or(&directory).to_path_buf();
+            Some((directory, parent))
+        },
+        Some(name) => {
+            let path = directory.join(name);
+            Some((directory, path))
+        },
+        None => {
+            global.message("No file on this line".to_string());
+            None
+        },
+    }
+}
+
+// The file on the cursor's line to rename, copy or delete. ".." is there to
+// go up, not to be operated on, since that would be the directory's parent.
+fn operable_file_at_cursor(global: &mut Global, buffer: &Buffer) -> Option<(PathBuf, PathBuf)> {
+    if name_at(&buffer.content, buffer.content.cursor.row).as_deref() == Some("..") {
+        global.message("Cannot operate on ..".to_string());
+        return None;
+    }
+    file_at_cursor(global, buffer)
+}
+
+// Lists the directory again, with the cursor on `name` when it's given and
+// listed, else staying on its row
+fn refresh(global: &mut Global, buffer: &mut Buffer, name: Option<&str>) {
+    let directory = match directory(buffer) {
+        Some(directory) => directory,
+        None => return,
+    };
+    match listing_content(&directory) {
+        Ok(content) => {
+            let row = buffer.content.cursor.row;
+            buffer.content = content;
+            let row = name
+                .and_then(|name| (1..buffer.content.line_count()).find(|&row| name_at(&buffer.content, row).as_deref() == Some(name)))
+                .unwrap_or(row);
+            move_to_name(&mut buffer.content, row);
+            buffer.content.modified = true;
+        },
+        Err(e) => global.message(format!("Listing failed: {}", e)),
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+pub fn dired_revert(global: &mut Global, buffer: &mut Buffer) {
+    refresh(global, buffer, None);
+}
+
+// Opens the file on the cursor's line, or lists it when it's a directory
+pub fn dired_find_file(global: &mut Global, buffer: &mut Buffer) {
+    if let Some((_, path)) = file_at_cursor(global, buffer) {
+        commands::visit_path(global, buffer, &path.to_string_lossy());
+    }
+}
+
+pub fn dired_up_directory(global: &mut Global, buffer: &mut Buffer) {
+    match directory(buffer).as_deref().and_then(Path::parent) {
+        Some(parent) => commands::visit_path(global, buffer, &parent.to_string_lossy()),
+        None => global.message("No directory above".to_string()),
+    }
+}
+
+pub fn dired_next_line(_: &mut Global, buffer: &mut Buffer) {
+    let row = buffer.content.cursor.row + 1;
+    move_to_name(&mut buffer.content, row);
+}
+
+pub fn dired_previous_line(_: &mut Global, buffer: &mut Buffer) {
+    let row = buffer.content.cursor.row.saturating_sub(1);
+    move_to_name(&mut buffer.content, row);
+}
+
+pub fn dired_do_rename(global: &mut Global, buffer: &mut Buffer) {
+    if let Some((directory, path)) = operable_file_at_cursor(global, buffer) {
+        let prompt = format!("Rename {} to: ", file_name(&path));
+        let initial = format!("{}/", directory.display());
+        global.minibuffer = Some(Minibuffer::new(&prompt, &initial, Some(complete_path), |global, buffer, input| {
+            let (_, from) = match operable_file_at_cursor(global, buffer) {
+                Some(found) if !input.is_empty() => found,
+                _ => return,
+            };
+            let to = PathBuf::from(input);
+            match fs::rename(&from, &to) {
+                Ok(()) => refresh(global, buffer, Some(&file_name(&to))),
+                Err(e) => global.message(format!("Renaming failed: {}", e)),
+            }
+        }));
+    }
+}
+
+pub fn dired_do_copy(global: &mut Global, buffer: &mut Buffer) {
+    if let Some((directory, path)) = operable_file_at_cursor(global, buffer) {
+        let prompt = format!("Copy {} to: ", file_name(&path));
+        let initial = format!("{}/", directory.display());
+        global.minibuffer = Some(Minibuffer::new(&prompt, &initial, Some(complete_path), |global, buffer, input| {
+            let (_, from) = match operable_file_at_cursor(global, buffer) {
+                Some(found) if !input.is_empty() => found,
+                _ => return,
+            };
+            // Into a directory named as the target, like cp does
+            let mut to = PathBuf::from(input);
+            if to.is_dir() {
+                to = to.join(file_name(&from));
+            }
+            match copy(&from, &to) {
+                Ok(()) => refresh(global, buffer, Some(&file_name(&to))),
+                Err(e) => global.message(format!("Copying failed: {}", e)),
+            }
+        }));
+    }
+}
+
+// Copies a file, or a directory and everything in it
+fn copy(from: &Path, to: &Path) -> Result<(), String> {
+    let error = |e: std::io::Error| format!("{}: {}", from.display(), e);
+    if !from.is_dir() {
+        return fs::copy(from, to).map(|_| ()).map_err(error);
+    }
+    fs::create_dir(to).map_err(error)?;
+    for entry in fs::read_dir(from).map_err(error)? {
+        let entry = entry.map_err(error)?;
+        copy(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+// Links to directories are deleted as links
+fn is_directory(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir())
+}
+
+// Deletes the file on the cursor's line, or the directory and everything in
+// it, after asking
+pub fn dired_do_delete(global: &mut Global, buffer: &mut Buffer) {
+    if let Some((_, path)) = operable_file_at_cursor(global, buffer) {
+        let prompt = if is_directory(&path) {
+            format!("Delete {} and everything in it? (y or n) ", file_name(&path))
+        } else {
+            format!("Delete {}? (y or n) ", file_name(&path))
+        };
+        global.minibuffer = Some(Minibuffer::new(&prompt, "", None, |global, buffer, answer| {
+            if !answer.trim_start().starts_with(['y', 'Y']) {
+                return;
+            }
+            let (_, path) = match operable_file_at_cursor(global, buffer) {
+                Some(found) => found,
+                None => return,
+            };
+            let deleted = if is_directory(&path) { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+            match deleted {
+                Ok(()) => refresh(global, buffer, None),
+                Err(e) => global.message(format!("Deleting failed: {}", e)),
+            }
+        }));
+    }
+}
+
+fn finish_create(global: &mut Global, buffer: &mut Buffer, input: &str, create: fn(&Path) -> std::io::Result<()>) {
+    if input.is_empty() {
+        return;
+    }
+    let path = PathBuf::from(input);
+    match create(&path) {
+        Ok(()) => refresh(global, buffer, Some(&file_name(&path))),
+        Err(e) => global.message(format!("Creating failed: {}", e)),
+    }
+}
+
+fn create_directory(path: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(path)
+}
+
+// Empty, and only when there's nothing there yet
+fn create_file(path: &Path) -> std::io::Result<()> {
+    fs::OpenOptions::new().write(true).create_new(true).open(path).map(|_| ())
+}
+
+pub fn dired_create_directory(global: &mut Global, buffer: &mut Buffer) {
+    if let Some(directory) = listed_directory(global, buffer) {
+        let initial = format!("{}/", directory.display());
+        global.minibuffer = Some(Minibuffer::new("Create directory: ", &initial, Some(complete_path), |global, buffer, input| {
+            finish_create(global, buffer, input, create_directory);
+        }));
+    }
+}
+
+pub fn dired_create_file(global: &mut Global, buffer: &mut Buffer) {
+    if let Some(directory) = listed_directory(global, buffer) {
+        let initial = format!("{}/", directory.display());
+        global.minibuffer = Some(Minibuffer::new("Create file: ", &initial, Some(complete_path), |global, buffer, input| {
+            finish_create(global, buffer, input, create_file);
+        }));
+    }
+}
@@ -0,0 +1,44 @@
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+const PAIRS: [(char, char); 4] = [
+    ('(', ')'),
+    ('[', ']'),
+    ('{', '}'),
+    ('"', '"'),
+];
+
+// Inserts the closing half of a pair along with the opening one
+pub struct ElectricPairMode {
+}
+
+impl TextMinorMode for ElectricPairMode {
+    fn name(&self) -> &'static str {
+        "electric-pair"
+    }
+
+    fn self_insert(&mut self, _global: &mut Global, content: &mut TextContent, text: &str) -> bool {
+        let mut chars = text.chars();
+        let c = match (chars.next(), chars.next()) {
+            (Some(c), None) => c,
+            _ => return false,
+        };
+
+        // Typing a closer in front of the same closer steps over it
+        let is_closer = PAIRS.iter().any(|&(_, close)| close == c);
+        if is_closer && content.char_at(content.cursor) == Some(c) {
+            content.move_right();
+            return true;
+        }
+
+        match PAIRS.iter().find(|&&(open, _)| open == c) {
+            Some(&(open, close)) => {
+                content.self_insert(&format!("{}{}", open, close));
+                content.move_left();
+                true
+            },
+            None => false,
+        }
+    }
+}
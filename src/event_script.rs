@@ -0,0 +1,278 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+
+use sdl2::event::Event;
+use sdl2::keyboard::Mod;
+use sdl2::mouse::MouseButton;
+use sdl2::mouse::MouseState;
+use sdl2::mouse::MouseWheelDirection;
+
+use crate::buffer::Buffer;
+use crate::keymap;
+use crate::keymap::KeyPress;
+use crate::text_content::Position;
+use crate::text_mode::Global;
+use crate::text_mode;
+
+// Scripts of input events, one step a line, fed through the main loop as
+// though they were typed, with checks on what the buffer ends up holding:
+//
+//     # comments and blank lines are skipped
+//     key C-x C-f        keys pressed, giving text like SDL does when plain
+//     type hello         text typed without the keys for it
+//     click 40 60 2      a left click at pixels 40, 60, here a double one
+//     right-click 40 60
+//     drag 90 60         the mouse moved with the left button down
+//     release 90 60
+//     wheel -3
+//     expect hello\n     the whole buffer, with \n, \t and \\ escaped
+//     expect-face 2 0 comment   the face at row 2, column 0, counting from 0
+//
+// Run with SDL_VIDEODRIVER=dummy, scripts need no display.
+
+enum Step {
+    Events(Vec<Event>),
+    Expect(String),
+    ExpectFace(Position, String),
+}
+
+pub struct Script {
+    // With the line each came from, for reporting failed checks
+    steps: VecDeque<(usize, Step)>,
+}
+
+// From EDIT0R_REPLAY, the path of a script to run in place of the keyboard
+// and mouse. The editor quits at its end.
+pub fn from_env() -> Result<Option<Script>, String> {
+    let path = match std::env::var("EDIT0R_REPLAY") {
+        Ok(path) => path,
+        Err(_) => return Ok(None),
+    };
+    let text = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+    Script::parse(&text).map(Some).map_err(|e| format!("{}: {}", path, e))
+}
+
+impl Script {
+    pub fn parse(text: &str) -> Result<Script, String> {
+        let mut steps = VecDeque::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim_end();
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let step = parse_step(line).map_err(|e| format!("line {}: {}", index + 1, e))?;
+            steps.push_back((index + 1, step));
+        }
+        Ok(Script { steps })
+    }
+
+    // The events of the next step, after checking `buffer` against the
+    // expectations before it, or None at the end of the script. The faces
+    // have to be up to date.
+    pub fn next_events(&mut self, global: &Global, buffer: &Buffer) -> Result<Option<Vec<Event>>, String> {
+        while let Some((line, step)) = self.steps.pop_front() {
+            match step {
+                Step::Events(events) => return Ok(Some(events)),
+                Step::Expect(expected) => {
+                    let text = buffer.content.text();
+                    if text != expected {
+                        return Err(format!("line {}: expected {:?} but the buffer has {:?}", line, expected, text));
+                    }
+                },
+                Step::ExpectFace(pos, expected) => {
+                    let content = &buffer.content;
+                    let line_len = if pos.row < content.line_count() { content.line_len(pos.row) } else { 0 };
+                    let face_id = content.faces.line_spans(pos.row, line_len)
+                        .into_iter()
+                        .find(|(range, _)| range.contains(&pos.col))
+                        .map_or(0, |(_, face_id)| face_id);
+                    if global.faces.get_face_id(&expected) != Some(face_id) {
+                        return Err(format!("line {}: expected face {} at {}:{}", line, expected, pos.row, pos.col));
+                    }
+                },
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn parse_step(line: &str) -> Result<Step, String> {
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let events = match command {
+        "key" => keymap::parse_keys(rest)?.into_iter().flat_map(key_events).collect(),
+        "type" => vec!(Event::TextInput { timestamp: 0, window_id: 0, text: rest.to_string() }),
+        "click" => {
+            let numbers = parse_numbers(rest, 2, 3)?;
+            let clicks = numbers.get(2).copied().unwrap_or(1);
+            vec!(button_down(MouseButton::Left, clicks as u8, numbers[0], numbers[1]))
+        },
+        "right-click" => {
+            let numbers = parse_numbers(rest, 2, 2)?;
+            vec!(button_down(MouseButton::Right, 1, numbers[0], numbers[1]))
+        },
+        "drag" => {
+            let numbers = parse_numbers(rest, 2, 2)?;
+            vec!(Event::MouseMotion {
+                timestamp: 0,
+                window_id: 0,
+                which: 0,
+                mousestate: MouseState::from_sdl_state(1),
+                x: numbers[0],
+                y: numbers[1],
+                xrel: 0,
+                yrel: 0,
+            })
+        },
+        "release" => {
+            let numbers = parse_numbers(rest, 2, 2)?;
+            vec!(Event::MouseButtonUp {
+                timestamp: 0,
+                window_id: 0,
+                which: 0,
+                mouse_btn: MouseButton::Left,
+                clicks: 1,
+                x: numbers[0],
+                y: numbers[1],
+            })
+        },
+        "wheel" => {
+            let numbers = parse_numbers(rest, 1, 1)?;
+            vec!(Event::MouseWheel {
+                timestamp: 0,
+                window_id: 0,
+                which: 0,
+                x: 0,
+                y: numbers[0],
+                direction: MouseWheelDirection::Normal,
+            })
+        },
+        "expect" => return Ok(Step::Expect(unescape(rest)?)),
+        "expect-face" => {
+            let (numbers, face) = rest.rsplit_once(' ').ok_or("Expected a row, column and face")?;
+            let numbers = parse_numbers(numbers, 2, 2)?;
+            if numbers.iter().any(|&number| number < 0) {
+                return Err("Rows and columns can't be negative".to_string());
+            }
+            return Ok(Step::ExpectFace(Position::new(numbers[0] as usize, numbers[1] as usize), face.to_string()));
+        },
+        _ => return Err(format!("Unknown step: {}", command)),
+    };
+    Ok(Step::Events(events))
+}
+
+fn parse_numbers(text: &str, min: usize, max: usize) -> Result<Vec<i32>, String> {
+    let numbers: Vec<i32> = text.split_whitespace()
+        .map(|number| number.parse().map_err(|_| format!("Not a number: {}", number)))
+        .collect::<Result<_, String>>()?;
+    if numbers.len() < min || numbers.len() > max {
+        return Err(format!("Expected {} to {} numbers, got {}", min, max, numbers.len()));
+    }
+    Ok(numbers)
+}
+
+fn button_down(mouse_btn: MouseButton, clicks: u8, x: i32, y: i32) -> Event {
+    Event::MouseButtonDown { timestamp: 0, window_id: 0, which: 0, mouse_btn, clicks, x, y }
+}
+
+fn key_mod(prefix: &str) -> Mod {
+    let mut keymod = Mod::NOMOD;
+    if prefix.contains("C-") {
+        keymod |= Mod::LCTRLMOD;
+    }
+    if prefix.contains("M-") {
+        keymod |= Mod::LALTMOD;
+    }
+    if prefix.contains("S-") {
+        keymod |= Mod::LSHIFTMOD;
+    }
+    keymod
+}
+
+// The text SDL gives for a key pressed, as far as it can be known without
+// the keyboard layout: printable keys alone, and letters with shift
+fn key_text((prefix, keycode): KeyPress) -> Option<String> {
+    let c = char::from_u32(keycode as i32 as u32).filter(|c| (' '..='~').contains(c))?;
+    match prefix {
+        "" => Some(c.to_string()),
+        "S-" if c.is_ascii_lowercase() => Some(c.to_ascii_uppercase().to_string()),
+        _ => None,
+    }
+}
+
+fn key_events(key: KeyPress) -> Vec<Event> {
+    let (prefix, keycode) = key;
+    let mut events = vec!(Event::KeyDown {
+        timestamp: 0,
+        window_id: 0,
+        keycode: Some(keycode),
+        scancode: None,
+        keymod: key_mod(prefix),
+        repeat: false,
+    });
+    if let Some(text) = key_text(key) {
+        events.push(Event::TextInput { timestamp: 0, window_id: 0, text });
+    }
+    events
+}
+
+fn unescape(text: &str) -> Result<String, String> {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('\\') => unescaped.push('\\'),
+            other => return Err(format!("Unknown escape: \\{}", other.map(String::from).unwrap_or_default())),
+        }
+    }
+    Ok(unescaped)
+}
+
+// Writes the events that come in as a script, to replay later
+pub struct Recorder {
+    file: File,
+    // The text the last key pressed gives, which its key line brings back
+    key_text: Option<String>,
+}
+
+// From EDIT0R_RECORD, the path to write a script of this session to
+pub fn recorder_from_env() -> Result<Option<Recorder>, String> {
+    match std::env::var("EDIT0R_RECORD") {
+        Ok(path) => File::create(&path)
+            .map(|file| Some(Recorder { file, key_text: None }))
+            .map_err(|e| format!("{}: {}", path, e)),
+        Err(_) => Ok(None),
+    }
+}
+
+impl Recorder {
+    pub fn record(&mut self, event: &Event) {
+        let line = match event {
+            Event::KeyDown { keycode: Some(keycode), keymod, .. } if !text_mode::is_modifier_key(*keycode) => {
+                let key = (text_mode::modifier_prefix(*keymod), *keycode);
+                self.key_text = key_text(key);
+                format!("key {}", keymap::describe_keys(&[key]))
+            },
+            Event::TextInput { text, .. } => {
+                if self.key_text.take().as_ref() == Some(text) {
+                    return;
+                }
+                format!("type {}", text)
+            },
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left, clicks, x, y, .. } => format!("click {} {} {}", x, y, clicks),
+            Event::MouseButtonDown { mouse_btn: MouseButton::Right, x, y, .. } => format!("right-click {} {}", x, y),
+            Event::MouseMotion { mousestate, x, y, .. } if mousestate.left() => format!("drag {} {}", x, y),
+            Event::MouseButtonUp { mouse_btn: MouseButton::Left, x, y, .. } => format!("release {} {}", x, y),
+            Event::MouseWheel { y, .. } => format!("wheel {}", y),
+            _ => return,
+        };
+        // Written as it goes, so a crash still leaves the steps up to it
+        let _ = writeln!(self.file, "{}", line).and_then(|_| self.file.flush());
+    }
+}
@@ -0,0 +1,136 @@
+use std::ops::Range;
+
+use crate::text_content::Position;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FaceSpan {
+    pub start: Position,
+    pub end: Position,
+    pub face_id: usize,
+}
+
+// The faces of a TextContent as non-overlapping spans ordered by position.
+// Text outside every span has the default face.
+#[derive(Default)]
+pub struct FaceSpans {
+    spans: Vec<FaceSpan>,
+}
+
+impl FaceSpans {
+    pub fn clear(&mut self) {
+        self.spans.clear();
+    }
+
+    // Gives `start..end` face `face_id`, taking over from any spans already there
+    pub fn add(&mut self, start: Position, end: Position, face_id: usize) {
+        if start >= end {
+            return;
+        }
+
+        // Spans are ordered by both ends since they don't overlap
+        let first = self.spans.partition_point(|span| span.end <= start);
+        let last = self.spans.partition_point(|span| span.start < end);
+
+        let mut replacement = vec!();
+        if let Some(before) = self.spans.get(first).filter(|span| first < last && span.start < start) {
+            replacement.push(FaceSpan { end: start, ..*before });
+        }
+        replacement.push(FaceSpan { start, end, face_id });
+        if let Some(after) = last.checked_sub(1).and_then(|i| self.spans.get(i)).filter(|span| first < last && span.end > end) {
+            replacement.push(FaceSpan { start: end, ..*after });
+        }
+        self.spans.splice(first..last, replacement);
+    }
+
+    // The faced byte ranges of line `row`, which is `line_len` bytes long
+    pub fn line_spans(&self, row: usize, line_len: usize) -> Vec<(Range<usize>, usize)> {
+        let first = self.spans.partition_point(|span| span.end.row < row);
+        self.spans[first..]
+            .iter()
+            .take_while(|span| span.start.row <= row)
+            .map(|span| {
+                let from = if span.start.row == row { span.start.col } else { 0 };
+                let to = if span.end.row == row { span.end.col } else { line_len };
+                (from..to.min(line_len), span.face_id)
+            })
+            .filter(|(range, _)| !range.is_empty())
+            .collect()
+    }
+
+    // Keeps the spans on the same text until the minor modes next rerun
+    pub fn after_insert(&mut self, at: Position, end: Position) {
+        for span in &mut self.spans {
+            span.start = span.start.after_insert(at, end);
+            span.end = span.end.after_insert(at, end);
+        }
+    }
+
+    pub fn after_delete(&mut self, start: Position, end: Position) {
+        for span in &mut self.spans {
+            span.start = span.start.after_delete(start, end);
+            span.end = span.end.after_delete(start, end);
+        }
+        self.spans.retain(|span| span.start < span.end);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(row: usize, col: usize) -> Position {
+        Position::new(row, col)
+    }
+
+    #[test]
+    fn span_over_rows_covers_the_lines_between() {
+        let mut faces = FaceSpans::default();
+        faces.add(pos(0, 2), pos(2, 4), 1);
+        assert_eq!(faces.line_spans(0, 6), vec!((2..6, 1)));
+        assert_eq!(faces.line_spans(1, 3), vec!((0..3, 1)));
+        assert_eq!(faces.line_spans(2, 8), vec!((0..4, 1)));
+        assert_eq!(faces.line_spans(3, 5), vec!());
+    }
+
+    #[test]
+    fn adding_inside_a_span_splits_it() {
+        let mut faces = FaceSpans::default();
+        faces.add(pos(0, 0), pos(2, 5), 1);
+        faces.add(pos(1, 1), pos(1, 3), 2);
+        assert_eq!(faces.line_spans(0, 4), vec!((0..4, 1)));
+        assert_eq!(faces.line_spans(1, 6), vec!((0..1, 1), (1..3, 2), (3..6, 1)));
+        assert_eq!(faces.line_spans(2, 5), vec!((0..5, 1)));
+    }
+
+    #[test]
+    fn adding_over_spans_replaces_them() {
+        let mut faces = FaceSpans::default();
+        faces.add(pos(0, 0), pos(0, 3), 1);
+        faces.add(pos(1, 0), pos(1, 3), 2);
+        faces.add(pos(0, 2), pos(1, 1), 3);
+        assert_eq!(faces.line_spans(0, 4), vec!((0..2, 1), (2..4, 3)));
+        assert_eq!(faces.line_spans(1, 3), vec!((0..1, 3), (1..3, 2)));
+    }
+
+    #[test]
+    fn spans_follow_lines_inserted_and_deleted() {
+        let mut faces = FaceSpans::default();
+        faces.add(pos(0, 0), pos(1, 3), 1);
+        // A line broken in two inside the span
+        faces.after_insert(pos(0, 2), pos(1, 0));
+        assert_eq!(faces.line_spans(1, 4), vec!((0..4, 1)));
+        assert_eq!(faces.line_spans(2, 3), vec!((0..3, 1)));
+        // Joined back up again
+        faces.after_delete(pos(0, 2), pos(1, 0));
+        assert_eq!(faces.line_spans(0, 6), vec!((0..6, 1)));
+        assert_eq!(faces.line_spans(1, 3), vec!((0..3, 1)));
+    }
+
+    #[test]
+    fn deleting_a_whole_span_drops_it() {
+        let mut faces = FaceSpans::default();
+        faces.add(pos(0, 1), pos(1, 2), 1);
+        faces.after_delete(pos(0, 0), pos(1, 4));
+        assert_eq!(faces.line_spans(0, 3), vec!());
+    }
+}
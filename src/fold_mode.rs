@@ -0,0 +1,88 @@
+use crate::buffer::Buffer;
+use crate::text_content::Position;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+// Folding away blocks of code, found from the syntax tree of the language
+// mode. Folded blocks show as their first line with an ellipsis after it.
+// The commands work with the mode off too, the mode has their keys.
+pub struct FoldMode {
+}
+
+impl TextMinorMode for FoldMode {
+    fn name(&self) -> &'static str {
+        "fold"
+    }
+}
+
+// Turning the mode off shows everything again
+pub fn fold_mode(_: &mut Global, buffer: &mut Buffer) {
+    buffer.toggle_minor_mode("fold", || Box::new(FoldMode {}));
+    if !buffer.has_minor_mode("fold") {
+        buffer.content.unfold_all();
+    }
+}
+
+// Whether `inner` lies within `outer`
+fn contains(outer: (Position, Position), inner: (Position, Position)) -> bool {
+    outer.0 <= inner.0 && inner.1 <= outer.1
+}
+
+// Folds the smallest block around the cursor's line, or the one around that
+// when it already is
+pub fn fold_block(global: &mut Global, buffer: &mut Buffer) {
+    let row = buffer.content.cursor.row;
+    let ranges = buffer.fold_ranges();
+    let block = ranges.into_iter()
+        .filter(|&(start, end)| start.row <= row && row <= end.row)
+        .filter(|range| !buffer.content.folds().contains(range))
+        .min_by_key(|(start, end)| (end.row - start.row, std::cmp::Reverse(*start)));
+    match block {
+        Some((start, end)) => {
+            buffer.content.fold(start, end);
+            buffer.content.cursor = start;
+            buffer.content.deactivate_mark();
+        },
+        None => global.message("No block to fold here".to_string()),
+    }
+}
+
+pub fn unfold_block(global: &mut Global, buffer: &mut Buffer) {
+    if !buffer.content.unfold(buffer.content.cursor.row) {
+        global.message("Nothing folded here".to_string());
+    }
+}
+
+pub fn toggle_fold(global: &mut Global, buffer: &mut Buffer) {
+    if buffer.content.is_folded(buffer.content.cursor.row) {
+        unfold_block(global, buffer);
+    } else {
+        fold_block(global, buffer);
+    }
+}
+
+// Folds every block that isn't inside another one
+pub fn fold_all(global: &mut Global, buffer: &mut Buffer) {
+    let ranges = buffer.fold_ranges();
+    if ranges.is_empty() {
+        global.message("No blocks to fold".to_string());
+        return;
+    }
+    let outermost: Vec<(Position, Position)> = ranges.iter()
+        .copied()
+        .filter(|&range| !ranges.iter().any(|&other| other != range && contains(other, range)))
+        .collect();
+    for (start, end) in outermost {
+        buffer.content.fold(start, end);
+    }
+    // Rather than unfolding around the cursor again
+    let row = buffer.content.shown_row(buffer.content.cursor.row);
+    if row != buffer.content.cursor.row {
+        buffer.content.cursor = Position::new(row, 0);
+        buffer.content.deactivate_mark();
+    }
+}
+
+pub fn unfold_all(_: &mut Global, buffer: &mut Buffer) {
+    buffer.content.unfold_all();
+}
@@ -0,0 +1,275 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+use sdl2::ttf::Font;
+use sdl2::ttf::Sdl2TtfContext;
+use sdl2::video::Window;
+
+// Used when none of the fonts asked for can be found
+const FONT_PATH: &str = "assets/VeraMono.ttf";
+
+// Point size on a 96 DPI screen with the text scale at 0
+const FONT_SIZE: u16 = 20;
+
+// Where font files are looked for by name when fontconfig can't help.
+// Relative ones are in the home directory.
+const FONT_DIRECTORIES: &[&str] = &[
+    "/usr/share/fonts",
+    "/usr/local/share/fonts",
+    ".local/share/fonts",
+    ".fonts",
+    "/System/Library/Fonts",
+    "/Library/Fonts",
+    "Library/Fonts",
+    "C:\\Windows\\Fonts",
+];
+
+// Fonts tried in order for characters the main font has no glyph for. The
+// ones not installed are skipped.
+const FALLBACK_FONTS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/noto/NotoSansSymbols2-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoEmoji-Regular.ttf",
+    "/System/Library/Fonts/Menlo.ttc",
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+    "/System/Library/Fonts/Apple Symbols.ttf",
+    "C:\\Windows\\Fonts\\consola.ttf",
+    "C:\\Windows\\Fonts\\msgothic.ttc",
+    "C:\\Windows\\Fonts\\seguisym.ttf",
+];
+
+// How much each step of text-scale-adjust grows or shrinks the text
+const TEXT_SCALE_STEP: f32 = 1.2;
+
+// How much bigger than a 96 DPI screen `window` is drawn on, so text comes
+// out the same physical size everywhere
+pub fn display_scale(window: &Window) -> f32 {
+    // Screens where the window has more pixels than points already say how
+    // many pixels a point takes
+    let (drawable_width, _) = window.drawable_size();
+    let (window_width, _) = window.size();
+    let pixel_ratio = drawable_width as f32 / window_width.max(1) as f32;
+    if pixel_ratio > 1.0 {
+        return pixel_ratio;
+    }
+
+    let dpi = window
+        .display_index()
+        .and_then(|index| window.subsystem().display_dpi(index));
+    match dpi {
+        Ok((diagonal_dpi, _, _)) => (diagonal_dpi / 96.0).max(1.0),
+        Err(_) => 1.0,
+    }
+}
+
+// The size to load fonts of `size` points at for a display scale and a
+// number of text-scale-adjust steps
+pub fn point_size(size: u16, display_scale: f32, text_scale: i32) -> u16 {
+    let size = size as f32 * display_scale * TEXT_SCALE_STEP.powi(text_scale);
+    size.round().clamp(4.0, 400.0) as u16
+}
+
+// The fonts asked for, by family name or file path, and their size
+#[derive(Clone, PartialEq)]
+pub struct FontSettings {
+    // The first one is the main font, the rest are tried ahead of the
+    // built-in fallbacks
+    pub families: Vec<String>,
+    pub size: u16,
+}
+
+impl Default for FontSettings {
+    fn default() -> FontSettings {
+        FontSettings {
+            families: vec!(FONT_PATH.to_string()),
+            size: FONT_SIZE,
+        }
+    }
+}
+
+impl FontSettings {
+    // From EDIT0R_FONT, in the form `parse` takes
+    pub fn from_env() -> FontSettings {
+        match std::env::var("EDIT0R_FONT") {
+            Ok(spec) => FontSettings::parse(&spec).unwrap_or_default(),
+            Err(_) => FontSettings::default(),
+        }
+    }
+
+    // Reads fonts separated by commas, the main one optionally ending in its
+    // size like Emacs' font names, e.g. "DejaVu Sans Mono-14, Noto Sans CJK JP"
+    pub fn parse(spec: &str) -> Option<FontSettings> {
+        let mut families: Vec<String> = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|family| !family.is_empty())
+            .map(str::to_string)
+            .collect();
+        let main = families.first_mut()?;
+
+        let mut size = FONT_SIZE;
+        if let Some((family, points)) = main.rsplit_once('-') {
+            if let Ok(points) = points.trim().parse() {
+                size = points;
+                *main = family.trim().to_string();
+            }
+        }
+        Some(FontSettings { families, size })
+    }
+
+    // Back in the form `parse` takes
+    pub fn spec(&self) -> String {
+        let mut families = self.families.clone();
+        if let Some(main) = families.first_mut() {
+            *main = format!("{}-{}", main, self.size);
+        }
+        families.join(", ")
+    }
+}
+
+// The font file for `family`, which can also be the path to one
+fn find_font(family: &str) -> Option<PathBuf> {
+    let path = Path::new(family);
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+    match_with_fontconfig(family).or_else(|| search_font_directories(family))
+}
+
+// Asks fontconfig, which knows about every font installed on Linux
+fn match_with_fontconfig(family: &str) -> Option<PathBuf> {
+    let output = Command::new("fc-match").arg("--format=%{family}\t%{file}").arg(family).output().ok()?;
+    let output = String::from_utf8(output.stdout).ok()?;
+    let (families, file) = output.split_once('\t')?;
+
+    // It always comes up with some font, even when it isn't the one asked for
+    let wanted = normalize(family);
+    families
+        .split(',')
+        .any(|name| normalize(name) == wanted)
+        .then(|| PathBuf::from(file))
+}
+
+// Looks for a file named after `family`, like "DejaVuSansMono.ttf" or
+// "dejavu-sans-mono-regular.otf"
+fn search_font_directories(family: &str) -> Option<PathBuf> {
+    let wanted = normalize(family);
+    let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+    FONT_DIRECTORIES
+        .iter()
+        .map(|directory| home.join(directory))
+        .find_map(|directory| search_directory(&directory, &wanted, 4))
+}
+
+fn search_directory(directory: &Path, wanted: &str, depth: usize) -> Option<PathBuf> {
+    let mut subdirectories = vec!();
+    for entry in std::fs::read_dir(directory).ok()?.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            subdirectories.push(path);
+            continue;
+        }
+        let is_font = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| ["ttf", "otf", "ttc"].contains(&extension.to_lowercase().as_str()));
+        let stem = path.file_stem().map(|stem| normalize(&stem.to_string_lossy())).unwrap_or_default();
+        if is_font && (stem == wanted || stem == format!("{}regular", wanted)) {
+            return Some(path);
+        }
+    }
+    if depth == 0 {
+        return None;
+    }
+    subdirectories.iter().find_map(|subdirectory| search_directory(subdirectory, wanted, depth - 1))
+}
+
+// Font names without case, spaces or dashes, which file names leave out
+fn normalize(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+// The main font and its fallbacks, all at one size
+pub struct Fonts<'ttf> {
+    paths: Vec<PathBuf>,
+    fonts: Vec<Font<'ttf, 'static>>,
+    // Which font draws each character, remembered since looking it up asks
+    // every font in turn
+    glyph_fonts: RefCell<HashMap<char, usize>>,
+}
+
+impl<'ttf> Fonts<'ttf> {
+    // Loads the fonts asked for that can be found, followed by the built-in
+    // fallbacks that are installed
+    pub fn load(ttf: &'ttf Sdl2TtfContext, settings: &FontSettings, size: u16) -> Result<Fonts<'ttf>, String> {
+        let mut paths = vec!();
+        for family in &settings.families {
+            match find_font(family) {
+                Some(path) => paths.push(path),
+                None => eprintln!("Font not found: {}", family),
+            }
+        }
+        if paths.is_empty() {
+            paths.push(PathBuf::from(FONT_PATH));
+        }
+        paths.extend(FALLBACK_FONTS.iter().map(PathBuf::from).filter(|path| path.is_file()));
+        Fonts::load_paths(ttf, paths, size)
+    }
+
+    // The same fonts at another size
+    pub fn at_size(&self, ttf: &'ttf Sdl2TtfContext, size: u16) -> Result<Fonts<'ttf>, String> {
+        Fonts::load_paths(ttf, self.paths.clone(), size)
+    }
+
+    fn load_paths(ttf: &'ttf Sdl2TtfContext, paths: Vec<PathBuf>, size: u16) -> Result<Fonts<'ttf>, String> {
+        let mut fonts = vec!();
+        let mut loaded = vec!();
+        for (i, path) in paths.into_iter().enumerate() {
+            match ttf.load_font(&path, size) {
+                Ok(font) => {
+                    fonts.push(font);
+                    loaded.push(path);
+                },
+                // Only the main font has to be there
+                Err(e) if i == 0 => return Err(format!("{}: {}", path.display(), e)),
+                Err(e) => eprintln!("Skipping fallback font {}: {}", path.display(), e),
+            }
+        }
+        Ok(Fonts {
+            paths: loaded,
+            fonts,
+            glyph_fonts: RefCell::new(HashMap::new()),
+        })
+    }
+
+    // The size of a character cell, which is the main font's 'a'
+    pub fn cell_size(&self) -> Result<(u32, u32), String> {
+        self.fonts[0].size_of_char('a').map_err(|e| e.to_string())
+    }
+
+    fn font_index(&self, c: char) -> Option<usize> {
+        if let Some(&index) = self.glyph_fonts.borrow().get(&c) {
+            return Some(index);
+        }
+        let index = self.fonts.iter().position(|font| font.find_glyph(c).is_some())?;
+        self.glyph_fonts.borrow_mut().insert(c, index);
+        Some(index)
+    }
+
+    // The first font with glyphs for all of `text`, or for its first
+    // character, or else the main font to draw its missing glyph boxes
+    pub fn for_text(&self, text: &str) -> &Font<'ttf, 'static> {
+        let first = text.chars().next().and_then(|c| self.font_index(c)).unwrap_or(0);
+        let all = (first..self.fonts.len())
+            .find(|&index| text.chars().all(|c| self.fonts[index].find_glyph(c).is_some()))
+            .unwrap_or(first);
+        &self.fonts[all]
+    }
+}
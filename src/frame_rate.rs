@@ -0,0 +1,33 @@
+use std::time::Duration;
+use std::time::Instant;
+
+// Frames drawn per second while animating, unless EDIT0R_MAX_FPS says otherwise
+const DEFAULT_MAX_FPS: u32 = 60;
+
+// How often frames are drawn. Frames are only drawn when something changed,
+// so this only limits animations like smooth scrolling.
+pub struct FrameRate {
+    // Presenting a frame waits for the display to refresh, which stops tearing
+    pub vsync: bool,
+    pub max_fps: u32,
+}
+
+impl FrameRate {
+    // From EDIT0R_VSYNC, which is "on" (the default) or "off", and
+    // EDIT0R_MAX_FPS
+    pub fn from_env() -> FrameRate {
+        let vsync = std::env::var("EDIT0R_VSYNC").map_or(true, |setting| setting != "off");
+        let max_fps = std::env::var("EDIT0R_MAX_FPS")
+            .ok()
+            .and_then(|setting| setting.trim().parse().ok())
+            .filter(|&fps| fps > 0)
+            .unwrap_or(DEFAULT_MAX_FPS);
+        FrameRate { vsync, max_fps }
+    }
+
+    // How long until the next frame after one drawn at `last_frame` is due
+    pub fn until_next_frame(&self, last_frame: Instant) -> Duration {
+        let interval = Duration::from_secs(1) / self.max_fps;
+        interval.saturating_sub(last_frame.elapsed())
+    }
+}
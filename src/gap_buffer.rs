@@ -0,0 +1,140 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+const MIN_GAP: usize = 1024;
+
+// UTF-8 text with a movable gap at the last edit, so typing in one place
+// doesn't shuffle the rest of the buffer around. Offsets are logical byte
+// offsets that skip over the gap.
+pub struct GapBuffer {
+    bytes: Vec<u8>,
+    gap: Range<usize>,
+    // Offset of the first byte of every line, always starting with 0
+    line_starts: Vec<usize>,
+}
+
+impl GapBuffer {
+    pub fn new(text: &str) -> GapBuffer {
+        let mut bytes = Vec::with_capacity(text.len() + MIN_GAP);
+        bytes.extend_from_slice(text.as_bytes());
+        let len = bytes.len();
+        bytes.resize(len + MIN_GAP, 0);
+
+        let mut line_starts = vec!(0);
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+
+        GapBuffer {
+            bytes,
+            gap: len..len + MIN_GAP,
+            line_starts,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len() - self.gap.len()
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    pub fn line_start(&self, row: usize) -> usize {
+        self.line_starts[row]
+    }
+
+    // The end of line `row`, before its newline
+    pub fn line_end(&self, row: usize) -> usize {
+        match self.line_starts.get(row + 1) {
+            Some(next) => next - 1,
+            None => self.len(),
+        }
+    }
+
+    // The row of the line containing byte `offset`
+    pub fn row_of(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    pub fn line(&self, row: usize) -> Cow<'_, str> {
+        self.slice(self.line_start(row)..self.line_end(row))
+    }
+
+    // Borrows the text when the range doesn't straddle the gap
+    pub fn slice(&self, range: Range<usize>) -> Cow<'_, str> {
+        let bytes: Cow<[u8]> = if range.end <= self.gap.start {
+            Cow::Borrowed(&self.bytes[range])
+        } else if range.start >= self.gap.start {
+            let gap_len = self.gap.len();
+            Cow::Borrowed(&self.bytes[range.start + gap_len..range.end + gap_len])
+        } else {
+            let mut joined = self.bytes[range.start..self.gap.start].to_vec();
+            joined.extend_from_slice(&self.bytes[self.gap.end..range.end + self.gap.len()]);
+            Cow::Owned(joined)
+        };
+
+        // Only whole strings ever go in and edits land on char boundaries
+        match bytes {
+            Cow::Borrowed(bytes) => Cow::Borrowed(std::str::from_utf8(bytes).expect("Gap buffer holds UTF-8")),
+            Cow::Owned(bytes) => Cow::Owned(String::from_utf8(bytes).expect("Gap buffer holds UTF-8")),
+        }
+    }
+
+    // The contiguous bytes from `offset` up to the gap or the end of the text
+    pub fn chunk_at(&self, offset: usize) -> &[u8] {
+        if offset < self.gap.start {
+            &self.bytes[offset..self.gap.start]
+        } else {
+            &self.bytes[(offset + self.gap.len()).min(self.bytes.len())..]
+        }
+    }
+
+    fn move_gap(&mut self, offset: usize) {
+        let gap_len = self.gap.len();
+        if offset < self.gap.start {
+            self.bytes.copy_within(offset..self.gap.start, offset + gap_len);
+        } else if offset > self.gap.start {
+            self.bytes.copy_within(self.gap.end..offset + gap_len, self.gap.start);
+        }
+        self.gap = offset..offset + gap_len;
+    }
+
+    fn ensure_gap(&mut self, needed: usize) {
+        if self.gap.len() >= needed {
+            return;
+        }
+
+        let grow = needed.max(self.bytes.len() / 2).max(MIN_GAP);
+        let tail = self.gap.end..self.bytes.len();
+        self.bytes.resize(self.bytes.len() + grow, 0);
+        self.bytes.copy_within(tail, self.gap.end + grow);
+        self.gap.end += grow;
+    }
+
+    pub fn insert(&mut self, offset: usize, text: &str) {
+        self.ensure_gap(text.len());
+        self.move_gap(offset);
+        self.bytes[offset..offset + text.len()].copy_from_slice(text.as_bytes());
+        self.gap.start += text.len();
+
+        let row = self.row_of(offset);
+        for start in &mut self.line_starts[row + 1..] {
+            *start += text.len();
+        }
+        let new_starts = text.match_indices('\n').map(|(i, _)| offset + i + 1);
+        self.line_starts.splice(row + 1..row + 1, new_starts);
+    }
+
+    pub fn delete(&mut self, range: Range<usize>) -> String {
+        let removed = self.slice(range.clone()).into_owned();
+        self.move_gap(range.end);
+        self.gap.start = range.start;
+
+        let first = self.row_of(range.start) + 1;
+        let last = self.line_starts.partition_point(|&start| start <= range.end);
+        self.line_starts.drain(first..last);
+        for start in &mut self.line_starts[first..] {
+            *start -= range.len();
+        }
+        removed
+    }
+}
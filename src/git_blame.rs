@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+use std::time::SystemTime;
+
+use crate::buffer::Buffer;
+use crate::buffer_local::Variable;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+use crate::undo::Edit;
+use crate::undo::end_of;
+
+// Says who last changed a line and when at the end of it, like
+//
+//     let x = 1;    1e49940a Alice, 3 days ago
+//
+// from git blame of the file as saved, which runs off the main thread when
+// the mode is turned on and again after each save. Lines edited since show
+// as not committed.
+
+// Annotates every line on screen rather than only the cursor's
+const EVERY_LINE: Variable<bool> = Variable::new("git-blame-every-line", false);
+
+const UNCOMMITTED: &str = "0000000000000000000000000000000000000000";
+
+struct Commit {
+    hash: String,
+    author: String,
+    // Seconds since the epoch
+    time: u64,
+}
+
+// The commit each line of the file came from, by index into commits
+struct Blame {
+    commits: Vec<Commit>,
+    lines: Vec<Option<usize>>,
+}
+
+// Runs git blame on the file at `path`
+fn blame(path: &Path) -> Result<Blame, String> {
+    let directory = path.parent().filter(|directory| !directory.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = path.file_name().ok_or("Not a file")?;
+    let output = Command::new("git")
+        .arg("-C").arg(directory)
+        .args(["blame", "--porcelain", "--"])
+        .arg(name)
+        .output()
+        .map_err(|e| format!("git: {}", e))?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(error.lines().next().unwrap_or("git blame failed").to_string());
+    }
+    Ok(parse_porcelain(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// Reads git blame --porcelain, where each line of the file comes after a
+// header naming its commit, with what's known of the commit the first time
+fn parse_porcelain(text: &str) -> Blame {
+    let mut commits: Vec<Commit> = vec!();
+    let mut by_hash: HashMap<String, usize> = HashMap::new();
+    let mut lines = vec!();
+    // The commit and final line of the header being read
+    let mut current: Option<(usize, usize)> = None;
+    for line in text.lines() {
+        if line.starts_with('\t') {
+            if let Some((commit, row)) = current.take() {
+                if lines.len() <= row {
+                    lines.resize(row + 1, None);
+                }
+                lines[row] = (commits[commit].hash != UNCOMMITTED).then_some(commit);
+            }
+            continue;
+        }
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        match (current, key) {
+            (None, hash) if hash.len() == 40 => {
+                let final_line: usize = value.split(' ').nth(1).and_then(|n| n.parse().ok()).unwrap_or(1);
+                let index = *by_hash.entry(hash.to_string()).or_insert_with(|| {
+                    commits.push(Commit { hash: hash.to_string(), author: String::new(), time: 0 });
+                    commits.len() - 1
+                });
+                current = Some((index, final_line.saturating_sub(1)));
+            },
+            (Some((commit, _)), "author") => commits[commit].author = value.to_string(),
+            (Some((commit, _)), "author-time") => commits[commit].time = value.parse().unwrap_or(0),
+            _ => {},
+        }
+    }
+    Blame { commits, lines }
+}
+
+// How long ago `time` was, roughly
+fn age(time: u64) -> String {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    let seconds = now.saturating_sub(time);
+    let (count, unit) = match seconds {
+        0..=59 => return "just now".to_string(),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86_399 => (seconds / 3600, "hour"),
+        86_400..=2_591_999 => (seconds / 86_400, "day"),
+        2_592_000..=31_535_999 => (seconds / 2_592_000, "month"),
+        _ => (seconds / 31_536_000, "year"),
+    };
+    format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" })
+}
+
+pub struct GitBlameMode {
+    path: PathBuf,
+    commits: Vec<Commit>,
+    // The commit of each row, moved along with edits since followed_at
+    lines: Vec<Option<usize>>,
+    followed_at: usize,
+    // The blame being worked out, and whether it's done
+    pending: Option<Receiver<Result<Blame, String>>>,
+    ready: Arc<AtomicBool>,
+    error: Option<String>,
+}
+
+impl GitBlameMode {
+    fn new(path: PathBuf) -> GitBlameMode {
+        let mut mode = GitBlameMode {
+            path,
+            commits: vec!(),
+            lines: vec!(),
+            followed_at: 0,
+            pending: None,
+            ready: Arc::new(AtomicBool::new(false)),
+            error: None,
+        };
+        mode.fetch();
+        mode
+    }
+
+    // Starts git blame on its own thread
+    fn fetch(&mut self) {
+        let (sender, receiver) = mpsc::channel();
+        let path = self.path.clone();
+        let ready = self.ready.clone();
+        thread::spawn(move || {
+            let _ = sender.send(blame(&path));
+            ready.store(true, Ordering::SeqCst);
+        });
+        self.pending = Some(receiver);
+    }
+
+    // Lines an edit touched are no longer the commit's
+    fn follow(&mut self, edit: &Edit) {
+        let (start, end) = match edit {
+            Edit::Insert { at, text } => (*at, end_of(*at, text)),
+            Edit::Delete { at, text } => (*at, end_of(*at, text)),
+        };
+        if start.row >= self.lines.len() {
+            return;
+        }
+        let rows = end.row - start.row;
+        match edit {
+            Edit::Insert { .. } => {
+                self.lines.splice(start.row + 1..start.row + 1, std::iter::repeat_n(None, rows));
+            },
+            Edit::Delete { .. } => {
+                self.lines.drain(start.row + 1..(end.row + 1).min(self.lines.len()));
+            },
+        }
+        self.lines[start.row] = None;
+    }
+}
+
+impl TextMinorMode for GitBlameMode {
+    fn name(&self) -> &'static str {
+        "git-blame"
+    }
+
+    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
+        let received = match self.pending.as_ref().map(Receiver::try_recv) {
+            Some(Ok(result)) => Some(result),
+            Some(Err(TryRecvError::Disconnected)) => Some(Err("git blame stopped".to_string())),
+            _ => None,
+        };
+        if let Some(result) = received {
+            self.pending = None;
+            self.ready.store(false, Ordering::SeqCst);
+            match result {
+                Ok(blame) => {
+                    self.commits = blame.commits;
+                    self.lines = blame.lines;
+                    self.error = None;
+                },
+                Err(e) => {
+                    global.message(format!("Blaming failed: {}", e));
+                    self.error = Some(e);
+                },
+            }
+            // The blame is of the file as saved, so any edits since are new
+            self.followed_at = content.change_count();
+        }
+        for edit in content.changes_since(self.followed_at) {
+            self.follow(edit);
+        }
+        self.followed_at = content.change_count();
+    }
+
+    fn after_save(&mut self, _content: &mut TextContent, path: &Path) {
+        self.path = path.to_path_buf();
+        self.fetch();
+    }
+
+    fn line_annotation(&self, content: &TextContent, row: usize) -> Option<(String, &'static str)> {
+        if self.pending.is_some() || self.error.is_some() {
+            return None;
+        }
+        if row != content.cursor.row && !content.locals.get(&EVERY_LINE) {
+            return None;
+        }
+        let text = match self.lines.get(row).copied().flatten().map(|commit| &self.commits[commit]) {
+            Some(commit) => format!("{} {}, {}", &commit.hash[..8], commit.author, age(commit.time)),
+            None => "Not committed yet".to_string(),
+        };
+        Some((text, "git-blame"))
+    }
+
+    fn has_results(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    fn is_busy(&self) -> bool {
+        self.pending.is_some()
+    }
+}
+
+// Turns saying who last changed the cursor's line on or off
+pub fn git_blame_mode(global: &mut Global, buffer: &mut Buffer) {
+    if buffer.has_minor_mode("git-blame") {
+        buffer.minor_modes.retain(|mode| mode.name() != "git-blame");
+        buffer.content.modified = true;
+        return;
+    }
+    match buffer.path.clone() {
+        Some(path) => {
+            buffer.minor_modes.push(Box::new(GitBlameMode::new(path)));
+            buffer.content.modified = true;
+        },
+        None => global.message("Buffer has no file to blame".to_string()),
+    }
+}
+
+// Switches between annotating the cursor's line and every line
+pub fn git_blame_toggle_every_line(_: &mut Global, buffer: &mut Buffer) {
+    let every_line = !buffer.content.locals.get(&EVERY_LINE);
+    buffer.content.locals.set(&EVERY_LINE, every_line);
+    buffer.content.modified = true;
+}
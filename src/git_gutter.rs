@@ -0,0 +1,193 @@
+use std::path::Path;
+use std::process::Command;
+
+use crate::buffer::Buffer;
+use crate::buffer_local::Variable;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+// Marks the lines changed since the last commit in the gutter, from what
+// git diff says of the file as saved. The marks move along with edits, and
+// are brought up to date on every save or with git-gutter-refresh.
+
+#[derive(Clone, Copy)]
+enum Change {
+    Added,
+    Modified,
+    // Lines taken out after this one
+    Deleted,
+}
+
+// Rows `start` up to `end` changed in the same way
+#[derive(Clone, Copy)]
+struct Hunk {
+    start: usize,
+    end: usize,
+    change: Change,
+}
+
+// The hunks as of change_count() being `at`
+#[derive(Clone)]
+struct Hunks {
+    hunks: Vec<Hunk>,
+    at: usize,
+}
+
+const HUNKS: Variable<Hunks> = Variable::new("git-gutter-hunks", Hunks { hunks: Vec::new(), at: 0 });
+
+// The buffer's hunks, moved along with the edits since they were found
+fn hunks(content: &TextContent) -> Vec<Hunk> {
+    let Hunks { mut hunks, at } = content.locals.get(&HUNKS);
+    for edit in content.changes_since(at) {
+        for hunk in &mut hunks {
+            hunk.start = edit.shift(Position::new(hunk.start, 0), false).row;
+            hunk.end = edit.shift(Position::new(hunk.end, 0), true).row.max(hunk.start + 1);
+        }
+    }
+    hunks
+}
+
+// Keeps `hunks` as they are now, so the edits they were moved along with
+// can be dropped
+fn store_hunks(content: &mut TextContent, hunks: Vec<Hunk>) {
+    let at = content.change_count();
+    content.locals.set(&HUNKS, Hunks { hunks, at });
+}
+
+fn set_hunks(content: &mut TextContent, hunks: Vec<Hunk>) {
+    store_hunks(content, hunks);
+    content.modified = true;
+}
+
+// What git diff -U0 says changed between the last commit and the file
+fn diff_against_head(path: &Path) -> Result<Vec<Hunk>, String> {
+    let directory = path.parent().filter(|directory| !directory.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = path.file_name().ok_or("Not a file")?;
+    let output = Command::new("git")
+        .arg("-C").arg(directory)
+        .args(["diff", "--no-color", "--no-ext-diff", "-U0", "HEAD", "--"])
+        .arg(name)
+        .output()
+        .map_err(|e| format!("git: {}", e))?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(error.lines().next().unwrap_or("git diff failed").to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().filter_map(parse_hunk_header).collect())
+}
+
+// Reads "@@ -old,count +new,count @@", where a missing count is one
+fn parse_hunk_header(line: &str) -> Option<Hunk> {
+    let ranges = line.strip_prefix("@@ -")?.split(" @@").next()?;
+    let (old, new) = ranges.split_once(" +")?;
+    let count = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((line, count)) => Some((line.parse().ok()?, count.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let (_, old_count) = count(old)?;
+    let (new_line, new_count) = count(new)?;
+    let (start, end, change) = match (old_count, new_count) {
+        // Taken out after line new_line, which is 0 at the top
+        (_, 0) => (new_line.saturating_sub(1), new_line.max(1), Change::Deleted),
+        (0, _) => (new_line - 1, new_line - 1 + new_count, Change::Added),
+        _ => (new_line - 1, new_line - 1 + new_count, Change::Modified),
+    };
+    Some(Hunk { start, end, change })
+}
+
+pub struct GitGutterMode {
+    // The hunks as of the last time modify ran
+    hunks: Vec<Hunk>,
+}
+
+impl TextMinorMode for GitGutterMode {
+    fn name(&self) -> &'static str {
+        "git-gutter"
+    }
+
+    fn modify(&mut self, _global: &mut Global, content: &mut TextContent) {
+        self.hunks = hunks(content);
+        store_hunks(content, self.hunks.clone());
+    }
+
+    fn after_save(&mut self, content: &mut TextContent, path: &Path) {
+        // Outside a repository there's just nothing to mark
+        set_hunks(content, diff_against_head(path).unwrap_or_default());
+    }
+
+    fn line_mark(&self, row: usize) -> Option<&'static str> {
+        let hunk = self.hunks.iter().find(|hunk| hunk.start <= row && row < hunk.end)?;
+        Some(match hunk.change {
+            Change::Added => "git-gutter-added",
+            Change::Modified => "git-gutter-modified",
+            Change::Deleted => "git-gutter-deleted",
+        })
+    }
+}
+
+// Diffs the file again, saying why when it can't
+fn refresh(global: &mut Global, buffer: &mut Buffer) -> bool {
+    let path = match &buffer.path {
+        Some(path) => path.clone(),
+        None => {
+            global.message("Buffer has no file to diff".to_string());
+            return false;
+        },
+    };
+    match diff_against_head(&path) {
+        Ok(hunks) => {
+            set_hunks(&mut buffer.content, hunks);
+            true
+        },
+        Err(e) => {
+            global.message(format!("Diffing failed: {}", e));
+            false
+        },
+    }
+}
+
+// Turns marking the lines changed since the last commit on or off
+pub fn git_gutter_mode(global: &mut Global, buffer: &mut Buffer) {
+    if buffer.has_minor_mode("git-gutter") || refresh(global, buffer) {
+        buffer.toggle_minor_mode("git-gutter", || Box::new(GitGutterMode { hunks: vec!() }));
+        buffer.view.line_marks = buffer.has_minor_mode("git-gutter");
+    }
+}
+
+// Brings the marks up to date with the file as saved, for when git changed
+// what it's compared to
+pub fn git_gutter_refresh(global: &mut Global, buffer: &mut Buffer) {
+    refresh(global, buffer);
+}
+
+fn step_hunk(global: &mut Global, buffer: &mut Buffer, forward: bool) {
+    let content = &mut buffer.content;
+    let row = content.cursor.row;
+    let mut hunks = hunks(content);
+    hunks.sort_by_key(|hunk| hunk.start);
+    let found = if forward {
+        hunks.iter().find(|hunk| hunk.start > row)
+    } else {
+        hunks.iter().rev().find(|hunk| hunk.start < row)
+    };
+    match found {
+        Some(hunk) => {
+            content.cursor = Position::new(hunk.start, 0);
+            content.deactivate_mark();
+        },
+        None => global.message(if forward { "No later changes" } else { "No earlier changes" }.to_string()),
+    }
+}
+
+// Goes to the start of the next lines changed since the last commit
+pub fn git_gutter_next_hunk(global: &mut Global, buffer: &mut Buffer) {
+    step_hunk(global, buffer, true);
+}
+
+pub fn git_gutter_previous_hunk(global: &mut Global, buffer: &mut Buffer) {
+    step_hunk(global, buffer, false);
+}
@@ -0,0 +1,548 @@
+use std::fs;
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use crate::buffer::Buffer;
+use crate::buffer_local::Variable;
+use crate::comint;
+use crate::commands;
+use crate::minibuffer::Minibuffer;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+// The state of a git repository like Emacs' magit shows it, with what's
+// changed in each file under it:
+//
+//     Head:     main 1e49940 Fix the tab bar
+//
+//     Unstaged changes (1)
+//     modified   src/main.rs
+//     @@ -10,1 +10,1 @@
+//     -    old();
+//     +    new();
+//
+//     Staged changes (0)
+//
+// Keys in the git-status keymap section stage, unstage or discard the file
+// or hunk on the cursor's line, commit what's staged and push.
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    Untracked,
+    Unstaged,
+    Staged,
+}
+
+// A file or one of its hunks, as listed on `rows`
+#[derive(Clone)]
+struct Item {
+    rows: Range<usize>,
+    section: Section,
+    path: String,
+    // The file's diff header followed by the hunk, for a hunk
+    patch: Option<String>,
+}
+
+// The repository a buffer shows and what's on its rows
+#[derive(Clone)]
+struct Status {
+    root: Option<PathBuf>,
+    items: Vec<Item>,
+}
+
+const STATUS: Variable<Status> = Variable::new("git-status", Status { root: None, items: Vec::new() });
+
+// Where a push running off the main thread says how it went
+#[derive(Clone)]
+struct Pusher {
+    results: Sender<Result<String, String>>,
+    running: Arc<AtomicBool>,
+}
+
+const PUSHER: Variable<Option<Pusher>> = Variable::new("git-status-pusher", None);
+
+// Runs git in `root` with `input` on its stdin, returning what it printed
+fn git(root: &Path, args: &[&str], input: Option<&str>) -> Result<String, String> {
+    let mut child = Command::new("git")
+        .arg("-C").arg(root)
+        .args(["-c", "core.quotePath=false"])
+        .args(args)
+        // Asking for a password would hang with nowhere to type it
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("git: {}", e))?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes()).map_err(|e| format!("git: {}", e))?;
+    }
+    let output = child.wait_with_output().map_err(|e| format!("git: {}", e))?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        let error = error.lines().rfind(|line| !line.trim().is_empty()).unwrap_or("");
+        return Err(if error.is_empty() { format!("git {} failed", args[0]) } else { error.trim().to_string() });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// The top of the repository `directory` is in
+fn repository_root(directory: &Path) -> Result<PathBuf, String> {
+    let root = git(directory, &["rev-parse", "--show-toplevel"], None)?;
+    Ok(PathBuf::from(root.trim_end()))
+}
+
+struct FileDiff {
+    path: String,
+    status: &'static str,
+    // From "diff --git" up to the first hunk
+    header: String,
+    hunks: Vec<String>,
+}
+
+// Splits the output of git diff into files and their hunks
+fn parse_diff(text: &str) -> Vec<FileDiff> {
+    let mut files: Vec<FileDiff> = vec!();
+    for line in text.split_inclusive('\n') {
+        if let Some(names) = line.strip_prefix("diff --git ") {
+            // "a/name b/name", taken apart in the middle until a line below names it
+            let names = names.trim_end();
+            let path = names.get(names.len() / 2 + 3..).unwrap_or(names).to_string();
+            files.push(FileDiff { path, status: "modified", header: String::new(), hunks: vec!() });
+            files.last_mut().unwrap().header.push_str(line);
+            continue;
+        }
+        let file = match files.last_mut() {
+            Some(file) => file,
+            None => continue,
+        };
+        if line.starts_with("@@") {
+            file.hunks.push(line.to_string());
+        } else if let Some(hunk) = file.hunks.last_mut() {
+            hunk.push_str(line);
+        } else {
+            file.header.push_str(line);
+            let field = line.trim_end();
+            if field.starts_with("new file mode") {
+                file.status = "new file";
+            } else if field.starts_with("deleted file mode") {
+                file.status = "deleted";
+            } else if let Some(to) = field.strip_prefix("rename to ") {
+                file.status = "renamed";
+                file.path = to.to_string();
+            } else if let Some(path) = field.strip_prefix("+++ b/") {
+                file.path = path.to_string();
+            } else if let Some(path) = field.strip_prefix("--- a/") {
+                file.path = path.to_string();
+            }
+        }
+    }
+    files
+}
+
+// The text of the status of the repository at `root`, and what's on each row
+fn status_text(root: &Path) -> Result<(String, Vec<Item>), String> {
+    let branch = git(root, &["symbolic-ref", "--short", "-q", "HEAD"], None)
+        .map_or_else(|_| "(detached)".to_string(), |branch| branch.trim_end().to_string());
+    let commit = git(root, &["log", "-1", "--format=%h %s"], None)
+        .map_or_else(|_| "No commits yet".to_string(), |commit| commit.trim_end().to_string());
+    let untracked = git(root, &["ls-files", "--others", "--exclude-standard", "-z"], None)?;
+    let unstaged = git(root, &["diff", "--no-color", "--no-ext-diff"], None)?;
+    let staged = git(root, &["diff", "--cached", "--no-color", "--no-ext-diff"], None)?;
+
+    let mut lines = vec!(format!("Head:     {} {}", branch, commit));
+    let mut items = vec!();
+    let untracked: Vec<&str> = untracked.split('\0').filter(|path| !path.is_empty()).collect();
+    if !untracked.is_empty() {
+        lines.push(String::new());
+        lines.push(format!("Untracked files ({})", untracked.len()));
+        for path in untracked {
+            let row = lines.len();
+            lines.push(path.to_string());
+            items.push(Item { rows: row..row + 1, section: Section::Untracked, path: path.to_string(), patch: None });
+        }
+    }
+    for (section, heading, diff) in [(Section::Unstaged, "Unstaged changes", unstaged), (Section::Staged, "Staged changes", staged)] {
+        let files = parse_diff(&diff);
+        if files.is_empty() {
+            continue;
+        }
+        lines.push(String::new());
+        lines.push(format!("{} ({})", heading, files.len()));
+        for file in files {
+            let row = lines.len();
+            lines.push(format!("{:<11}{}", file.status, file.path));
+            items.push(Item { rows: row..row + 1, section, path: file.path.clone(), patch: None });
+            for hunk in file.hunks {
+                let row = lines.len();
+                lines.extend(hunk.lines().map(str::to_string));
+                let rows = row..lines.len();
+                let patch = Some(format!("{}{}", file.header, hunk));
+                items.push(Item { rows, section, path: file.path.clone(), patch });
+            }
+        }
+    }
+    if items.is_empty() {
+        lines.push(String::new());
+        lines.push("Nothing to commit, working tree clean".to_string());
+    }
+    Ok((lines.join("\n"), items))
+}
+
+// Shows the status of the repository at `root` in `content`, keeping the
+// cursor on its row
+fn fill(content: &mut TextContent, root: &Path) -> Result<(), String> {
+    let (text, items) = status_text(root)?;
+    let row = content.cursor.row;
+    let end = content.end_of_buffer();
+    content.replace_range(Position::new(0, 0), end, &text);
+    content.cursor = Position::new(row.min(content.line_count() - 1), 0);
+    content.deactivate_mark();
+    content.locals.set(&STATUS, Status { root: Some(root.to_path_buf()), items });
+    content.modified = true;
+    Ok(())
+}
+
+pub struct GitStatusMode {
+    pushed: Receiver<Result<String, String>>,
+    pushing: Arc<AtomicBool>,
+}
+
+impl TextMinorMode for GitStatusMode {
+    fn name(&self) -> &'static str {
+        "git-status"
+    }
+
+    fn is_major(&self) -> bool {
+        true
+    }
+
+    fn poll(&mut self, global: &mut Global, _content: &mut TextContent) {
+        if let Ok(result) = self.pushed.try_recv() {
+            self.pushing.store(false, Ordering::SeqCst);
+            match result {
+                Ok(branch) => global.message(format!("Pushed {}", branch)),
+                Err(e) => global.message(format!("Pushing failed: {}", e)),
+            }
+        }
+    }
+
+    fn is_busy(&self) -> bool {
+        self.pushing.load(Ordering::SeqCst)
+    }
+
+    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
+        let heading_face_id = global.faces.get_face_id("git-status-heading");
+        let file_face_id = global.faces.get_face_id("git-status-file");
+        let hunk_face_id = global.faces.get_face_id("diff-hunk-header");
+        let added_face_id = global.faces.get_face_id("diff-added");
+        let removed_face_id = global.faces.get_face_id("diff-removed");
+
+        let items = content.locals.get(&STATUS).items;
+        let mut spans = vec!();
+        let mut item = items.iter().peekable();
+        for row in 0..content.line_count() {
+            while item.peek().is_some_and(|item| item.rows.end <= row) {
+                item.next();
+            }
+            let line = content.line(row);
+            let face_id = match item.peek() {
+                Some(item) if item.rows.contains(&row) => match (item.patch.is_some(), line.chars().next()) {
+                    (false, _) => file_face_id,
+                    (true, _) if row == item.rows.start => hunk_face_id,
+                    (true, Some('+')) => added_face_id,
+                    (true, Some('-')) => removed_face_id,
+                    _ => None,
+                },
+                _ => heading_face_id,
+            };
+            // Only the label of the head line
+            let end = if row == 0 { line.find(' ').unwrap_or(line.len()) } else { line.len() };
+            if let Some(face_id) = face_id {
+                spans.push((row, end, face_id));
+            }
+        }
+        for (row, end, face_id) in spans {
+            content.faces.add(Position::new(row, 0), Position::new(row, end), face_id);
+        }
+    }
+}
+
+fn find_status_buffer(global: &mut Global, root: &Path) -> Option<String> {
+    global.buffers
+        .iter_mut()
+        .find(|buffer| buffer.content.locals.get(&STATUS).root.as_deref() == Some(root))
+        .map(|buffer| buffer.name.clone())
+}
+
+// A buffer showing the status of the repository at `root`
+fn open(root: &Path) -> Result<Buffer, String> {
+    let (results, pushed) = mpsc::channel();
+    let pushing = Arc::new(AtomicBool::new(false));
+    let name = root.file_name().map_or_else(|| root.display().to_string(), |name| name.to_string_lossy().into_owned());
+    let mode = GitStatusMode { pushed, pushing: pushing.clone() };
+    let mut buffer = Buffer::new(&format!("*git-status {}*", name), TextContent::new(""), vec!(Box::new(mode)));
+    buffer.read_only = true;
+    buffer.content.locals.set(&PUSHER, Some(Pusher { results, running: pushing }));
+    fill(&mut buffer.content, root)?;
+    buffer.content.cursor = Position::new(0, 0);
+    Ok(buffer)
+}
+
+// Shows the status of the repository the current file or directory is in
+pub fn git_status(global: &mut Global, buffer: &mut Buffer) {
+    let directory = comint::default_directory(buffer).unwrap_or_else(|| PathBuf::from("."));
+    let root = match repository_root(&directory) {
+        Ok(root) => root,
+        Err(e) => {
+            global.message(format!("No repository: {}", e));
+            return;
+        },
+    };
+    if buffer.content.locals.get(&STATUS).root.as_deref() == Some(root.as_path()) {
+        git_status_refresh(global, buffer);
+        return;
+    }
+    if let Some(name) = find_status_buffer(global, &root) {
+        if let Some(shown) = global.buffers.find_mut(&name) {
+            if let Err(e) = fill(&mut shown.content, &root) {
+                global.message(format!("Git status failed: {}", e));
+            }
+        }
+        global.buffers.switch_to(&name);
+        return;
+    }
+    match open(&root) {
+        Ok(status) => {
+            let name = global.buffers.add(status);
+            global.buffers.switch_to(&name);
+        },
+        Err(e) => global.message(format!("Git status failed: {}", e)),
+    }
+}
+
+// The repository `buffer` shows, saying so when it doesn't show one
+fn shown_root(global: &mut Global, buffer: &Buffer) -> Option<PathBuf> {
+    let root = buffer.content.locals.get(&STATUS).root;
+    if root.is_none() {
+        global.message("Not a git status buffer".to_string());
+    }
+    root
+}
+
+fn refresh(global: &mut Global, buffer: &mut Buffer, root: &Path) {
+    // The buffer is read-only to typing, not to this
+    buffer.content.inhibit_read_only();
+    if let Err(e) = fill(&mut buffer.content, root) {
+        global.message(format!("Git status failed: {}", e));
+    }
+}
+
+pub fn git_status_refresh(global: &mut Global, buffer: &mut Buffer) {
+    if let Some(root) = shown_root(global, buffer) {
+        refresh(global, buffer, &root);
+    }
+}
+
+// The repository shown and the file or hunk on the cursor's line
+fn item_at_cursor(global: &mut Global, buffer: &Buffer) -> Option<(PathBuf, Item)> {
+    let root = shown_root(global, buffer)?;
+    let row = buffer.content.cursor.row;
+    let item = buffer.content.locals.get(&STATUS).items.into_iter().find(|item| item.rows.contains(&row));
+    if item.is_none() {
+        global.message("No file or hunk on this line".to_string());
+    }
+    Some((root, item?))
+}
+
+// Runs git on the item at the cursor and shows the status that leaves
+fn change(global: &mut Global, buffer: &mut Buffer, root: &Path, args: &[&str], input: Option<&str>) {
+    match git(root, args, input) {
+        Ok(_) => refresh(global, buffer, root),
+        Err(e) => global.message(e),
+    }
+}
+
+// Stages the file or hunk on the cursor's line
+pub fn git_status_stage(global: &mut Global, buffer: &mut Buffer) {
+    let (root, item) = match item_at_cursor(global, buffer) {
+        Some(found) => found,
+        None => return,
+    };
+    match (item.section, &item.patch) {
+        (Section::Staged, _) => global.message("Already staged".to_string()),
+        (_, Some(patch)) => change(global, buffer, &root, &["apply", "--cached", "-"], Some(patch)),
+        (_, None) => change(global, buffer, &root, &["add", "--", &item.path], None),
+    }
+}
+
+pub fn git_status_unstage(global: &mut Global, buffer: &mut Buffer) {
+    let (root, item) = match item_at_cursor(global, buffer) {
+        Some(found) => found,
+        None => return,
+    };
+    match (item.section, &item.patch) {
+        (Section::Staged, Some(patch)) => change(global, buffer, &root, &["apply", "--cached", "--reverse", "-"], Some(patch)),
+        (Section::Staged, None) => change(global, buffer, &root, &["reset", "-q", "--", &item.path], None),
+        _ => global.message("Not staged".to_string()),
+    }
+}
+
+// Throws away the changes to the file or hunk on the cursor's line, or the
+// untracked file, after asking. Staged changes are to be unstaged first.
+pub fn git_status_discard(global: &mut Global, buffer: &mut Buffer) {
+    let item = match item_at_cursor(global, buffer) {
+        Some((_, item)) => item,
+        None => return,
+    };
+    let prompt = match (item.section, &item.patch) {
+        (Section::Staged, _) => {
+            global.message("Unstage the changes to discard them".to_string());
+            return;
+        },
+        (Section::Untracked, _) => format!("Delete {}? (y or n) ", item.path),
+        (Section::Unstaged, Some(_)) => "Discard this hunk? (y or n) ".to_string(),
+        (Section::Unstaged, None) => format!("Discard the changes to {}? (y or n) ", item.path),
+    };
+    global.minibuffer = Some(Minibuffer::new(&prompt, "", None, |global, buffer, answer| {
+        if !answer.trim_start().starts_with(['y', 'Y']) {
+            return;
+        }
+        let (root, item) = match item_at_cursor(global, buffer) {
+            Some(found) => found,
+            None => return,
+        };
+        match (item.section, &item.patch) {
+            (Section::Untracked, _) => match fs::remove_file(root.join(&item.path)) {
+                Ok(()) => refresh(global, buffer, &root),
+                Err(e) => global.message(format!("Deleting failed: {}", e)),
+            },
+            (_, Some(patch)) => change(global, buffer, &root, &["apply", "--reverse", "-"], Some(patch)),
+            (_, None) => change(global, buffer, &root, &["checkout", "--", &item.path], None),
+        }
+    }));
+}
+
+// Commits what's staged, prompting for the message
+pub fn git_status_commit(global: &mut Global, buffer: &mut Buffer) {
+    let status = buffer.content.locals.get(&STATUS);
+    if shown_root(global, buffer).is_none() {
+        return;
+    }
+    if !status.items.iter().any(|item| item.section == Section::Staged) {
+        global.message("Nothing staged to commit".to_string());
+        return;
+    }
+    global.minibuffer = Some(Minibuffer::new("Commit message: ", "", None, |global, buffer, message| {
+        let root = match shown_root(global, buffer) {
+            Some(root) => root,
+            None => return,
+        };
+        if message.trim().is_empty() {
+            global.message("Not committing with no message".to_string());
+            return;
+        }
+        match git(&root, &["commit", "-m", message], None) {
+            // Like "[main 1e49940] Fix the tab bar"
+            Ok(said) => {
+                refresh(global, buffer, &root);
+                global.message(said.lines().next().unwrap_or("Committed").to_string());
+            },
+            Err(e) => global.message(format!("Committing failed: {}", e)),
+        }
+    }));
+}
+
+// Pushes the current branch to where it's tracking, off the main thread
+pub fn git_status_push(global: &mut Global, buffer: &mut Buffer) {
+    let root = match shown_root(global, buffer) {
+        Some(root) => root,
+        None => return,
+    };
+    let Pusher { results, running } = match buffer.content.locals.get(&PUSHER) {
+        Some(pusher) => pusher,
+        None => return,
+    };
+    if running.swap(true, Ordering::SeqCst) {
+        global.message("Already pushing".to_string());
+        return;
+    }
+    global.message("Pushing...".to_string());
+    thread::spawn(move || {
+        let branch = git(&root, &["symbolic-ref", "--short", "-q", "HEAD"], None).unwrap_or_default();
+        let pushed = git(&root, &["push"], None).map(|_| branch.trim_end().to_string());
+        let _ = results.send(pushed);
+    });
+}
+
+// Opens the file on the cursor's line, at the line of the hunk it's on
+pub fn git_status_visit(global: &mut Global, buffer: &mut Buffer) {
+    let (root, item) = match item_at_cursor(global, buffer) {
+        Some(found) => found,
+        None => return,
+    };
+    let path = root.join(&item.path);
+    if !path.exists() {
+        global.message(format!("{} is deleted", item.path));
+        return;
+    }
+    let line = item.patch.as_ref().map(|_| line_in_hunk(&buffer.content, item.rows.start, buffer.content.cursor.row));
+    commands::visit_path(global, buffer, &path.to_string_lossy());
+    let name = global.buffers.find_visiting(&path).map(|visited| visited.name.clone());
+    if let (Some(line), Some(visited)) = (line, name.and_then(|name| global.buffers.find_mut(&name))) {
+        let row = line.saturating_sub(1).min(visited.content.line_count() - 1);
+        visited.content.cursor = Position::new(row, 0);
+        visited.content.deactivate_mark();
+    }
+}
+
+// The line of the file `row` is at, in the hunk starting on `first_row`
+fn line_in_hunk(content: &TextContent, first_row: usize, row: usize) -> usize {
+    // "@@ -old,count +new,count @@"
+    let header = content.line(first_row);
+    let start: usize = header.split(" +").nth(1)
+        .and_then(|range| range.split([',', ' ']).next())
+        .and_then(|line| line.parse().ok())
+        .unwrap_or(1);
+    let kept = (first_row + 1..row).filter(|&row| !content.line(row).starts_with(['-', '\\'])).count();
+    start + kept
+}
+
+fn step_item(global: &mut Global, buffer: &mut Buffer, forward: bool) {
+    let row = buffer.content.cursor.row;
+    let items = buffer.content.locals.get(&STATUS).items;
+    let found = if forward {
+        items.iter().find(|item| item.rows.start > row)
+    } else {
+        items.iter().rev().find(|item| item.rows.start < row)
+    };
+    match found {
+        Some(item) => {
+            buffer.content.cursor = Position::new(item.rows.start, 0);
+            buffer.content.deactivate_mark();
+        },
+        None => global.message(if forward { "No more files" } else { "No earlier files" }.to_string()),
+    }
+}
+
+// Goes to the next file or hunk listed
+pub fn git_status_next(global: &mut Global, buffer: &mut Buffer) {
+    step_item(global, buffer, true);
+}
+
+pub fn git_status_previous(global: &mut Global, buffer: &mut Buffer) {
+    step_item(global, buffer, false);
+}
@@ -0,0 +1,30 @@
+use crate::tree_sitter_mode::Grammar;
+use crate::tree_sitter_mode::Language;
+use crate::tree_sitter_mode::SYNTAX_ITEMS;
+
+// Nodes whose contents are indented one level, as in RUST_INDENT_QUERY.
+// Cases line up with their switch, as gofmt has them.
+const GO_INDENT_QUERY: &str = r#"
+[
+  (block)
+  (literal_value)
+  (argument_list)
+  (parameter_list)
+  (field_declaration_list)
+  (method_spec_list)
+] @indent
+
+(ERROR "{" @indent.open)
+"#;
+
+pub const GO: Language = Language {
+    name: "go",
+    grammar: Grammar::Builtin(tree_sitter_go::language),
+    highlight_queries: &[tree_sitter_go::HIGHLIGHT_QUERY],
+    indent_query: Some(GO_INDENT_QUERY),
+    comment_start: Some("//"),
+    context_menu: SYNTAX_ITEMS,
+    injection_query: None,
+    fold_query: None,
+    tags_queries: &[tree_sitter_go::TAGGING_QUERY],
+};
@@ -0,0 +1,146 @@
+use std::ops::Range;
+use std::process::Command;
+use std::process::Stdio;
+
+use crate::buffer::Buffer;
+use crate::comint;
+use crate::compilation;
+use crate::compilation::Location;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+// URLs and places in files like src/main.rs:10 underlined as links, as in
+// Emacs' goto-address-mode. follow-link, or clicking one with Ctrl held,
+// opens URLs in the browser, EDIT0R_BROWSER if it's set, and goes to places.
+
+const SCHEMES: &[&str] = &["http", "https", "ftp", "file"];
+
+enum Link {
+    Url(String),
+    Place(Location),
+}
+
+// The URLs on `line`, like https://example.com/a?b=c
+fn find_urls(line: &str) -> Vec<Range<usize>> {
+    let mut urls = vec!();
+    let mut from = 0;
+    while let Some(i) = line[from..].find("://") {
+        let separator = from + i;
+        from = separator + 3;
+        let start = line[..separator]
+            .rfind(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')))
+            .map_or(0, |i| i + 1);
+        if !SCHEMES.contains(&line[start..separator].to_ascii_lowercase().as_str()) {
+            continue;
+        }
+        let end = line[from..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>' | '`'))
+            .map_or(line.len(), |i| from + i);
+        // Punctuation ending a sentence isn't part of it, nor a closing
+        // bracket it's wrapped in
+        let mut url = &line[start..end];
+        loop {
+            let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?']);
+            let unbalanced = trimmed.ends_with(')') && trimmed.matches('(').count() < trimmed.matches(')').count();
+            url = if unbalanced { &trimmed[..trimmed.len() - 1] } else { trimmed };
+            if url.len() == trimmed.len() {
+                break;
+            }
+        }
+        if url.len() > line[start..separator].len() + 3 {
+            urls.push(start..start + url.len());
+        }
+        from = end.max(from);
+    }
+    urls
+}
+
+// The links on `line` and where they are
+fn find_links(line: &str) -> Vec<(Range<usize>, Link)> {
+    let urls = find_urls(line);
+    let places = compilation::find_locations(line).into_iter()
+        // Words with a colon and a number are often not files at all
+        .filter(|place| place.file.contains(['.', '/']))
+        .filter(|place| !urls.iter().any(|url| url.start < place.cols.end && place.cols.start < url.end))
+        .map(|place| (place.cols.clone(), Link::Place(place)));
+    let mut links: Vec<(Range<usize>, Link)> = urls.iter().map(|url| (url.clone(), Link::Url(line[url.clone()].to_string()))).collect();
+    links.extend(places);
+    links
+}
+
+pub struct GotoAddressMode {
+}
+
+impl TextMinorMode for GotoAddressMode {
+    fn name(&self) -> &'static str {
+        "goto-address"
+    }
+
+    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
+        let face_id = match global.faces.get_face_id("link") {
+            Some(face_id) => face_id,
+            None => return,
+        };
+        let mut spans = vec!();
+        let (start, end) = content.visible_range();
+        for row in start.row..=end.row {
+            for (cols, _) in find_links(&content.line(row)) {
+                spans.push((row, cols));
+            }
+        }
+        for (row, cols) in spans {
+            content.faces.add(Position::new(row, cols.start), Position::new(row, cols.end), face_id);
+        }
+    }
+}
+
+// Turns underlining URLs and places in files on or off
+pub fn goto_address_mode(_: &mut Global, buffer: &mut Buffer) {
+    buffer.toggle_minor_mode("goto-address", || Box::new(GotoAddressMode {}));
+}
+
+// Opens `url` in the browser, without waiting for it
+fn browse_url(url: &str) -> Result<(), String> {
+    let browser = std::env::var("EDIT0R_BROWSER").ok().filter(|browser| !browser.trim().is_empty());
+    let default = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    let command = browser.unwrap_or_else(|| default.to_string());
+    let mut words = command.split_whitespace();
+    let program = words.next().unwrap_or(default);
+    Command::new(program)
+        .args(words)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("{}: {}", program, e))
+}
+
+// Opens the URL at the cursor in the browser, or goes to the place in a
+// file it's on
+pub fn follow_link(global: &mut Global, buffer: &mut Buffer) {
+    let cursor = buffer.content.cursor;
+    let link = find_links(&buffer.content.line(cursor.row))
+        .into_iter()
+        .find(|(cols, _)| cols.start <= cursor.col && cursor.col <= cols.end);
+    match link {
+        Some((_, Link::Url(url))) => match browse_url(&url) {
+            Ok(()) => global.message(format!("Opened {}", url)),
+            Err(e) => global.message(format!("Opening the browser failed: {}", e)),
+        },
+        Some((_, Link::Place(place))) => {
+            let directory = comint::default_directory(buffer);
+            compilation::visit_location(global, buffer, directory, &place);
+        },
+        None => global.message("No link at the cursor".to_string()),
+    }
+}
@@ -0,0 +1,225 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use libloading::Library;
+use libloading::Symbol;
+use tree_sitter::Parser;
+use tree_sitter::Query;
+
+use crate::auto_mode::AutoMode;
+use crate::text_mode::TextMinorMode;
+use crate::tree_sitter_mode::Grammar;
+use crate::tree_sitter_mode::Language;
+
+// Grammars added without recompiling, each in its own directory under
+// EDIT0R_GRAMMARS, else ~/.config/edit0r/grammars. A directory holds the
+// compiled parser, the queries as tree-sitter keeps them, all of them
+// optional, and a grammar.toml saying what's what:
+//
+//     # ~/.config/edit0r/grammars/ruby/grammar.toml
+//     name = "ruby"
+//     library = "ruby.so"
+//     # Defaults to tree_sitter_ followed by the name
+//     symbol = "tree_sitter_ruby"
+//     extensions = ["rb", "rake"]
+//     filenames = ["Rakefile"]
+//     interpreters = ["ruby"]
+//     comment_start = "#"
+//
+// The queries are highlights.scm, indents.scm, injections.scm, folds.scm
+// and tags.scm. A grammar that doesn't load, or whose queries don't fit
+// it, is left out with an error saying why.
+
+static LOADED: OnceLock<Vec<AutoMode>> = OnceLock::new();
+
+struct Manifest {
+    name: String,
+    library: String,
+    symbol: Option<String>,
+    extensions: Vec<String>,
+    filenames: Vec<String>,
+    interpreters: Vec<String>,
+    comment_start: Option<String>,
+}
+
+fn grammars_directory() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("EDIT0R_GRAMMARS") {
+        return Some(PathBuf::from(path));
+    }
+    Some(PathBuf::from(std::env::var_os("HOME")?).join(".config/edit0r/grammars"))
+}
+
+// The modes of the grammars loaded, which come before the built-in ones
+// so they can take over their files
+pub fn loaded() -> &'static [AutoMode] {
+    LOADED.get().map_or(&[], |modes| &modes[..])
+}
+
+// Loads every grammar in the grammars directory, once at startup. Returns
+// what went wrong with the ones that didn't load.
+pub fn load_from_env() -> Vec<String> {
+    let directory = match grammars_directory() {
+        Some(directory) if directory.is_dir() => directory,
+        _ => return vec!(),
+    };
+    let entries = match fs::read_dir(&directory) {
+        Ok(entries) => entries,
+        Err(e) => return vec!(format!("{}: {}", directory.display(), e)),
+    };
+
+    let mut modes = vec!();
+    let mut errors = vec!();
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| Some(entry.ok()?.path())).collect();
+    paths.sort();
+    for path in paths.iter().filter(|path| path.join("grammar.toml").exists()) {
+        match load(path) {
+            Ok(mode) => modes.push(mode),
+            Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    let _ = LOADED.set(modes);
+    errors
+}
+
+fn load(directory: &Path) -> Result<AutoMode, String> {
+    let manifest_path = directory.join("grammar.toml");
+    let text = fs::read_to_string(&manifest_path).map_err(|e| e.to_string())?;
+    let manifest = parse(&text)?;
+
+    let symbol = manifest.symbol.clone().unwrap_or_else(|| format!("tree_sitter_{}", manifest.name.replace('-', "_")));
+    let grammar = open_library(&directory.join(&manifest.library), &symbol)?;
+
+    // Checked here, since the language mode takes them on trust
+    let mut parser = Parser::new();
+    parser.set_language(grammar).map_err(|e| e.to_string())?;
+    let query = |file: &str| -> Result<Option<&'static str>, String> {
+        let path = directory.join(file);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let source = fs::read_to_string(&path).map_err(|e| format!("{}: {}", file, e))?;
+        Query::new(grammar, &source).map_err(|e| format!("{}: line {}: {}", file, e.row + 1, e.message))?;
+        Ok(Some(leak(source)))
+    };
+
+    let highlight_query = query("highlights.scm")?;
+    let tags_query = query("tags.scm")?;
+    let comment_start = manifest.comment_start.map(leak);
+    let language = Language {
+        name: leak(manifest.name.clone()),
+        grammar: Grammar::Loaded(grammar),
+        highlight_queries: leak_slice(highlight_query.into_iter().collect()),
+        indent_query: query("indents.scm")?,
+        comment_start,
+        context_menu: &[],
+        injection_query: query("injections.scm")?,
+        fold_query: query("folds.scm")?,
+        tags_queries: leak_slice(tags_query.into_iter().collect()),
+    };
+    Ok(AutoMode {
+        name: language.name,
+        extensions: leak_strings(manifest.extensions),
+        filenames: leak_strings(manifest.filenames),
+        interpreters: leak_strings(manifest.interpreters),
+        comment_start,
+        language: Some(Box::leak(Box::new(language))),
+        extra_modes: no_extra_modes,
+    })
+}
+
+fn no_extra_modes() -> Vec<Box<dyn TextMinorMode>> {
+    vec!()
+}
+
+// The library stays loaded for as long as the editor runs, since the
+// language points into it
+fn open_library(path: &Path, symbol: &str) -> Result<tree_sitter::Language, String> {
+    // Safety: loading runs the library's initializers, which we trust as
+    // much as the grammar itself. The symbol is the function tree-sitter
+    // generates for every parser, taking nothing and giving its language.
+    unsafe {
+        let library = Library::new(path).map_err(|e| e.to_string())?;
+        let language = {
+            let function: Symbol<unsafe extern "C" fn() -> tree_sitter::Language> = library
+                .get(symbol.as_bytes())
+                .map_err(|e| format!("{}: {}", symbol, e))?;
+            function()
+        };
+        std::mem::forget(library);
+        Ok(language)
+    }
+}
+
+// Loaded grammars live as long as the built-in ones, which are 'static
+fn leak(text: String) -> &'static str {
+    Box::leak(text.into_boxed_str())
+}
+
+fn leak_slice(items: Vec<&'static str>) -> &'static [&'static str] {
+    Box::leak(items.into_boxed_slice())
+}
+
+fn leak_strings(strings: Vec<String>) -> &'static [&'static str] {
+    leak_slice(strings.into_iter().map(leak).collect())
+}
+
+// Reads grammar.toml, which takes strings and lists of strings in the
+// style of TOML
+fn parse(text: &str) -> Result<Manifest, String> {
+    let mut manifest = Manifest {
+        name: String::new(),
+        library: String::new(),
+        symbol: None,
+        extensions: vec!(),
+        filenames: vec!(),
+        interpreters: vec!(),
+        comment_start: None,
+    };
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: &str| format!("line {}: {}", i + 1, message);
+        let (key, value) = line.split_once('=').ok_or_else(|| error("Expected key = value"))?;
+        let value = value.trim();
+        let string = || parse_string(value).ok_or_else(|| error("Expected a string like \"name\""));
+        let list = || parse_list(value).ok_or_else(|| error("Expected a list like [\"a\", \"b\"]"));
+        match key.trim() {
+            "name" => manifest.name = string()?,
+            "library" => manifest.library = string()?,
+            "symbol" => manifest.symbol = Some(string()?),
+            "extensions" => manifest.extensions = list()?,
+            "filenames" => manifest.filenames = list()?,
+            "interpreters" => manifest.interpreters = list()?,
+            "comment_start" => manifest.comment_start = Some(string()?),
+            key => return Err(error(&format!("Unknown setting {}", key))),
+        }
+    }
+    if manifest.name.is_empty() {
+        return Err("grammar.toml has no name".to_string());
+    }
+    if manifest.library.is_empty() {
+        return Err("grammar.toml has no library".to_string());
+    }
+    Ok(manifest)
+}
+
+fn parse_string(value: &str) -> Option<String> {
+    let inner = value.strip_prefix('"')?.strip_suffix('"')?;
+    (!inner.contains('"')).then(|| inner.to_string())
+}
+
+fn parse_list(value: &str) -> Option<Vec<String>> {
+    let inner = value.strip_prefix('[')?.strip_suffix(']')?.trim();
+    if inner.is_empty() {
+        return Some(vec!());
+    }
+    inner.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(parse_string)
+        .collect()
+}
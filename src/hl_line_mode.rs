@@ -0,0 +1,22 @@
+use crate::text_mode::TextMinorMode;
+
+// Paints the background of the cursor's line so it is easy to find
+pub struct HlLineMode {
+}
+
+impl TextMinorMode for HlLineMode {
+    fn name(&self) -> &'static str {
+        "hl-line"
+    }
+}
+
+// Draws a thin line down the screen at the fill column, to see where long
+// lines go past it
+pub struct FillColumnIndicatorMode {
+}
+
+impl TextMinorMode for FillColumnIndicatorMode {
+    fn name(&self) -> &'static str {
+        "fill-column-indicator"
+    }
+}
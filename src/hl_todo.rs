@@ -0,0 +1,247 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+use tree_sitter::Parser;
+use tree_sitter::Tree;
+
+use crate::auto_mode;
+use crate::buffer::Buffer;
+use crate::comint;
+use crate::compilation;
+use crate::motion::is_word_char;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+use crate::tree_sitter_mode;
+
+// Keywords like TODO in comments, in a face for each, as in Emacs'
+// hl-todo. todo-list finds them in every file of the project whose
+// language has a grammar, listing them like grep does.
+
+const KEYWORDS: &[(&str, &str)] = &[
+    ("TODO", "hl-todo-todo"),
+    ("FIXME", "hl-todo-fixme"),
+    ("HACK", "hl-todo-hack"),
+    ("NOTE", "hl-todo-note"),
+];
+
+const BUFFER_NAME: &str = "*todo*";
+
+// Files bigger than this are skipped when listing, being generated more
+// often than not
+const MAX_FILE_SIZE: u64 = 1 << 20;
+
+// The keywords between `from` and `to` in `line` as whole words, with where
+// they start and their face
+fn keywords(line: &str, from: usize, to: usize) -> Vec<(usize, &'static str, &'static str)> {
+    let mut found = vec!();
+    for &(keyword, face) in KEYWORDS {
+        let mut start = from;
+        while let Some(i) = line.get(start..to).and_then(|text| text.find(keyword)) {
+            let col = start + i;
+            let end = col + keyword.len();
+            let before = line[..col].chars().next_back().is_some_and(is_word_char);
+            let after = line[end..].chars().next().is_some_and(is_word_char);
+            if !before && !after {
+                found.push((col, keyword, face));
+            }
+            start = end;
+        }
+    }
+    found.sort_by_key(|&(col, _, _)| col);
+    found
+}
+
+// Where the comments on the rows from `first` to `last` are
+fn comments(tree: &Tree, first: usize, last: usize) -> Vec<(Position, Position)> {
+    let mut ranges = vec!();
+    tree_sitter_mode::find_nodes(tree.root_node(), first, last, |kind| kind.contains("comment"), &mut ranges);
+    ranges
+}
+
+pub struct HlTodoMode {
+}
+
+impl TextMinorMode for HlTodoMode {
+    fn name(&self) -> &'static str {
+        "hl-todo"
+    }
+
+    fn uses_syntax_tree(&self) -> bool {
+        true
+    }
+
+    fn modify_syntax(&mut self, global: &mut Global, content: &mut TextContent, tree: &Tree) {
+        let (start, end) = content.visible_range();
+        let mut spans = vec!();
+        for (comment_start, comment_end) in comments(tree, start.row, end.row) {
+            for row in comment_start.row.max(start.row)..=comment_end.row.min(end.row) {
+                let line = content.line(row);
+                let from = if row == comment_start.row { comment_start.col } else { 0 };
+                let to = if row == comment_end.row { comment_end.col.min(line.len()) } else { line.len() };
+                for (col, keyword, face) in keywords(&line, from.min(to), to) {
+                    if let Some(face_id) = global.faces.get_face_id(face) {
+                        spans.push((row, col, keyword.len(), face_id));
+                    }
+                }
+            }
+        }
+        for (row, col, len, face_id) in spans {
+            content.faces.add(Position::new(row, col), Position::new(row, col + len), face_id);
+        }
+    }
+}
+
+// Turns highlighting TODO and the like in comments on or off
+pub fn hl_todo_mode(_: &mut Global, buffer: &mut Buffer) {
+    buffer.toggle_minor_mode("hl-todo", || Box::new(HlTodoMode {}));
+}
+
+// The keywords listed by todo-list, in their faces as in comments
+pub struct TodoListMode {
+}
+
+impl TextMinorMode for TodoListMode {
+    fn name(&self) -> &'static str {
+        "todo-list"
+    }
+
+    fn is_major(&self) -> bool {
+        true
+    }
+
+    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
+        let mut spans = vec!();
+        let (start, end) = content.visible_range();
+        for row in start.row..=end.row {
+            let line = content.line(row);
+            // After the "file:line:column: " the line starts with
+            let from = line.find(": ").map_or(line.len(), |i| i + 2);
+            for (col, keyword, face) in keywords(&line, from, line.len()) {
+                if let Some(face_id) = global.faces.get_face_id(face) {
+                    spans.push((row, col, keyword.len(), face_id));
+                }
+            }
+        }
+        for (row, col, len, face_id) in spans {
+            content.faces.add(Position::new(row, col), Position::new(row, col + len), face_id);
+        }
+    }
+}
+
+// The top of the repository `directory` is in, or else `directory`
+fn project_root(directory: &Path) -> PathBuf {
+    let directory = fs::canonicalize(directory).unwrap_or_else(|_| directory.to_path_buf());
+    directory.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .unwrap_or(&directory)
+        .to_path_buf()
+}
+
+// The files of the project under `root` relative to it, leaving out what
+// git ignores, or hidden files and build output outside of a repository
+fn project_files(root: &Path) -> Vec<PathBuf> {
+    let listed = Command::new("git")
+        .arg("-C").arg(root)
+        .args(["ls-files", "-z", "--cached", "--others", "--exclude-standard"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success());
+    if let Some(output) = listed {
+        return String::from_utf8_lossy(&output.stdout).split('\0').filter(|path| !path.is_empty()).map(PathBuf::from).collect();
+    }
+    let mut files = vec!();
+    walk(root, Path::new(""), &mut files);
+    files
+}
+
+fn walk(root: &Path, relative: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(root.join(relative)) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry.file_name();
+        let name_text = name.to_string_lossy();
+        if name_text.starts_with('.') || name_text == "target" || name_text == "node_modules" {
+            continue;
+        }
+        let path = relative.join(&name);
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => walk(root, &path, files),
+            Ok(file_type) if file_type.is_file() => files.push(path),
+            _ => {},
+        }
+    }
+}
+
+// Adds a line for each keyword in the comments of the file at `relative`
+// under `root` to `listing`
+fn list_file(parser: &mut Parser, root: &Path, relative: &Path, listing: &mut String) {
+    let path = root.join(relative);
+    if fs::metadata(&path).map_or(true, |metadata| metadata.len() > MAX_FILE_SIZE) {
+        return;
+    }
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    if !KEYWORDS.iter().any(|(keyword, _)| text.contains(keyword)) {
+        return;
+    }
+    let first_line = text.lines().next().unwrap_or("");
+    let language = match auto_mode::for_file(&path, first_line).and_then(|mode| mode.language) {
+        Some(language) => language,
+        None => return,
+    };
+    if parser.set_language(language.grammar()).is_err() {
+        return;
+    }
+    let tree = match parser.parse(&text, None) {
+        Some(tree) => tree,
+        None => return,
+    };
+    let lines: Vec<&str> = text.split('\n').collect();
+    for (start, end) in comments(&tree, 0, lines.len()) {
+        for (row, line) in lines.iter().enumerate().take(end.row + 1).skip(start.row) {
+            let from = if row == start.row { start.col } else { 0 };
+            let to = if row == end.row { end.col.min(line.len()) } else { line.len() };
+            for (col, _, _) in keywords(line, from.min(to), to) {
+                let column = line[..col].chars().count() + 1;
+                listing.push_str(&format!("{}:{}:{}: {}\n", relative.display(), row + 1, column, line.trim()));
+            }
+        }
+    }
+}
+
+// Lists the TODOs and the like in the comments of every file in the
+// project, in a buffer where return goes to each
+pub fn todo_list(global: &mut Global, buffer: &mut Buffer) {
+    let root = match compilation::listed_directory(buffer).filter(|_| buffer.has_minor_mode("todo-list")) {
+        Some(root) => root,
+        None => project_root(&comint::default_directory(buffer).unwrap_or_else(|| PathBuf::from("."))),
+    };
+    let mut parser = Parser::new();
+    let mut listing = String::new();
+    for relative in project_files(&root) {
+        list_file(&mut parser, &root, &relative, &mut listing);
+    }
+    let count = listing.lines().count();
+    let text = format!("{} in {}\n\n{}", if count == 1 { "1 keyword".to_string() } else { format!("{} keywords", count) }, root.display(), listing);
+    let list = compilation::location_list(BUFFER_NAME, &text, root, Box::new(TodoListMode {}));
+
+    if buffer.has_minor_mode("todo-list") {
+        let row = buffer.content.cursor.row;
+        *buffer = list;
+        buffer.content.cursor = Position::new(row.min(buffer.content.line_count() - 1), 0);
+    } else {
+        global.buffers.remove(BUFFER_NAME);
+        global.buffers.add(list);
+        global.buffers.switch_to(BUFFER_NAME);
+    }
+}
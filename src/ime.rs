@@ -0,0 +1,25 @@
+// Text an input method is composing, e.g. Japanese kana before they're
+// converted, shown at the cursor until it's committed as text input
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct Preedit {
+    pub text: String,
+    // Byte offset of the input method's cursor in the text
+    pub cursor: usize,
+}
+
+impl Preedit {
+    // Takes SDL's TextEditing event, where `start` counts characters
+    pub fn update(&mut self, text: String, start: i32) {
+        self.cursor = text.char_indices().nth(start.max(0) as usize).map_or(text.len(), |(i, _)| i);
+        self.text = text;
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+}
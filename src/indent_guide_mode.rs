@@ -0,0 +1,39 @@
+use crate::buffer::leading_whitespace;
+use crate::buffer_local::INDENT_WIDTH;
+use crate::text_content::TextContent;
+use crate::text_mode::TextMinorMode;
+use crate::unicode::display_width;
+
+// Draws faint vertical lines at each indentation level, to follow deeply
+// nested code down the screen
+pub struct IndentGuideMode {
+}
+
+impl TextMinorMode for IndentGuideMode {
+    fn name(&self) -> &'static str {
+        "indent-guide"
+    }
+}
+
+// Cells of indentation of a line, or None when it's blank
+fn indent_cells(content: &TextContent, row: usize) -> Option<usize> {
+    let line = content.line(row);
+    if line.trim().is_empty() {
+        return None;
+    }
+    Some(display_width(leading_whitespace(&line)))
+}
+
+// The cells guides go in on a line, one per indent width of its leading
+// whitespace. Blank lines carry on the guides of the shallower of the lines
+// around them, so guides don't break at gaps in a block.
+pub fn guide_cells(content: &TextContent, row: usize) -> Vec<usize> {
+    let (start, end) = content.visible_range();
+    let indent = indent_cells(content, row).unwrap_or_else(|| {
+        let above = (start.row..row).rev().find_map(|row| indent_cells(content, row));
+        let below = (row + 1..=end.row).find_map(|row| indent_cells(content, row));
+        above.unwrap_or(0).min(below.unwrap_or(0))
+    });
+    let indent_width = content.locals.get(&INDENT_WIDTH).max(1);
+    (0..indent / indent_width).map(|level| level * indent_width).collect()
+}
@@ -0,0 +1,83 @@
+use crate::tree_sitter_mode::Grammar;
+use crate::tree_sitter_mode::Language;
+use crate::tree_sitter_mode::SYNTAX_ITEMS;
+
+// Nodes whose contents are indented one level, as in RUST_INDENT_QUERY
+const JAVASCRIPT_INDENT_QUERY: &str = r#"
+[
+  (statement_block)
+  (class_body)
+  (object)
+  (array)
+  (arguments)
+  (formal_parameters)
+  (switch_body)
+  (object_pattern)
+  (array_pattern)
+  (named_imports)
+  (export_clause)
+] @indent
+
+(ERROR "{" @indent.open)
+"#;
+
+const TYPESCRIPT_INDENT_QUERY: &str = r#"
+[
+  (statement_block)
+  (class_body)
+  (object)
+  (array)
+  (arguments)
+  (formal_parameters)
+  (switch_body)
+  (object_pattern)
+  (array_pattern)
+  (named_imports)
+  (export_clause)
+  (enum_body)
+  (object_type)
+] @indent
+
+(ERROR "{" @indent.open)
+"#;
+
+pub const JAVASCRIPT: Language = Language {
+    name: "javascript",
+    grammar: Grammar::Builtin(tree_sitter_javascript::language),
+    highlight_queries: &[tree_sitter_javascript::JSX_HIGHLIGHT_QUERY, tree_sitter_javascript::HIGHLIGHT_QUERY],
+    indent_query: Some(JAVASCRIPT_INDENT_QUERY),
+    comment_start: Some("//"),
+    context_menu: SYNTAX_ITEMS,
+    injection_query: Some(tree_sitter_javascript::INJECTION_QUERY),
+    fold_query: None,
+    tags_queries: &[tree_sitter_javascript::TAGGING_QUERY],
+};
+
+// TypeScript's queries only cover what it adds to JavaScript
+pub const TYPESCRIPT: Language = Language {
+    name: "typescript",
+    grammar: Grammar::Builtin(tree_sitter_typescript::language_typescript),
+    highlight_queries: &[tree_sitter_typescript::HIGHLIGHT_QUERY, tree_sitter_javascript::HIGHLIGHT_QUERY],
+    indent_query: Some(TYPESCRIPT_INDENT_QUERY),
+    comment_start: Some("//"),
+    context_menu: SYNTAX_ITEMS,
+    injection_query: Some(tree_sitter_javascript::INJECTION_QUERY),
+    fold_query: None,
+    tags_queries: &[tree_sitter_javascript::TAGGING_QUERY, tree_sitter_typescript::TAGGING_QUERY],
+};
+
+pub const TSX: Language = Language {
+    name: "tsx",
+    grammar: Grammar::Builtin(tree_sitter_typescript::language_tsx),
+    highlight_queries: &[
+        tree_sitter_javascript::JSX_HIGHLIGHT_QUERY,
+        tree_sitter_typescript::HIGHLIGHT_QUERY,
+        tree_sitter_javascript::HIGHLIGHT_QUERY,
+    ],
+    indent_query: Some(TYPESCRIPT_INDENT_QUERY),
+    comment_start: Some("//"),
+    context_menu: SYNTAX_ITEMS,
+    injection_query: Some(tree_sitter_javascript::INJECTION_QUERY),
+    fold_query: None,
+    tags_queries: &[tree_sitter_javascript::TAGGING_QUERY, tree_sitter_typescript::TAGGING_QUERY],
+};
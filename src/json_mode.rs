@@ -0,0 +1,22 @@
+use crate::tree_sitter_mode::Grammar;
+use crate::tree_sitter_mode::Language;
+use crate::tree_sitter_mode::SYNTAX_ITEMS;
+
+const JSON_INDENT_QUERY: &str = r#"
+[
+  (object)
+  (array)
+] @indent
+"#;
+
+pub const JSON: Language = Language {
+    name: "json",
+    grammar: Grammar::Builtin(tree_sitter_json::language),
+    highlight_queries: &[tree_sitter_json::HIGHLIGHT_QUERY],
+    indent_query: Some(JSON_INDENT_QUERY),
+    comment_start: None,
+    context_menu: SYNTAX_ITEMS,
+    injection_query: None,
+    fold_query: None,
+    tags_queries: &[],
+};
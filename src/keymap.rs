@@ -0,0 +1,661 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use sdl2::keyboard::Keycode;
+
+use crate::buffer::Buffer;
+use crate::command_registry;
+use crate::text_mode::Global;
+
+// Commands are bound by their name in the command registry
+#[derive(Clone, Copy)]
+pub enum Binding {
+    Command(&'static str),
+    // Finishing with the minibuffer, run against the current buffer rather
+    // than the minibuffer's input
+    MinibufferCommand(&'static str),
+    // Answering the menu on top, run against the current buffer even while
+    // the minibuffer is open
+    PopupCommand(&'static str),
+    // The keys so far start a longer sequence
+    Prefix,
+    // C-u, multiplying the count of the next command
+    UniversalArgument,
+    // M- and a digit or -, starting a numeric argument
+    DigitArgument,
+    // C-x z, running the last command again
+    Repeat,
+    // C-q, inserting the next key as is
+    QuotedInsert,
+    Unbound,
+}
+
+// A key with the modifiers held, e.g. ("C-", Keycode::S)
+pub type KeyPress = (&'static str, Keycode);
+
+// The prefix written before a key for the modifiers held
+pub fn modifier_prefix(ctrl: bool, alt: bool, shift: bool) -> &'static str {
+    match (ctrl, alt, shift) {
+        (false, false, false) => "",
+        (true, false, false)  => "C-",
+        (false, true, false)  => "M-",
+        (false, false, true)  => "S-",
+        (true, true, false)   => "C-M-",
+        (true, false, true)   => "C-S-",
+        (false, true, true)   => "M-S-",
+        (true, true, true)    => "C-M-S-",
+    }
+}
+
+// A key sequence the way bindings are written, e.g. "C-x C-s"
+pub fn describe_keys(keys: &[KeyPress]) -> String {
+    keys.iter()
+        .map(|(prefix, keycode)| format!("{}{}", prefix, keycode.name().to_lowercase()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Reads a key sequence written like describe_keys does it. Modifiers can come
+// in any order, and key names are SDL's, in any case.
+pub fn parse_keys(text: &str) -> Result<Vec<KeyPress>, String> {
+    let keys: Result<Vec<KeyPress>, String> = text.split_whitespace().map(parse_key).collect();
+    match keys {
+        Ok(keys) if keys.is_empty() => Err("No keys given".to_string()),
+        keys => keys,
+    }
+}
+
+fn parse_key(text: &str) -> Result<KeyPress, String> {
+    let (mut ctrl, mut alt, mut shift) = (false, false, false);
+    let mut rest = text;
+    while rest.len() > 2 {
+        match &rest[..2] {
+            "C-" => ctrl = true,
+            "M-" => alt = true,
+            "S-" => shift = true,
+            _ => break,
+        }
+        rest = &rest[2..];
+    }
+    // Single characters are their own keycode, lowercase for letters
+    let mut chars = rest.chars();
+    let keycode = match (chars.next(), chars.next()) {
+        (Some(c), None) => c.to_lowercase().next().and_then(|c| Keycode::from_i32(c as i32)),
+        _ => Keycode::from_name(rest),
+    };
+    match keycode {
+        Some(keycode) => Ok((modifier_prefix(ctrl, alt, shift), keycode)),
+        None => Err(format!("No key is called {}", rest)),
+    }
+}
+
+// Key sequences and what they're bound to
+#[derive(Default)]
+pub struct Keymap {
+    bindings: Vec<(Vec<KeyPress>, Binding)>,
+}
+
+impl Keymap {
+    // Panics on a sequence that can't be read, for the built-in keymaps
+    fn from_table(table: &[(&str, Binding)]) -> Keymap {
+        let mut keymap = Keymap::default();
+        for &(keys, binding) in table {
+            keymap.bind(keys, binding).unwrap();
+        }
+        keymap
+    }
+
+    // Binds `keys`, replacing what they were bound to
+    pub fn bind(&mut self, keys: &str, binding: Binding) -> Result<(), String> {
+        let keys = parse_keys(keys)?;
+        self.bindings.retain(|(bound, _)| *bound != keys);
+        self.bindings.push((keys, binding));
+        Ok(())
+    }
+
+    // What `keys` are bound to, Prefix when they start longer sequences
+    pub fn lookup(&self, keys: &[KeyPress]) -> Binding {
+        if let Some((_, binding)) = self.bindings.iter().find(|(bound, _)| bound == keys) {
+            return *binding;
+        }
+        if self.bindings.iter().any(|(bound, _)| bound.len() > keys.len() && bound.starts_with(keys)) {
+            return Binding::Prefix;
+        }
+        Binding::Unbound
+    }
+
+    // The keys that can come after `keys`, with what each does
+    fn continuations(&self, keys: &[KeyPress]) -> Vec<(KeyPress, Binding)> {
+        let mut next: Vec<(KeyPress, Binding)> = vec!();
+        for (bound, binding) in &self.bindings {
+            if bound.len() <= keys.len() || !bound.starts_with(keys) {
+                continue;
+            }
+            let key = bound[keys.len()];
+            if !next.iter().any(|(seen, _)| *seen == key) {
+                let binding = if bound.len() == keys.len() + 1 { *binding } else { Binding::Prefix };
+                next.push((key, binding));
+            }
+        }
+        next
+    }
+}
+
+// What a binding does, in a word
+pub fn describe_binding(binding: Binding) -> &'static str {
+    match binding {
+        Binding::Command(name) | Binding::MinibufferCommand(name) | Binding::PopupCommand(name) => name,
+        Binding::Prefix => "+prefix",
+        Binding::UniversalArgument => "universal-argument",
+        Binding::DigitArgument => "digit-argument",
+        Binding::Repeat => "repeat",
+        Binding::QuotedInsert => "quoted-insert",
+        Binding::Unbound => "undefined",
+    }
+}
+
+// The keymaps keys are looked up in. The first one with the keys bound, or
+// starting a sequence, wins: the menu popped up, then the minibuffer's while
+// it's open, else the buffer's minor modes and major mode, then the global one.
+pub struct Keymaps {
+    pub global: Keymap,
+    pub minibuffer: Keymap,
+    pub popup: Keymap,
+    // By the name of the mode
+    modes: HashMap<String, Keymap>,
+}
+
+impl Default for Keymaps {
+    fn default() -> Keymaps {
+        Keymaps {
+            global: Keymap::from_table(GLOBAL_KEYS),
+            minibuffer: Keymap::from_table(MINIBUFFER_KEYS),
+            popup: Keymap::from_table(POPUP_KEYS),
+            modes: MODE_KEYS.iter().map(|&(mode, table)| (mode.to_string(), Keymap::from_table(table))).collect(),
+        }
+    }
+}
+
+impl Keymaps {
+    // The keymap a section of a keys file binds keys in
+    fn section_mut(&mut self, section: &str) -> &mut Keymap {
+        match section {
+            "global" => &mut self.global,
+            "minibuffer" => &mut self.minibuffer,
+            "popup" => &mut self.popup,
+            mode => self.modes.entry(mode.to_string()).or_default(),
+        }
+    }
+
+    // Binds the keys in a keys file, in the style of TOML like theme files.
+    // Sections bind keys for a mode, or for the minibuffer and menus, where
+    // commands finish with them and run on the current buffer.
+    //
+    //     # Comments start with a hash
+    //     "C-x C-f" = "find-file"
+    //
+    //     [rust]
+    //     "C-c C-c" = "toggle-comment"
+    pub fn bind_all(&mut self, text: &str) -> Result<(), String> {
+        let mut section = "global";
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("line {}: {}", i + 1, message);
+
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                section = name.trim();
+                continue;
+            }
+
+            // The keys can have an = in them, so they're read up to the quote
+            let (keys, value) = line
+                .strip_prefix('"')
+                .and_then(|line| line.split_once('"'))
+                .and_then(|(keys, rest)| Some((keys, rest.trim_start().strip_prefix('=')?.trim())))
+                .ok_or_else(|| error("Expected \"keys\" = \"command\" or a [section]"))?;
+            let name = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .ok_or_else(|| error("Expected the command's name in quotes"))?;
+            let binding = binding_named(name, section).map_err(|e| error(&e))?;
+            self.section_mut(section).bind(keys, binding).map_err(|e| error(&format!("{}: {}", keys, e)))?;
+        }
+        Ok(())
+    }
+
+    // The keymaps in force in `buffer`, the last minor mode turned on first,
+    // or the minibuffer's while it's open, where the global keymap edits its
+    // input
+    fn in_force(&self, buffer: &Buffer, minibuffer: bool) -> Vec<&Keymap> {
+        if minibuffer {
+            return vec!(&self.minibuffer, &self.global);
+        }
+        let minor = buffer.minor_modes.iter().rev().filter(|mode| !mode.is_major());
+        let major = buffer.minor_modes.iter().filter(|mode| mode.is_major());
+        minor
+            .chain(major)
+            .filter_map(|mode| self.modes.get(mode.name()))
+            .chain(std::iter::once(&self.global))
+            .collect()
+    }
+
+    pub fn lookup(&self, buffer: &Buffer, minibuffer: bool, keys: &[KeyPress]) -> Binding {
+        self.in_force(buffer, minibuffer)
+            .into_iter()
+            .map(|keymap| keymap.lookup(keys))
+            .find(|binding| !matches!(binding, Binding::Unbound))
+            .unwrap_or(Binding::Unbound)
+    }
+
+    // The keys that can come after the prefix `keys`, with what each does
+    pub fn continuations(&self, buffer: &Buffer, minibuffer: bool, keys: &[KeyPress]) -> Vec<(KeyPress, Binding)> {
+        let mut next: Vec<(KeyPress, Binding)> = vec!();
+        for keymap in self.in_force(buffer, minibuffer) {
+            for (key, binding) in keymap.continuations(keys) {
+                if !next.iter().any(|(seen, _)| *seen == key) {
+                    next.push((key, binding));
+                }
+            }
+        }
+        next
+    }
+}
+
+// What binding to `name` in `section` of a keys file does
+fn binding_named(name: &str, section: &str) -> Result<Binding, String> {
+    let name = match name {
+        "universal-argument" => return Ok(Binding::UniversalArgument),
+        "digit-argument" => return Ok(Binding::DigitArgument),
+        "repeat" => return Ok(Binding::Repeat),
+        "quoted-insert" => return Ok(Binding::QuotedInsert),
+        name => command_registry::find(name).ok_or_else(|| format!("{} is not a command", name))?.name,
+    };
+    Ok(match section {
+        "minibuffer" => Binding::MinibufferCommand(name),
+        "popup" => Binding::PopupCommand(name),
+        _ => Binding::Command(name),
+    })
+}
+
+// The keys file named by EDIT0R_KEYS, else ~/.config/edit0r/keys.toml when
+// there is one
+fn keys_file() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("EDIT0R_KEYS") {
+        return Some(PathBuf::from(path));
+    }
+    let path = PathBuf::from(std::env::var_os("HOME")?).join(".config/edit0r/keys.toml");
+    path.exists().then_some(path)
+}
+
+// The built-in keymaps with the keys file's bindings on top
+pub fn from_env() -> Result<Keymaps, String> {
+    let mut keymaps = Keymaps::default();
+    if let Some(path) = keys_file() {
+        let text = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        keymaps.bind_all(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(keymaps)
+}
+
+// Reads the keys file again, keeping the bindings as they were when it has a
+// mistake
+pub fn reload_keys(global: &mut Global, _: &mut Buffer) {
+    match from_env() {
+        Ok(keymaps) => {
+            global.keymaps = keymaps;
+            global.message("Reloaded the keys".to_string());
+        },
+        Err(e) => global.message(format!("Loading keys failed: {}", e)),
+    }
+}
+
+const GLOBAL_KEYS: &[(&str, Binding)] = &[
+    ("return", Binding::Command("newline-and-indent")),
+    ("S-return", Binding::Command("newline")),
+    ("tab", Binding::Command("indent-for-tab")),
+    ("S-tab", Binding::Command("backtab")),
+    ("backspace", Binding::Command("delete-backward-char")),
+    ("delete", Binding::Command("delete-char")),
+    ("insert", Binding::Command("overwrite-mode")),
+    ("left", Binding::Command("backward-char")),
+    ("right", Binding::Command("forward-char")),
+    ("up", Binding::Command("previous-line")),
+    ("down", Binding::Command("next-line")),
+    ("pagedown", Binding::Command("scroll-up")),
+    ("pageup", Binding::Command("scroll-down")),
+    ("C-v", Binding::Command("scroll-up")),
+    ("M-v", Binding::Command("scroll-down")),
+    ("home", Binding::Command("beginning-of-line")),
+    ("end", Binding::Command("end-of-line")),
+    ("C-b", Binding::Command("backward-char")),
+    ("C-f", Binding::Command("forward-char")),
+    ("C-p", Binding::Command("previous-line")),
+    ("C-n", Binding::Command("next-line")),
+    ("C-a", Binding::Command("beginning-of-line")),
+    ("C-e", Binding::Command("end-of-line")),
+    ("M-f", Binding::Command("forward-word")),
+    ("M-b", Binding::Command("backward-word")),
+    ("C-right", Binding::Command("forward-word")),
+    ("C-left", Binding::Command("backward-word")),
+    ("M-S-]", Binding::Command("forward-paragraph")),
+    ("M-S-[", Binding::Command("backward-paragraph")),
+    ("C-down", Binding::Command("forward-paragraph")),
+    ("C-up", Binding::Command("backward-paragraph")),
+    ("M-S-,", Binding::Command("beginning-of-buffer")),
+    ("M-S-.", Binding::Command("end-of-buffer")),
+    ("C-home", Binding::Command("beginning-of-buffer")),
+    ("C-end", Binding::Command("end-of-buffer")),
+    ("M-d", Binding::Command("kill-word")),
+    ("M-backspace", Binding::Command("backward-kill-word")),
+    ("C-delete", Binding::Command("delete-word")),
+    ("C-backspace", Binding::Command("backward-delete-word")),
+    ("C-k", Binding::Command("kill-line")),
+    ("C-t", Binding::Command("transpose-chars")),
+    ("M-t", Binding::Command("transpose-words")),
+    ("M-u", Binding::Command("upcase-word")),
+    ("M-l", Binding::Command("downcase-word")),
+    ("M-c", Binding::Command("capitalize-word")),
+    ("M-up", Binding::Command("move-lines-up")),
+    ("M-down", Binding::Command("move-lines-down")),
+    ("C-S-d", Binding::Command("duplicate-lines")),
+    ("C-S-j", Binding::Command("join-line")),
+    ("M-;", Binding::Command("toggle-comment")),
+    ("C-M-m", Binding::Command("jump-to-matching-bracket")),
+    ("C-/", Binding::Command("undo")),
+    ("C-S-/", Binding::Command("redo")),
+    ("C-space", Binding::Command("set-mark")),
+    ("C-g", Binding::Command("keyboard-quit")),
+    ("escape", Binding::Command("kill-editor")),
+    ("C-x C-c", Binding::Command("kill-editor")),
+    ("C-w", Binding::Command("kill-region")),
+    ("M-w", Binding::Command("copy-region")),
+    ("C-y", Binding::Command("yank")),
+    ("C-S-x", Binding::Command("kill-region")),
+    ("C-S-c", Binding::Command("copy-region")),
+    ("C-S-v", Binding::Command("yank")),
+    ("M-x", Binding::Command("execute-extended-command")),
+    ("C-h x", Binding::Command("describe-command")),
+    ("C-h .", Binding::Command("describe-syntax-at-point")),
+    ("C-M-i", Binding::Command("completion-at-point")),
+    ("C-x C-s", Binding::Command("save-buffer")),
+    ("C-x h", Binding::Command("mark-whole-buffer")),
+    ("C-x C-w", Binding::Command("write-file")),
+    ("C-x C-f", Binding::Command("find-file")),
+    ("C-x C-q", Binding::Command("toggle-read-only")),
+    ("C-x C-=", Binding::Command("text-scale-increase")),
+    ("C-x C--", Binding::Command("text-scale-decrease")),
+    ("C-x C-0", Binding::Command("text-scale-reset")),
+    ("C-x n n", Binding::Command("narrow-to-region")),
+    ("C-x n w", Binding::Command("widen")),
+    ("C-x return f", Binding::Command("cycle-line-ending")),
+    ("C-x x g", Binding::Command("revert-buffer")),
+    ("C-x x n", Binding::Command("scratch-buffer")),
+    ("C-x x i", Binding::Command("set-indent-width")),
+    ("C-x x t", Binding::Command("toggle-truncate-lines")),
+    ("C-x x l", Binding::Command("cycle-line-numbers")),
+    ("C-x x c", Binding::Command("cycle-cursor-style")),
+    ("C-x x b", Binding::Command("toggle-cursor-blink")),
+    ("C-x x h", Binding::Command("load-theme")),
+    ("C-x x f", Binding::Command("set-font")),
+    ("C-x x s", Binding::Command("toggle-smooth-scroll")),
+    ("C-x x m", Binding::Command("toggle-minimap")),
+    ("C-x x v", Binding::Command("scroll-bar-mode")),
+    ("C-x x e", Binding::Command("hl-line-mode")),
+    ("C-x x r", Binding::Command("fill-column-indicator-mode")),
+    ("C-x x d", Binding::Command("indent-guide-mode")),
+    ("C-x x p", Binding::Command("rainbow-delimiters-mode")),
+    ("C-x f", Binding::Command("set-fill-column")),
+    ("C-x b", Binding::Command("switch-to-buffer")),
+    ("C-x right", Binding::Command("next-buffer")),
+    ("C-x left", Binding::Command("previous-buffer")),
+    ("C-x k", Binding::Command("kill-buffer")),
+    ("C-x `", Binding::Command("next-error")),
+    ("M-S-4", Binding::Command("spell-correct-word")),
+    ("C-x v ]", Binding::Command("git-gutter-next-hunk")),
+    ("C-x v g", Binding::Command("git-blame-mode")),
+    ("C-x v [", Binding::Command("git-gutter-previous-hunk")),
+    ("C-x g", Binding::Command("git-status")),
+    ("M-g n", Binding::Command("next-error")),
+    ("M-g p", Binding::Command("previous-error")),
+    ("C-x 2", Binding::Command("split-window-below")),
+    ("C-x 3", Binding::Command("split-window-right")),
+    ("C-x o", Binding::Command("other-window")),
+    ("C-x 0", Binding::Command("delete-window")),
+    ("C-x 1", Binding::Command("delete-other-windows")),
+    ("C-x S-=", Binding::Command("balance-windows")),
+    ("C-tab", Binding::Command("tab-next")),
+    ("C-S-tab", Binding::Command("tab-previous")),
+    ("C-x t o", Binding::Command("tab-next")),
+    ("C-x t S-o", Binding::Command("tab-previous")),
+    ("C-x t m", Binding::Command("tab-move")),
+    ("C-x t S-m", Binding::Command("tab-move-back")),
+    ("C-x t 0", Binding::Command("kill-buffer")),
+    ("C-x t t", Binding::Command("toggle-tab-bar")),
+    ("C-x r k", Binding::Command("kill-rectangle")),
+    ("C-x r d", Binding::Command("delete-rectangle")),
+    ("C-x r y", Binding::Command("yank-rectangle")),
+    ("C-x r t", Binding::Command("string-rectangle")),
+    ("C-x r m", Binding::Command("bookmark-set")),
+    ("C-x r b", Binding::Command("bookmark-jump")),
+    ("C-M-s", Binding::Command("search-forward-regexp")),
+    ("C-M-r", Binding::Command("search-backward-regexp")),
+    ("M-S-5", Binding::Command("query-replace")),
+    ("C-M-S-5", Binding::Command("query-replace-regexp")),
+    ("C-c e", Binding::Command("toggle-electric-pair-mode")),
+    ("C-c =", Binding::Command("increment-number")),
+    ("C-c S-=", Binding::Command("increment-number")),
+    ("C-c -", Binding::Command("decrement-number")),
+    ("C-c s s", Binding::Command("sort-lines")),
+    ("C-c s n", Binding::Command("sort-lines-numerically")),
+    ("C-c s r", Binding::Command("reverse-lines")),
+    ("C-c s u", Binding::Command("delete-duplicate-lines")),
+    ("C-u", Binding::UniversalArgument),
+    ("M-0", Binding::DigitArgument),
+    ("M-1", Binding::DigitArgument),
+    ("M-2", Binding::DigitArgument),
+    ("M-3", Binding::DigitArgument),
+    ("M-4", Binding::DigitArgument),
+    ("M-5", Binding::DigitArgument),
+    ("M-6", Binding::DigitArgument),
+    ("M-7", Binding::DigitArgument),
+    ("M-8", Binding::DigitArgument),
+    ("M-9", Binding::DigitArgument),
+    ("M--", Binding::DigitArgument),
+    ("C-x z", Binding::Repeat),
+    ("C-x 8 return", Binding::Command("insert-char")),
+    ("C-q", Binding::QuotedInsert),
+];
+
+// Keys while the minibuffer is open
+const MINIBUFFER_KEYS: &[(&str, Binding)] = &[
+    ("return", Binding::MinibufferCommand("exit-minibuffer")),
+    ("tab", Binding::MinibufferCommand("minibuffer-complete")),
+    ("M-p", Binding::MinibufferCommand("previous-history-element")),
+    ("M-n", Binding::MinibufferCommand("next-history-element")),
+    ("C-p", Binding::MinibufferCommand("minibuffer-previous-completion")),
+    ("up", Binding::MinibufferCommand("minibuffer-previous-completion")),
+    ("C-n", Binding::MinibufferCommand("minibuffer-next-completion")),
+    ("down", Binding::MinibufferCommand("minibuffer-next-completion")),
+    ("C-g", Binding::MinibufferCommand("abort-minibuffer")),
+    ("escape", Binding::MinibufferCommand("abort-minibuffer")),
+];
+
+// Keys while a menu pops up, which any other key closes
+const POPUP_KEYS: &[(&str, Binding)] = &[
+    ("up", Binding::PopupCommand("popup-previous")),
+    ("C-p", Binding::PopupCommand("popup-previous")),
+    ("down", Binding::PopupCommand("popup-next")),
+    ("C-n", Binding::PopupCommand("popup-next")),
+    ("return", Binding::PopupCommand("popup-choose")),
+    ("tab", Binding::PopupCommand("popup-choose")),
+    ("escape", Binding::PopupCommand("popup-close")),
+    ("C-g", Binding::PopupCommand("popup-close")),
+];
+
+// Editing by syntax node, for the modes that parse the buffer
+const SYNTAX_TREE_KEYS: &[(&str, Binding)] = &[
+    ("C-M-space", Binding::Command("select-enclosing-node")),
+    ("C-=", Binding::Command("expand-selection")),
+    ("C--", Binding::Command("shrink-selection")),
+    ("C-M-n", Binding::Command("forward-sibling")),
+    ("C-M-p", Binding::Command("backward-sibling")),
+    ("M-r", Binding::Command("raise-node")),
+    ("M-s", Binding::Command("splice-node")),
+    ("C-M-e", Binding::Command("next-definition")),
+    ("C-M-a", Binding::Command("previous-definition")),
+    ("M-g i", Binding::Command("imenu")),
+];
+
+// Folding like Emacs' hideshow, under C-c @ as typed on a US layout
+const FOLD_KEYS: &[(&str, Binding)] = &[
+    ("C-c S-2 C-c", Binding::Command("toggle-fold")),
+    ("C-c S-2 C-h", Binding::Command("fold-block")),
+    ("C-c S-2 C-s", Binding::Command("unfold-block")),
+    ("C-c S-2 C-t", Binding::Command("fold-all")),
+    ("C-c S-2 C-a", Binding::Command("unfold-all")),
+];
+
+// Markdown's syntax tree keys, and hiding its markup like Emacs'
+// markdown-mode
+const MARKDOWN_KEYS: &[(&str, Binding)] = &[
+    ("C-M-e", Binding::Command("next-definition")),
+    ("C-M-a", Binding::Command("previous-definition")),
+    ("M-g i", Binding::Command("imenu")),
+    ("C-c C-x C-m", Binding::Command("markdown-toggle-markup-hiding")),
+];
+
+// Going through the hunks of a patch, as in Emacs' diff-mode
+const DIFF_KEYS: &[(&str, Binding)] = &[
+    ("M-n", Binding::Command("diff-next-change")),
+    ("M-p", Binding::Command("diff-previous-change")),
+];
+
+// Going through the changes between two buffers compared side by side
+const DIFF_SIDE_KEYS: &[(&str, Binding)] = &[
+    ("n", Binding::Command("diff-next-change")),
+    ("p", Binding::Command("diff-previous-change")),
+    ("M-n", Binding::Command("diff-next-change")),
+    ("M-p", Binding::Command("diff-previous-change")),
+    ("q", Binding::Command("kill-buffer")),
+];
+
+// Changing heading levels like Org does. Tab folds headings through the
+// mode's tab hook.
+const OUTLINE_KEYS: &[(&str, Binding)] = &[
+    ("M-left", Binding::Command("outline-promote")),
+    ("M-right", Binding::Command("outline-demote")),
+    ("M-S-left", Binding::Command("outline-promote-subtree")),
+    ("M-S-right", Binding::Command("outline-demote-subtree")),
+];
+
+// Typing to a program running in the buffer
+const COMINT_KEYS: &[(&str, Binding)] = &[
+    ("return", Binding::Command("comint-send-input")),
+    ("M-p", Binding::Command("comint-previous-input")),
+    ("M-n", Binding::Command("comint-next-input")),
+    ("C-a", Binding::Command("comint-beginning-of-line")),
+    ("home", Binding::Command("comint-beginning-of-line")),
+    ("C-c C-d", Binding::Command("comint-send-eof")),
+];
+
+// Going through what a build printed
+const COMPILATION_KEYS: &[(&str, Binding)] = &[
+    ("return", Binding::Command("compile-goto-error")),
+    ("n", Binding::Command("compilation-next-error")),
+    ("p", Binding::Command("compilation-previous-error")),
+    ("g", Binding::Command("recompile")),
+    ("q", Binding::Command("kill-buffer")),
+];
+
+// Going through the TODOs of a project like through a build's errors
+const TODO_LIST_KEYS: &[(&str, Binding)] = &[
+    ("return", Binding::Command("compile-goto-error")),
+    ("n", Binding::Command("compilation-next-error")),
+    ("p", Binding::Command("compilation-previous-error")),
+    ("g", Binding::Command("todo-list")),
+    ("q", Binding::Command("kill-buffer")),
+];
+
+// Working on the files of a directory listing, as in Emacs' dired
+const DIRED_KEYS: &[(&str, Binding)] = &[
+    ("return", Binding::Command("dired-find-file")),
+    ("f", Binding::Command("dired-find-file")),
+    ("S-6", Binding::Command("dired-up-directory")),
+    ("n", Binding::Command("dired-next-line")),
+    ("space", Binding::Command("dired-next-line")),
+    ("p", Binding::Command("dired-previous-line")),
+    ("g", Binding::Command("dired-revert")),
+    ("S-r", Binding::Command("dired-do-rename")),
+    ("S-c", Binding::Command("dired-do-copy")),
+    ("S-d", Binding::Command("dired-do-delete")),
+    ("S-=", Binding::Command("dired-create-directory")),
+    ("c", Binding::Command("dired-create-file")),
+    ("q", Binding::Command("kill-buffer")),
+];
+
+// Working on what's changed in a repository, as in Emacs' magit
+const GIT_STATUS_KEYS: &[(&str, Binding)] = &[
+    ("return", Binding::Command("git-status-visit")),
+    ("s", Binding::Command("git-status-stage")),
+    ("u", Binding::Command("git-status-unstage")),
+    ("k", Binding::Command("git-status-discard")),
+    ("c", Binding::Command("git-status-commit")),
+    ("S-p", Binding::Command("git-status-push")),
+    ("g", Binding::Command("git-status-refresh")),
+    ("n", Binding::Command("git-status-next")),
+    ("p", Binding::Command("git-status-previous")),
+    ("q", Binding::Command("kill-buffer")),
+];
+
+// Following the links goto-address-mode underlines
+const GOTO_ADDRESS_KEYS: &[(&str, Binding)] = &[
+    ("C-c return", Binding::Command("follow-link")),
+];
+
+// Answering query-replace about each match
+const QUERY_REPLACE_KEYS: &[(&str, Binding)] = &[
+    ("y", Binding::Command("query-replace-yes")),
+    ("space", Binding::Command("query-replace-yes")),
+    ("n", Binding::Command("query-replace-no")),
+    ("backspace", Binding::Command("query-replace-no")),
+    ("delete", Binding::Command("query-replace-no")),
+    ("S-1", Binding::Command("query-replace-all")),
+    (".", Binding::Command("query-replace-last")),
+    ("q", Binding::Command("query-replace-quit")),
+    ("return", Binding::Command("query-replace-quit")),
+    ("escape", Binding::Command("query-replace-quit")),
+    ("C-g", Binding::Command("query-replace-quit")),
+];
+
+// Keys in vim mode, whatever state it's in
+const VIM_KEYS: &[(&str, Binding)] = &[
+    ("escape", Binding::Command("vim-normal-state")),
+];
+
+// The keymaps modes start with, by the name of the mode
+const MODE_KEYS: &[(&str, &[(&str, Binding)])] = &[
+    ("rust", SYNTAX_TREE_KEYS),
+    ("python", SYNTAX_TREE_KEYS),
+    ("javascript", SYNTAX_TREE_KEYS),
+    ("typescript", SYNTAX_TREE_KEYS),
+    ("tsx", SYNTAX_TREE_KEYS),
+    ("go", SYNTAX_TREE_KEYS),
+    ("json", SYNTAX_TREE_KEYS),
+    ("markdown", MARKDOWN_KEYS),
+    ("outline", OUTLINE_KEYS),
+    ("diff", DIFF_KEYS),
+    ("diff-side", DIFF_SIDE_KEYS),
+    ("dired", DIRED_KEYS),
+    ("git-status", GIT_STATUS_KEYS),
+    ("comint", COMINT_KEYS),
+    ("compilation", COMPILATION_KEYS),
+    ("todo-list", TODO_LIST_KEYS),
+    ("fold", FOLD_KEYS),
+    ("goto-address", GOTO_ADDRESS_KEYS),
+    ("query-replace", QUERY_REPLACE_KEYS),
+    ("vim", VIM_KEYS),
+];
@@ -0,0 +1,77 @@
+// How lines end in a file. Buffers always hold "\n" and the file's own line
+// ending only comes back when writing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    // The most common line ending in `text` (LF when there are no lines to go
+    // by), and whether any other ones turn up too
+    pub fn detect(text: &str) -> (LineEnding, bool) {
+        let bytes = text.as_bytes();
+        let (mut lf, mut crlf, mut cr) = (0, 0, 0);
+        for (i, &byte) in bytes.iter().enumerate() {
+            match byte {
+                b'\n' if i > 0 && bytes[i - 1] == b'\r' => crlf += 1,
+                b'\n' => lf += 1,
+                b'\r' if bytes.get(i + 1) != Some(&b'\n') => cr += 1,
+                _ => {},
+            }
+        }
+
+        let dominant = if crlf > lf && crlf >= cr {
+            LineEnding::CrLf
+        } else if cr > lf && cr > crlf {
+            LineEnding::Cr
+        } else {
+            LineEnding::Lf
+        };
+        let kinds = [lf, crlf, cr].iter().filter(|&&count| count > 0).count();
+        (dominant, kinds > 1)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+            LineEnding::Cr => "CR",
+        }
+    }
+
+    // LF, CRLF, CR and round again
+    pub fn next(self) -> LineEnding {
+        match self {
+            LineEnding::Lf => LineEnding::CrLf,
+            LineEnding::CrLf => LineEnding::Cr,
+            LineEnding::Cr => LineEnding::Lf,
+        }
+    }
+
+    // Turns text read from a file with this line ending into buffer text.
+    // Stray CRs in LF and CRLF files are left alone to show as ^M.
+    pub fn decode(self, text: &str) -> String {
+        match self {
+            LineEnding::Lf | LineEnding::CrLf => text.replace("\r\n", "\n"),
+            LineEnding::Cr => text.replace("\r\n", "\n").replace('\r', "\n"),
+        }
+    }
+
+    // Turns buffer text back into text for the file
+    pub fn encode(self, text: &str) -> String {
+        match self {
+            LineEnding::Lf => text.to_string(),
+            _ => text.replace('\n', self.as_str()),
+        }
+    }
+}
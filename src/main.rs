@@ -1,20 +1,93 @@
+mod abbrev_mode;
+mod auto_mode;
+mod auto_save;
+mod backup;
+mod bookmark;
+mod buffer;
+mod buffer_local;
+mod buffer_list;
+mod color_preview_mode;
+mod comint;
+mod command_registry;
+mod commands;
+mod compilation;
+mod completion;
+mod context_menu;
+mod cursor;
+mod damage;
+mod diff_mode;
+mod dired;
+mod electric_pair_mode;
+mod event_script;
+mod face_spans;
+mod fold_mode;
+mod font;
+mod frame_rate;
+mod gap_buffer;
+mod git_blame;
+mod git_gutter;
+mod git_status;
+mod go_mode;
+mod goto_address_mode;
+mod grammars;
+mod hl_line_mode;
+mod hl_todo;
+mod ime;
+mod indent_guide_mode;
+mod javascript_mode;
+mod json_mode;
+mod keymap;
+mod line_ending;
+mod markdown_mode;
+mod marker;
+mod minibuffer;
+mod minimap_mode;
 mod mode;
+mod modeline;
+mod motion;
+mod outline_mode;
+mod overwrite_mode;
+mod popup;
+mod python_mode;
+mod rainbow_delimiters_mode;
+mod rectangle;
+mod rust_mode;
+mod scroll_bar;
+mod search;
+mod show_paren;
+mod snippet_mode;
+mod spell_mode;
+mod tab_bar;
+mod text_content;
 mod text_mode;
+mod theme;
+mod tree_sitter_mode;
+mod undo;
+mod undo_file;
+mod unicode;
+mod unicode_names;
+mod view;
+mod vim_mode;
+mod whitespace_cleanup_mode;
+mod window;
 
+use font::FontSettings;
+use font::Fonts;
+use frame_rate::FrameRate;
 use mode::MajorMode;
 use text_mode::TextMode;
 
-use std::path::Path;
-
 use sdl2::Sdl;
 use sdl2::render::Canvas;
-use sdl2::ttf::Font;
+use sdl2::ttf::Sdl2TtfContext;
 use sdl2::video::Window;
 
 pub struct RenderContext<'a> {
     sdl: &'a Sdl,
     canvas: &'a mut Canvas<Window>,
-    font: &'a Font<'a, 'a>,
+    // For loading the fonts again at another size
+    ttf: &'a Sdl2TtfContext,
+    fonts: &'a Fonts<'a>,
 }
 
 fn main() -> Result<(), String> {
@@ -25,23 +98,38 @@ fn main() -> Result<(), String> {
 
     static SCREEN_WIDTH: u32 = 1000;
     static SCREEN_HEIGHT: u32 = 800;
-    let window = video_subsys
-        .window("edit0r", SCREEN_WIDTH, SCREEN_HEIGHT)
-        .position_centered()
-        .opengl()
-        .build()
-        .map_err(|e| e.to_string())?;
+    static MIN_COLS: u32 = 20;
+    static MIN_ROWS: u32 = 4;
+    let mut window = video_subsys.window("edit0r", SCREEN_WIDTH, SCREEN_HEIGHT);
+    window.position_centered().resizable().allow_highdpi();
+    // The dummy driver, used to replay scripts without a display, has no OpenGL
+    if video_subsys.current_video_driver() != "dummy" {
+        window.opengl();
+    }
+    let window = window.build().map_err(|e| e.to_string())?;
 
-    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    let mut canvas = window.into_canvas();
+    if FrameRate::from_env().vsync {
+        canvas = canvas.present_vsync();
+    }
+    let mut canvas = canvas.build().map_err(|e| e.to_string())?;
 
-    // Load a font
-    let font_path = Path::new("assets/VeraMono.ttf");
-    let font = ttf_context.load_font(font_path, 20)?;
+    // Load the fonts, at the size that suits the display
+    let settings = FontSettings::from_env();
+    let size = font::point_size(settings.size, font::display_scale(canvas.window()), 0);
+    let fonts = Fonts::load(&ttf_context, &settings, size)?;
+
+    // Small enough to shrink out of the way, but still a few lines of text
+    let (char_width, char_height) = fonts.cell_size()?;
+    canvas.window_mut()
+        .set_minimum_size(char_width * MIN_COLS, char_height * MIN_ROWS)
+        .map_err(|e| e.to_string())?;
 
     let mut context = RenderContext {
         sdl: &sdl_context,
         canvas: &mut canvas,
-        font: &font,
+        ttf: &ttf_context,
+        fonts: &fonts,
     };
 
     TextMode {}.draw(&mut context)
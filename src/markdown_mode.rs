@@ -0,0 +1,185 @@
+use std::ops::Range;
+
+use tree_sitter::Node;
+use tree_sitter::Tree;
+
+use crate::buffer::Buffer;
+use crate::buffer_local::Variable;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+use crate::tree_sitter_mode::Grammar;
+use crate::tree_sitter_mode::Language;
+
+// The grammar comes without a highlight query, so this is our own
+const MARKDOWN_HIGHLIGHT_QUERY: &str = r#"
+[(atx_heading) (setext_heading)] @text.title
+(emphasis) @text.emphasis
+(strong_emphasis) @text.strong
+[(code_span) (fenced_code_block) (indented_code_block)] @text.literal
+[(link_destination) (uri_autolink) (email_autolink) (www_autolink)] @text.uri
+[(link_text) (image_description)] @text.reference
+(html_comment) @comment
+[(list_marker) (thematic_break)] @punctuation.special
+"#;
+
+// Code in fences is highlighted as the language named after the backticks
+const MARKDOWN_INJECTION_QUERY: &str = r#"
+(fenced_code_block
+  (info_string) @injection.language
+  (code_fence_content) @injection.content)
+"#;
+
+const MARKDOWN_FOLD_QUERY: &str = r#"
+[
+  (fenced_code_block)
+  (indented_code_block)
+  (html_block)
+  (tight_list)
+  (loose_list)
+  (block_quote)
+] @fold
+"#;
+
+// Headings, to jump between
+const MARKDOWN_TAGS_QUERY: &str = r#"
+(atx_heading (heading_content) @name) @definition.heading
+(setext_heading (heading_content) @name) @definition.heading
+"#;
+
+pub const MARKDOWN: Language = Language {
+    name: "markdown",
+    grammar: Grammar::Builtin(tree_sitter_markdown::language),
+    highlight_queries: &[MARKDOWN_HIGHLIGHT_QUERY],
+    indent_query: None,
+    comment_start: None,
+    context_menu: &[],
+    injection_query: Some(MARKDOWN_INJECTION_QUERY),
+    fold_query: Some(MARKDOWN_FOLD_QUERY),
+    tags_queries: &[MARKDOWN_TAGS_QUERY],
+};
+
+// Whether markup like the stars around emphasis is left out when drawing,
+// so only the faces show it
+const HIDE_MARKUP: Variable<bool> = Variable::new("markdown-hide-markup", false);
+
+// Renders Markdown beyond its highlighting: each level of heading gets its
+// own face, text.title.1 and on, and the markup can be hidden away
+pub struct MarkdownMode {
+}
+
+impl TextMinorMode for MarkdownMode {
+    fn name(&self) -> &'static str {
+        "markdown-view"
+    }
+
+    fn uses_syntax_tree(&self) -> bool {
+        true
+    }
+
+    fn modify_syntax(&mut self, global: &mut Global, content: &mut TextContent, tree: &Tree) {
+        let hide_markup = content.locals.get(&HIDE_MARKUP);
+        let (start, end) = content.visible_range();
+        let shown = content.offset(start)..content.offset(end);
+
+        let mut headings = vec!();
+        let mut markup = vec!();
+        let mut nodes = vec!(tree.root_node());
+        while let Some(node) = nodes.pop() {
+            if node.end_byte() < shown.start || node.start_byte() > shown.end {
+                continue;
+            }
+            if let Some(level) = heading_level(node) {
+                headings.push((node.byte_range(), level));
+            }
+            if hide_markup {
+                markup.extend(markup_bytes(node, content));
+            }
+
+            let mut walk = node.walk();
+            for child in node.children(&mut walk) {
+                nodes.push(child);
+            }
+        }
+
+        for (bytes, level) in headings {
+            if let Some(face_id) = global.faces.get_capture_face_id(&format!("text.title.{}", level)) {
+                let start = content.position_at(bytes.start);
+                let end = content.position_at(bytes.end);
+                content.faces.add(start, end, face_id);
+            }
+        }
+        for bytes in markup.into_iter().filter(|bytes| !bytes.is_empty()) {
+            let start = content.position_at(bytes.start);
+            let end = content.position_at(bytes.end);
+            content.invisible.add(start, end, 0);
+        }
+    }
+}
+
+pub fn extra_modes() -> Vec<Box<dyn TextMinorMode>> {
+    vec!(Box::new(MarkdownMode {}))
+}
+
+// From 1 for the biggest headings
+fn heading_level(node: Node) -> Option<usize> {
+    let mut walk = node.walk();
+    let level = match node.kind() {
+        "atx_heading" => node.children(&mut walk)
+            .find_map(|child| child.kind().strip_prefix("atx_h")?.strip_suffix("_marker")?.parse().ok()),
+        "setext_heading" => node.children(&mut walk)
+            .find_map(|child| child.kind().strip_prefix("setext_h")?.strip_suffix("_underline")?.parse().ok()),
+        _ => None,
+    };
+    level
+}
+
+// The byte ranges of `node` that are markup rather than text, leaving out
+// markup nested in its children
+fn markup_bytes(node: Node, content: &TextContent) -> Vec<Range<usize>> {
+    match node.kind() {
+        // The delimiters around the text
+        "emphasis" | "strong_emphasis" | "code_span" | "uri_autolink" | "email_autolink" => {
+            let first = node.named_child(0);
+            let last = node.named_child(node.named_child_count().saturating_sub(1));
+            match (first, last) {
+                (Some(first), Some(last)) => vec!(node.start_byte()..first.start_byte(), last.end_byte()..node.end_byte()),
+                _ => vec!(),
+            }
+        },
+        // Everything but what the link says, like the destination
+        "link" => {
+            let mut walk = node.walk();
+            let text = node.children(&mut walk).find(|child| child.kind() == "link_text");
+            match text {
+                Some(text) => vec!(node.start_byte()..text.start_byte(), text.end_byte()..node.end_byte()),
+                None => vec!(),
+            }
+        },
+        // The hashes and the space after them
+        "atx_heading" => {
+            let mut walk = node.walk();
+            let heading = node.children(&mut walk).find(|child| child.kind() == "heading_content");
+            match heading {
+                Some(heading) => {
+                    let start = content.position_at(heading.start_byte());
+                    let line = content.line(start.row);
+                    let spaces = line[start.col..].len() - line[start.col..].trim_start().len();
+                    let marker = node.start_byte()..heading.start_byte() + spaces;
+                    vec!(marker)
+                },
+                None => vec!(),
+            }
+        },
+        _ => vec!(),
+    }
+}
+
+// Shows or hides the markup of Markdown, which still shows on the cursor's
+// line for editing it
+pub fn toggle_markup_hiding(global: &mut Global, buffer: &mut Buffer) {
+    let hide = !buffer.content.locals.get(&HIDE_MARKUP);
+    buffer.content.locals.set(&HIDE_MARKUP, hide);
+    buffer.content.modified = true;
+    global.message(if hide { "Markup hidden" } else { "Markup shown" }.to_string());
+}
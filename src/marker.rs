@@ -0,0 +1,96 @@
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use crate::text_content::Position;
+
+static NEXT_OWNER: AtomicUsize = AtomicUsize::new(0);
+
+// A place in a TextContent that stays on the same text as it's edited
+// around, for whatever needs to remember places in a buffer past the next
+// edit, like bookmarks. The marker is a handle, which leads nowhere once
+// it's removed, nor in any other TextContent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Marker {
+    owner: usize,
+    index: usize,
+    generation: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Slot {
+    position: Position,
+    // Text inserted right at the marker goes after it rather than before
+    stick_left: bool,
+    // Bumped when the slot is freed, so old handles to it don't match
+    generation: u32,
+}
+
+// The markers of a TextContent. They sit in one flat list, and every edit
+// moves them all in a single pass with no allocation, which keeps up with
+// thousands of them. Freed slots are used again for the next ones added.
+pub struct Markers {
+    // Tells its markers from those of other contents
+    owner: usize,
+    slots: Vec<Slot>,
+    free: Vec<usize>,
+}
+
+impl Default for Markers {
+    fn default() -> Markers {
+        Markers { owner: NEXT_OWNER.fetch_add(1, Ordering::Relaxed), slots: vec!(), free: vec!() }
+    }
+}
+
+impl Markers {
+    // A marker at `at`. With `stick_left` it stays put when text is
+    // inserted right where it is, as the start of a range would.
+    pub fn add(&mut self, at: Position, stick_left: bool) -> Marker {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.position = at;
+                slot.stick_left = stick_left;
+                Marker { owner: self.owner, index, generation: slot.generation }
+            },
+            None => {
+                self.slots.push(Slot { position: at, stick_left, generation: 0 });
+                Marker { owner: self.owner, index: self.slots.len() - 1, generation: 0 }
+            },
+        }
+    }
+
+    fn slot(&self, marker: Marker) -> Option<&Slot> {
+        self.slots
+            .get(marker.index)
+            .filter(|slot| marker.owner == self.owner && marker.generation == slot.generation)
+    }
+
+    // Where `marker` is now, or None once it's been removed
+    pub fn get(&self, marker: Marker) -> Option<Position> {
+        self.slot(marker).map(|slot| slot.position)
+    }
+
+    pub fn remove(&mut self, marker: Marker) {
+        if self.slot(marker).is_some() {
+            let slot = &mut self.slots[marker.index];
+            slot.generation = slot.generation.wrapping_add(1);
+            self.free.push(marker.index);
+        }
+    }
+
+    pub fn after_insert(&mut self, at: Position, end: Position) {
+        for slot in &mut self.slots {
+            if slot.position > at || (slot.position == at && !slot.stick_left) {
+                slot.position = slot.position.after_insert(at, end);
+            }
+        }
+    }
+
+    pub fn after_delete(&mut self, start: Position, end: Position) {
+        for slot in &mut self.slots {
+            if slot.position > start {
+                slot.position = slot.position.after_delete(start, end);
+            }
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::buffer::Buffer;
+use crate::commands;
+use crate::completion;
+use crate::completion::Candidate;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+
+// Candidates for completing the input so far, which are narrowed down to the
+// ones matching it
+pub type Completer = fn(&str) -> Vec<String>;
+
+enum Candidates {
+    None,
+    Completer(Completer),
+    List(Vec<String>),
+}
+
+// Called with the main buffer and the input once Return is pressed
+pub type Callback = fn(&mut Global, &mut Buffer, &str);
+
+// A one line prompt at the bottom of the window. While it is open typing and
+// editing commands go to its input instead of the current buffer.
+pub struct Minibuffer {
+    pub prompt: String,
+    pub input: Buffer,
+    // The candidates matching the input, shown above it
+    pub completions: Vec<Candidate>,
+    // The candidate picked out with the arrow keys, if any, which Return
+    // gives instead of the input
+    pub selected: Option<usize>,
+    candidates: Candidates,
+    // The input the completions are for
+    completed_for: Option<String>,
+    on_done: Callback,
+    // How far back through the prompt's history the input came from, and
+    // what was typed before going back
+    history_index: Option<usize>,
+    draft: String,
+}
+
+// Inputs given to each prompt, oldest first
+#[derive(Default)]
+pub struct History {
+    inputs: HashMap<String, Vec<String>>,
+}
+
+impl History {
+    // Remembers `input`, moving it to the end when it was given before
+    fn add(&mut self, prompt: &str, input: &str) {
+        if input.trim().is_empty() {
+            return;
+        }
+        let inputs = self.inputs.entry(prompt.to_string()).or_default();
+        inputs.retain(|earlier| earlier != input);
+        inputs.push(input.to_string());
+    }
+
+    fn get(&self, prompt: &str) -> &[String] {
+        self.inputs.get(prompt).map_or(&[], |inputs| inputs.as_slice())
+    }
+}
+
+impl Minibuffer {
+    pub fn new(prompt: &str, initial: &str, completer: Option<Completer>, on_done: Callback) -> Minibuffer {
+        let candidates = completer.map_or(Candidates::None, Candidates::Completer);
+        Minibuffer::with_candidates(prompt, initial, candidates, on_done)
+    }
+
+    // A prompt completing from a fixed list, e.g. of buffer names
+    pub fn with_list(prompt: &str, initial: &str, list: Vec<String>, on_done: Callback) -> Minibuffer {
+        Minibuffer::with_candidates(prompt, initial, Candidates::List(list), on_done)
+    }
+
+    fn with_candidates(prompt: &str, initial: &str, candidates: Candidates, on_done: Callback) -> Minibuffer {
+        let mut input = Buffer::new("*minibuffer*", TextContent::new(initial), vec!());
+        input.content.cursor = input.content.end_of_buffer();
+        Minibuffer {
+            prompt: prompt.to_string(),
+            input,
+            completions: vec!(),
+            selected: None,
+            candidates,
+            completed_for: None,
+            on_done,
+            history_index: None,
+            draft: String::new(),
+        }
+    }
+
+    fn set_text(&mut self, text: &str) {
+        let content = &mut self.input.content;
+        let end = content.end_of_buffer();
+        content.replace_range(Default::default(), end, text);
+        content.cursor = content.end_of_buffer();
+    }
+
+    // Swaps the input for an older one given to the same prompt, or a newer
+    // one and finally what was being typed
+    fn step_history(&mut self, history: &History, older: bool) {
+        let inputs = history.get(&self.prompt);
+        let index = match (self.history_index, older) {
+            (None, true) if !inputs.is_empty() => {
+                self.draft = self.text();
+                Some(0)
+            },
+            (Some(index), true) if index + 1 < inputs.len() => Some(index + 1),
+            (Some(0), false) => None,
+            (Some(index), false) => Some(index - 1),
+            _ => return,
+        };
+
+        self.history_index = index;
+        let text = match index {
+            Some(index) => inputs[inputs.len() - 1 - index].clone(),
+            None => std::mem::take(&mut self.draft),
+        };
+        self.set_text(&text);
+    }
+
+    pub fn text(&self) -> String {
+        self.input.content.text()
+    }
+
+    // Narrows the candidates down again after the input changed
+    pub fn update_completions(&mut self) {
+        let text = self.text();
+        if self.completed_for.as_ref() == Some(&text) {
+            return;
+        }
+        let candidates = match &self.candidates {
+            Candidates::None => return,
+            Candidates::Completer(completer) => completer(&text),
+            Candidates::List(list) => list.clone(),
+        };
+        self.completions = completion::filter(&text, &candidates);
+        self.selected = None;
+        self.completed_for = Some(text);
+    }
+
+    // Picks out the next or previous candidate, going round at the ends.
+    // Returns false when there are none.
+    fn step_completion(&mut self, forward: bool) -> bool {
+        let len = self.completions.len();
+        if len == 0 {
+            return false;
+        }
+        self.selected = Some(match (self.selected, forward) {
+            (None, true) => 0,
+            (None, false) => len - 1,
+            (Some(index), true) => (index + 1) % len,
+            (Some(index), false) => (index + len - 1) % len,
+        });
+        true
+    }
+
+    // Replaces the input with the picked out candidate, or the best one
+    fn complete(&mut self) {
+        self.update_completions();
+        if let Some(candidate) = self.completions.get(self.selected.unwrap_or(0)) {
+            let text = candidate.text.clone();
+            self.set_text(&text);
+        }
+    }
+
+    // What Return gives the callback
+    fn answer(&self) -> String {
+        match self.selected.and_then(|index| self.completions.get(index)) {
+            Some(candidate) => candidate.text.clone(),
+            None => self.text(),
+        }
+    }
+}
+
+pub fn exit_minibuffer(global: &mut Global, buffer: &mut Buffer) {
+    if let Some(minibuffer) = global.minibuffer.take() {
+        let text = minibuffer.answer();
+        global.minibuffer_history.add(&minibuffer.prompt, &text);
+        // Answering edits the buffer as the command that prompted would
+        commands::run_guarded(global, buffer, |global, buffer| (minibuffer.on_done)(global, buffer, &text));
+    }
+}
+
+pub fn previous_history_element(global: &mut Global, _: &mut Buffer) {
+    if let Some(minibuffer) = &mut global.minibuffer {
+        minibuffer.step_history(&global.minibuffer_history, true);
+    }
+}
+
+pub fn next_history_element(global: &mut Global, _: &mut Buffer) {
+    if let Some(minibuffer) = &mut global.minibuffer {
+        minibuffer.step_history(&global.minibuffer_history, false);
+    }
+}
+
+// Picks out the previous candidate, or goes back through the history when
+// there are none
+pub fn minibuffer_previous_completion(global: &mut Global, _: &mut Buffer) {
+    if let Some(minibuffer) = &mut global.minibuffer {
+        if !minibuffer.step_completion(false) {
+            minibuffer.step_history(&global.minibuffer_history, true);
+        }
+    }
+}
+
+pub fn minibuffer_next_completion(global: &mut Global, _: &mut Buffer) {
+    if let Some(minibuffer) = &mut global.minibuffer {
+        if !minibuffer.step_completion(true) {
+            minibuffer.step_history(&global.minibuffer_history, false);
+        }
+    }
+}
+
+pub fn minibuffer_complete(global: &mut Global, _: &mut Buffer) {
+    if let Some(minibuffer) = &mut global.minibuffer {
+        minibuffer.complete();
+    }
+}
+
+pub fn abort_minibuffer(global: &mut Global, _: &mut Buffer) {
+    global.minibuffer = None;
+}
+
+// Files and directories in the directory of the input, directories ending
+// in '/'
+pub fn complete_path(input: &str) -> Vec<String> {
+    let dir = match input.rfind('/') {
+        Some(i) => &input[..=i],
+        None => "",
+    };
+    let entries = match std::fs::read_dir(if dir.is_empty() { Path::new(".") } else { Path::new(dir) }) {
+        Ok(entries) => entries,
+        Err(_) => return vec!(),
+    };
+
+    let mut completions: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }))
+        })
+        .collect();
+    completions.sort();
+    completions
+}
@@ -0,0 +1,56 @@
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::TextMinorMode;
+use crate::view::View;
+
+// Character cells the minimap takes up on the right edge of the window
+pub const MINIMAP_COLS: usize = 10;
+
+// Shows a shrunken overview of the whole buffer beside the text, each line a
+// couple of pixels tall with its words as blocks of their syntax colors
+pub struct MinimapMode {
+}
+
+impl TextMinorMode for MinimapMode {
+    fn name(&self) -> &'static str {
+        "minimap"
+    }
+}
+
+// Pixels tall each line is in a minimap next to text `char_height` tall,
+// and wide each character is
+pub fn line_height(char_height: u32) -> u32 {
+    (char_height / 8).max(2)
+}
+
+pub fn char_width(char_height: u32) -> u32 {
+    (line_height(char_height) / 2).max(1)
+}
+
+// The first line in a minimap `lines` lines tall, which scrolls along to keep
+// the part of the buffer on screen in the middle
+pub fn first_line(content: &TextContent, view: &View, lines: usize) -> usize {
+    let (start, end) = content.visible_range();
+    let count = end.row - start.row + 1;
+    if count <= lines {
+        return start.row;
+    }
+    let centered = (view.top_row + view.rows / 2).saturating_sub(lines / 2);
+    centered.clamp(start.row, end.row + 1 - lines)
+}
+
+// Moves to the line at `y` pixels down a minimap next to text `char_height`
+// tall, bringing it to the middle of the screen
+pub fn jump(content: &mut TextContent, view: &mut View, char_height: u32, y: u32) {
+    let height = view.rows as u32 * char_height;
+    if y >= height {
+        return;
+    }
+    let line_height = line_height(char_height);
+    let first = first_line(content, view, (height / line_height) as usize);
+    let row = (first + (y / line_height) as usize).min(content.visible_range().1.row);
+
+    content.cursor = Position::new(row, 0);
+    content.clamp_to_narrowing();
+    view.top_row = row.saturating_sub(view.rows / 2);
+}
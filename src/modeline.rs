@@ -0,0 +1,62 @@
+use crate::buffer::Buffer;
+use crate::unicode::display_width;
+
+// A piece of a modeline and the face it shows in
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Segment {
+    pub text: String,
+    pub face: &'static str,
+}
+
+impl Segment {
+    pub fn new(text: String, face: &'static str) -> Segment {
+        Segment { text, face }
+    }
+}
+
+// How far through the buffer the window is: "All" of it showing, at the
+// "Top", at the "Bot"tom or the percentage of lines above it
+fn scroll_position(buffer: &Buffer) -> String {
+    let (start, end) = buffer.content.visible_range();
+    let top = buffer.view.top_row;
+    let at_top = top <= start.row;
+    let at_bottom = buffer.view.bottom_row(&buffer.content) >= end.row;
+    match (at_top, at_bottom) {
+        (true, true) => "All".to_string(),
+        (true, false) => "Top".to_string(),
+        (false, true) => "Bot".to_string(),
+        (false, false) => format!("{}%", (top - start.row) * 100 / (end.row - start.row + 1)),
+    }
+}
+
+// The modeline of a window showing `buffer`: whether it's modified, its
+// name, the cursor's line and column, how far through it the window is and
+// its modes, then whatever the modes add
+pub fn segments(buffer: &Buffer) -> Vec<Segment> {
+    let content = &buffer.content;
+    let flag = match (buffer.read_only, buffer.is_dirty()) {
+        (true, true) => "%*",
+        (true, false) => "%%",
+        (false, true) => "**",
+        (false, false) => "--",
+    };
+    let cursor = content.cursor;
+    let col = display_width(&content.line(cursor.row)[..cursor.col]);
+
+    let major = buffer.minor_modes.iter().find(|mode| mode.is_major()).map_or("text", |mode| mode.name());
+    let modes: Vec<&str> = std::iter::once(major)
+        .chain(buffer.minor_modes.iter().filter(|mode| !mode.is_major()).map(|mode| mode.name()))
+        .collect();
+
+    let mut segments = vec!(
+        Segment::new(format!(" {} ", flag), "mode-line"),
+        Segment::new(buffer.name.clone(), "mode-line-buffer-id"),
+        Segment::new(format!("  {}:{}  {}  ", cursor.row + 1, col, scroll_position(buffer)), "mode-line"),
+        Segment::new(format!("({})", modes.join(" ")), "mode-line-mode"),
+    );
+    for segment in buffer.minor_modes.iter().filter_map(|mode| mode.modeline_segment(content)) {
+        segments.push(Segment::new("  ".to_string(), "mode-line"));
+        segments.push(segment);
+    }
+    segments
+}
@@ -0,0 +1,106 @@
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+
+// Motions map a position to where the motion would move it, so the same
+// functions drive both cursor movement and the commands deleting over them
+pub type Motion = fn(&TextContent, Position) -> Position;
+
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// The start and end of the word around `pos`, or of the character at it
+// when it isn't in one
+pub fn word_at(content: &TextContent, pos: Position) -> (Position, Position) {
+    let line = content.line(pos.row);
+    let before: usize = line[..pos.col].chars().rev().take_while(|&c| is_word_char(c)).map(char::len_utf8).sum();
+    let after: usize = line[pos.col..].chars().take_while(|&c| is_word_char(c)).map(char::len_utf8).sum();
+    if before + after > 0 {
+        return (Position::new(pos.row, pos.col - before), Position::new(pos.row, pos.col + after));
+    }
+    (pos, content.next_position(pos).unwrap_or(pos))
+}
+
+fn is_blank_line(content: &TextContent, row: usize) -> bool {
+    content.line(row).trim().is_empty()
+}
+
+// Moves to the end of the next word
+pub fn forward_word(content: &TextContent, pos: Position) -> Position {
+    let mut pos = pos;
+    let mut in_word = false;
+    while let Some(c) = content.char_at(pos) {
+        if is_word_char(c) {
+            in_word = true;
+        } else if in_word {
+            break;
+        }
+        match content.next_position(pos) {
+            Some(next) => pos = next,
+            None => break,
+        }
+    }
+    pos
+}
+
+// Moves to the start of the previous word
+pub fn backward_word(content: &TextContent, pos: Position) -> Position {
+    let mut pos = pos;
+    let mut in_word = false;
+    while let Some(prev) = content.prev_position(pos) {
+        let is_word = content.char_at(prev).is_some_and(is_word_char);
+        if is_word {
+            in_word = true;
+        } else if in_word {
+            break;
+        }
+        pos = prev;
+    }
+    pos
+}
+
+pub fn beginning_of_line(_content: &TextContent, pos: Position) -> Position {
+    Position::new(pos.row, 0)
+}
+
+pub fn end_of_line(content: &TextContent, pos: Position) -> Position {
+    Position::new(pos.row, content.line_len(pos.row))
+}
+
+// Moves to the blank line after the current (or next) paragraph
+pub fn forward_paragraph(content: &TextContent, pos: Position) -> Position {
+    let last = content.line_count() - 1;
+    let mut row = pos.row;
+    while row < last && is_blank_line(content, row) {
+        row += 1;
+    }
+    while row < last && !is_blank_line(content, row) {
+        row += 1;
+    }
+
+    if is_blank_line(content, row) {
+        Position::new(row, 0)
+    } else {
+        end_of_line(content, Position::new(row, 0))
+    }
+}
+
+// Moves to the blank line before the current (or previous) paragraph
+pub fn backward_paragraph(content: &TextContent, pos: Position) -> Position {
+    let mut row = pos.row;
+    while row > 0 && is_blank_line(content, row - 1) {
+        row -= 1;
+    }
+    while row > 0 && !is_blank_line(content, row - 1) {
+        row -= 1;
+    }
+    Position::new(row.saturating_sub(1), 0)
+}
+
+pub fn beginning_of_buffer(_content: &TextContent, _pos: Position) -> Position {
+    Position::new(0, 0)
+}
+
+pub fn end_of_buffer(content: &TextContent, _pos: Position) -> Position {
+    content.end_of_buffer()
+}
@@ -0,0 +1,236 @@
+use crate::buffer::Buffer;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+// How many faces heading levels cycle through, named outline-1 and on
+const LEVELS: usize = 4;
+
+// Words starting a heading's title that say where its task is at
+const TODO_KEYWORDS: &[&str] = &["TODO", "NEXT", "WAITING"];
+const DONE_KEYWORDS: &[&str] = &["DONE", "CANCELLED"];
+
+// Outlines in the style of Org: headings start with a star for each level,
+// like "** Heading", and Tab on one folds away what's under it
+pub struct OutlineMode {
+}
+
+impl TextMinorMode for OutlineMode {
+    fn name(&self) -> &'static str {
+        "outline"
+    }
+
+    fn is_major(&self) -> bool {
+        true
+    }
+
+    fn comment_syntax(&self) -> Option<&'static str> {
+        Some("#")
+    }
+
+    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
+        let level_face_ids: Vec<usize> = (1..=LEVELS)
+            .filter_map(|level| global.faces.get_face_id(&format!("outline-{}", level)))
+            .collect();
+        let todo_face_id = global.faces.get_face_id("outline-todo");
+        let done_face_id = global.faces.get_face_id("outline-done");
+
+        let mut spans = vec!();
+        let (start, end) = content.visible_range();
+        for row in start.row..=end.row {
+            let line = content.line(row);
+            let level = match heading_level(&line) {
+                Some(level) => level,
+                None => continue,
+            };
+            if !level_face_ids.is_empty() {
+                spans.push((row, 0..line.len(), level_face_ids[(level - 1) % level_face_ids.len()]));
+            }
+
+            let title = line[level..].trim_start();
+            let keyword = title.split_whitespace().next().unwrap_or("");
+            let face_id = if TODO_KEYWORDS.contains(&keyword) {
+                todo_face_id
+            } else if DONE_KEYWORDS.contains(&keyword) {
+                done_face_id
+            } else {
+                None
+            };
+            if let Some(face_id) = face_id {
+                let col = line.len() - title.len();
+                spans.push((row, col..col + keyword.len(), face_id));
+            }
+        }
+
+        for (row, cols, face_id) in spans {
+            content.faces.add(Position::new(row, cols.start), Position::new(row, cols.end), face_id);
+        }
+    }
+
+    // Tab on a heading cycles what's under it, Shift-Tab anywhere cycles
+    // the whole outline
+    fn tab(&mut self, content: &mut TextContent, backward: bool) -> bool {
+        if backward {
+            cycle_outline(content);
+            return true;
+        }
+        let row = content.cursor.row;
+        match heading_level(&content.line(row)) {
+            Some(level) => {
+                cycle_heading(content, row, level);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+// The number of stars a heading line starts with, or None for other lines
+fn heading_level(line: &str) -> Option<usize> {
+    let stars = line.len() - line.trim_start_matches('*').len();
+    (stars > 0 && (stars == line.len() || line[stars..].starts_with(' '))).then_some(stars)
+}
+
+// The headings from `row` down to the end of the content, by row and level
+fn headings_from(content: &TextContent, row: usize) -> Vec<(usize, usize)> {
+    (row..content.line_count())
+        .filter_map(|row| heading_level(&content.line(row)).map(|level| (row, level)))
+        .collect()
+}
+
+// The end of the last line under the heading at `row`, before the next one
+// at its level or above
+fn subtree_end(content: &TextContent, row: usize, level: usize) -> Position {
+    let end_row = headings_from(content, row + 1)
+        .into_iter()
+        .find(|&(_, other)| other <= level)
+        .map_or(content.line_count() - 1, |(next, _)| next - 1);
+    Position::new(end_row, content.line_len(end_row))
+}
+
+// The end of the text right under the heading at `row`, before any heading
+fn body_end(content: &TextContent, row: usize) -> Position {
+    let end_row = headings_from(content, row + 1)
+        .first()
+        .map_or(content.line_count() - 1, |&(next, _)| next - 1);
+    Position::new(end_row, content.line_len(end_row))
+}
+
+// Goes from the heading folded, to its children folded, to everything under
+// it shown, and back
+fn cycle_heading(content: &mut TextContent, row: usize, level: usize) {
+    let end = subtree_end(content, row, level);
+    let folded_under: Vec<usize> = content.folds()
+        .iter()
+        .map(|(start, _)| start.row)
+        .filter(|&start| row < start && start <= end.row)
+        .collect();
+
+    if content.is_folded(row) {
+        content.unfold(row);
+        let mut shallowest = usize::MAX;
+        for (child, child_level) in headings_from(content, row + 1).into_iter().take_while(|&(child, _)| child <= end.row) {
+            if child_level <= shallowest {
+                let child_end = subtree_end(content, child, child_level);
+                content.fold(Position::new(child, 0), child_end);
+                shallowest = child_level;
+            }
+        }
+    } else if !folded_under.is_empty() {
+        for start in folded_under {
+            content.unfold(start);
+        }
+    } else {
+        content.fold(Position::new(row, 0), end);
+    }
+}
+
+// Goes from the top headings alone, to every heading without the text under
+// them, to everything shown, and back
+fn cycle_outline(content: &mut TextContent) {
+    let headings = headings_from(content, 0);
+    let top = headings.iter().map(|&(_, level)| level).min().unwrap_or(1);
+    let overview: Vec<(Position, Position)> = headings.iter()
+        .filter(|&&(_, level)| level == top)
+        .map(|&(row, level)| (Position::new(row, 0), subtree_end(content, row, level)))
+        .filter(|(start, end)| start.row < end.row)
+        .collect();
+
+    if content.folds().is_empty() {
+        for (start, end) in overview {
+            content.fold(start, end);
+        }
+    } else if content.folds() == &overview[..] {
+        content.unfold_all();
+        for (row, _) in headings {
+            let end = body_end(content, row);
+            content.fold(Position::new(row, 0), end);
+        }
+    } else {
+        content.unfold_all();
+    }
+
+    // Rather than unfolding around the cursor again
+    let row = content.shown_row(content.cursor.row);
+    if row != content.cursor.row {
+        content.cursor = Position::new(row, 0);
+        content.deactivate_mark();
+    }
+}
+
+// The heading the cursor is under, by row and level
+fn current_heading(content: &TextContent) -> Option<(usize, usize)> {
+    (0..=content.cursor.row)
+        .rev()
+        .find_map(|row| heading_level(&content.line(row)).map(|level| (row, level)))
+}
+
+// Takes a star off or puts one on the heading the cursor is under, or every
+// heading in its subtree too
+fn change_levels(global: &mut Global, buffer: &mut Buffer, promote: bool, subtree: bool) {
+    let content = &mut buffer.content;
+    let (row, level) = match current_heading(content) {
+        Some(heading) => heading,
+        None => {
+            global.message("Not under a heading".to_string());
+            return;
+        },
+    };
+    let headings = if subtree {
+        let end = subtree_end(content, row, level);
+        headings_from(content, row).into_iter().take_while(|&(other, _)| other <= end.row).collect()
+    } else {
+        vec!((row, level))
+    };
+    if promote && headings.iter().any(|&(_, level)| level == 1) {
+        global.message("Can't promote a top level heading".to_string());
+        return;
+    }
+
+    content.begin_transaction();
+    for (row, _) in headings {
+        if promote {
+            content.delete_range(Position::new(row, 0), Position::new(row, 1));
+        } else {
+            content.insert(Position::new(row, 0), "*");
+        }
+    }
+    content.commit_transaction();
+}
+
+pub fn outline_promote(global: &mut Global, buffer: &mut Buffer) {
+    change_levels(global, buffer, true, false);
+}
+
+pub fn outline_demote(global: &mut Global, buffer: &mut Buffer) {
+    change_levels(global, buffer, false, false);
+}
+
+pub fn outline_promote_subtree(global: &mut Global, buffer: &mut Buffer) {
+    change_levels(global, buffer, true, true);
+}
+
+pub fn outline_demote_subtree(global: &mut Global, buffer: &mut Buffer) {
+    change_levels(global, buffer, false, true);
+}
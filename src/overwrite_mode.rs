@@ -0,0 +1,24 @@
+use crate::modeline::Segment;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+// Typed text replaces the characters under the cursor instead of pushing
+// them along
+pub struct OverwriteMode {
+}
+
+impl TextMinorMode for OverwriteMode {
+    fn name(&self) -> &'static str {
+        "overwrite"
+    }
+
+    fn self_insert(&mut self, _global: &mut Global, content: &mut TextContent, text: &str) -> bool {
+        content.self_overwrite(text);
+        true
+    }
+
+    fn modeline_segment(&self, _content: &TextContent) -> Option<Segment> {
+        Some(Segment::new("Ovwrt".to_string(), "mode-line-emphasis"))
+    }
+}
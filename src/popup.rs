@@ -0,0 +1,124 @@
+use crate::buffer::Buffer;
+use crate::text_content::Position;
+use crate::text_mode::Global;
+
+// Called with the line chosen from a menu
+pub type Action = fn(&mut Global, &mut Buffer, &str);
+
+// A box floating over the windows, under a place in the current buffer. Menus
+// have a line to choose, the others just show something until the next key.
+pub struct Popup {
+    pub lines: Vec<String>,
+    pub anchor: Position,
+    pub face: &'static str,
+    // The line picked out in a menu
+    pub selected: usize,
+    on_choose: Option<Action>,
+}
+
+impl Popup {
+    // Shows `lines` until the next key, like documentation
+    pub fn info(anchor: Position, lines: Vec<String>) -> Popup {
+        Popup { lines, anchor, face: "popup", selected: 0, on_choose: None }
+    }
+
+    // A menu of `lines`, calling `on_choose` with the one chosen
+    pub fn menu(anchor: Position, lines: Vec<String>, on_choose: Action) -> Popup {
+        Popup { lines, anchor, face: "popup", selected: 0, on_choose: Some(on_choose) }
+    }
+
+    pub fn is_menu(&self) -> bool {
+        self.on_choose.is_some()
+    }
+}
+
+// The popups open, the last one opened on top. Only the top one takes keys.
+#[derive(Default)]
+pub struct Popups {
+    stack: Vec<Popup>,
+    // Counts changes, so the frame is only drawn again after one
+    generation: usize,
+}
+
+impl Popups {
+    pub fn open(&mut self, popup: Popup) {
+        if !popup.lines.is_empty() {
+            self.stack.push(popup);
+            self.generation += 1;
+        }
+    }
+
+    // Bottom to top
+    pub fn iter(&self) -> impl Iterator<Item = &Popup> {
+        self.stack.iter()
+    }
+
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    pub fn has_menu(&self) -> bool {
+        self.stack.last().is_some_and(Popup::is_menu)
+    }
+
+    pub fn close_all(&mut self) {
+        if !self.stack.is_empty() {
+            self.stack.clear();
+            self.generation += 1;
+        }
+    }
+
+    // Closes the popups that only last until the next key
+    pub fn close_info(&mut self) {
+        let len = self.stack.len();
+        self.stack.retain(Popup::is_menu);
+        if self.stack.len() != len {
+            self.generation += 1;
+        }
+    }
+
+    fn step(&mut self, forward: bool) {
+        if let Some(menu) = self.stack.last_mut().filter(|popup| popup.is_menu()) {
+            let len = menu.lines.len();
+            menu.selected = if forward { (menu.selected + 1) % len } else { (menu.selected + len - 1) % len };
+            self.generation += 1;
+        }
+    }
+
+    // Picks out line `index` of popup `popup`, counting from the bottom one
+    pub fn select(&mut self, popup: usize, index: usize) {
+        if let Some(menu) = self.stack.get_mut(popup).filter(|menu| index < menu.lines.len()) {
+            menu.selected = index;
+            self.generation += 1;
+        }
+    }
+}
+
+pub fn popup_next(global: &mut Global, _: &mut Buffer) {
+    global.popups.step(true);
+}
+
+pub fn popup_previous(global: &mut Global, _: &mut Buffer) {
+    global.popups.step(false);
+}
+
+// Closes the top menu, and the popups under it, running its action with the
+// line picked out
+pub fn popup_choose(global: &mut Global, buffer: &mut Buffer) {
+    if !global.popups.has_menu() {
+        return;
+    }
+    let menu = match global.popups.stack.pop() {
+        Some(menu) => menu,
+        None => return,
+    };
+    global.popups.stack.clear();
+    global.popups.generation += 1;
+    if let (Some(on_choose), Some(line)) = (menu.on_choose, menu.lines.get(menu.selected)) {
+        on_choose(global, buffer, line);
+    }
+}
+
+pub fn popup_close(global: &mut Global, _: &mut Buffer) {
+    global.popups.close_all();
+}
@@ -0,0 +1,36 @@
+use crate::tree_sitter_mode::Grammar;
+use crate::tree_sitter_mode::Language;
+use crate::tree_sitter_mode::SYNTAX_ITEMS;
+
+// Blocks without brackets, which folding wouldn't find otherwise
+const PYTHON_FOLD_QUERY: &str = r#"
+[
+  (function_definition)
+  (class_definition)
+  (if_statement)
+  (elif_clause)
+  (else_clause)
+  (for_statement)
+  (while_statement)
+  (with_statement)
+  (try_statement)
+  (except_clause)
+  (finally_clause)
+  (match_statement)
+  (case_clause)
+] @fold
+"#;
+
+// Blocks are only closed by dedenting, which a query can't tell apart from
+// staying in the block, so new lines keep the indentation above
+pub const PYTHON: Language = Language {
+    name: "python",
+    grammar: Grammar::Builtin(tree_sitter_python::language),
+    highlight_queries: &[tree_sitter_python::HIGHLIGHT_QUERY],
+    indent_query: None,
+    comment_start: Some("#"),
+    context_menu: SYNTAX_ITEMS,
+    injection_query: None,
+    fold_query: Some(PYTHON_FOLD_QUERY),
+    tags_queries: &[tree_sitter_python::TAGGING_QUERY],
+};
@@ -0,0 +1,67 @@
+use tree_sitter::Tree;
+
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+use crate::tree_sitter_mode;
+
+// How many faces the colors cycle through, named rainbow-delimiters-depth-1
+// and on
+const DEPTHS: usize = 6;
+
+// Colors nested brackets by how deep they are, so matching pairs share a
+// color. Works in any language mode with a tree-sitter syntax tree.
+pub struct RainbowDelimitersMode {
+}
+
+impl TextMinorMode for RainbowDelimitersMode {
+    fn name(&self) -> &'static str {
+        "rainbow-delimiters"
+    }
+
+    fn uses_syntax_tree(&self) -> bool {
+        true
+    }
+
+    fn modify_syntax(&mut self, global: &mut Global, content: &mut TextContent, tree: &Tree) {
+        let face_ids: Vec<usize> = (1..=DEPTHS)
+            .filter_map(|depth| global.faces.get_face_id(&format!("rainbow-delimiters-depth-{}", depth)))
+            .collect();
+        if face_ids.is_empty() {
+            return;
+        }
+
+        // Nothing outside the narrowed part is shown
+        let (start, end) = content.visible_range();
+        let shown = content.offset(start)..content.offset(end);
+
+        let mut brackets = vec!();
+        let mut nodes = vec!((tree.root_node(), 0));
+        while let Some((node, depth)) = nodes.pop() {
+            if node.end_byte() < shown.start || node.start_byte() > shown.end {
+                continue;
+            }
+
+            let inner_depth = match tree_sitter_mode::delimiters(node) {
+                Some((open, close)) if open.kind() != "<" => {
+                    let face_id = face_ids[depth % face_ids.len()];
+                    brackets.push((open.byte_range(), face_id));
+                    brackets.push((close.byte_range(), face_id));
+                    depth + 1
+                },
+                _ => depth,
+            };
+
+            let mut walk = node.walk();
+            for child in node.children(&mut walk) {
+                nodes.push((child, inner_depth));
+            }
+        }
+
+        for (bytes, face_id) in brackets {
+            let start = content.position_at(bytes.start);
+            let end = content.position_at(bytes.end);
+            content.faces.add(start, end, face_id);
+        }
+    }
+}
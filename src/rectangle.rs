@@ -0,0 +1,115 @@
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+
+// A block of text spanning a range of lines between two columns. Columns
+// count characters rather than bytes so the block lines up on screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rectangle {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Rectangle {
+    // The rectangle with the mark and the cursor at opposite corners
+    pub fn from_region(content: &TextContent) -> Option<Rectangle> {
+        let mark = content.mark?;
+        let cursor = content.cursor;
+        let mark_column = char_column(&content.line(mark.row), mark.col);
+        let cursor_column = char_column(&content.line(cursor.row), cursor.col);
+
+        Some(Rectangle {
+            top: mark.row.min(cursor.row),
+            bottom: mark.row.max(cursor.row),
+            left: mark_column.min(cursor_column),
+            right: mark_column.max(cursor_column),
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.right - self.left
+    }
+}
+
+// The character column of byte offset `col`
+pub fn char_column(line: &str, col: usize) -> usize {
+    line[..col].chars().count()
+}
+
+// The byte offset of character column `column`, or the line end past it
+pub fn byte_column(line: &str, column: usize) -> usize {
+    line.char_indices().nth(column).map_or(line.len(), |(i, _)| i)
+}
+
+// Pads line `row` with spaces so it is at least `column` characters wide
+fn pad_line_to(content: &mut TextContent, row: usize, column: usize) {
+    let width = content.line(row).chars().count();
+    if width < column {
+        let end = Position::new(row, content.line_len(row));
+        content.insert(end, &" ".repeat(column - width));
+    }
+}
+
+// The text of each line inside the rectangle, padded out to its width
+pub fn extract_rectangle(content: &TextContent, rect: &Rectangle) -> Vec<String> {
+    (rect.top..=rect.bottom)
+        .map(|row| {
+            let line = content.line(row);
+            let piece = &line[byte_column(&line, rect.left)..byte_column(&line, rect.right)];
+            format!("{:width$}", piece, width = rect.width())
+        })
+        .collect()
+}
+
+pub fn delete_rectangle(content: &mut TextContent, rect: &Rectangle) -> Vec<String> {
+    let pieces = extract_rectangle(content, rect);
+    content.begin_transaction();
+    for row in rect.top..=rect.bottom {
+        let line = content.line(row);
+        let start = Position::new(row, byte_column(&line, rect.left));
+        let end = Position::new(row, byte_column(&line, rect.right));
+        content.delete_range(start, end);
+    }
+    content.commit_transaction();
+    content.cursor = Position::new(rect.top, byte_column(&content.line(rect.top), rect.left));
+    pieces
+}
+
+// Inserts each piece on successive lines at the cursor's column, adding
+// lines at the end of the buffer when the rectangle runs past it
+pub fn insert_rectangle(content: &mut TextContent, pieces: &[String]) {
+    let top = content.cursor.row;
+    let column = char_column(&content.line(top), content.cursor.col);
+
+    let mut end = content.cursor;
+    content.begin_transaction();
+    for (i, piece) in pieces.iter().enumerate() {
+        let row = top + i;
+        if row == content.line_count() {
+            let buffer_end = content.end_of_buffer();
+            content.insert(buffer_end, "\n");
+        }
+
+        pad_line_to(content, row, column);
+        let at = Position::new(row, byte_column(&content.line(row), column));
+        if let Some(piece_end) = content.insert(at, piece) {
+            end = piece_end;
+        }
+    }
+    content.commit_transaction();
+    content.cursor = end;
+}
+
+// Replaces the contents of the rectangle on each line with `text`
+pub fn string_rectangle(content: &mut TextContent, rect: &Rectangle, text: &str) {
+    content.begin_transaction();
+    for row in rect.top..=rect.bottom {
+        pad_line_to(content, row, rect.left);
+        let line = content.line(row);
+        let start = Position::new(row, byte_column(&line, rect.left));
+        let end = Position::new(row, byte_column(&line, rect.right));
+        content.replace_range(start, end, text);
+    }
+    content.commit_transaction();
+}
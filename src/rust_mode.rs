@@ -0,0 +1,77 @@
+use crate::abbrev_mode::AbbrevMode;
+use crate::abbrev_mode::AbbrevTable;
+use crate::snippet_mode::SnippetMode;
+use crate::text_mode::TextMinorMode;
+use crate::tree_sitter_mode::Grammar;
+use crate::tree_sitter_mode::Language;
+use crate::tree_sitter_mode::SYNTAX_ITEMS;
+
+// Nodes whose contents are indented one level. An unclosed "{" (which
+// tree-sitter leaves inside an ERROR) indents everything after it.
+const RUST_INDENT_QUERY: &str = r#"
+[
+  (block)
+  (declaration_list)
+  (field_declaration_list)
+  (ordered_field_declaration_list)
+  (enum_variant_list)
+  (field_initializer_list)
+  (match_block)
+  (use_list)
+  (arguments)
+  (parameters)
+  (array_expression)
+  (tuple_expression)
+  (token_tree)
+] @indent
+
+(ERROR "{" @indent.open)
+"#;
+
+// The grammar comes without a tags query, so this one is ours
+const RUST_TAGS_QUERY: &str = r#"
+(function_item name: (identifier) @name) @definition.function
+(function_signature_item name: (identifier) @name) @definition.function
+(struct_item name: (type_identifier) @name) @definition.class
+(enum_item name: (type_identifier) @name) @definition.class
+(union_item name: (type_identifier) @name) @definition.class
+(type_item name: (type_identifier) @name) @definition.type
+(trait_item name: (type_identifier) @name) @definition.interface
+(impl_item type: (_) @name) @definition.implementation
+(mod_item name: (identifier) @name) @definition.module
+(macro_definition name: (identifier) @name) @definition.macro
+(const_item name: (identifier) @name) @definition.constant
+(static_item name: (identifier) @name) @definition.constant
+"#;
+
+pub const RUST: Language = Language {
+    name: "rust",
+    grammar: Grammar::Builtin(tree_sitter_rust::language),
+    highlight_queries: &[tree_sitter_rust::HIGHLIGHT_QUERY],
+    indent_query: Some(RUST_INDENT_QUERY),
+    comment_start: Some("//"),
+    context_menu: SYNTAX_ITEMS,
+    injection_query: None,
+    fold_query: None,
+    tags_queries: &[RUST_TAGS_QUERY],
+};
+
+const RUST_ABBREVS: &[(&str, &str)] = &[
+    ("fn!", "fn main() {\n}"),
+    ("pc!", "println!(\"{:?}\", );"),
+];
+
+const RUST_SNIPPETS: &[(&str, &str)] = &[
+    ("fn", "fn ${1:name}($2)${3: -> ()} {\n    $0\n}"),
+    ("impl", "impl ${1:Type} {\n    $0\n}"),
+    ("match", "match ${1:expr} {\n    ${2:_} => $0,\n}"),
+    ("for", "for ${1:item} in ${2:iter} {\n    $0\n}"),
+    ("test", "#[test]\nfn ${1:name}() {\n    $0\n}"),
+];
+
+pub fn extra_modes() -> Vec<Box<dyn TextMinorMode>> {
+    vec!(
+        Box::new(SnippetMode::new(RUST_SNIPPETS)),
+        Box::new(AbbrevMode::new(AbbrevTable::new(RUST_ABBREVS))),
+    )
+}
@@ -0,0 +1,49 @@
+use crate::text_content::TextContent;
+use crate::view::View;
+
+// Pixels wide the scroll bar on the right of each window is
+pub const SCROLL_BAR_WIDTH: u32 = 10;
+
+// Pixels tall the thumb is at least, so it stays easy to grab in long buffers
+const MIN_THUMB_HEIGHT: u32 = 16;
+
+// From EDIT0R_SCROLL_BAR, which is "on" (the default) or "off"
+pub fn enabled_from_env() -> bool {
+    std::env::var("EDIT0R_SCROLL_BAR").map_or(true, |setting| setting != "off")
+}
+
+// Lines in the visible part of the content
+fn total_rows(content: &TextContent) -> u64 {
+    let (start, end) = content.visible_range();
+    (end.row - start.row + 1) as u64
+}
+
+// The top and height of the thumb in a scroll bar `height` pixels tall,
+// covering the part of the content on screen
+pub fn thumb(content: &TextContent, view: &View, height: u32) -> (u32, u32) {
+    let total = total_rows(content);
+    let start = content.visible_range().0.row;
+    let shown = (view.bottom_row(content) + 1 - view.top_row) as u64;
+    let thumb_height = ((shown * height as u64 / total) as u32).clamp(MIN_THUMB_HEIGHT.min(height), height);
+    let top = ((view.top_row - start) as u64 * height as u64 / total) as u32;
+    (top.min(height - thumb_height), thumb_height)
+}
+
+// Scrolls so the top of the thumb is `y` pixels down a scroll bar `height`
+// pixels tall, taking the cursor along
+pub fn drag_to(content: &mut TextContent, view: &mut View, height: u32, y: i64) {
+    let (start, end) = content.visible_range();
+    let y = y.clamp(0, height as i64) as u64;
+    let row = (start.row + (y * total_rows(content) / height.max(1) as u64) as usize).min(end.row);
+    view.scroll_by(content, row as isize - view.top_row as isize);
+}
+
+// Pages towards `y` pixels down the scroll bar, when it's off the thumb
+pub fn page_towards(content: &mut TextContent, view: &mut View, height: u32, y: u32) {
+    let (top, thumb_height) = thumb(content, view, height);
+    if y < top {
+        view.scroll_by(content, -(view.page() as isize));
+    } else if y >= top + thumb_height {
+        view.scroll_by(content, view.page() as isize);
+    }
+}
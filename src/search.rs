@@ -0,0 +1,411 @@
+use regex::CaptureLocations;
+use regex::Match;
+use regex::Regex;
+
+use crate::buffer::Buffer;
+use crate::buffer_local::Variable;
+use crate::marker::Marker;
+use crate::minibuffer::Minibuffer;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+// Searching by regexp, and query-replace as in Emacs, which goes from
+// match to match asking whether to replace each: y or space replaces it,
+// n or backspace leaves it, ! replaces it and all the rest, . replaces it
+// and stops, and q stops. Replacements can have what the groups matched
+// put in with \1 or $1, ${name} for named groups and \& or $0 for the
+// whole match. With the region active only what's in it is replaced.
+
+const LAST_SEARCH: Variable<Option<String>> = Variable::new("last-search", None);
+
+// The regexp to replace, between the two prompts of query-replace, and
+// whether the replacement goes in as is
+const REPLACING: Variable<Option<(Regex, bool)>> = Variable::new("replacing", None);
+
+#[derive(Clone)]
+struct Query {
+    regex: Regex,
+    replacement: String,
+    // The replacement goes in as is, without groups put in
+    literal: bool,
+    // The start and end of the match being asked about
+    current: Option<(Marker, Marker)>,
+    // Where to look for the next match from, and up to where
+    next: Marker,
+    end: Marker,
+    // An empty match right at `next` is passed over, having just ended a match
+    skip_empty: bool,
+    replaced: usize,
+}
+
+const QUERY: Variable<Option<Query>> = Variable::new("query-replace", None);
+
+// The first match of `regex` in `text` at or after `from`, passing over an
+// empty one right at `from` when `skip_empty`
+fn find_from<'t>(regex: &Regex, locations: &mut CaptureLocations, text: &'t str, from: usize, skip_empty: bool) -> Option<Match<'t>> {
+    let found = regex.captures_read_at(locations, text, from)?;
+    if !(skip_empty && found.start() == from && found.end() == from) {
+        return Some(found);
+    }
+    let next = from + text[from..].chars().next()?.len_utf8();
+    regex.captures_read_at(locations, text, next)
+}
+
+// `replacement` with what the groups of the match at `locations` in `text`
+// matched put in for \1 to \9, $1, ${1} or ${name}, and the whole match for
+// \& or $0. \\ and $$ stand for \ and $.
+fn expand(regex: &Regex, locations: &CaptureLocations, text: &str, replacement: &str) -> String {
+    let group = |index: usize| locations.get(index).map_or("", |(start, end)| &text[start..end]);
+    let named = |name: &str| regex.capture_names().position(|group_name| group_name == Some(name)).map_or("", group);
+    let mut expanded = String::new();
+    let mut rest = replacement;
+    while let Some(i) = rest.find(['\\', '$']) {
+        expanded.push_str(&rest[..i]);
+        let sigil = if rest[i..].starts_with('\\') { '\\' } else { '$' };
+        let after = &rest[i + 1..];
+        rest = match (sigil, after.chars().next()) {
+            ('\\', Some('&')) => {
+                expanded.push_str(group(0));
+                &after[1..]
+            },
+            (_, Some(digit)) if digit.is_ascii_digit() => {
+                expanded.push_str(group(digit as usize - '0' as usize));
+                &after[1..]
+            },
+            ('$', Some('{')) if after.contains('}') => {
+                let close = after.find('}').unwrap_or(0);
+                let name = &after[1..close];
+                expanded.push_str(name.parse().map_or_else(|_| named(name), group));
+                &after[close + 1..]
+            },
+            (_, Some(c)) if c == sigil => {
+                expanded.push(c);
+                &after[1..]
+            },
+            _ => {
+                expanded.push(sigil);
+                after
+            },
+        };
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+fn compile(global: &mut Global, pattern: &str) -> Option<Regex> {
+    match Regex::new(pattern) {
+        Ok(regex) => Some(regex),
+        Err(e) => {
+            global.message(format!("Invalid regexp: {}", e));
+            None
+        },
+    }
+}
+
+// Moves the cursor to the next match of `regex` after it, or the previous
+// one before it
+fn search(global: &mut Global, content: &mut TextContent, regex: &Regex, forward: bool) -> bool {
+    let (start, end) = content.visible_range();
+    let (start, end) = (content.offset(start), content.offset(end));
+    let cursor = content.offset(content.cursor).clamp(start, end);
+    let text = content.text();
+    let text = &text[..end];
+    let mut locations = regex.capture_locations();
+    let found = if forward {
+        find_from(regex, &mut locations, text, cursor, true).map(|found| found.end())
+    } else {
+        let mut last = None;
+        let (mut at, mut skip_empty) = (start, false);
+        while let Some(found) = find_from(regex, &mut locations, text, at, skip_empty).filter(|found| found.start() < cursor) {
+            last = Some(found.start());
+            at = found.end();
+            skip_empty = true;
+        }
+        last
+    };
+    match found {
+        Some(offset) => {
+            content.cursor = content.position_at(offset);
+            content.reveal_cursor();
+            true
+        },
+        None => {
+            global.message(format!("Search failed: {}", regex.as_str()));
+            false
+        },
+    }
+}
+
+fn prompt_search(global: &mut Global, buffer: &mut Buffer, prompt: &str, on_done: fn(&mut Global, &mut Buffer, &str)) {
+    let initial = buffer.content.locals.get(&LAST_SEARCH).unwrap_or_default();
+    global.minibuffer = Some(Minibuffer::new(prompt, &initial, None, on_done));
+}
+
+// Goes to the end of the next match of a regexp, prompting for it with the
+// last one searched for
+pub fn search_forward_regexp(global: &mut Global, buffer: &mut Buffer) {
+    prompt_search(global, buffer, "Regexp search: ", |global, buffer, input| {
+        buffer.content.locals.set(&LAST_SEARCH, Some(input.to_string()));
+        if let Some(regex) = compile(global, input) {
+            search(global, &mut buffer.content, &regex, true);
+        }
+    });
+}
+
+// Goes to the start of the previous match of a regexp
+pub fn search_backward_regexp(global: &mut Global, buffer: &mut Buffer) {
+    prompt_search(global, buffer, "Regexp search backward: ", |global, buffer, input| {
+        buffer.content.locals.set(&LAST_SEARCH, Some(input.to_string()));
+        if let Some(regex) = compile(global, input) {
+            search(global, &mut buffer.content, &regex, false);
+        }
+    });
+}
+
+// Shows the match being asked about, and keeps keys other than the
+// answers from typing over it
+pub struct QueryReplaceMode {
+}
+
+impl TextMinorMode for QueryReplaceMode {
+    fn name(&self) -> &'static str {
+        "query-replace"
+    }
+
+    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
+        let current = content.locals.get(&QUERY).and_then(|query| query.current);
+        let (start, end) = match current.and_then(|(start, end)| Some((content.markers.get(start)?, content.markers.get(end)?))) {
+            Some(range) => range,
+            None => return,
+        };
+        if let Some(face_id) = global.faces.get_face_id("query-replace") {
+            content.faces.add(start, end, face_id);
+        }
+    }
+
+    fn self_insert(&mut self, global: &mut Global, _: &mut TextContent, _: &str) -> bool {
+        global.message("Answer y, n, !, . or q".to_string());
+        true
+    }
+}
+
+// Replaces the matches of a regexp one by one, asking about each
+pub fn query_replace_regexp(global: &mut Global, _: &mut Buffer) {
+    global.minibuffer = Some(Minibuffer::new("Query replace regexp: ", "", None, |global, buffer, input| {
+        if input.is_empty() {
+            return;
+        }
+        if let Some(regex) = compile(global, input) {
+            buffer.content.locals.set(&REPLACING, Some((regex, false)));
+            let prompt = format!("Query replace regexp {} with: ", input);
+            global.minibuffer = Some(Minibuffer::new(&prompt, "", None, start));
+        }
+    }));
+}
+
+// Like query-replace-regexp for a plain string, replaced by a plain string
+pub fn query_replace(global: &mut Global, _: &mut Buffer) {
+    global.minibuffer = Some(Minibuffer::new("Query replace: ", "", None, |global, buffer, input| {
+        if input.is_empty() {
+            return;
+        }
+        if let Some(regex) = compile(global, &regex::escape(input)) {
+            buffer.content.locals.set(&REPLACING, Some((regex, true)));
+            let prompt = format!("Query replace {} with: ", input);
+            global.minibuffer = Some(Minibuffer::new(&prompt, "", None, start));
+        }
+    }));
+}
+
+// Starts asking about the matches from the cursor on, or in the region
+fn start(global: &mut Global, buffer: &mut Buffer, replacement: &str) {
+    let (regex, literal) = match buffer.content.locals.get(&REPLACING) {
+        Some(replacing) => replacing,
+        None => return,
+    };
+    let content = &mut buffer.content;
+    let (start, end) = content.region().unwrap_or_else(|| (content.cursor, content.visible_range().1));
+    content.deactivate_mark();
+    let query = Query {
+        regex,
+        replacement: replacement.to_string(),
+        literal,
+        current: None,
+        next: content.markers.add(start, false),
+        end: content.markers.add(end, false),
+        skip_empty: false,
+        replaced: 0,
+    };
+    content.locals.set(&QUERY, Some(query));
+    if !buffer.has_minor_mode("query-replace") {
+        buffer.minor_modes.push(Box::new(QueryReplaceMode {}));
+    }
+    ask_next(global, buffer);
+}
+
+// The offsets of the start and end of the part being replaced in, while
+// both are still there
+fn bounds(content: &TextContent, query: &Query) -> Option<(usize, usize)> {
+    let next = content.offset(content.markers.get(query.next)?);
+    let end = content.offset(content.markers.get(query.end)?);
+    (next <= end).then_some((next, end))
+}
+
+// Goes to the next match and asks about it, or stops when there's none
+fn ask_next(global: &mut Global, buffer: &mut Buffer) {
+    let content = &mut buffer.content;
+    let mut query = match content.locals.get(&QUERY) {
+        Some(query) => query,
+        None => return,
+    };
+    if let Some((start, end)) = query.current.take() {
+        content.markers.remove(start);
+        content.markers.remove(end);
+    }
+    let text = content.text();
+    let mut locations = query.regex.capture_locations();
+    let found = bounds(content, &query)
+        .and_then(|(next, end)| find_from(&query.regex, &mut locations, &text[..end], next, query.skip_empty))
+        .map(|found| (found.start(), found.end()));
+    let (start, end) = match found {
+        Some((start, end)) => (content.position_at(start), content.position_at(end)),
+        None => {
+            content.locals.set(&QUERY, Some(query));
+            finish(global, buffer);
+            return;
+        },
+    };
+    let replacement = if query.literal {
+        query.replacement.clone()
+    } else {
+        expand(&query.regex, &locations, &text, &query.replacement)
+    };
+    global.message(format!("Replace {} with {}? (y, n, !, . or q)", content.text_range(start, end), replacement));
+    // Text typed right after the match isn't part of it
+    query.current = Some((content.markers.add(start, false), content.markers.add(end, true)));
+    content.cursor = end;
+    content.reveal_cursor();
+    content.locals.set(&QUERY, Some(query));
+}
+
+// Replaces the match at `start`, returning where its replacement ends
+fn replace_at(content: &mut TextContent, query: &Query, start: usize, end: usize, text: &str) -> Option<Position> {
+    let mut locations = query.regex.capture_locations();
+    let found = find_from(&query.regex, &mut locations, &text[..end], start, false).filter(|found| found.start() == start)?;
+    let replacement = if query.literal {
+        query.replacement.clone()
+    } else {
+        expand(&query.regex, &locations, text, &query.replacement)
+    };
+    let (from, to) = (content.position_at(found.start()), content.position_at(found.end()));
+    content.replace_range(from, to, &replacement)
+}
+
+// Where the match being asked about starts, while it's still there
+fn current_start(content: &TextContent, query: &Query) -> Option<usize> {
+    query.current.and_then(|(start, _)| content.markers.get(start)).map(|start| content.offset(start))
+}
+
+// Replaces the match being asked about and goes on from after it
+fn replace_current(buffer: &mut Buffer) {
+    let content = &mut buffer.content;
+    let mut query = match content.locals.get(&QUERY) {
+        Some(query) => query,
+        None => return,
+    };
+    let text = content.text();
+    let replaced = match (current_start(content, &query), bounds(content, &query)) {
+        (Some(start), Some((_, end))) => replace_at(content, &query, start, end, &text),
+        _ => None,
+    };
+    if let Some(replaced) = replaced {
+        content.markers.remove(query.next);
+        query.next = content.markers.add(replaced, false);
+        query.replaced += 1;
+    }
+    query.skip_empty = true;
+    content.locals.set(&QUERY, Some(query));
+}
+
+// Stops asking, saying how many matches were replaced
+fn finish(global: &mut Global, buffer: &mut Buffer) {
+    let content = &mut buffer.content;
+    if let Some(query) = content.locals.get(&QUERY) {
+        let current = query.current.map_or(vec!(), |(start, end)| vec!(start, end));
+        for marker in current.into_iter().chain([query.next, query.end]) {
+            content.markers.remove(marker);
+        }
+        global.message(format!("Replaced {} occurrence{}", query.replaced, if query.replaced == 1 { "" } else { "s" }));
+    }
+    content.locals.set(&QUERY, None);
+    buffer.minor_modes.retain(|mode| mode.name() != "query-replace");
+}
+
+pub fn query_replace_yes(global: &mut Global, buffer: &mut Buffer) {
+    replace_current(buffer);
+    ask_next(global, buffer);
+}
+
+pub fn query_replace_no(global: &mut Global, buffer: &mut Buffer) {
+    let content = &mut buffer.content;
+    if let Some(mut query) = content.locals.get(&QUERY) {
+        if let Some(end) = query.current.and_then(|(_, end)| content.markers.get(end)) {
+            content.markers.remove(query.next);
+            query.next = content.markers.add(end, false);
+        }
+        query.skip_empty = true;
+        content.locals.set(&QUERY, Some(query));
+    }
+    ask_next(global, buffer);
+}
+
+pub fn query_replace_last(global: &mut Global, buffer: &mut Buffer) {
+    replace_current(buffer);
+    finish(global, buffer);
+}
+
+// Replaces the match being asked about and all those after it, as one edit
+pub fn query_replace_all(global: &mut Global, buffer: &mut Buffer) {
+    let content = &mut buffer.content;
+    let mut query = match content.locals.get(&QUERY) {
+        Some(query) => query,
+        None => return,
+    };
+    let (from, end) = match (current_start(content, &query), bounds(content, &query)) {
+        (Some(from), Some((_, end))) => (from, end),
+        _ => {
+            finish(global, buffer);
+            return;
+        },
+    };
+    let text = content.text();
+    let mut locations = query.regex.capture_locations();
+    let mut replacements = vec!();
+    let (mut at, mut skip_empty) = (from, false);
+    while let Some(found) = find_from(&query.regex, &mut locations, &text[..end], at, skip_empty) {
+        let replacement = if query.literal {
+            query.replacement.clone()
+        } else {
+            expand(&query.regex, &locations, &text, &query.replacement)
+        };
+        replacements.push((content.position_at(found.start()), content.position_at(found.end()), replacement));
+        at = found.end();
+        skip_empty = true;
+    }
+    // From the last so the positions of the others stay right
+    content.begin_transaction();
+    for (start, end, replacement) in replacements.iter().rev() {
+        if content.replace_range(*start, *end, replacement).is_some() {
+            query.replaced += 1;
+        }
+    }
+    content.commit_transaction();
+    content.locals.set(&QUERY, Some(query));
+    finish(global, buffer);
+}
+
+pub fn query_replace_quit(global: &mut Global, buffer: &mut Buffer) {
+    finish(global, buffer);
+}
@@ -0,0 +1,86 @@
+use tree_sitter::Tree;
+
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::tree_sitter_mode;
+
+const BRACKETS: &[(char, char)] = &[
+    ('(', ')'),
+    ('[', ']'),
+    ('{', '}'),
+];
+
+// A bracket at the cursor and where its match is, if it has one
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct BracketMatch {
+    pub bracket: Position,
+    pub matching: Option<Position>,
+}
+
+fn is_bracket(c: char) -> bool {
+    BRACKETS.iter().any(|&(open, close)| c == open || c == close)
+}
+
+// The bracket the cursor is on, or else the one right before it
+fn bracket_near_cursor(content: &TextContent) -> Option<Position> {
+    let cursor = content.cursor;
+    if content.char_at(cursor).is_some_and(is_bracket) {
+        return Some(cursor);
+    }
+    content.prev_position(cursor).filter(|&pos| content.char_at(pos).is_some_and(is_bracket))
+}
+
+// The bracket near the cursor and its match. The syntax tree knows which
+// brackets are in strings and comments, without one the text is scanned.
+// The content is only parsed with `syntax_tree` when there is a bracket.
+pub fn find(content: &TextContent, syntax_tree: impl FnOnce() -> Option<Tree>) -> Option<BracketMatch> {
+    let bracket = bracket_near_cursor(content)?;
+    let matching = match syntax_tree() {
+        Some(tree) => tree_sitter_mode::matching_bracket(content, &tree, bracket)?,
+        None => scan_for_match(content, bracket),
+    };
+    Some(BracketMatch { bracket, matching })
+}
+
+// Counts brackets of the same kind forwards or backwards from the one at `at`
+fn scan_for_match(content: &TextContent, at: Position) -> Option<Position> {
+    let c = content.char_at(at)?;
+    let &(open, close) = BRACKETS.iter().find(|&&(open, close)| c == open || c == close)?;
+    let (start, end) = content.visible_range();
+
+    let mut depth = 0;
+    if c == open {
+        for row in at.row..=end.row {
+            let line = content.line(row);
+            let from = if row == at.row { at.col } else { 0 };
+            let to = if row == end.row { end.col.min(line.len()) } else { line.len() };
+            for (i, c) in line[from..to].char_indices() {
+                if c == open {
+                    depth += 1;
+                } else if c == close {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(Position::new(row, from + i));
+                    }
+                }
+            }
+        }
+    } else {
+        for row in (start.row..=at.row).rev() {
+            let line = content.line(row);
+            let from = if row == start.row { start.col } else { 0 };
+            let to = if row == at.row { at.col + c.len_utf8() } else { line.len() };
+            for (i, c) in line[from..to].char_indices().rev() {
+                if c == close {
+                    depth += 1;
+                } else if c == open {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(Position::new(row, from + i));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
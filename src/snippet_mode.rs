@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use crate::buffer::leading_whitespace;
+use crate::motion;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+// Snippet templates are plain text with tab stops: $1, ${1:default}, and $0
+// for where the cursor ends up once all the others have been visited
+enum Piece {
+    Text(String),
+    Field { index: usize, default: String },
+}
+
+fn parse_template(template: &str) -> Vec<Piece> {
+    let mut pieces = vec!();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some('$')) => {
+                text.push('$');
+                chars.next();
+            },
+            ('$', Some(next)) if next.is_ascii_digit() || *next == '{' => {
+                let braced = *next == '{';
+                if braced {
+                    chars.next();
+                }
+
+                let mut index = String::new();
+                while let Some(digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    index.push(*digit);
+                    chars.next();
+                }
+
+                let mut default = String::new();
+                if braced {
+                    if chars.peek() == Some(&':') {
+                        chars.next();
+                    }
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            break;
+                        }
+                        default.push(c);
+                    }
+                }
+
+                pieces.push(Piece::Text(std::mem::take(&mut text)));
+                pieces.push(Piece::Field {
+                    index: index.parse().unwrap_or(0),
+                    default,
+                });
+            },
+            _ => text.push(c),
+        }
+    }
+
+    pieces.push(Piece::Text(text));
+    pieces
+}
+
+struct Field {
+    index: usize,
+    start: Position,
+    end: Position,
+    // Still holding its default text, which gets replaced when typing
+    pristine: bool,
+}
+
+// An expanded snippet whose fields are being filled in
+struct ActiveSnippet {
+    // Ordered by visiting order, ending with $0
+    fields: Vec<Field>,
+    current: usize,
+    // TextContent::change_count() the field positions are up to date with
+    seen_changes: usize,
+}
+
+impl ActiveSnippet {
+    // Moves the fields along with any edits made since we last looked
+    fn catch_up(&mut self, content: &TextContent) {
+        for edit in content.changes_since(self.seen_changes) {
+            for field in &mut self.fields {
+                field.start = edit.shift(field.start, true);
+                field.end = edit.shift(field.end, false);
+            }
+        }
+        self.seen_changes = content.change_count();
+    }
+
+    fn contains(&self, pos: Position) -> bool {
+        self.fields.iter().any(|field| field.start <= pos && pos <= field.end)
+    }
+
+    fn is_on_last_field(&self) -> bool {
+        self.current + 1 >= self.fields.len()
+    }
+
+    // Puts the cursor at the end of the current field with its text selected
+    fn select_current(&self, content: &mut TextContent) {
+        let field = &self.fields[self.current];
+        content.cursor = field.end;
+        content.mark = if field.pristine && field.start != field.end {
+            Some(field.start)
+        } else {
+            None
+        };
+    }
+}
+
+pub struct SnippetMode {
+    // Trigger word to template
+    snippets: HashMap<String, String>,
+    active: Option<ActiveSnippet>,
+}
+
+impl SnippetMode {
+    pub fn new(snippets: &[(&str, &str)]) -> SnippetMode {
+        let mut mode = SnippetMode {
+            snippets: HashMap::new(),
+            active: None,
+        };
+        for (trigger, template) in snippets {
+            mode.define(trigger, template);
+        }
+        mode
+    }
+
+    pub fn define(&mut self, trigger: &str, template: &str) {
+        self.snippets.insert(trigger.to_string(), template.to_string());
+    }
+
+    // The start of the word before the cursor and its template, if it is a trigger
+    fn trigger_before_cursor(&self, content: &TextContent) -> Option<(Position, String)> {
+        let cursor = content.cursor;
+        let start = motion::backward_word(content, cursor);
+        if start.row != cursor.row {
+            return None;
+        }
+
+        let word = &content.line(cursor.row)[start.col..cursor.col];
+        self.snippets.get(word).map(|template| (start, template.clone()))
+    }
+
+    fn expand(&mut self, content: &mut TextContent, start: Position, template: &str) {
+        content.begin_transaction();
+        if content.delete_range(start, content.cursor).is_none() {
+            content.commit_transaction();
+            return;
+        }
+
+        // Continuation lines of the template keep the trigger line's indentation
+        let newline = format!("\n{}", leading_whitespace(&content.line(start.row)));
+
+        let mut at = start;
+        let mut fields = vec!();
+        // The trigger could be deleted, so the edit guard allows these too
+        for piece in parse_template(template) {
+            match piece {
+                Piece::Text(text) => {
+                    at = content.insert(at, &text.replace('\n', &newline)).unwrap_or(at);
+                },
+                Piece::Field { index, default } => {
+                    let end = content.insert(at, &default.replace('\n', &newline)).unwrap_or(at);
+                    fields.push(Field { index, start: at, end, pristine: true });
+                    at = end;
+                },
+            }
+        }
+
+        // $0 goes last, and the snippet end doubles as $0 when there isn't one
+        fields.sort_by_key(|field| if field.index == 0 { usize::MAX } else { field.index });
+        if fields.last().is_none_or(|field| field.index != 0) {
+            fields.push(Field { index: 0, start: at, end: at, pristine: false });
+        }
+        content.commit_transaction();
+
+        let snippet = ActiveSnippet {
+            fields,
+            current: 0,
+            seen_changes: content.change_count(),
+        };
+        snippet.select_current(content);
+        self.active = if snippet.is_on_last_field() { None } else { Some(snippet) };
+    }
+}
+
+impl TextMinorMode for SnippetMode {
+    fn name(&self) -> &'static str {
+        "snippet"
+    }
+
+    fn modify(&mut self, _global: &mut Global, content: &mut TextContent) {
+        if let Some(snippet) = &mut self.active {
+            snippet.catch_up(content);
+            if !snippet.contains(content.cursor) {
+                self.active = None;
+            }
+        }
+    }
+
+    // Typing into a field still showing its default replaces the default
+    fn self_insert(&mut self, _global: &mut Global, content: &mut TextContent, _text: &str) -> bool {
+        if let Some(snippet) = &mut self.active {
+            snippet.catch_up(content);
+            let field = &mut snippet.fields[snippet.current];
+            if field.pristine && content.cursor == field.end && content.mark == Some(field.start) {
+                content.delete_range(field.start, field.end);
+                content.deactivate_mark();
+            }
+            field.pristine = false;
+        }
+        false
+    }
+
+    fn tab(&mut self, content: &mut TextContent, backward: bool) -> bool {
+        if let Some(mut snippet) = self.active.take() {
+            snippet.catch_up(content);
+            if backward {
+                snippet.current = snippet.current.saturating_sub(1);
+            } else {
+                snippet.current += 1;
+            }
+            snippet.select_current(content);
+            if !snippet.is_on_last_field() {
+                self.active = Some(snippet);
+            }
+            return true;
+        }
+
+        if backward {
+            return false;
+        }
+
+        match self.trigger_before_cursor(content) {
+            Some((start, template)) => {
+                self.expand(content, start, &template);
+                true
+            },
+            None => false,
+        }
+    }
+}
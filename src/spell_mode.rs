@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::process::Child;
+use std::process::ChildStdin;
+use std::process::ChildStdout;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use tree_sitter::Tree;
+
+use crate::buffer::Buffer;
+use crate::motion::is_word_char;
+use crate::motion::word_at;
+use crate::popup::Popup;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+use crate::tree_sitter_mode;
+
+// Spell checking by a program speaking ispell's pipe protocol, hunspell
+// unless EDIT0R_SPELLER says otherwise, e.g. "aspell -a" or
+// "hunspell -a -d de_DE". Prose is checked throughout, except for code in
+// backticks; code only in its comments and strings.
+const DEFAULT_SPELLER: &str = "hunspell -a -i utf-8";
+
+fn speller_command() -> String {
+    std::env::var("EDIT0R_SPELLER").unwrap_or_else(|_| DEFAULT_SPELLER.to_string())
+}
+
+// A running spell checker, asked about one word at a time
+struct Speller {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Speller {
+    fn start() -> Result<Speller, String> {
+        let command = speller_command();
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or("EDIT0R_SPELLER is empty")?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("{}: {}", program, e))?;
+        let stdin = child.stdin.take().ok_or("No stdin")?;
+        let mut stdout = BufReader::new(child.stdout.take().ok_or("No stdout")?);
+
+        // It starts by saying which version of ispell it passes for
+        let mut banner = String::new();
+        stdout.read_line(&mut banner).map_err(|e| e.to_string())?;
+        if !banner.starts_with('@') {
+            return Err(format!("{} doesn't speak the ispell protocol", program));
+        }
+        Ok(Speller { child, stdin, stdout })
+    }
+
+    // None when `word` is spelled right, else what it might have meant
+    fn check(&mut self, word: &str) -> Result<Option<Vec<String>>, String> {
+        // The caret keeps words starting with ispell's commands from being taken as one
+        writeln!(self.stdin, "^{}", word).and_then(|_| self.stdin.flush()).map_err(|e| e.to_string())?;
+        let mut suggestions = None;
+        loop {
+            let mut line = String::new();
+            if self.stdout.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+                return Err("The spell checker stopped".to_string());
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                return Ok(suggestions);
+            }
+            // & word count offset: one, two
+            // # word offset, for no suggestions
+            // ? word count offset: guesses, from aspell
+            match line.chars().next() {
+                Some('&') | Some('?') => {
+                    let list = line.split_once(": ").map_or("", |(_, list)| list);
+                    suggestions = Some(list.split(", ").filter(|s| !s.is_empty()).map(str::to_string).collect());
+                },
+                Some('#') => suggestions = Some(vec!()),
+                _ => {},
+            }
+        }
+    }
+}
+
+impl Drop for Speller {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// Checks the words it's sent on its own thread, sending back which are
+// misspelled. Words it couldn't check are taken to be right.
+fn work(mut speller: Speller, jobs: Receiver<Vec<String>>, results: Sender<Vec<(String, bool)>>, ready: Arc<AtomicBool>) {
+    let mut working = true;
+    for words in jobs {
+        let checked = words.into_iter().map(|word| {
+            let misspelled = working && match speller.check(&word) {
+                Ok(suggestions) => suggestions.is_some(),
+                Err(_) => {
+                    working = false;
+                    false
+                },
+            };
+            (word, misspelled)
+        }).collect();
+        if results.send(checked).is_err() {
+            return;
+        }
+        ready.store(true, Ordering::SeqCst);
+    }
+}
+
+pub struct SpellMode {
+    // Checked throughout, rather than only in comments and strings
+    prose: bool,
+    // Whether each word checked was misspelled
+    checked: HashMap<String, bool>,
+    // Sent to be checked and not back yet
+    pending: HashSet<String>,
+    jobs: Sender<Vec<String>>,
+    results: Receiver<Vec<(String, bool)>>,
+    ready: Arc<AtomicBool>,
+}
+
+impl SpellMode {
+    fn new(prose: bool) -> Result<SpellMode, String> {
+        let speller = Speller::start()?;
+        let (jobs, job_receiver) = mpsc::channel();
+        let (result_sender, results) = mpsc::channel();
+        let ready = Arc::new(AtomicBool::new(false));
+        let thread_ready = ready.clone();
+        thread::Builder::new()
+            .name("speller".to_string())
+            .spawn(move || work(speller, job_receiver, result_sender, thread_ready))
+            .map_err(|e| e.to_string())?;
+        Ok(SpellMode { prose, checked: HashMap::new(), pending: HashSet::new(), jobs, results, ready })
+    }
+
+    // Underlines the misspelled words between each start and end, sending
+    // off the ones not checked yet
+    fn check(&mut self, global: &mut Global, content: &mut TextContent, ranges: &[(Position, Position)]) {
+        let face_id = global.faces.get_face_id("spell-error");
+        let mut misspelled = vec!();
+        let mut unchecked = vec!();
+        for &(start, end) in ranges {
+            for row in start.row..=end.row {
+                let line = content.line(row);
+                let from = if row == start.row { start.col } else { 0 };
+                let to = if row == end.row { end.col.min(line.len()) } else { line.len() };
+                for (col, word) in words(&line[from.min(to)..to]) {
+                    match self.checked.get(word) {
+                        Some(true) => misspelled.push((row, from + col, word.len())),
+                        Some(false) => {},
+                        None if !self.pending.contains(word) => {
+                            self.pending.insert(word.to_string());
+                            unchecked.push(word.to_string());
+                        },
+                        None => {},
+                    }
+                }
+            }
+        }
+        if !unchecked.is_empty() && self.jobs.send(unchecked).is_err() {
+            self.pending.clear();
+        }
+        if let Some(face_id) = face_id {
+            for (row, col, len) in misspelled {
+                content.faces.add(Position::new(row, col), Position::new(row, col + len), face_id);
+            }
+        }
+    }
+}
+
+impl TextMinorMode for SpellMode {
+    fn name(&self) -> &'static str {
+        "spell"
+    }
+
+    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
+        self.ready.store(false, Ordering::SeqCst);
+        while let Ok(checked) = self.results.try_recv() {
+            for (word, misspelled) in checked {
+                self.pending.remove(&word);
+                self.checked.insert(word, misspelled);
+            }
+        }
+        if self.prose {
+            let ranges = prose_ranges(content);
+            self.check(global, content, &ranges);
+        }
+    }
+
+    fn uses_syntax_tree(&self) -> bool {
+        !self.prose
+    }
+
+    fn modify_syntax(&mut self, global: &mut Global, content: &mut TextContent, tree: &Tree) {
+        let (start, end) = content.visible_range();
+        let mut ranges = vec!();
+        let wanted = |kind: &str| kind.contains("comment") || kind.contains("string");
+        tree_sitter_mode::find_nodes(tree.root_node(), start.row, end.row, wanted, &mut ranges);
+        self.check(global, content, &ranges);
+    }
+
+    fn has_results(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    fn is_busy(&self) -> bool {
+        !self.pending.is_empty()
+    }
+}
+
+// The words in `text` worth checking, by where they start. Ones with digits
+// or underscores, or in camelCase, are taken for code.
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut found = vec!();
+    let mut start = None;
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (start, is_word_char(c) || c == '\'') {
+            (None, true) => start = Some(i),
+            (Some(from), false) => {
+                let word = text[from..i].trim_matches('\'');
+                let from = from + text[from..i].find(word).unwrap_or(0);
+                let chars: Vec<char> = word.chars().collect();
+                let code = chars.iter().any(|&c| c.is_ascii_digit() || c == '_')
+                    || chars.windows(2).any(|pair| pair[0].is_lowercase() && pair[1].is_uppercase());
+                if chars.len() > 1 && !code {
+                    found.push((from, word));
+                }
+                start = None;
+            },
+            _ => {},
+        }
+    }
+    found
+}
+
+// The visible text of a prose buffer, leaving out fenced code blocks, code
+// in backticks and URLs
+fn prose_ranges(content: &TextContent) -> Vec<(Position, Position)> {
+    let is_fence = |line: &str| line.trim_start().starts_with("```") || line.trim_start().starts_with("~~~");
+    let (start, end) = content.visible_range();
+    let mut in_fence = (0..start.row).filter(|&row| is_fence(&content.line(row))).count() % 2 == 1;
+
+    let mut ranges = vec!();
+    for row in start.row..=end.row {
+        let line = content.line(row);
+        if is_fence(&line) {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        let mut col = 0;
+        for (i, part) in line.split('`').enumerate() {
+            if i % 2 == 0 {
+                let mut chunk_col = col;
+                for chunk in part.split(' ') {
+                    if !chunk.contains("://") && !chunk.contains('@') {
+                        ranges.push((Position::new(row, chunk_col), Position::new(row, chunk_col + chunk.len())));
+                    }
+                    chunk_col += chunk.len() + 1;
+                }
+            }
+            col += part.len() + 1;
+        }
+    }
+    ranges
+}
+
+// Turns checking spelling on or off, throughout the buffer when it's prose
+// and in comments and strings when it's code
+pub fn spell_mode(global: &mut Global, buffer: &mut Buffer) {
+    if buffer.has_minor_mode("spell") {
+        buffer.minor_modes.retain(|mode| mode.name() != "spell");
+        buffer.content.modified = true;
+        return;
+    }
+    let prose = buffer.syntax_tree().is_none() || buffer.has_minor_mode("markdown-view");
+    match SpellMode::new(prose) {
+        Ok(mode) => {
+            buffer.minor_modes.push(Box::new(mode));
+            buffer.content.modified = true;
+        },
+        Err(e) => global.message(format!("Spell checking failed: {}", e)),
+    }
+}
+
+// Offers what the word at the cursor might have meant in a menu, the one
+// chosen taking its place
+pub fn spell_correct_word(global: &mut Global, buffer: &mut Buffer) {
+    let content = &buffer.content;
+    let (start, end) = word_at(content, content.cursor);
+    let word = content.text_range(start, end);
+    let word = word.trim_matches('\'');
+    if word.is_empty() {
+        global.message("No word at the cursor".to_string());
+        return;
+    }
+    let checked = Speller::start().and_then(|mut speller| speller.check(word));
+    match checked {
+        Ok(None) => global.message(format!("{} is spelled right", word)),
+        Ok(Some(suggestions)) if suggestions.is_empty() => global.message(format!("No suggestions for {}", word)),
+        Ok(Some(suggestions)) => global.popups.open(Popup::menu(start, suggestions, |_, buffer, suggestion| {
+            let (start, end) = word_at(&buffer.content, buffer.content.cursor);
+            buffer.content.replace_range(start, end, suggestion);
+        })),
+        Err(e) => global.message(format!("Spell checking failed: {}", e)),
+    }
+}
@@ -0,0 +1,118 @@
+use std::ops::Range;
+
+use crate::buffer_list::BufferList;
+use crate::unicode::display_width;
+
+// Clicking it on a tab closes the tab
+const CLOSE_BUTTON: &str = "×";
+
+// A tab for each open buffer, in an order of their own which stays put while
+// the buffer list keeps changing its order to the most recently shown
+pub struct TabBar {
+    pub enabled: bool,
+    tabs: Vec<String>,
+}
+
+// A tab as laid out on the bar, in character cells from its left edge
+pub struct Tab<'a> {
+    pub name: &'a str,
+    pub label: String,
+    pub cells: Range<usize>,
+    // Where the close button is, at the end of the tab
+    pub close: Range<usize>,
+    pub current: bool,
+}
+
+pub enum Click {
+    Select(String),
+    Close(String),
+}
+
+impl TabBar {
+    // From EDIT0R_TAB_BAR, which is "on" to show it from the start
+    pub fn from_env() -> TabBar {
+        TabBar {
+            enabled: std::env::var("EDIT0R_TAB_BAR").is_ok_and(|setting| setting == "on"),
+            tabs: vec!(),
+        }
+    }
+
+    // Catches up with the buffers opened, killed and renamed since the last
+    // frame. New buffers go on the end and a renamed one keeps its place.
+    pub fn sync(&mut self, current: &str, buffers: &BufferList) {
+        let open: Vec<&str> = std::iter::once(current).chain(buffers.names()).collect();
+        let gone: Vec<usize> = (0..self.tabs.len()).filter(|&i| !open.contains(&self.tabs[i].as_str())).collect();
+        let new: Vec<&str> = open.iter().copied().filter(|name| !self.tabs.iter().any(|tab| tab == name)).collect();
+
+        if let ([renamed], [name]) = (gone.as_slice(), new.as_slice()) {
+            self.tabs[*renamed] = name.to_string();
+            return;
+        }
+        self.tabs.retain(|tab| open.contains(&tab.as_str()));
+        self.tabs.extend(new.into_iter().map(str::to_string));
+    }
+
+    fn position(&self, current: &str) -> Option<usize> {
+        self.tabs.iter().position(|tab| tab == current)
+    }
+
+    // The buffer of the tab `offset` places along from the current one's,
+    // going round at the ends
+    pub fn neighbour(&self, current: &str, offset: isize) -> Option<&str> {
+        let index = self.position(current)? as isize + offset;
+        let len = self.tabs.len() as isize;
+        Some(&self.tabs[index.rem_euclid(len) as usize])
+    }
+
+    // Moves the current buffer's tab `offset` places along, going round at
+    // the ends
+    pub fn move_tab(&mut self, current: &str, offset: isize) {
+        if let Some(index) = self.position(current) {
+            let tab = self.tabs.remove(index);
+            let to = (index as isize + offset).rem_euclid(self.tabs.len() as isize + 1) as usize;
+            self.tabs.insert(to, tab);
+        }
+    }
+
+    // The tabs that fit in `cols` cells, scrolled along so the current one
+    // is shown
+    pub fn layout(&self, current: &str, cols: usize) -> Vec<Tab<'_>> {
+        let mut tabs = vec!();
+        let mut cell = 0;
+        for tab in &self.tabs {
+            let label = format!(" {} {} ", tab, CLOSE_BUTTON);
+            let width = display_width(&label);
+            tabs.push(Tab {
+                name: tab,
+                cells: cell..cell + width,
+                close: cell + width - 2..cell + width - 1,
+                label,
+                current: tab == current,
+            });
+            cell += width;
+        }
+
+        let shift = match tabs.iter().find(|tab| tab.current) {
+            Some(tab) => tab.cells.end.saturating_sub(cols).min(tab.cells.start),
+            None => 0,
+        };
+        tabs.into_iter()
+            .filter(|tab| tab.cells.start >= shift && tab.cells.start < shift + cols)
+            .map(|tab| Tab {
+                cells: tab.cells.start - shift..tab.cells.end - shift,
+                close: tab.close.start - shift..tab.close.end - shift,
+                ..tab
+            })
+            .collect()
+    }
+
+    // What clicking cell `cell` of the bar does
+    pub fn click(&self, current: &str, cols: usize, cell: usize) -> Option<Click> {
+        let tab = self.layout(current, cols).into_iter().find(|tab| tab.cells.contains(&cell))?;
+        if tab.close.contains(&cell) {
+            Some(Click::Close(tab.name.to_string()))
+        } else {
+            Some(Click::Select(tab.name.to_string()))
+        }
+    }
+}
@@ -0,0 +1,703 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use std::ops::RangeInclusive;
+
+use crate::buffer_local::BufferLocals;
+use crate::face_spans::FaceSpans;
+use crate::gap_buffer::GapBuffer;
+use crate::marker::Markers;
+use crate::undo::end_of;
+use crate::undo::Edit;
+use crate::undo::UndoHistory;
+use crate::unicode::col_at_cell;
+use crate::unicode::display_width;
+use crate::unicode::graphemes;
+use crate::unicode::is_cluster_boundary;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub row: usize,
+    // Byte offset into the line
+    pub col: usize,
+}
+
+impl Position {
+    pub fn new(row: usize, col: usize) -> Position {
+        Position { row, col }
+    }
+
+    // Where this position ends up after `at..end` was inserted
+    pub fn after_insert(self, at: Position, end: Position) -> Position {
+        if self < at {
+            self
+        } else if self.row == at.row {
+            Position::new(end.row, end.col + (self.col - at.col))
+        } else {
+            Position::new(self.row + (end.row - at.row), self.col)
+        }
+    }
+
+    // Where this position ends up after `start..end` was deleted
+    pub fn after_delete(self, start: Position, end: Position) -> Position {
+        if self <= start {
+            self
+        } else if self <= end {
+            start
+        } else if self.row == end.row {
+            Position::new(start.row, start.col + (self.col - end.col))
+        } else {
+            Position::new(self.row - (end.row - start.row), self.col)
+        }
+    }
+}
+
+// Which edits a command is refused while it runs in the buffer. They are
+// refused as they're made, so there's nothing to take back afterwards.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EditGuard {
+    Unguarded,
+    // Every edit, in a read-only buffer
+    ReadOnly,
+    // Edits reaching outside the narrowed part
+    Narrowing,
+}
+
+pub struct TextContent {
+    text: GapBuffer,
+    pub faces: FaceSpans,
+    // Text not drawn at all, like markup a mode shows only as its effect.
+    // The face ids in it don't matter.
+    pub invisible: FaceSpans,
+    pub cursor: Position,
+    // The other end of the region from the cursor, when active
+    pub mark: Option<Position>,
+    // Set on every edit so the minor modes know to rerun
+    pub modified: bool,
+    history: UndoHistory,
+    // Every edit since the minor modes last ran, so they can follow positions
+    change_log: Vec<Edit>,
+    // The byte offset each edit in change_log was made at, for tree-sitter
+    change_offsets: Vec<usize>,
+    // Number of edits already dropped from the front of change_log
+    change_base: usize,
+    // The only part shown and editable, from narrow_to_region
+    narrowing: Option<(Position, Position)>,
+    guard: EditGuard,
+    // Whether the guard refused an edit since it was set
+    refused_edit: bool,
+    // Folded text, which hides the rows after the first row of each down to
+    // its last one
+    folds: Vec<(Position, Position)>,
+    // Places features keep track of through the edits
+    pub markers: Markers,
+    // Settings for this buffer alone. They live here rather than on Buffer
+    // since the minor modes only ever see the content.
+    pub locals: BufferLocals,
+}
+
+impl TextContent {
+    // A final newline leaves an empty last line, so saving gives it back
+    pub fn new(text: &str) -> TextContent {
+        TextContent {
+            text: GapBuffer::new(&text.replace("\r\n", "\n")),
+            faces: FaceSpans::default(),
+            invisible: FaceSpans::default(),
+            cursor: Position::default(),
+            mark: None,
+            modified: true,
+            history: UndoHistory::default(),
+            change_log: vec!(),
+            change_offsets: vec!(),
+            change_base: 0,
+            narrowing: None,
+            guard: EditGuard::Unguarded,
+            refused_edit: false,
+            folds: vec!(),
+            markers: Markers::default(),
+            locals: BufferLocals::default(),
+        }
+    }
+
+    // The whole content as it gets written to disk
+    pub fn text(&self) -> String {
+        self.text.slice(0..self.text.len()).into_owned()
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.text.line_count()
+    }
+
+    // The text of line `row` without its newline
+    pub fn line(&self, row: usize) -> Cow<'_, str> {
+        self.text.line(row)
+    }
+
+    pub fn slice(&self, bytes: Range<usize>) -> Cow<'_, str> {
+        self.text.slice(bytes)
+    }
+
+    pub fn line_range(&self, rows: RangeInclusive<usize>) -> Vec<String> {
+        rows.map(|row| self.line(row).into_owned()).collect()
+    }
+
+    pub fn line_len(&self, row: usize) -> usize {
+        self.text.line_end(row) - self.text.line_start(row)
+    }
+
+    // The byte offset of `pos` from the start of the buffer
+    pub fn offset(&self, pos: Position) -> usize {
+        self.text.line_start(pos.row) + pos.col
+    }
+
+    pub fn position_at(&self, offset: usize) -> Position {
+        let row = self.text.row_of(offset);
+        Position::new(row, offset - self.text.line_start(row))
+    }
+
+    // As much of the text from byte `offset` on as is stored in one piece
+    pub fn chunk_at(&self, offset: usize) -> &[u8] {
+        self.text.chunk_at(offset)
+    }
+
+    pub fn end_of_buffer(&self) -> Position {
+        let row = self.line_count() - 1;
+        Position::new(row, self.line_len(row))
+    }
+
+    // The character at `pos`, with line ends reading as '\n'
+    pub fn char_at(&self, pos: Position) -> Option<char> {
+        match self.line(pos.row)[pos.col..].chars().next() {
+            Some(c) => Some(c),
+            None if pos.row + 1 < self.line_count() => Some('\n'),
+            None => None,
+        }
+    }
+
+    // The position one character after `pos`, wrapping onto the next line.
+    // Characters are whole grapheme clusters, so accents and joined emoji
+    // are stepped over together.
+    pub fn next_position(&self, pos: Position) -> Option<Position> {
+        match graphemes(&self.line(pos.row)[pos.col..]).next() {
+            Some((_, cluster)) => Some(Position::new(pos.row, pos.col + cluster.len())),
+            None if pos.row + 1 < self.line_count() => Some(Position::new(pos.row + 1, 0)),
+            None => None,
+        }
+    }
+
+    // The position one character before `pos`, wrapping onto the previous line
+    pub fn prev_position(&self, pos: Position) -> Option<Position> {
+        if pos.col > 0 {
+            let col = graphemes(&self.line(pos.row)[..pos.col])
+                .last()
+                .map_or(0, |(i, _)| i);
+            Some(Position::new(pos.row, col))
+        } else if pos.row > 0 {
+            Some(Position::new(pos.row - 1, self.line_len(pos.row - 1)))
+        } else {
+            None
+        }
+    }
+
+    // Inserts text at `at` and returns the position right after it, or None
+    // when the edit guard refuses it. The cursor is shifted if it sits at or
+    // after the insertion point.
+    pub fn insert(&mut self, at: Position, text: &str) -> Option<Position> {
+        if !self.allows(at, at) {
+            return None;
+        }
+        let cursor_before = self.cursor;
+        let end = self.insert_raw(at, text);
+        if !text.is_empty() {
+            let edit = Edit::Insert { at, text: text.to_string() };
+            self.history.record(edit, cursor_before, self.cursor);
+        }
+        Some(end)
+    }
+
+    // Removes the text between `start` and `end` and returns it, or None
+    // when the edit guard refuses it
+    pub fn delete_range(&mut self, start: Position, end: Position) -> Option<String> {
+        let (start, end) = if start <= end { (start, end) } else { (end, start) };
+        if !self.allows(start, end) {
+            return None;
+        }
+        let cursor_before = self.cursor;
+        let removed = self.delete_raw(start, end);
+        if !removed.is_empty() {
+            let edit = Edit::Delete { at: start, text: removed.clone() };
+            self.history.record(edit, cursor_before, self.cursor);
+        }
+        Some(removed)
+    }
+
+    // Replaces the text between `start` and `end`, returning the end of the
+    // new text, or None when the edit guard refuses it
+    pub fn replace_range(&mut self, start: Position, end: Position, text: &str) -> Option<Position> {
+        let (start, end) = if start <= end { (start, end) } else { (end, start) };
+        if !self.allows(start, end) {
+            return None;
+        }
+        self.begin_transaction();
+        self.delete_range(start, end);
+        let end = self.insert(start, text);
+        self.commit_transaction();
+        end
+    }
+
+    fn insert_raw(&mut self, at: Position, text: &str) -> Position {
+        let offset = self.offset(at);
+        self.text.insert(offset, text);
+        let end = end_of(at, text);
+        self.faces.after_insert(at, end);
+        self.invisible.after_insert(at, end);
+        self.markers.after_insert(at, end);
+        self.cursor = self.cursor.after_insert(at, end);
+        self.mark = self.mark.map(|mark| mark.after_insert(at, end));
+        let edit = Edit::Insert { at, text: text.to_string() };
+        self.narrowing = self.narrowing.map(|bounds| shift_bounds(bounds, &edit));
+        self.shift_folds(&edit);
+        self.change_log.push(edit);
+        self.change_offsets.push(offset);
+        self.modified = true;
+        end
+    }
+
+    fn delete_raw(&mut self, start: Position, end: Position) -> String {
+        let offset = self.offset(start);
+        let removed = self.text.delete(offset..self.offset(end));
+        self.faces.after_delete(start, end);
+        self.invisible.after_delete(start, end);
+        self.markers.after_delete(start, end);
+        self.cursor = self.cursor.after_delete(start, end);
+        self.mark = self.mark.map(|mark| mark.after_delete(start, end));
+        let edit = Edit::Delete { at: start, text: removed.clone() };
+        self.narrowing = self.narrowing.map(|bounds| shift_bounds(bounds, &edit));
+        self.shift_folds(&edit);
+        self.change_log.push(edit);
+        self.change_offsets.push(offset);
+        self.modified = true;
+        removed
+    }
+
+    fn apply(&mut self, edit: &Edit) {
+        match edit {
+            Edit::Insert { at, text } => { self.insert_raw(*at, text); },
+            Edit::Delete { at, text } => { self.delete_raw(*at, end_of(*at, text)); },
+        }
+    }
+
+    // Total number of edits ever made, to pass back into changes_since
+    pub fn change_count(&self) -> usize {
+        self.change_base + self.change_log.len()
+    }
+
+    // The edits made after change_count() returned `count`. Older edits are
+    // dropped once all minor modes have run, so this only reaches back that far.
+    pub fn changes_since(&self, count: usize) -> &[Edit] {
+        let start = count.saturating_sub(self.change_base).min(self.change_log.len());
+        &self.change_log[start..]
+    }
+
+    // Like changes_since with the byte offset of each edit, or None when
+    // some of them were already dropped
+    pub fn changes_with_offsets_since(&self, count: usize) -> Option<impl Iterator<Item = (&Edit, usize)>> {
+        if count < self.change_base || count > self.change_count() {
+            return None;
+        }
+        let start = count - self.change_base;
+        Some(self.change_log[start..].iter().zip(self.change_offsets[start..].iter().copied()))
+    }
+
+    pub fn trim_changes(&mut self) {
+        self.change_base += self.change_log.len();
+        self.change_log.clear();
+        self.change_offsets.clear();
+    }
+
+    pub fn history(&self) -> &UndoHistory {
+        &self.history
+    }
+
+    pub fn set_history(&mut self, history: UndoHistory) {
+        self.history = history;
+    }
+
+    // Refuses the edits `guard` is against until the next call, returning
+    // whether the guard before it refused any
+    pub fn guard_edits(&mut self, guard: EditGuard) -> bool {
+        self.guard = guard;
+        std::mem::take(&mut self.refused_edit)
+    }
+
+    // Lets the running command edit the buffer even though it's read-only,
+    // as commands that refresh a listing do
+    pub fn inhibit_read_only(&mut self) {
+        if self.guard == EditGuard::ReadOnly {
+            self.guard = EditGuard::Unguarded;
+        }
+    }
+
+    // Whether the guard lets the text from `start` to `end` be edited with
+    // the part narrowed to being `narrowing`, noting it when not
+    fn allows_within(&mut self, narrowing: Option<(Position, Position)>, start: Position, end: Position) -> bool {
+        let allowed = match (self.guard, narrowing) {
+            (EditGuard::ReadOnly, _) => false,
+            (EditGuard::Narrowing, Some((bounds_start, bounds_end))) => start >= bounds_start && end <= bounds_end,
+            _ => true,
+        };
+        self.refused_edit |= !allowed;
+        allowed
+    }
+
+    fn allows(&mut self, start: Position, end: Position) -> bool {
+        self.allows_within(self.narrowing, start, end)
+    }
+
+    // Whether the guard lets `edits` be made one after another, as undo and
+    // redo make them
+    fn allows_all(&mut self, edits: impl IntoIterator<Item = Edit>) -> bool {
+        if self.guard == EditGuard::Unguarded {
+            return true;
+        }
+        let mut narrowing = self.narrowing;
+        for edit in edits {
+            let (start, end) = edit.range();
+            if !self.allows_within(narrowing, start, end) {
+                return false;
+            }
+            narrowing = narrowing.map(|bounds| shift_bounds(bounds, &edit));
+        }
+        true
+    }
+
+    // Shows and allows editing only `start` to `end` until widen
+    pub fn narrow(&mut self, start: Position, end: Position) {
+        let (start, end) = if start <= end { (start, end) } else { (end, start) };
+        self.narrowing = Some((start, end));
+        self.clamp_to_narrowing();
+    }
+
+    pub fn widen(&mut self) {
+        self.narrowing = None;
+    }
+
+    pub fn is_narrowed(&self) -> bool {
+        self.narrowing.is_some()
+    }
+
+    // The part of the content that is shown, all of it unless narrowed
+    pub fn visible_range(&self) -> (Position, Position) {
+        self.narrowing.unwrap_or((Position::default(), self.end_of_buffer()))
+    }
+
+    // Hides the rows after the one `start` is on, down to the one `end` is on
+    pub fn fold(&mut self, start: Position, end: Position) {
+        let (start, end) = if start <= end { (start, end) } else { (end, start) };
+        if start.row < end.row && !self.folds.contains(&(start, end)) {
+            self.folds.push((start, end));
+            self.folds.sort_unstable();
+        }
+    }
+
+    // Shows the rows of the folds starting on `row` or hiding it again,
+    // returning false if there weren't any
+    pub fn unfold(&mut self, row: usize) -> bool {
+        let count = self.folds.len();
+        self.folds.retain(|&(start, end)| start.row != row && !(start.row < row && row <= end.row));
+        self.folds.len() != count
+    }
+
+    pub fn unfold_all(&mut self) {
+        self.folds.clear();
+    }
+
+    pub fn folds(&self) -> &[(Position, Position)] {
+        &self.folds
+    }
+
+    // Whether `row` shows with the rows after it folded away
+    pub fn is_folded(&self, row: usize) -> bool {
+        !self.is_hidden(row) && self.folds.iter().any(|&(start, _)| start.row == row)
+    }
+
+    pub fn is_hidden(&self, row: usize) -> bool {
+        self.folds.iter().any(|&(start, end)| start.row < row && row <= end.row)
+    }
+
+    // The row shown in place of `row`, which is the first row of the fold
+    // hiding it if there is one
+    pub fn shown_row(&self, row: usize) -> usize {
+        let mut row = row;
+        while let Some(start) = self.folds.iter().filter(|&&(start, end)| start.row < row && row <= end.row).map(|(start, _)| start.row).min() {
+            row = start;
+        }
+        row
+    }
+
+    // The first row after `row` that isn't folded away, which can be past
+    // the last row
+    pub fn next_shown_row(&self, row: usize) -> usize {
+        let mut next = row + 1;
+        while let Some(end) = self.folds.iter().filter(|&&(start, end)| start.row < next && next <= end.row).map(|(_, end)| end.row).max() {
+            next = end + 1;
+        }
+        next
+    }
+
+    // Shows the text the cursor went into, when something moved it there
+    pub fn reveal_cursor(&mut self) {
+        let row = self.cursor.row;
+        self.folds.retain(|&(start, end)| !(start.row < row && row <= end.row));
+    }
+
+    // Keeps the folds on the text they were made on, dropping the ones
+    // left with nothing to hide
+    fn shift_folds(&mut self, edit: &Edit) {
+        if self.folds.is_empty() {
+            return;
+        }
+        for fold in &mut self.folds {
+            *fold = shift_bounds(*fold, edit);
+        }
+        self.folds.retain(|&(start, end)| start.row < end.row);
+    }
+
+    // The byte range of line `row` inside the narrowed part
+    pub fn visible_cols(&self, row: usize) -> Range<usize> {
+        let (start, end) = self.visible_range();
+        let from = if row == start.row { start.col } else { 0 };
+        let to = if row == end.row { end.col } else { self.line_len(row) };
+        from..to
+    }
+
+    // Keeps the cursor and mark inside the narrowed part
+    pub fn clamp_to_narrowing(&mut self) {
+        if let Some((start, end)) = self.narrowing {
+            self.cursor = self.cursor.clamp(start, end);
+            self.mark = self.mark.map(|mark| mark.clamp(start, end));
+        }
+    }
+
+    // Closes the current undo group so the next edit starts a new one
+    pub fn undo_boundary(&mut self) {
+        self.history.boundary();
+    }
+
+    // Edits made until the matching commit_transaction undo as one and are
+    // only seen by the minor modes once they are all done. Transactions nest.
+    pub fn begin_transaction(&mut self) {
+        self.history.begin_transaction();
+    }
+
+    pub fn commit_transaction(&mut self) {
+        self.history.commit_transaction();
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.history.in_transaction()
+    }
+
+    // Returns false when there is nothing left to undo, or it isn't allowed
+    pub fn undo(&mut self) -> bool {
+        match self.history.pop_undo() {
+            Some(group) if !self.allows_all(group.edits.iter().rev().map(Edit::inverse)) => {
+                self.history.push_undo(group);
+                false
+            },
+            Some(group) => {
+                for edit in group.edits.iter().rev() {
+                    self.apply(&edit.inverse());
+                }
+                self.cursor = group.cursor_before;
+                self.history.push_redo(group);
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Returns false when there is nothing left to redo, or it isn't allowed
+    pub fn redo(&mut self) -> bool {
+        match self.history.pop_redo() {
+            Some(group) if !self.allows_all(group.edits.iter().cloned()) => {
+                self.history.push_redo(group);
+                false
+            },
+            Some(group) => {
+                for edit in &group.edits {
+                    self.apply(edit);
+                }
+                self.cursor = group.cursor_after;
+                self.history.push_undo(group);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn insert_str(&mut self, text: &str) {
+        self.insert(self.cursor, text);
+    }
+
+    // Inserts typed text, grouping consecutive keystrokes into one undo
+    pub fn self_insert(&mut self, text: &str) {
+        self.history.amalgamate_insert();
+        self.deactivate_mark();
+        self.insert_str(text);
+    }
+
+    // Types over as many characters as are typed, never past the line end
+    pub fn self_overwrite(&mut self, text: &str) {
+        self.history.amalgamate_insert();
+        self.deactivate_mark();
+
+        let line = self.line(self.cursor.row);
+        let replaced: usize = line[self.cursor.col..]
+            .chars()
+            .zip(text.chars().take_while(|&c| c != '\n'))
+            .map(|(c, _)| c.len_utf8())
+            .sum();
+        let end = Position::new(self.cursor.row, self.cursor.col + replaced);
+        self.delete_range(self.cursor, end);
+        self.insert_str(text);
+    }
+
+    pub fn set_mark(&mut self) {
+        self.mark = Some(self.cursor);
+    }
+
+    pub fn deactivate_mark(&mut self) {
+        self.mark = None;
+    }
+
+    // The ordered (start, end) of the active region
+    pub fn region(&self) -> Option<(Position, Position)> {
+        self.mark.map(|mark| (mark.min(self.cursor), mark.max(self.cursor)))
+    }
+
+    // The byte range of line `row` covered by the active region
+    pub fn region_cols(&self, row: usize) -> Option<Range<usize>> {
+        let (start, end) = self.region()?;
+        if row < start.row || row > end.row {
+            return None;
+        }
+
+        let from = if row == start.row { start.col } else { 0 };
+        let to = if row == end.row { end.col } else { self.line_len(row) };
+        Some(from..to)
+    }
+
+    pub fn region_text(&self) -> Option<String> {
+        let (start, end) = self.region()?;
+        Some(self.text_range(start, end))
+    }
+
+    // Deletes the active region and returns its text
+    pub fn delete_region(&mut self) -> Option<String> {
+        let (start, end) = self.region()?;
+        let removed = self.delete_range(start, end)?;
+        self.deactivate_mark();
+        Some(removed)
+    }
+
+    pub fn text_range(&self, start: Position, end: Position) -> String {
+        self.slice(self.offset(start)..self.offset(end)).into_owned()
+    }
+
+    // Deletes the character under the cursor, joining lines at the end of one
+    pub fn delete_char(&mut self) {
+        if let Some(next) = self.next_position(self.cursor) {
+            self.delete_range(self.cursor, next);
+        }
+    }
+
+    // Deletes the character before the cursor, joining lines at the start of one
+    pub fn delete_backward_char(&mut self) {
+        if let Some(prev) = self.prev_position(self.cursor) {
+            self.delete_range(prev, self.cursor);
+        }
+    }
+
+    // Kills to the end of the line, or the newline itself when already there
+    pub fn kill_line(&mut self) -> Option<String> {
+        let Position { row, col } = self.cursor;
+        if col < self.line_len(row) {
+            self.delete_range(self.cursor, Position::new(row, self.line_len(row)))
+        } else if row + 1 < self.line_count() {
+            self.delete_range(self.cursor, Position::new(row + 1, 0))
+        } else {
+            Some(String::new())
+        }
+    }
+
+    // Resets every character to the default face before the minor modes rerun
+    pub fn clear_faces(&mut self) {
+        self.faces.clear();
+        self.invisible.clear();
+    }
+
+    // The columns of `row` left out when drawing it. The cursor's row shows
+    // everything, so what's hidden can still be edited.
+    pub fn invisible_cols(&self, row: usize) -> Vec<Range<usize>> {
+        if row == self.cursor.row || row >= self.line_count() {
+            return vec!();
+        }
+        self.invisible.line_spans(row, self.line_len(row))
+            .into_iter()
+            .map(|(cols, _)| cols)
+            .collect()
+    }
+
+    // Moving by character or line steps over folded text
+    pub fn move_left(&mut self) {
+        if let Some(prev) = self.prev_position(self.cursor) {
+            let row = self.shown_row(prev.row);
+            self.cursor = if row == prev.row { prev } else { Position::new(row, self.line_len(row)) };
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if let Some(next) = self.next_position(self.cursor) {
+            if !self.is_hidden(next.row) {
+                self.cursor = next;
+            } else if self.next_shown_row(self.cursor.row) < self.line_count() {
+                self.cursor = Position::new(self.next_shown_row(self.cursor.row), 0);
+            }
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        if self.cursor.row > 0 {
+            self.move_to_row(self.shown_row(self.cursor.row - 1));
+        }
+    }
+
+    pub fn move_down(&mut self) {
+        let row = self.next_shown_row(self.cursor.row);
+        if row < self.line_count() {
+            self.move_to_row(row);
+        }
+    }
+
+    // Moves the cursor onto `row`, staying in the same screen column where
+    // wide characters on either line make byte offsets differ
+    fn move_to_row(&mut self, row: usize) {
+        let cells = display_width(&self.line(self.cursor.row)[..self.cursor.col]);
+        self.cursor = Position::new(row, col_at_cell(&self.line(row), cells));
+    }
+
+    // Snaps a column onto the nearest cluster boundary at or before it
+    pub fn clamp_col(&self, row: usize, col: usize) -> usize {
+        let line = self.line(row);
+        let mut col = col.min(line.len());
+        while !is_cluster_boundary(&line, col) {
+            col -= 1;
+        }
+        col
+    }
+}
+
+// Narrowed bounds after `edit`, text typed at the start going inside them
+fn shift_bounds((start, end): (Position, Position), edit: &Edit) -> (Position, Position) {
+    (edit.shift(start, true), edit.shift(end, false))
+}
@@ -1,21 +1,158 @@
 use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
 
-use tree_sitter::Node;
-use tree_sitter::QueryCursor;
-use tree_sitter::Query;
-use tree_sitter::Point;
+use sdl2::clipboard::ClipboardUtil;
 use sdl2::event::Event;
+use sdl2::event::WindowEvent;
 use sdl2::keyboard::Keycode;
+use sdl2::keyboard::Mod;
+use sdl2::mouse::MouseButton;
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
+use sdl2::render::Texture;
+use sdl2::render::TextureCreator;
 use sdl2::render::TextureQuery;
-use tree_sitter::Parser;
+use sdl2::video::WindowContext;
+use tree_sitter::Tree;
 
 use crate::RenderContext;
+use crate::abbrev_mode::AbbrevTable;
+use crate::auto_mode;
+use crate::auto_save;
+use crate::backup::Backups;
+use crate::bookmark::BookmarkMode;
+use crate::bookmark::Bookmarks;
+use crate::buffer::Buffer;
+use crate::buffer_list::BufferList;
+use crate::command_registry;
+use crate::buffer_local::FILL_COLUMN;
+use crate::commands;
+use crate::context_menu;
+use crate::context_menu::MenuItem;
+use crate::commands::CommandContext;
+use crate::commands::PrefixArg;
+use crate::commands::Repeatable;
+use crate::cursor::Cursor;
+use crate::cursor::CursorStyle;
+use crate::damage::Damage;
+use crate::dired;
+use crate::event_script;
+use crate::font;
+use crate::font::FontSettings;
+use crate::font::Fonts;
+use crate::frame_rate::FrameRate;
+use crate::goto_address_mode;
+use crate::grammars;
+use crate::ime::Preedit;
+use crate::indent_guide_mode;
+use crate::keymap;
+use crate::keymap::Binding;
+use crate::keymap::KeyPress;
+use crate::keymap::Keymaps;
+use crate::keymap::describe_keys;
+use crate::line_ending::LineEnding;
+use crate::minibuffer::History;
+use crate::minibuffer::Minibuffer;
+use crate::minimap_mode;
+use crate::minimap_mode::MINIMAP_COLS;
+use crate::popup;
+use crate::popup::Popup;
+use crate::popup::Popups;
+use crate::scroll_bar;
+use crate::scroll_bar::SCROLL_BAR_WIDTH;
+use crate::tab_bar::Click;
+use crate::tab_bar::TabBar;
 use crate::mode::MajorMode;
+use crate::motion;
+use crate::modeline;
+use crate::modeline::Segment;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::tree_sitter_mode::Definition;
+use crate::theme;
+use crate::undo_file;
+use crate::unicode::cluster_cells;
+use crate::unicode::col_at_cell;
+use crate::unicode::display_width;
+use crate::unicode::graphemes;
+use crate::unicode::is_escaped;
+use crate::vim_mode;
+use crate::window::Area;
+use crate::window::Windows;
 
 pub struct Global {
-    faces: Faces,
+    pub faces: Faces,
+    // Most recent kill last
+    kill_ring: Vec<String>,
+    clipboard: ClipboardUtil,
+    pub killed_rectangle: Vec<String>,
+    // The last command run and how many times, for C-x z
+    pub last_command: Option<(Repeatable, CommandContext)>,
+    // The prefix argument of the command running
+    pub command: CommandContext,
+    // Abbrevs expanded in every buffer with abbrev mode on
+    pub abbrevs: AbbrevTable,
+    // Every buffer but the current one
+    pub buffers: BufferList,
+    // The prompt being answered, if any
+    pub minibuffer: Option<Minibuffer>,
+    pub minibuffer_history: History,
+    pub windows: Windows,
+    pub tab_bar: TabBar,
+    pub popups: Popups,
+    // Shown in the echo area, where the minibuffer goes, until the next key
+    pub message: Option<String>,
+    // Where files are backed up to before their first save
+    pub backups: Backups,
+    // Undo histories are saved with their files and restored on opening them
+    pub undo_files: bool,
+    pub bookmarks: Bookmarks,
+    pub cursor: Cursor,
+    // Steps the text has been made bigger, or smaller when negative
+    pub text_scale: i32,
+    pub font: FontSettings,
+    // Scrolling slides the view over rather than jumping
+    pub smooth_scroll: bool,
+    pub scroll_bar: bool,
+    pub keymaps: Keymaps,
+    // What the input method is composing
+    pub preedit: Preedit,
+    // Set by kill-editor to leave once the command is done
+    pub quit: bool,
+}
+
+impl Global {
+    // Shows errors and feedback from commands at the bottom of the window
+    pub fn message(&mut self, text: String) {
+        self.message = Some(text);
+    }
+
+    // Saves killed text to the kill ring and the system clipboard
+    pub fn kill_new(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        // Other programs just miss out on the text if this fails
+        let _ = self.clipboard.set_clipboard_text(&text);
+        self.kill_ring.push(text);
+    }
+
+    // The text to yank, picking up anything newer copied in another program
+    pub fn current_kill(&mut self) -> Option<&str> {
+        if self.clipboard.has_clipboard_text() {
+            if let Ok(text) = self.clipboard.clipboard_text() {
+                let text = text.replace("\r\n", "\n");
+                if !text.is_empty() && self.kill_ring.last() != Some(&text) {
+                    self.kill_ring.push(text);
+                }
+            }
+        }
+        self.kill_ring.last().map(String::as_str)
+    }
 }
 
 pub struct Faces {
@@ -23,11 +160,16 @@ pub struct Faces {
     faces: Vec<Face>,
     // Maps face names to face ids (to lookup in faces)
     face_ids: HashMap<String, usize>,
+    // Bumped whenever a face changes, so the screen knows to redraw
+    generation: usize,
+    // Faces made on the fly without a name, by what they look like
+    anonymous_face_ids: HashMap<Face, usize>,
 }
 
 impl Faces {
     // Returns the face ID
     pub fn put_face(&mut self, name: String, face: Face) -> usize {
+        self.generation += 1;
         match self.face_ids.get(&name) {
             Some(&id) => {
                 self.faces[id] = face;
@@ -42,7 +184,7 @@ impl Faces {
         }
     }
 
-    pub fn get_face_by_name(&self, name: &String) -> Option<&Face> {
+    pub fn get_face_by_name(&self, name: &str) -> Option<&Face> {
         self.face_ids.get(name).map(|&id| &self.faces[id])
     }
 
@@ -50,8 +192,38 @@ impl Faces {
         self.faces.get(id)
     }
 
-    pub fn get_face_id(&self, name: &String) -> Option<usize> {
-        self.face_ids.get(name).map(|&id| id)
+    pub fn get_face_id(&self, name: &str) -> Option<usize> {
+        self.face_ids.get(name).copied()
+    }
+
+    // The id of a face with no name looking like `face`, made the first time
+    // it's asked for, e.g. to show a color written in the text. Themes leave
+    // these be.
+    pub fn anonymous_face_id(&mut self, face: Face) -> usize {
+        if let Some(&id) = self.anonymous_face_ids.get(&face) {
+            return id;
+        }
+        let id = self.faces.len();
+        self.faces.push(face);
+        self.anonymous_face_ids.insert(face, id);
+        id
+    }
+
+    // The face for a tree-sitter capture like "function.method.call", falling
+    // back to "function.method" and then "function" when there's no face for
+    // the whole name
+    pub fn get_capture_face_id(&self, capture: &str) -> Option<usize> {
+        let mut name = capture;
+        loop {
+            if let Some(id) = self.get_face_id(name) {
+                return Some(id);
+            }
+            name = &name[..name.rfind('.')?];
+        }
+    }
+
+    pub fn generation(&self) -> usize {
+        self.generation
     }
 
     fn unload_theme_faces(&mut self) {
@@ -62,9 +234,9 @@ impl Faces {
 
     pub fn load_theme_faces(&mut self, theme: Vec<(String, Face)>) {
         self.unload_theme_faces();
+        self.generation += 1;
 
-        let mut i = 0;
-        for (name, face) in theme {
+        for (i, (name, face)) in theme.into_iter().enumerate() {
             if i < self.theme_face_ids.len() {
                 let id = self.theme_face_ids[i];
                 self.face_ids.insert(name.to_string(), id);
@@ -73,42 +245,208 @@ impl Faces {
                 let id = self.put_face(name, face);
                 self.theme_face_ids.push(id);
             }
-            i += 1;
         }
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum FaceColor {
     Rgb(u8, u8, u8),
+    // Lets the color underneath show through, e.g. syntax colors under a
+    // translucent region
+    Rgba(u8, u8, u8, u8),
+}
+
+impl FaceColor {
+    fn channels(self) -> [u8; 4] {
+        match self {
+            FaceColor::Rgb(r, g, b) => [r, g, b, 255],
+            FaceColor::Rgba(r, g, b, a) => [r, g, b, a],
+        }
+    }
+
+    // The same color letting through what is underneath as much as `alpha`
+    // is below 255
+    pub fn with_alpha(self, alpha: u8) -> FaceColor {
+        let [r, g, b, _] = self.channels();
+        FaceColor::Rgba(r, g, b, alpha)
+    }
+
+    // This color with `over` laid on top of it
+    pub fn blend(self, over: FaceColor) -> FaceColor {
+        let [r0, g0, b0, a0] = self.channels();
+        let [r1, g1, b1, a1] = over.channels();
+        let (a0, a1) = (a0 as u32, a1 as u32);
+
+        // Plain "over" compositing with 8 bit alpha
+        let alpha = a1 + a0 * (255 - a1) / 255;
+        if alpha == 0 {
+            return FaceColor::Rgba(0, 0, 0, 0);
+        }
+        let mix = |under: u8, over: u8| ((over as u32 * a1 + under as u32 * a0 * (255 - a1) / 255) / alpha) as u8;
+        let (r, g, b) = (mix(r0, r1), mix(g0, g1), mix(b0, b1));
+        if alpha == 255 {
+            FaceColor::Rgb(r, g, b)
+        } else {
+            FaceColor::Rgba(r, g, b, alpha as u8)
+        }
+    }
 }
 
+// Unset attributes are inherited from the face underneath when layering
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Face {
-    bg: FaceColor,
-    fg: FaceColor,
+    pub bg: Option<FaceColor>,
+    pub fg: Option<FaceColor>,
+    // Set on either layer, these stay set
+    pub bold: bool,
+    pub underline: bool,
+}
+
+impl Face {
+    // Layers `other` on top of this face. Its set attributes win, or are
+    // blended with these ones where they're translucent.
+    pub fn overlay(&self, other: &Face) -> Face {
+        let layer = |under: Option<FaceColor>, over: Option<FaceColor>| match (under, over) {
+            (Some(under), Some(over)) => Some(under.blend(over)),
+            (under, over) => over.or(under),
+        };
+        Face {
+            bg: layer(self.bg, other.bg),
+            fg: layer(self.fg, other.fg),
+            bold: self.bold || other.bold,
+            underline: self.underline || other.underline,
+        }
+    }
 }
 
 impl Default for Face {
     fn default() -> Face {
         Face {
-            bg: FaceColor::Rgb(0, 0, 0),
-            fg: FaceColor::Rgb(255, 255, 255),
+            bg: Some(FaceColor::Rgb(0, 0, 0)),
+            fg: Some(FaceColor::Rgb(255, 255, 255)),
+            bold: false,
+            underline: false,
         }
     }
 }
 
+impl From<FaceColor> for Color {
+    fn from(color: FaceColor) -> Color {
+        match color {
+            FaceColor::Rgb(r, g, b) => Color::RGB(r, g, b),
+            FaceColor::Rgba(r, g, b, a) => Color::RGBA(r, g, b, a),
+        }
+    }
+}
 
+pub trait TextMinorMode {
+    // Used to find the mode again when toggling it
+    fn name(&self) -> &'static str;
 
+    fn modify(&mut self, _global: &mut Global, _lines: &mut TextContent) {
+    }
 
-pub trait TextMinorMode {
-    fn modify(&mut self, global: &mut Global, lines: &mut TextContent);
-}
+    // Gets the first look at typed text, returns true if it inserted it itself
+    fn self_insert(&mut self, _global: &mut Global, _content: &mut TextContent, _text: &str) -> bool {
+        false
+    }
 
-// TODO: Add margins
-pub struct TextContent {
-    // Dumb character by character face mapping
-    // usize is the face id
-    faces: Vec<Vec<usize>>,
-    lines: Vec<String>,
+    // Whether buffers with this mode start out wrapping long lines
+    fn wraps_lines(&self) -> bool {
+        false
+    }
+
+    // Gets the first look at Tab, returns true if it acted on it
+    fn tab(&mut self, _content: &mut TextContent, _backward: bool) -> bool {
+        false
+    }
+
+    // The line comment prefix of the language, for language modes
+    fn comment_syntax(&self) -> Option<&'static str> {
+        None
+    }
+
+    // How many columns line `row` should be indented, if this mode knows
+    fn indent_level(&mut self, _content: &TextContent, _row: usize) -> Option<usize> {
+        None
+    }
+
+    // The parts of the content that can be folded away, for language modes
+    // that know its structure
+    fn fold_ranges(&mut self, _content: &TextContent) -> Option<Vec<(Position, Position)>> {
+        None
+    }
+
+    // What the content defines in order, for language modes that can tell
+    fn definitions(&mut self, _content: &TextContent) -> Option<Vec<Definition>> {
+        None
+    }
+
+    // Gets to tidy up the content right before it is written to disk
+    fn before_save(&mut self, _content: &mut TextContent) {
+    }
+
+    // Gets to look at the file once the content was written to `path`
+    fn after_save(&mut self, _content: &mut TextContent, _path: &Path) {
+    }
+
+    // Text the mode shows after the end of line `row`, and its face
+    fn line_annotation(&self, _content: &TextContent, _row: usize) -> Option<(String, &'static str)> {
+        None
+    }
+
+    // The face of the mark the mode puts in the gutter beside line `row`,
+    // shown when the view has room for line marks
+    fn line_mark(&self, _row: usize) -> Option<&'static str> {
+        None
+    }
+
+    // Language modes stand in for the major mode, e.g. in the modeline
+    fn is_major(&self) -> bool {
+        false
+    }
+
+    // Something to show in the modeline after the modes
+    fn modeline_segment(&self, _content: &TextContent) -> Option<Segment> {
+        None
+    }
+
+    // Items the mode adds to the context menu
+    fn context_menu(&self) -> &'static [MenuItem] {
+        &[]
+    }
+
+    // The syntax tree of the content, for modes built on tree-sitter
+    fn syntax_tree(&mut self, _content: &TextContent) -> Option<Tree> {
+        None
+    }
+
+    // Whether the mode wants modify_syntax called, which needs the content parsed
+    fn uses_syntax_tree(&self) -> bool {
+        false
+    }
+
+    // Like modify, but after every mode has had its turn and with the syntax
+    // tree of the language mode
+    fn modify_syntax(&mut self, _global: &mut Global, _content: &mut TextContent, _tree: &Tree) {
+    }
+
+    // Gets to edit the content with what came in off the main thread, e.g.
+    // from a program running in the buffer, before the modes run
+    fn poll(&mut self, _global: &mut Global, _content: &mut TextContent) {
+    }
+
+    // Whether work done off the main thread came back since modify last ran,
+    // so it has to run again
+    fn has_results(&self) -> bool {
+        false
+    }
+
+    // Whether the mode is waiting on work done off the main thread
+    fn is_busy(&self) -> bool {
+        false
+    }
 }
 
 // handle the annoying Rect i32
@@ -118,6 +456,19 @@ macro_rules! rect(
     )
 );
 
+fn display_text(text: &str) -> String {
+    let mut shown = String::with_capacity(text.len());
+    for c in text.chars() {
+        if is_escaped(c) {
+            shown.push('^');
+            shown.push((c as u8 ^ 0x40) as char);
+        } else {
+            shown.push(c);
+        }
+    }
+    shown
+}
+
 fn draw_segment(
     context: &mut RenderContext,
     x_offset: usize,
@@ -125,280 +476,1781 @@ fn draw_segment(
     face: &Face,
     text: &str
 ) -> Result<(), String> {
-    let fg_color = match &face.fg {
-        FaceColor::Rgb(r, g, b) => Color::RGB(*r, *g, *b),
-    };
+    let fg_color: Color = face.fg.unwrap_or(FaceColor::Rgb(255, 255, 255)).into();
 
     let texture_creator = context.canvas.texture_creator();
 
     // render a surface, and convert it to a texture bound to the canvas
-    let surface = context.font
-        .render(text)
+    let surface = context.fonts
+        .for_text(text)
+        .render(&display_text(text))
         .blended(fg_color)
         .map_err(|e| e.to_string())?;
     let texture = texture_creator
         .create_texture_from_surface(&surface)
         .map_err(|e| e.to_string())?;
 
+    // Glyphs the font draws bigger than their cells, like ones from a
+    // fallback font, are cut off so they don't run into the next character
+    let (char_width, char_height) = context.fonts.cell_size()?;
+    let cells_width = display_width(text) as u32 * char_width;
     let TextureQuery { width, height, .. } = texture.query();
+    let (width, height) = (width.min(cells_width), height.min(char_height));
     let target = rect!(x_offset as u32, y_offset, width, height);
 
-    match &face.bg {
-        FaceColor::Rgb(r, g, b) => {
-            context.canvas.set_draw_color(Color::RGB(*r, *g, *b));
-            context.canvas.fill_rect(target)?;
-        },
-    };
+    if let Some(bg) = face.bg {
+        context.canvas.set_draw_color(bg);
+        context.canvas.fill_rect(rect!(x_offset as u32, y_offset, cells_width, char_height))?;
+    }
 
-    context.canvas.copy(&texture, None, Some(target))?;
+    context.canvas.copy(&texture, Some(rect!(0, 0, width, height)), Some(target))?;
+    // The fonts come in one weight, so bold is the glyphs drawn twice a
+    // pixel apart
+    if face.bold {
+        let target = rect!(x_offset as u32 + 1, y_offset, width.min(cells_width.saturating_sub(1)), height);
+        context.canvas.copy(&texture, Some(rect!(0, 0, target.width(), height)), Some(target))?;
+    }
+    if face.underline {
+        context.canvas.set_draw_color(fg_color);
+        context.canvas.fill_rect(rect!(x_offset as u32, y_offset + char_height - 1, cells_width, 1))?;
+    }
     Ok(())
 }
 
-// Returns the height of the rendered line
+// Returns the height of the rendered line, which starts `x_offset` pixels in.
+// Syntax faces and overlays are byte ranges of the line with a face id, the
+// overlays being layered over the syntax faces
 fn draw_line(
     context: &mut RenderContext,
     global: &Global,
+    x_offset: usize,
     y_offset: u32,
-    char_faces: &Vec<usize>,
-    line: &String,
+    syntax_faces: &[(Range<usize>, usize)],
+    line: &str,
+    overlays: &[(Range<usize>, usize)],
 ) -> Result<u32, String> {
     let invalid_face = Face {
-        bg: FaceColor::Rgb(255, 0, 0),
-        fg: FaceColor::Rgb(255, 255, 255),
+        bg: Some(FaceColor::Rgb(255, 0, 0)),
+        fg: Some(FaceColor::Rgb(255, 255, 255)),
+        ..Face::default()
+    };
+    let default_face = global.faces.get_face_by_id(0).copied().unwrap_or_default();
+
+    let (char_width, char_height) = context.fonts.cell_size()?;
+
+    // The composited face of the character starting at byte `col`
+    let face_at = |col: usize| -> Face {
+        let syntax_face_id = syntax_faces
+            .iter()
+            .find(|(range, _)| range.contains(&col))
+            .map_or(0, |&(_, face_id)| face_id);
+        let mut face = match global.faces.get_face_by_id(syntax_face_id) {
+            Some(syntax_face) => default_face.overlay(syntax_face),
+            None              => invalid_face,
+        };
+        for (range, face_id) in overlays {
+            if let (true, Some(overlay_face)) = (range.contains(&col), global.faces.get_face_by_id(*face_id)) {
+                face = face.overlay(overlay_face);
+            }
+        }
+        face
     };
-    let mut current_face_id = usize::MAX;
-    let mut current_face: &Face = &Default::default();
-
-    if line.len() != char_faces.len() {
-        panic!("Line length must equal face length");
-    }
 
+    let mut current_face: Option<Face> = None;
     let mut segment_start: usize = 0;
-    let mut segment_len: usize = 0;
-
-    let (char_width, char_height) = context.font.size_of_char('a').unwrap();
-
-    for col in 0..line.len() {
-        let char_face_id = char_faces[col];
-        segment_len += 1;
-
-        if char_face_id != current_face_id {
-            draw_segment(
-                context,
-                segment_start * (char_width as usize),
-                y_offset,
-                current_face,
-                &line[segment_start..segment_start + segment_len])?;
+    let mut segment_x: usize = 0;
+    let mut after_cluster = false;
+
+    // Runs of plain text are drawn together. Anything else is drawn a cluster
+    // at a time in its own cells, since the font's advance for it needn't
+    // match the cells it takes up.
+    for (col, cluster) in graphemes(line) {
+        let face = face_at(col);
+        let plain = cluster.len() == 1 && !is_escaped(cluster.as_bytes()[0] as char);
+        if current_face != Some(face) || !plain || after_cluster {
+            after_cluster = !plain;
+            if let Some(segment_face) = &current_face {
+                draw_segment(
+                    context,
+                    x_offset + segment_x * (char_width as usize),
+                    y_offset,
+                    segment_face,
+                    &line[segment_start..col])?;
+            }
 
-            segment_len = 0;
+            current_face = Some(face);
+            segment_x += display_width(&line[segment_start..col]);
             segment_start = col;
-            current_face_id = char_face_id;
-            current_face = match global.faces.get_face_by_id(current_face_id) {
-                Some(face) => face,
-                None       => &invalid_face,
-            };
         }
     }
 
-    if segment_len > 0 {
+    if let Some(segment_face) = &current_face {
         draw_segment(
             context,
-            segment_start * (char_width as usize),
+            x_offset + segment_x * (char_width as usize),
             y_offset,
-            current_face,
+            segment_face,
             &line[segment_start..])?;
     }
 
     Ok(char_height)
 }
 
-fn draw_content(context: &mut RenderContext, global: &Global, content: &TextContent) -> Result<(), String> {
-    let mut y_offset = 0;
-    for i in 0..content.lines.len() {
-        let line = &content.lines[i];
-        y_offset += match content.faces.get(i) {
-            Some(faces) => draw_line(context, global, y_offset, faces, line)?,
-            None        => draw_line(context, global, y_offset, &vec!(), line)?,
+// Draws the cursor at character cell `cell` of the screen row at `y_offset`,
+// on top of `under`, the cluster there (empty at the end of a line)
+fn draw_cursor(
+    context: &mut RenderContext,
+    global: &Global,
+    cell: usize,
+    y_offset: u32,
+    under: &str,
+) -> Result<(), String> {
+    let (char_width, char_height) = context.fonts.cell_size()?;
+    let x_offset = cell as u32 * char_width;
+    let width = cluster_cells(under).max(1) as u32 * char_width;
+
+    let color = global.faces.get_face_by_name("cursor")
+        .and_then(|face| face.fg)
+        .unwrap_or(FaceColor::Rgb(255, 255, 255));
+    context.canvas.set_draw_color(color);
+
+    match global.cursor.style {
+        CursorStyle::Bar => context.canvas.fill_rect(rect!(x_offset, y_offset, 2, char_height)),
+        CursorStyle::Underline => context.canvas.fill_rect(rect!(x_offset, y_offset + char_height - 2, width, 2)),
+        CursorStyle::Block if under.trim().is_empty() => context.canvas.fill_rect(rect!(x_offset, y_offset, width, char_height)),
+        // The character shows through in the background color
+        CursorStyle::Block => {
+            let background = global.faces.get_face_by_id(0).and_then(|face| face.bg).unwrap_or(FaceColor::Rgb(0, 0, 0));
+            let inverted = Face { bg: Some(color), fg: Some(background), ..Face::default() };
+            draw_segment(context, x_offset as usize, y_offset, &inverted, under)
+        },
+    }
+}
+
+// The buffer name, unsaved changes, read-only state, line ending and whether
+// the file changed underneath it, for the window list and task switcher,
+// which don't see the mode line
+fn window_title(buffer: &Buffer) -> String {
+    format!(
+        "{}{}{} [{}{}]{} - edit0r",
+        buffer.name,
+        if buffer.is_dirty() { " *" } else { "" },
+        if buffer.read_only { " %" } else { "" },
+        buffer.line_ending.name(),
+        if buffer.mixed_line_endings { ", mixed" } else { "" },
+        if buffer.changed_on_disk { " (changed on disk)" } else { "" },
+    )
+}
+
+// Faced byte ranges of a line moved to be relative to `cols`, dropping what
+// falls outside of it
+fn clip_spans(spans: &[(Range<usize>, usize)], cols: &Range<usize>) -> Vec<(Range<usize>, usize)> {
+    spans
+        .iter()
+        .filter_map(|(range, face_id)| {
+            let start = range.start.max(cols.start);
+            let end = range.end.min(cols.end);
+            (start < end).then(|| (start - cols.start..end - cols.start, *face_id))
+        })
+        .collect()
+}
+
+// The text of a segment with the `hidden` byte ranges of it left out
+fn hide_text(text: &str, hidden: &[Range<usize>]) -> String {
+    let mut shown = String::with_capacity(text.len());
+    let mut from = 0;
+    for range in hidden {
+        shown.push_str(&text[from..range.start]);
+        from = range.end;
+    }
+    shown.push_str(&text[from..]);
+    shown
+}
+
+// Faced byte ranges of a segment moved to match its text from hide_text
+fn hide_spans(spans: &[(Range<usize>, usize)], hidden: &[Range<usize>]) -> Vec<(Range<usize>, usize)> {
+    let shift = |col: usize| col - hidden.iter().map(|range| range.end.min(col).saturating_sub(range.start)).sum::<usize>();
+    spans
+        .iter()
+        .map(|(range, face_id)| (shift(range.start)..shift(range.end), *face_id))
+        .filter(|(range, _)| !range.is_empty())
+        .collect()
+}
+
+// Paints over a screen row with the default background
+fn clear_row(context: &mut RenderContext, global: &Global, y_offset: u32) -> Result<(), String> {
+    let background = global.faces.get_face_by_id(0).and_then(|face| face.bg).unwrap_or(FaceColor::Rgb(0, 0, 0));
+    fill_row(context, background, 0, y_offset)
+}
+
+// Paints a screen row from `x_offset` pixels in to the right edge
+fn fill_row(context: &mut RenderContext, color: FaceColor, x_offset: usize, y_offset: u32) -> Result<(), String> {
+    let (_, char_height) = context.fonts.cell_size()?;
+    let (window_width, _) = context.canvas.output_size()?;
+    context.canvas.set_draw_color(color);
+    context.canvas.fill_rect(rect!(x_offset, y_offset, window_width.saturating_sub(x_offset as u32), char_height))
+}
+
+// Stands in for the rows of a fold
+const FOLD_MARK: &str = "...";
+
+// Draws the rows of the content on screen that changed since the last frame,
+// only looking at the narrowed part when there is one. Lines carrying on past
+// the window edge end in "$", and wrapped ones in "\\". Line numbers go in a
+// gutter on the left, after a column of line marks when the view has one.
+// Text a mode made invisible is left out, except on the cursor's row. One
+// more row than fits is drawn, to slide into view while scrolling.
+fn draw_content(
+    context: &mut RenderContext,
+    global: &Global,
+    buffer: &Buffer,
+    show_cursor: bool,
+    preedit: Option<&Preedit>,
+    damage: &mut Damage,
+) -> Result<(), String> {
+    let (content, view) = (&buffer.content, &buffer.view);
+    let region_face_id = global.faces.get_face_id("region");
+    let continuation_face = global.faces.get_face_by_name("continuation").copied().unwrap_or_default();
+    let fold_face = global.faces.get_face_by_name("fold").copied().unwrap_or(continuation_face);
+    let line_number_face_id = global.faces.get_face_id("line-number").unwrap_or(0);
+    let current_line_number_face_id = global.faces.get_face_id("line-number-current-line").unwrap_or(line_number_face_id);
+    let (char_width, char_height) = context.fonts.cell_size()?;
+    let gutter_width = view.gutter_width(content);
+    let text_x = gutter_width * char_width as usize;
+    let line_number_x = view.line_marks as usize * char_width as usize;
+    let line_number_width = view.line_number_width(content);
+    let mark_x = text_x + view.text_cols() * char_width as usize;
+    let last_row = content.visible_range().1.row;
+    let (first_row, _) = view.first_shown_row(content);
+    let cursor_cell = (show_cursor && content.cursor.row >= first_row).then(|| view.cursor_cell_from(content, first_row));
+    let cursor_line = content.line(content.cursor.row);
+    let under_cursor = graphemes(&cursor_line[content.cursor.col..]).next().map_or("", |(_, cluster)| cluster);
+    // Composed text shows at the cursor even while it blinks off
+    let preedit_cell = preedit
+        .filter(|_| content.cursor.row >= first_row)
+        .map(|preedit| (preedit, view.cursor_cell_from(content, first_row)));
+
+    // The cursor's line is painted over from the text to the right edge
+    let background = global.faces.get_face_by_id(0).and_then(|face| face.bg).unwrap_or(FaceColor::Rgb(0, 0, 0));
+    let hl_line = global.faces.get_face_id("hl-line").filter(|_| buffer.has_minor_mode("hl-line"));
+    let hl_line_background = hl_line
+        .and_then(|face_id| global.faces.get_face_by_id(face_id))
+        .and_then(|face| face.bg)
+        .map(|bg| background.blend(bg));
+
+    // The cell the fill column falls in, when it is on screen
+    let fill_column_cell = buffer.has_minor_mode("fill-column-indicator")
+        .then(|| content.locals.get(&FILL_COLUMN))
+        .and_then(|column| column.checked_sub(if view.wrap { 0 } else { view.left_col }))
+        .filter(|&cell| cell < view.text_cols());
+    let fill_column_color = global.faces.get_face_by_name("fill-column-indicator")
+        .and_then(|face| face.fg)
+        .unwrap_or(FaceColor::Rgb(70, 70, 70));
+
+    // The bracket at the cursor shows with its match, or stands out on its own
+    let brackets = buffer.brackets.and_then(|found| {
+        let face_name = if found.matching.is_some() { "show-paren-match" } else { "show-paren-mismatch" };
+        let face_id = global.faces.get_face_id(face_name)?;
+        Some((std::iter::once(found.bracket).chain(found.matching), face_id))
+    });
+
+    let indent_guides = buffer.has_minor_mode("indent-guide");
+    let indent_guide_color = global.faces.get_face_by_name("indent-guide")
+        .and_then(|face| face.fg)
+        .unwrap_or(FaceColor::Rgb(55, 55, 55));
+
+    let rows = view.rows + 1;
+    let mut screen_row = 0;
+    let mut row = first_row;
+    while screen_row < rows {
+        // Rows past the end of the content are left blank
+        if row > last_row {
+            if damage.needs_drawing(screen_row, fill_column_cell) {
+                let y_offset = screen_row as u32 * char_height;
+                clear_row(context, global, y_offset)?;
+                if let Some(cell) = fill_column_cell {
+                    context.canvas.set_draw_color(fill_column_color);
+                    context.canvas.fill_rect(rect!(text_x + cell * char_width as usize, y_offset, 1, char_height))?;
+                }
+            }
+            screen_row += 1;
+            continue;
+        }
+
+        let line = content.line(row);
+        let cols = content.visible_cols(row);
+
+        let syntax_faces = content.faces.line_spans(row, line.len());
+        let invisible = content.invisible_cols(row);
+
+        let is_hl_line = hl_line.is_some() && row == content.cursor.row;
+        let mut overlays = vec!();
+        if let (Some(face_id), true) = (hl_line, is_hl_line) {
+            overlays.push((0..line.len(), face_id));
+        }
+        if let (Some(face_id), Some(range)) = (region_face_id, content.region_cols(row)) {
+            overlays.push((range, face_id));
+        }
+        if let Some((positions, face_id)) = brackets.clone() {
+            for pos in positions.filter(|pos| pos.row == row) {
+                overlays.push((pos.col..pos.col + 1, face_id));
+            }
+        }
+
+        let label = view.line_number_label(content, row).map(|label| {
+            let face_id = if row == content.cursor.row { current_line_number_face_id } else { line_number_face_id };
+            (format!("{:>width$} ", label, width = line_number_width - 1), face_id)
+        });
+        let line_mark = view.line_marks
+            .then(|| buffer.minor_modes.iter().find_map(|mode| mode.line_mark(row)))
+            .flatten()
+            .and_then(|face_name| global.faces.get_face_id(face_name));
+        let annotation = buffer.minor_modes.iter().find_map(|mode| mode.line_annotation(content, row));
+
+        // Guides only go down the first screen row of a wrapped line
+        let guides: Vec<usize> = if indent_guides {
+            indent_guide_mode::guide_cells(content, row)
+                .into_iter()
+                .filter_map(|cell| cell.checked_sub(if view.wrap { 0 } else { view.left_col }))
+                .filter(|&cell| cell < view.text_cols())
+                .collect()
+        } else {
+            vec!()
         };
+
+        let folded = content.is_folded(row);
+        let segments = view.segments(&line, cols.clone());
+        for (i, segment) in segments.iter().enumerate() {
+            if screen_row >= rows {
+                break;
+            }
+
+            let hidden: Vec<Range<usize>> = invisible
+                .iter()
+                .filter_map(|range| {
+                    let start = range.start.max(segment.start);
+                    let end = range.end.min(segment.end);
+                    (start < end).then(|| start - segment.start..end - segment.start)
+                })
+                .collect();
+            let text = hide_text(&line[segment.clone()], &hidden);
+            let text = text.as_str();
+            let segment_faces = hide_spans(&clip_spans(&syntax_faces, segment), &hidden);
+            let segment_overlays = hide_spans(&clip_spans(&overlays, segment), &hidden);
+            let label = label.as_ref().filter(|_| i == 0);
+            let line_mark = line_mark.filter(|_| i == 0);
+            let mark = if view.wrap && i + 1 < segments.len() {
+                Some("\\")
+            } else if !view.wrap && segment.end < cols.end {
+                Some("$")
+            } else {
+                None
+            };
+            let cursor = cursor_cell.filter(|&(cursor_row, _)| cursor_row == screen_row).map(|(_, cell)| cell);
+            let preedit = preedit_cell.filter(|&(_, (preedit_row, _))| preedit_row == screen_row).map(|(preedit, (_, cell))| (preedit, cell));
+
+            let segment_guides = if i == 0 { &guides[..] } else { &[] };
+            // Folded text shows as an ellipsis after the row before it
+            let fold_cell = (folded && i + 1 == segments.len())
+                .then(|| display_width(text))
+                .filter(|&cell| cell < view.text_cols());
+            // Annotations go a little way after the end of the line, cut
+            // off at the window edge
+            let annotation_cell = display_width(text) + fold_cell.map_or(0, |_| display_width(FOLD_MARK)) + 2;
+            let segment_annotation = annotation.as_ref()
+                .filter(|_| i + 1 == segments.len() && annotation_cell < view.text_cols())
+                .map(|(text, face)| (&text[..col_at_cell(text, view.text_cols() - annotation_cell)], *face));
+            let key = (text, &segment_faces, &segment_overlays, label, line_mark, mark, cursor, is_hl_line, fill_column_cell, segment_guides, preedit, (fold_cell, &segment_annotation));
+            if damage.needs_drawing(screen_row, key) {
+                let y_offset = screen_row as u32 * char_height;
+                clear_row(context, global, y_offset)?;
+                if let (Some(color), true) = (hl_line_background, is_hl_line) {
+                    fill_row(context, color, text_x, y_offset)?;
+                }
+                if let Some(cell) = fill_column_cell {
+                    context.canvas.set_draw_color(fill_column_color);
+                    context.canvas.fill_rect(rect!(text_x + cell * char_width as usize, y_offset, 1, char_height))?;
+                }
+                context.canvas.set_draw_color(indent_guide_color);
+                for cell in segment_guides {
+                    context.canvas.fill_rect(rect!(text_x + cell * char_width as usize, y_offset, 1, char_height))?;
+                }
+                // A bar down the left of the mark's column
+                let mark_color = line_mark.and_then(|face_id| global.faces.get_face_by_id(face_id)).and_then(|face| face.fg.or(face.bg));
+                if let Some(color) = mark_color {
+                    context.canvas.set_draw_color(color);
+                    context.canvas.fill_rect(rect!(0, y_offset, (char_width / 3).max(2), char_height))?;
+                }
+                if let Some((label, face_id)) = label {
+                    draw_line(context, global, line_number_x, y_offset, &[(0..label.len(), *face_id)], label, &[])?;
+                }
+                draw_line(context, global, text_x, y_offset, &segment_faces, text, &segment_overlays)?;
+                if let Some(mark) = mark {
+                    draw_segment(context, mark_x, y_offset, &continuation_face, mark)?;
+                }
+                if let Some(cell) = fold_cell {
+                    draw_segment(context, text_x + cell * char_width as usize, y_offset, &fold_face, FOLD_MARK)?;
+                }
+                if let Some((text, face_name)) = &segment_annotation {
+                    let face = global.faces.get_face_by_name(face_name).copied().unwrap_or(continuation_face);
+                    draw_segment(context, text_x + annotation_cell * char_width as usize, y_offset, &face, text)?;
+                }
+                // The cursor goes where the input method has it in the
+                // composed text
+                let under = match preedit {
+                    Some((preedit, cell)) => {
+                        draw_preedit(context, global, gutter_width + cell, y_offset, preedit)?;
+                        None
+                    },
+                    None => Some(under_cursor),
+                };
+                if let Some(cell) = cursor {
+                    let cell = cell + preedit.map_or(0, |(preedit, _)| display_width(&preedit.text[..preedit.cursor]));
+                    draw_cursor(context, global, gutter_width + cell, y_offset, under.unwrap_or(""))?;
+                }
+            }
+            screen_row += 1;
+        }
+        row = content.next_shown_row(row);
     }
     Ok(())
 }
 
-fn run(context: &mut RenderContext) -> Result<(), String> {
-    let mut global = Global {
-        faces: Faces {
-            theme_face_ids: vec!(),
-            faces: vec!(),
-            // Maps face names to face ids (to lookup in faces)
-            face_ids: HashMap::new(),
-        },
-    };
+// Draws what the input method is composing over the text from `cell`,
+// underlined
+fn draw_preedit(context: &mut RenderContext, global: &Global, cell: usize, y_offset: u32, preedit: &Preedit) -> Result<(), String> {
+    let (char_width, char_height) = context.fonts.cell_size()?;
+    let face = global.faces.get_face_by_name("preedit").copied().unwrap_or_default();
+    let background = global.faces.get_face_by_id(0).and_then(|face| face.bg).unwrap_or(FaceColor::Rgb(0, 0, 0));
+    let x_offset = cell as u32 * char_width;
+    let width = display_width(&preedit.text) as u32 * char_width;
+    context.canvas.set_draw_color(face.bg.map_or(background, |bg| background.blend(bg)));
+    context.canvas.fill_rect(rect!(x_offset, y_offset, width, char_height))?;
+    draw_segment(context, x_offset as usize, y_offset, &face, &preedit.text)?;
+    context.canvas.set_draw_color(face.fg.unwrap_or(FaceColor::Rgb(255, 255, 255)));
+    context.canvas.fill_rect(rect!(x_offset, y_offset + char_height - 1, width, 1))
+}
 
-    global.faces.put_face("default".to_string(), Face {
-        bg: FaceColor::Rgb(0, 0, 0),
-        fg: FaceColor::Rgb(255, 255, 255),
-    });
+// Draws the modeline of a window showing `buffer` on screen row `row`,
+// `width` pixels wide. Windows other than the selected one have it dimmed.
+fn draw_modeline(
+    context: &mut RenderContext,
+    global: &Global,
+    buffer: &Buffer,
+    selected: bool,
+    row: usize,
+    width: u32,
+    damage: &mut Damage,
+) -> Result<(), String> {
+    let segments = modeline::segments(buffer);
+    if !damage.needs_drawing(row, (&segments, width, selected)) {
+        return Ok(());
+    }
 
-    let theme = vec!(
-        ("keyword".to_string(), Face {
-            bg: FaceColor::Rgb(0, 0, 0),
-            fg: FaceColor::Rgb(255, 0, 0),
-        }),
-        ("function".to_string(), Face {
-            bg: FaceColor::Rgb(0, 0, 0),
-            fg: FaceColor::Rgb(0, 255, 0),
-        }),
-        ("comment".to_string(), Face {
-            bg: FaceColor::Rgb(0, 0, 0),
-            fg: FaceColor::Rgb(150, 150, 150),
-        }),
-    );
-
-    global.faces.load_theme_faces(theme);
-
-
-    let content = std::fs::read_to_string("src/main.rs")
-        .map_err(|e| e.to_string())?;
+    let base_face = if selected { "mode-line" } else { "mode-line-inactive" };
+    let mut line = String::new();
+    let mut faces = vec!();
+    for segment in &segments {
+        let face = if segment.face == "mode-line" { base_face } else { segment.face };
+        if let Some(face_id) = global.faces.get_face_id(face) {
+            faces.push((line.len()..line.len() + segment.text.len(), face_id));
+        }
+        line.push_str(&segment.text);
+    }
 
-    let mut lines: Vec<String>     = vec!();
-    let mut faces: Vec<Vec<usize>> = vec!();
+    let (_, char_height) = context.fonts.cell_size()?;
+    let y_offset = row as u32 * char_height;
+    let mode_line_face_id = global.faces.get_face_id(base_face).unwrap_or(0);
+    let background = global.faces.get_face_by_id(0).and_then(|face| face.bg).unwrap_or(FaceColor::Rgb(0, 0, 0));
+    let mode_line_background = global.faces.get_face_by_id(mode_line_face_id).and_then(|face| face.bg).map_or(background, |bg| background.blend(bg));
+    context.canvas.set_draw_color(mode_line_background);
+    context.canvas.fill_rect(rect!(0, y_offset, width, char_height))?;
+    draw_line(context, global, 0, y_offset, &[(0..line.len(), mode_line_face_id)], &line, &faces)?;
+    Ok(())
+}
+
+// Draws a tab for each buffer along the top row, `width` pixels wide, the
+// current buffer's standing out
+fn draw_tab_bar(
+    context: &mut RenderContext,
+    global: &Global,
+    current: &str,
+    width: u32,
+    damage: &mut Damage,
+) -> Result<(), String> {
+    let (char_width, _) = context.fonts.cell_size()?;
+    let tabs = global.tab_bar.layout(current, (width / char_width) as usize);
+    let key: Vec<(&str, usize, bool)> = tabs.iter().map(|tab| (tab.name, tab.cells.start, tab.current)).collect();
+    if !damage.needs_drawing(0, (&key, width)) {
+        return Ok(());
+    }
 
-    for line in content.lines() {
-        lines.push(line.to_string());
-        faces.push(vec![0; line.len()]);
+    let background = global.faces.get_face_by_id(0).and_then(|face| face.bg).unwrap_or(FaceColor::Rgb(0, 0, 0));
+    let bar_background = global.faces.get_face_by_name("tab-bar").and_then(|face| face.bg).map_or(background, |bg| background.blend(bg));
+    fill_row(context, bar_background, 0, 0)?;
+    for tab in &tabs {
+        let face = if tab.current { "tab-bar-tab" } else { "tab-bar-tab-inactive" };
+        let face_id = global.faces.get_face_id(face).unwrap_or(0);
+        draw_line(context, global, tab.cells.start * char_width as usize, 0, &[(0..tab.label.len(), face_id)], &tab.label, &[])?;
     }
+    Ok(())
+}
 
-    let mut content = TextContent {
-        faces: faces,
-        lines: lines,
-    };
+// A window's texture, which keeps the rows that didn't change since the last
+// frame, and where its parts are on it
+#[derive(Default)]
+struct WindowFrame<'t> {
+    texture: Option<Texture<'t>>,
+    damage: Damage,
+    text_width: u32,
+    content_height: u32,
+    // Pixels the content is moved up by while scrolling, now and as of the
+    // last frame shown
+    shift: u32,
+    shown_shift: u32,
+}
 
-    let mut minor_modes: Vec<Box<dyn TextMinorMode>> = vec!(
-        Box::new(RustMode::new()),
-    );
+// Lays out a window showing `buffer` in `area` and draws what changed in it
+// onto its frame
+fn draw_window<'t>(
+    context: &mut RenderContext,
+    global: &Global,
+    buffer: &mut Buffer,
+    selected: bool,
+    area: Area,
+    frame: &mut WindowFrame<'t>,
+    texture_creator: &'t TextureCreator<WindowContext>,
+) -> Result<(), String> {
+    let (char_width, char_height) = context.fonts.cell_size()?;
+    // The bottom row is for the modeline
+    buffer.view.rows = ((area.height / char_height) as usize).saturating_sub(1);
+    let minimap_width = if buffer.has_minor_mode("minimap") { MINIMAP_COLS as u32 * char_width } else { 0 };
+    let scroll_bar_width = if global.scroll_bar { SCROLL_BAR_WIDTH } else { 0 };
+    let text_width = area.width.saturating_sub(minimap_width + scroll_bar_width);
+    buffer.view.cols = ((text_width / char_width) as usize).saturating_sub(buffer.view.gutter_width(&buffer.content));
+    // Folded text the cursor went into is shown again
+    buffer.content.reveal_cursor();
+    buffer.view.follow(&buffer.content);
+    buffer.view.animate_scroll(global.smooth_scroll);
+    frame.text_width = text_width;
+    frame.content_height = buffer.view.rows as u32 * char_height;
+
+    // The frame has an extra content row, between the content and the
+    // modeline, which only shows part way through a scroll
+    let layout = (area.width, area.height, char_width, char_height, global.faces.generation(), global.cursor.style, minimap_width, scroll_bar_width);
+    let fresh = frame.damage.begin(layout, buffer.view.rows + 2);
+    if fresh {
+        frame.texture = Some(texture_creator
+            .create_texture_target(None, area.width.max(1), area.height + char_height)
+            .map_err(|e| e.to_string())?);
+    }
+    let texture = frame.texture.as_mut().ok_or("No frame texture")?;
+    let damage = &mut frame.damage;
+
+    let (sdl, ttf, fonts) = (context.sdl, context.ttf, context.fonts);
+    let buffer: &Buffer = buffer;
+    let background = global.faces.get_face_by_id(0).and_then(|face| face.bg).unwrap_or(FaceColor::Rgb(0, 0, 0));
+    let show_cursor = selected && global.minibuffer.is_none() && global.cursor.is_on();
+    let preedit = (selected && global.minibuffer.is_none() && !global.preedit.is_empty()).then_some(&global.preedit);
+    let mut drawn = Ok(());
+    context.canvas.with_texture_canvas(texture, |canvas| {
+        // Including the strip below the last full row
+        if fresh {
+            canvas.set_draw_color(background);
+            canvas.clear();
+        }
+        let mut target = RenderContext { sdl, canvas, ttf, fonts };
+        // The text stays out of the minimap's strip
+        target.canvas.set_clip_rect(rect!(0, 0, text_width, area.height + char_height));
+        let content_drawn = draw_content(&mut target, global, buffer, show_cursor, preedit, damage);
+        target.canvas.set_clip_rect(None);
+        drawn = content_drawn
+            .and_then(|_| match minimap_width {
+                0 => Ok(()),
+                _ => draw_minimap(&mut target, global, buffer, text_width, buffer.view.rows as u32 * char_height, damage),
+            })
+            .and_then(|_| match scroll_bar_width {
+                0 => Ok(()),
+                _ => draw_scroll_bar(&mut target, global, buffer, area.width - scroll_bar_width, buffer.view.rows as u32 * char_height, damage),
+            })
+            .and_then(|_| draw_modeline(&mut target, global, buffer, selected, buffer.view.rows + 1, area.width, damage));
+    }).map_err(|e| e.to_string())?;
+    drawn?;
+
+    // Scrolling slides the content up or down by the part of the first row
+    // that is above the top edge
+    let (_, hidden_rows) = buffer.view.first_shown_row(&buffer.content);
+    frame.shift = (hidden_rows * char_height as f32).round() as u32;
+    Ok(())
+}
 
-    for minor_mode in &mut minor_modes {
-        minor_mode.modify(&mut global, &mut content);
+// Shows a window's frame in `area` of the screen, with text `char_height`
+// pixels tall
+fn copy_window(context: &mut RenderContext, frame: &mut WindowFrame, area: Area, char_height: u32) -> Result<(), String> {
+    let texture = frame.texture.as_ref().ok_or("No frame texture")?;
+    let (text_width, content_height) = (frame.text_width, frame.content_height);
+    context.canvas.copy(
+        texture,
+        rect!(0, frame.shift, text_width, content_height),
+        rect!(area.x, area.y, text_width, content_height))?;
+    // The minimap and scroll bar don't scroll along
+    if text_width < area.width {
+        let strip_width = area.width - text_width;
+        context.canvas.copy(
+            texture,
+            rect!(text_width, 0, strip_width, content_height),
+            rect!(area.x + text_width, area.y, strip_width, content_height))?;
     }
+    // The modeline is below the extra content row
+    let modeline_height = area.height - content_height;
+    context.canvas.copy(
+        texture,
+        rect!(0, content_height + char_height, area.width, modeline_height),
+        rect!(area.x, area.y + content_height, area.width, modeline_height))?;
+    frame.shown_shift = frame.shift;
+    Ok(())
+}
 
+// Draws the scroll bar of the buffer into the `height` pixels tall strip at
+// `x_offset`, when the thumb moved since the last frame
+fn draw_scroll_bar(
+    context: &mut RenderContext,
+    global: &Global,
+    buffer: &Buffer,
+    x_offset: u32,
+    height: u32,
+    damage: &mut Damage,
+) -> Result<(), String> {
+    let (top, thumb_height) = scroll_bar::thumb(&buffer.content, &buffer.view, height);
+    if !damage.needs_drawing_part("scroll-bar", (top, thumb_height, height)) {
+        return Ok(());
+    }
 
-    // TODO: Move loop outta here!
-    'mainloop: loop {
-        for event in context.sdl.event_pump()?.poll_iter() {
-            match event {
-                Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
+    let background = global.faces.get_face_by_id(0).and_then(|face| face.bg).unwrap_or(FaceColor::Rgb(0, 0, 0));
+    let face = global.faces.get_face_by_name("scroll-bar").copied().unwrap_or_default();
+    context.canvas.set_draw_color(face.bg.map_or(background, |bg| background.blend(bg)));
+    context.canvas.fill_rect(rect!(x_offset, 0, SCROLL_BAR_WIDTH, height))?;
+    context.canvas.set_draw_color(face.fg.unwrap_or(FaceColor::Rgb(100, 100, 110)));
+    context.canvas.fill_rect(rect!(x_offset + 2, top, SCROLL_BAR_WIDTH - 4, thumb_height))?;
+    Ok(())
+}
+
+// Most lines a popup shows at once, scrolling to keep the one picked out in
+// view
+const POPUP_ROWS: usize = 10;
+
+// Draws the popups over the selected window, which shows `buffer` in `area`,
+// under the places they are anchored to, or over them when there's no room
+// below. Returns where each one went on screen and its first line shown, for
+// clicking on them, or None when its anchor is scrolled out of view.
+fn draw_popups(context: &mut RenderContext, global: &Global, buffer: &Buffer, area: Area) -> Result<Vec<Option<(Area, usize)>>, String> {
+    let (char_width, char_height) = context.fonts.cell_size()?;
+    let (content, view) = (&buffer.content, &buffer.view);
+    let gutter_width = view.gutter_width(content);
+    let text_height = view.rows as u32 * char_height;
+    let border = global.faces.get_face_by_name("popup-border").and_then(|face| face.fg).unwrap_or(FaceColor::Rgb(90, 90, 110));
+    let selected_face_id = global.faces.get_face_id("popup-selected");
+
+    let mut shown = vec!();
+    context.canvas.set_clip_rect(rect!(area.x, area.y, area.width, text_height));
+    for popup in global.popups.iter() {
+        if popup.anchor.row < view.top_row || popup.anchor.row > view.bottom_row(content) {
+            shown.push(None);
+            continue;
+        }
+
+        let (row, cell) = view.position_cell(content, popup.anchor);
+        let rows = popup.lines.len().min(POPUP_ROWS);
+        let first = if popup.is_menu() { (popup.selected + 1).saturating_sub(rows) } else { 0 };
+        let cols = popup.lines.iter().map(|line| display_width(line)).max().unwrap_or(0) + 2;
+        let width = (cols as u32 * char_width).min(area.width);
+        let height = rows as u32 * char_height;
+        let below = (row as u32 + 1) * char_height;
+        let y = if below + height <= text_height || row as u32 * char_height < height { below } else { row as u32 * char_height - height };
+        let x = ((gutter_width + cell) as u32 * char_width).min(area.width - width);
+        let popup_area = Area { x: area.x + x, y: area.y + y, width, height };
+
+        let face_id = global.faces.get_face_id(popup.face).unwrap_or(0);
+        let background = global.faces.get_face_by_id(face_id).and_then(|face| face.bg).unwrap_or(FaceColor::Rgb(0, 0, 0));
+        context.canvas.set_draw_color(background);
+        context.canvas.fill_rect(rect!(popup_area.x, popup_area.y, width, height))?;
+        for (i, line) in popup.lines.iter().enumerate().skip(first).take(rows) {
+            let line_y = popup_area.y + (i - first) as u32 * char_height;
+            let mut faces = vec!((0..line.len(), face_id));
+            if let (true, Some(selected_face_id)) = (popup.is_menu() && i == popup.selected, selected_face_id) {
+                if let Some(bg) = global.faces.get_face_by_id(selected_face_id).and_then(|face| face.bg) {
+                    context.canvas.set_draw_color(bg);
+                    context.canvas.fill_rect(rect!(popup_area.x, line_y, width, char_height))?;
                 }
-                | Event::Quit { .. } => break 'mainloop,
-                _ => {}
+                faces.push((0..line.len(), selected_face_id));
             }
+            draw_line(context, global, (popup_area.x + char_width) as usize, line_y, &faces[..1], line, &faces[1..])?;
         }
+        context.canvas.set_draw_color(border);
+        context.canvas.draw_rect(rect!(popup_area.x, popup_area.y, width, height))?;
+        shown.push(Some((popup_area, first)));
+    }
+    context.canvas.set_clip_rect(None);
+    Ok(shown)
+}
 
-        context.canvas.set_draw_color(Color::RGBA(0, 0, 0, 0));
-        context.canvas.clear();
-
-        draw_content(context, &global, &content)?;
+// Draws the minimap of the buffer into the `height` pixels tall strip at
+// `x_offset`, when it changed since the last frame
+fn draw_minimap(
+    context: &mut RenderContext,
+    global: &Global,
+    buffer: &Buffer,
+    x_offset: u32,
+    height: u32,
+    damage: &mut Damage,
+) -> Result<(), String> {
+    let (content, view) = (&buffer.content, &buffer.view);
+    let (char_width, char_height) = context.fonts.cell_size()?;
+    let width = MINIMAP_COLS as u32 * char_width;
+    let line_height = minimap_mode::line_height(char_height);
+    let dot_width = minimap_mode::char_width(char_height);
+    let lines = (height / line_height) as usize;
+    let first = minimap_mode::first_line(content, view, lines);
+    let shown = view.top_row..=view.bottom_row(content);
+
+    let key = (&buffer.name, content.change_count(), first, shown.clone(), height);
+    if !damage.needs_drawing_part("minimap", key) {
+        return Ok(());
+    }
 
-        context.canvas.present();
+    let default_face = global.faces.get_face_by_id(0).copied().unwrap_or_default();
+    let background = default_face.bg.unwrap_or(FaceColor::Rgb(0, 0, 0));
+    context.canvas.set_draw_color(background);
+    context.canvas.fill_rect(rect!(x_offset, 0, width, height))?;
+
+    // The part of the buffer on screen
+    if let Some(viewport) = global.faces.get_face_by_name("minimap-viewport").and_then(|face| face.bg) {
+        let top = shown.start().saturating_sub(first) as u32 * line_height;
+        let bottom = ((shown.end() + 1).saturating_sub(first) as u32 * line_height).min(height);
+        context.canvas.set_draw_color(background.blend(viewport));
+        context.canvas.fill_rect(rect!(x_offset, top, width, bottom.saturating_sub(top)))?;
     }
 
+    // Every word is a block in its syntax color, faded into the background
+    let end = (content.visible_range().1.row + 1).min(first + lines);
+    for row in first..end {
+        let line = content.line(row);
+        let spans = content.faces.line_spans(row, line.len());
+        let y_offset = (row - first) as u32 * line_height;
+
+        let mut blocks: Vec<(usize, usize, FaceColor)> = vec!();
+        let mut cell = 0;
+        for (col, cluster) in graphemes(&line) {
+            let cells = cluster_cells(cluster);
+            if !cluster.trim().is_empty() {
+                let face_id = spans.iter().find(|(range, _)| range.contains(&col)).map_or(0, |&(_, face_id)| face_id);
+                let face = global.faces.get_face_by_id(face_id).map_or(default_face, |face| default_face.overlay(face));
+                let color = face.fg.unwrap_or(FaceColor::Rgb(255, 255, 255));
+                match blocks.last_mut() {
+                    Some((start, len, block_color)) if *start + *len == cell && *block_color == color => *len += cells,
+                    _ => blocks.push((cell, cells, color)),
+                }
+            }
+            cell += cells;
+            if cell as u32 * dot_width >= width {
+                break;
+            }
+        }
+
+        for (start, len, color) in blocks {
+            let x = start as u32 * dot_width;
+            let block_width = (len as u32 * dot_width).min(width.saturating_sub(x));
+            context.canvas.set_draw_color(background.blend(color.with_alpha(160)));
+            context.canvas.fill_rect(rect!(x_offset + x, y_offset, block_width, (line_height - 1).max(1)))?;
+        }
+    }
     Ok(())
 }
 
-pub struct TextMode {
+// Converts a mouse position in window coordinates to pixels, which differ on
+// high DPI screens
+fn window_to_pixels(context: &RenderContext, x: i32, y: i32) -> Result<(u32, u32), String> {
+    let (pixel_width, pixel_height) = context.canvas.output_size()?;
+    let (window_width, window_height) = context.canvas.window().size();
+    let scale = |position: i32, pixels: u32, size: u32| (position.max(0) as u64 * pixels as u64 / size.max(1) as u64) as u32;
+    Ok((scale(x, pixel_width, window_width), scale(y, pixel_height, window_height)))
 }
 
-impl MajorMode for TextMode  {
-    fn draw(&mut self, context: &mut RenderContext) -> Result<(), String> {
-        run(context)
-    }
+// The position of the text under pixel (x, y) of the selected window
+fn position_at_pixel(buffer: &Buffer, char_width: u32, char_height: u32, x: u32, y: u32) -> Position {
+    let cell = ((x / char_width) as usize).saturating_sub(buffer.view.gutter_width(&buffer.content));
+    buffer.view.position_at_cell(&buffer.content, (y / char_height) as usize, cell)
 }
 
-pub struct RustMode {
-    ts_parser: Parser,
+// The keys that can come next and what they do, in as many columns as it
+// takes to fit in a popup
+fn which_key_lines(next: &[(KeyPress, Binding)]) -> Vec<String> {
+    let mut next: Vec<(String, &str)> = next
+        .iter()
+        .map(|(key, binding)| (describe_keys(&[*key]), keymap::describe_binding(*binding)))
+        .collect();
+    next.sort();
+    let key_width = next.iter().map(|(key, _)| display_width(key)).max().unwrap_or(0);
+    let hints: Vec<String> = next
+        .iter()
+        .map(|(key, name)| format!("{}{}  {}", " ".repeat(key_width - display_width(key)), key, name))
+        .collect();
+    let width = hints.iter().map(|hint| display_width(hint)).max().unwrap_or(0);
+    let columns = hints.len().div_ceil(POPUP_ROWS).max(1);
+    let rows = hints.len().div_ceil(columns);
+    (0..rows)
+        .map(|row| {
+            let cells: Vec<String> = hints
+                .iter()
+                .skip(row)
+                .step_by(rows)
+                .map(|hint| format!("{}{}", hint, " ".repeat(width - display_width(hint))))
+                .collect();
+            cells.join("   ").trim_end().to_string()
+        })
+        .collect()
 }
 
-impl RustMode {
-    fn new() -> RustMode {
-        let mut parser = Parser::new();
-        parser.set_language(tree_sitter_rust::language()).expect("Error loading Rust grammar");
+// Converts a rectangle in pixels to window coordinates, the other way from
+// window_to_pixels
+fn pixels_to_window(context: &RenderContext, rect: Rect) -> Result<Rect, String> {
+    let (pixel_width, pixel_height) = context.canvas.output_size()?;
+    let (window_width, window_height) = context.canvas.window().size();
+    let scale = |position: i64, size: u32, pixels: u32| position * size as i64 / pixels.max(1) as i64;
+    Ok(rect!(
+        scale(rect.x() as i64, window_width, pixel_width),
+        scale(rect.y() as i64, window_height, pixel_height),
+        scale(rect.width() as i64, window_width, pixel_width).max(1),
+        scale(rect.height() as i64, window_height, pixel_height).max(1)
+    ))
+}
 
+// Most candidates listed above the minibuffer at once
+const COMPLETION_ROWS: usize = 8;
 
-        RustMode {
-            ts_parser: parser
-        }
+// Rows the minibuffer takes at the bottom of the window, including the
+// candidates matching its input
+fn minibuffer_rows(minibuffer: Option<&Minibuffer>) -> usize {
+    match minibuffer {
+        Some(minibuffer) => 1 + minibuffer.completions.len().min(COMPLETION_ROWS),
+        None => 1,
     }
-} // end impl RustMode
+}
 
+// Draws the prompt and its input on the bottom rows of the window, starting
+// at screen row `first_row`. The last row stays blank when there isn't one.
+fn draw_minibuffer(
+    context: &mut RenderContext,
+    global: &Global,
+    minibuffer: Option<&Minibuffer>,
+    first_row: usize,
+    damage: &mut Damage,
+) -> Result<(), String> {
+    let (_, char_height) = context.fonts.cell_size()?;
+    let minibuffer = match minibuffer {
+        Some(minibuffer) => minibuffer,
+        // Otherwise it's the echo area
+        None => {
+            if damage.needs_drawing(first_row, &global.message) {
+                let y_offset = first_row as u32 * char_height;
+                clear_row(context, global, y_offset)?;
+                if let Some(message) = &global.message {
+                    draw_line(context, global, 0, y_offset, &[], message, &[])?;
+                }
+            }
+            return Ok(());
+        },
+    };
 
-impl TextMinorMode for RustMode {
-    // TODO: Use an "on change" hook
-    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
-        let tree = self.ts_parser.parse_with(&mut |_byte: usize, position: Point| -> &[u8] {
-            let row = position.row as usize;
-            let column = position.column as usize;
-            if row < content.lines.len() {
-                if column < content.lines[row].as_bytes().len() {
-                    &content.lines[row].as_bytes()[column..]
-                } else {
-                    "\n".as_bytes()
+    // The candidates go above the input, scrolled along to keep the one
+    // picked out in view, with the characters the input matched highlighted
+    let mut row = first_row;
+    let shown = minibuffer.completions.len().min(COMPLETION_ROWS);
+    let first = minibuffer.selected.map_or(0, |index| (index + 1).saturating_sub(shown));
+    let matched_face = global.faces.get_face_id("completions-common-part");
+    let background = global.faces.get_face_by_id(0).and_then(|face| face.bg).unwrap_or(FaceColor::Rgb(0, 0, 0));
+    for (index, candidate) in minibuffer.completions.iter().enumerate().skip(first).take(shown) {
+        let selected = minibuffer.selected == Some(index);
+        if damage.needs_drawing(row, (&candidate.text, &candidate.matched, selected)) {
+            let y_offset = row as u32 * char_height;
+            let highlight = global.faces.get_face_by_name("completions-highlight").and_then(|face| face.bg);
+            match highlight {
+                Some(bg) if selected => fill_row(context, background.blend(bg), 0, y_offset)?,
+                _ => clear_row(context, global, y_offset)?,
+            }
+            let mut faces = vec!();
+            if let Some(face_id) = matched_face {
+                for &i in &candidate.matched {
+                    let len = candidate.text[i..].chars().next().map_or(1, char::len_utf8);
+                    faces.push((i..i + len, face_id));
                 }
-            } else {
-                &[]
             }
-        }, None).unwrap();
+            let mut base_faces = vec!();
+            if let (true, Some(face_id)) = (selected, global.faces.get_face_id("completions-highlight")) {
+                base_faces.push((0..candidate.text.len(), face_id));
+            }
+            draw_line(context, global, 0, y_offset, &base_faces, &candidate.text, &faces)?;
+        }
+        row += 1;
+    }
 
-        let highlight_query = Query::new(
-            tree_sitter_rust::language(),
-            tree_sitter_rust::HIGHLIGHT_QUERY
-        ).unwrap();
-        let mut cursor = QueryCursor::new();
+    // Text the input method is composing goes in at the cursor
+    let content = &minibuffer.input.content;
+    let input = content.line(0);
+    let (before, after) = input.split_at(content.cursor.col);
+    let line = format!("{}{}{}{}", minibuffer.prompt, before, global.preedit.text, after);
+    let preedit_start = minibuffer.prompt.len() + content.cursor.col;
+    let preedit_range = preedit_start..preedit_start + global.preedit.text.len();
+    let cursor_col = global.cursor.is_on().then(|| preedit_start + global.preedit.cursor);
+    if !damage.needs_drawing(row, (&line, cursor_col, &preedit_range)) {
+        return Ok(());
+    }
 
-        let lines = &content.lines;
+    let y_offset = row as u32 * char_height;
+    clear_row(context, global, y_offset)?;
+    let mut prompt_faces = vec!();
+    if let Some(face_id) = global.faces.get_face_id("minibuffer-prompt") {
+        prompt_faces.push((0..minibuffer.prompt.len(), face_id));
+    }
+    let preedit_face = global.faces.get_face_id("preedit").filter(|_| !preedit_range.is_empty());
+    if let Some(face_id) = preedit_face {
+        prompt_faces.push((preedit_range.clone(), face_id));
+    }
+    draw_line(context, global, 0, y_offset, &prompt_faces, &line, &[])?;
+    if !preedit_range.is_empty() {
+        let (char_width, _) = context.fonts.cell_size()?;
+        let underline = global.faces.get_face_by_name("preedit").and_then(|face| face.fg).unwrap_or(FaceColor::Rgb(255, 255, 255));
+        let x_offset = display_width(&line[..preedit_range.start]) as u32 * char_width;
+        let width = display_width(&line[preedit_range]) as u32 * char_width;
+        context.canvas.set_draw_color(underline);
+        context.canvas.fill_rect(rect!(x_offset, y_offset + char_height - 1, width, 1))?;
+    }
+    match cursor_col {
+        Some(col) => {
+            let under = graphemes(&line[col..]).next().map_or("", |(_, cluster)| cluster);
+            draw_cursor(context, global, display_width(&line[..col]), y_offset, under)
+        },
+        None => Ok(()),
+    }
+}
+
+// The argument made up of `digits`, which may start with a minus
+fn prefix_from_digits(digits: &str) -> PrefixArg {
+    match digits {
+        "-" => PrefixArg::Minus,
+        _ => PrefixArg::Number(digits.parse().unwrap_or(i64::MAX)),
+    }
+}
+
+fn digit_value(keycode: Keycode) -> Option<char> {
+    match keycode as i32 {
+        code @ 0x30..=0x39 => Some(code as u8 as char),
+        _ => None,
+    }
+}
+
+// The control character a key stands for after C-q, e.g. C-a for ^A. Keys
+// without one are typed as normal text instead.
+fn control_char(prefix: &str, keycode: Keycode) -> Option<char> {
+    let code = keycode as i32;
+    match (prefix, keycode) {
+        ("C-", _) if (0x61..=0x7a).contains(&code) => char::from_u32(code as u32 - 0x60),
+        ("C-", Keycode::Space)  => Some('\0'),
+        ("", Keycode::Tab)      => Some('\t'),
+        ("", Keycode::Return)   => Some('\r'),
+        ("", Keycode::Escape)   => Some('\u{1b}'),
+        ("", Keycode::Backspace) | ("", Keycode::Delete) => Some('\u{7f}'),
+        _ => None,
+    }
+}
 
-        let text_callback = |node: Node| {
-            let start = node.start_position();
-            let end = node.end_position();
+// Keys that only modify others never start or continue a sequence
+pub fn is_modifier_key(keycode: Keycode) -> bool {
+    matches!(keycode,
+        Keycode::LCtrl | Keycode::RCtrl |
+        Keycode::LShift | Keycode::RShift |
+        Keycode::LAlt | Keycode::RAlt |
+        Keycode::LGui | Keycode::RGui |
+        Keycode::CapsLock | Keycode::Mode)
+}
+
+// Emacs style modifier prefix for a key press, e.g. "C-M-"
+pub fn modifier_prefix(keymod: Mod) -> &'static str {
+    let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+    let alt = keymod.intersects(Mod::LALTMOD | Mod::RALTMOD);
+    let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+    keymap::modifier_prefix(ctrl, alt, shift)
+}
+
+// Longest an idle frame waits for an event, which bounds how late timers
+// like auto-saving can run
+const IDLE_WAIT_MS: u32 = 100;
+
+// Shorter while highlighting is being worked out off the main thread, so it
+// shows soon after it's done
+const BUSY_WAIT_MS: u32 = 10;
+
+// How long a prefix key waits before listing the keys that can follow it
+const WHICH_KEY_DELAY: Duration = Duration::from_millis(1000);
 
-            lines[start.row][start.column..end.column].as_bytes()
+// Rows scrolled per notch of the mouse wheel
+const MOUSE_WHEEL_ROWS: isize = 3;
+
+// How often open files are checked for changes made by other programs
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+fn run(context: &mut RenderContext) -> Result<(), String> {
+    let mut global = Global {
+        faces: Faces {
+            theme_face_ids: vec!(),
+            faces: vec!(),
+            // Maps face names to face ids (to lookup in faces)
+            face_ids: HashMap::new(),
+            generation: 0,
+            anonymous_face_ids: HashMap::new(),
+        },
+        kill_ring: vec!(),
+        clipboard: context.sdl.video()?.clipboard(),
+        killed_rectangle: vec!(),
+        last_command: None,
+        command: CommandContext::default(),
+        abbrevs: AbbrevTable::new(&[
+            ("teh", "the"),
+            ("adn", "and"),
+            ("recieve", "receive"),
+        ]),
+        buffers: BufferList::default(),
+        minibuffer: None,
+        minibuffer_history: History::default(),
+        windows: Windows::default(),
+        tab_bar: TabBar::from_env(),
+        popups: Popups::default(),
+        message: None,
+        backups: Backups::from_env(),
+        undo_files: undo_file::enabled_from_env(),
+        bookmarks: Bookmarks::from_env(),
+        cursor: Cursor::from_env(),
+        text_scale: 0,
+        font: FontSettings::from_env(),
+        smooth_scroll: true,
+        scroll_bar: scroll_bar::enabled_from_env(),
+        keymaps: Keymaps::default(),
+        preedit: Preedit::default(),
+        quit: false,
+    };
+
+    theme::apply(&mut global.faces, theme::from_env());
+    match keymap::from_env() {
+        Ok(keymaps) => global.keymaps = keymaps,
+        Err(e) => global.message(format!("Loading keys failed: {}", e)),
+    }
+    // Before any file is opened, so they get the languages
+    let grammar_errors = grammars::load_from_env();
+    if !grammar_errors.is_empty() {
+        global.message(format!("Loading grammars failed: {}", grammar_errors.join("; ")));
+    }
+
+
+    // Files to open come from the command line
+    let mut paths: Vec<PathBuf> = std::env::args().skip(1).map(PathBuf::from).collect();
+    if paths.is_empty() {
+        paths.push(PathBuf::from("src/main.rs"));
+    }
+
+    let mut buffer = visit_file(&paths[0], &global)?;
+    for path in &paths[1..] {
+        let visited = visit_file(path, &global)?;
+        global.buffers.add(visited);
+    }
+    if auto_save::has_newer_auto_save(&paths[0]) {
+        auto_save::offer_recovery(&mut global);
+    }
+    let mut last_auto_save = Instant::now();
+    let mut last_disk_check = Instant::now();
+
+
+    let mut pending_keys: Vec<KeyPress> = vec!();
+    // When the last prefix key was pressed, to list what can follow it if
+    // nothing does for a moment
+    let mut prefix_since: Option<Instant> = None;
+    let mut swallow_text_input = false;
+
+    // The C-u argument for the next command, and the digits typed after C-u
+    let mut prefix_arg: Option<PrefixArg> = None;
+    let mut prefix_digits: Option<String> = None;
+
+    // C-q was pressed and the next key goes in literally
+    let mut quoted_insert = false;
+
+    // Each window is drawn onto a texture of its own, which keeps the rows
+    // that didn't change, and so are the minibuffer and tab bar
+    let texture_creator = context.canvas.texture_creator();
+    let mut window_frames: HashMap<usize, WindowFrame> = HashMap::new();
+    let mut minibuffer_frame = None;
+    let mut damage = Damage::default();
+    let mut tab_bar_frame = None;
+    let mut tab_bar_damage = Damage::default();
+    // Where the windows were on screen last frame
+    let mut shown_areas: Vec<Area> = vec!();
+    // Where the popups were, and which of their changes was shown
+    let mut shown_popups: Vec<Option<(Area, usize)>> = vec!();
+    let mut shown_popups_generation = 0;
+    // Where the scroll bar's thumb was grabbed, in pixels from its top, while
+    // it's being dragged
+    let mut scroll_bar_drag: Option<u32> = None;
+    // Where the selection started, while the mouse is dragged over the text
+    let mut mouse_selection: Option<Position> = None;
+    // Where the input method was last told the cursor is
+    let mut shown_input_rect: Option<Rect> = None;
+    // Animation frames are spaced out to the maximum frame rate
+    let frame_rate = FrameRate::from_env();
+    let mut last_frame = Instant::now();
+
+    // Other fonts than the ones first loaded, once the text has been scaled,
+    // the window moved to a screen with another DPI or the font set
+    let mut display_scale = font::display_scale(context.canvas.window());
+    let mut font_size = font::point_size(global.font.size, display_scale, 0);
+    let mut loaded_font = (global.font.clone(), global.text_scale);
+    let mut scaled_fonts = None;
+
+    let mut event_pump = context.sdl.event_pump()?;
+    // The event that ended the wait after an idle frame
+    let mut waited_event: Option<Event> = None;
+    let mut script = event_script::from_env()?;
+    let mut recorder = event_script::recorder_from_env()?;
+
+    // TODO: Move loop outta here!
+    'mainloop: loop {
+        // A script being replayed stands in for the keyboard and mouse
+        if script.is_some() {
+            buffer.refresh_faces(&mut global);
+            // Checks on faces wait for the highlighting to come back
+            while buffer.is_busy() {
+                std::thread::sleep(Duration::from_millis(1));
+                buffer.refresh_faces(&mut global);
+            }
+        }
+        let events: Vec<Event> = match &mut script {
+            Some(script) => match script.next_events(&global, &buffer)? {
+                Some(events) => events.into_iter()
+                    .chain(event_pump.poll_iter().filter(|event| matches!(event, Event::Quit { .. } | Event::Window { .. })))
+                    .collect(),
+                None => break 'mainloop,
+            },
+            None => waited_event.take().into_iter().chain(event_pump.poll_iter()).collect(),
         };
+        for event in events {
+            if let Some(recorder) = &mut recorder {
+                recorder.record(&event);
+            }
+            if matches!(event, Event::KeyDown { .. } | Event::TextInput { .. } | Event::MouseWheel { .. } | Event::MouseButtonDown { .. }) {
+                global.cursor.reset_blink();
+            }
+            if matches!(event, Event::KeyDown { keycode: Some(keycode), .. } if !is_modifier_key(keycode)) {
+                global.message = None;
+                global.popups.close_info();
+            }
+            match event {
+                Event::Quit { .. } => break 'mainloop,
+                // The view follows the new size when laying out the frame
+                Event::Window {
+                    win_event: WindowEvent::Resized(..) | WindowEvent::SizeChanged(..) | WindowEvent::Exposed,
+                    ..
+                } => {
+                    display_scale = font::display_scale(context.canvas.window());
+                    damage.invalidate();
+                    tab_bar_damage.invalidate();
+                    for frame in window_frames.values_mut() {
+                        frame.damage.invalidate();
+                    }
+                },
+                Event::MouseWheel { y, .. } => {
+                    buffer.view.scroll_by(&mut buffer.content, -y as isize * MOUSE_WHEEL_ROWS);
+                },
+                // Clicking a window selects it, and clicking the selected
+                // window's minimap jumps there. Clicking a tab switches to its
+                // buffer, or kills it when on its close button.
+                Event::MouseButtonDown { mouse_btn: MouseButton::Left, clicks, x, y, .. } => {
+                    let (char_width, char_height) = scaled_fonts.as_ref().unwrap_or(context.fonts).cell_size()?;
+                    let (x, y) = window_to_pixels(context, x, y)?;
+                    // Clicking a menu chooses the line clicked, and clicking
+                    // anywhere else closes the popups
+                    let clicked_popup = shown_popups.iter().enumerate().find_map(|(index, shown)| match shown {
+                        Some((area, first)) if (area.x..area.x + area.width).contains(&x) && (area.y..area.y + area.height).contains(&y) => {
+                            Some((index, first + ((y - area.y) / char_height) as usize))
+                        },
+                        _ => None,
+                    });
+                    if let Some((index, line)) = clicked_popup {
+                        global.popups.select(index, line);
+                        if index + 1 == shown_popups.len() {
+                            commands::execute(&mut global, &mut buffer, Repeatable::Command(popup::popup_choose), CommandContext::default());
+                        }
+                        continue;
+                    }
+                    global.popups.close_all();
+                    if global.tab_bar.enabled && y < char_height {
+                        let (window_width, _) = context.canvas.output_size()?;
+                        let cols = (window_width / char_width) as usize;
+                        match global.tab_bar.click(&buffer.name, cols, (x / char_width) as usize) {
+                            Some(Click::Select(name)) => global.buffers.switch_to(&name),
+                            Some(Click::Close(name)) => {
+                                global.buffers.switch_to(&name);
+                                global.buffers.apply_request(&mut buffer);
+                                commands::kill_buffer(&mut global, &mut buffer);
+                            },
+                            None => {},
+                        }
+                        global.buffers.apply_request(&mut buffer);
+                        continue;
+                    }
+                    let clicked = shown_areas
+                        .iter()
+                        .position(|area| (area.x..area.x + area.width).contains(&x) && (area.y..area.y + area.height).contains(&y));
+                    match clicked {
+                        Some(index) if index != global.windows.selected() => {
+                            global.windows.select(index, &buffer, &mut global.buffers);
+                            global.buffers.apply_request(&mut buffer);
+                        },
+                        // Clicking the scroll bar's thumb starts dragging it,
+                        // and clicking either side of it pages that way
+                        Some(index) => {
+                            let area = shown_areas[index];
+                            let scroll_bar_x = area.x + area.width - if global.scroll_bar { SCROLL_BAR_WIDTH } else { 0 };
+                            let minimap_x = scroll_bar_x.saturating_sub(MINIMAP_COLS as u32 * char_width);
+                            let height = buffer.view.rows as u32 * char_height;
+                            let y = y - area.y;
+                            if x >= scroll_bar_x && y < height {
+                                let (top, thumb_height) = scroll_bar::thumb(&buffer.content, &buffer.view, height);
+                                if (top..top + thumb_height).contains(&y) {
+                                    scroll_bar_drag = Some(y - top);
+                                } else {
+                                    scroll_bar::page_towards(&mut buffer.content, &mut buffer.view, height, y);
+                                }
+                            } else if x >= minimap_x && buffer.has_minor_mode("minimap") {
+                                minimap_mode::jump(&mut buffer.content, &mut buffer.view, char_height, y);
+                            } else if y < height {
+                                // One click moves the cursor there, two select
+                                // the word and three the line
+                                let at = position_at_pixel(&buffer, char_width, char_height, x - area.x, y);
+                                let (start, end) = match clicks {
+                                    1 => (at, at),
+                                    2 => motion::word_at(&buffer.content, at),
+                                    _ => {
+                                        let start = Position::new(at.row, 0);
+                                        let end = if at.row + 1 < buffer.content.line_count() {
+                                            Position::new(at.row + 1, 0)
+                                        } else {
+                                            Position::new(at.row, buffer.content.line_len(at.row))
+                                        };
+                                        (start, end)
+                                    },
+                                };
+                                // Ctrl-clicking a link follows it
+                                let ctrl = context.sdl.keyboard().mod_state().intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+                                if clicks == 1 && ctrl && buffer.has_minor_mode("goto-address") {
+                                    buffer.content.deactivate_mark();
+                                    buffer.content.cursor = at;
+                                    buffer.content.clamp_to_narrowing();
+                                    commands::execute(&mut global, &mut buffer, Repeatable::Command(goto_address_mode::follow_link), CommandContext::default());
+                                    continue;
+                                }
+                                buffer.content.mark = if clicks == 1 { None } else { Some(start) };
+                                buffer.content.cursor = end;
+                                buffer.content.clamp_to_narrowing();
+                                mouse_selection = Some(start);
+                            }
+                        },
+                        None => {},
+                    }
+                },
+                // Right clicking the selected window's text pops up the context
+                // menu there, keeping the region when the click is inside it
+                Event::MouseButtonDown { mouse_btn: MouseButton::Right, x, y, .. } => {
+                    let (char_width, char_height) = scaled_fonts.as_ref().unwrap_or(context.fonts).cell_size()?;
+                    let (x, y) = window_to_pixels(context, x, y)?;
+                    global.popups.close_all();
+                    let area = match shown_areas.get(global.windows.selected()) {
+                        Some(&area) if (area.x..area.x + area.width).contains(&x) && (area.y..area.y + area.height).contains(&y) => area,
+                        _ => continue,
+                    };
+                    let y = y - area.y;
+                    if y >= buffer.view.rows as u32 * char_height {
+                        continue;
+                    }
+                    let at = position_at_pixel(&buffer, char_width, char_height, x - area.x, y);
+                    let in_region = buffer.content.region().is_some_and(|(start, end)| (start..=end).contains(&at));
+                    if !in_region {
+                        buffer.content.deactivate_mark();
+                        buffer.content.cursor = at;
+                        buffer.content.clamp_to_narrowing();
+                    }
+                    context_menu::open(&mut global, &buffer, at);
+                },
+                Event::MouseMotion { y, .. } if scroll_bar_drag.is_some() => {
+                    let (_, char_height) = scaled_fonts.as_ref().unwrap_or(context.fonts).cell_size()?;
+                    let (_, y) = window_to_pixels(context, 0, y)?;
+                    if let (Some(grab), Some(area)) = (scroll_bar_drag, shown_areas.get(global.windows.selected())) {
+                        let height = buffer.view.rows as u32 * char_height;
+                        scroll_bar::drag_to(&mut buffer.content, &mut buffer.view, height, y as i64 - area.y as i64 - grab as i64);
+                    }
+                },
+                // Dragging over the text selects from where the button went
+                // down, scrolling when past the top or bottom of the window
+                Event::MouseMotion { x, y, .. } if mouse_selection.is_some() => {
+                    let (char_width, char_height) = scaled_fonts.as_ref().unwrap_or(context.fonts).cell_size()?;
+                    let (x, y) = window_to_pixels(context, x, y)?;
+                    if let (Some(anchor), Some(area)) = (mouse_selection, shown_areas.get(global.windows.selected())) {
+                        let y = y as i64 - area.y as i64;
+                        let height = (buffer.view.rows as u32 * char_height) as i64;
+                        if y < 0 {
+                            buffer.view.scroll_by(&mut buffer.content, -1);
+                        } else if y >= height {
+                            buffer.view.scroll_by(&mut buffer.content, 1);
+                        }
+                        let at = position_at_pixel(&buffer, char_width, char_height, x.saturating_sub(area.x), y.clamp(0, (height - 1).max(0)) as u32);
+                        buffer.content.mark = Some(anchor);
+                        buffer.content.cursor = at;
+                        buffer.content.clamp_to_narrowing();
+                    }
+                },
+                Event::MouseButtonUp { mouse_btn: MouseButton::Left, .. } => {
+                    scroll_bar_drag = None;
+                    mouse_selection = None;
+                },
+                Event::TextEditing { text, start, .. } => global.preedit.update(text, start),
+                Event::TextInput { text, .. } => {
+                    global.preedit.clear();
+                    if swallow_text_input {
+                        swallow_text_input = false;
+                    } else if quoted_insert {
+                        quoted_insert = false;
+                        let context = CommandContext::new(prefix_arg.take());
+                        commands::execute(&mut global, &mut buffer, Repeatable::QuotedInsert(text), context);
+                    } else if let Some(minibuffer) = &mut global.minibuffer {
+                        minibuffer.input.content.self_insert(&text);
+                    } else if vim_mode::takes_text(&buffer) {
+                        prefix_arg = None;
+                        prefix_digits = None;
+                        vim_mode::text_input(&mut global, &mut buffer, &text);
+                    } else {
+                        let context = CommandContext::new(prefix_arg.take());
+                        prefix_digits = None;
+                        commands::execute(&mut global, &mut buffer, Repeatable::SelfInsert(text), context);
+                    }
+                },
+                // Keys go to the input method while it's composing
+                Event::KeyDown { keycode: Some(keycode), keymod, .. } if !is_modifier_key(keycode) && global.preedit.is_empty() => {
+                    let plain = matches!(modifier_prefix(keymod), "" | "S-");
+
+                    // Printable keys wait for their text input instead
+                    if quoted_insert {
+                        if let Some(c) = control_char(modifier_prefix(keymod), keycode) {
+                            quoted_insert = false;
+                            let context = CommandContext::new(prefix_arg.take());
+                            commands::execute(&mut global, &mut buffer, Repeatable::QuotedInsert(c.to_string()), context);
+                        } else if !plain {
+                            quoted_insert = false;
+                        }
+                        continue;
+                    }
+
+                    // Digits straight after C-u or M- and a digit make up the
+                    // argument, which a minus can start
+                    if let (Some(digits), true) = (&mut prefix_digits, plain) {
+                        let typed = digit_value(keycode).or_else(|| (keycode == Keycode::Minus && digits.is_empty()).then_some('-'));
+                        if let Some(c) = typed {
+                            digits.push(c);
+                            prefix_arg = Some(prefix_from_digits(digits));
+                            swallow_text_input = true;
+                            continue;
+                        }
+                    }
+
+                    // Escape or C-g part way through a sequence gives up on it
+                    if !pending_keys.is_empty() && matches!((modifier_prefix(keymod), keycode), ("", Keycode::Escape) | ("C-", Keycode::G)) {
+                        pending_keys.clear();
+                        prefix_arg = None;
+                        prefix_digits = None;
+                        global.message("Quit".to_string());
+                        continue;
+                    }
+
+                    pending_keys.push((modifier_prefix(keymod), keycode));
+
+                    // A plain key continuing a sequence must not also be typed
+                    swallow_text_input = plain && pending_keys.len() > 1;
+
+                    let popup_binding = if global.popups.has_menu() {
+                        global.keymaps.popup.lookup(&pending_keys)
+                    } else {
+                        Binding::Unbound
+                    };
+                    if matches!(popup_binding, Binding::Unbound) {
+                        global.popups.close_all();
+                    }
+                    let binding = match popup_binding {
+                        Binding::Unbound => global.keymaps.lookup(&buffer, global.minibuffer.is_some(), &pending_keys),
+                        binding => binding,
+                    };
+                    match binding {
+                        Binding::Prefix => prefix_since = Some(Instant::now()),
+                        Binding::UniversalArgument => {
+                            pending_keys.clear();
+                            prefix_arg = Some(match prefix_arg {
+                                Some(PrefixArg::Universal(presses)) => PrefixArg::Universal(presses + 1),
+                                _ => PrefixArg::Universal(1),
+                            });
+                            prefix_digits = Some(String::new());
+                        },
+                        // M- and a digit, or M-- to start a negative one
+                        Binding::DigitArgument => {
+                            pending_keys.clear();
+                            let digits = prefix_digits.get_or_insert_with(String::new);
+                            match digit_value(keycode) {
+                                Some(digit) => digits.push(digit),
+                                None if digits.is_empty() => digits.push('-'),
+                                None => {},
+                            }
+                            prefix_arg = Some(prefix_from_digits(digits));
+                        },
+                        Binding::QuotedInsert => {
+                            pending_keys.clear();
+                            quoted_insert = true;
+                            prefix_digits = None;
+                        },
+                        Binding::Repeat => {
+                            pending_keys.clear();
+                            if let Some((repeatable, context)) = global.last_command.clone() {
+                                let context = prefix_arg.take().map_or(context, |arg| CommandContext::new(Some(arg)));
+                                commands::execute(&mut global, &mut buffer, repeatable, context);
+                            }
+                            prefix_digits = None;
+                        },
+                        Binding::Command(name) => {
+                            pending_keys.clear();
+                            // Nor must a printable key bound on its own, as in dired
+                            swallow_text_input |= plain && (' ' as i32..='~' as i32).contains(&(keycode as i32));
+                            let context = CommandContext::new(prefix_arg.take());
+                            prefix_digits = None;
+                            let command = match command_registry::command(name) {
+                                Ok(command) => command,
+                                Err(e) => {
+                                    global.message(e);
+                                    continue;
+                                },
+                            };
+                            match global.minibuffer.take() {
+                                // Editing commands work on the prompt's input
+                                Some(mut minibuffer) => {
+                                    for _ in 0..context.repeat_count() {
+                                        command(&mut global, &mut minibuffer.input);
+                                    }
+                                    global.minibuffer.get_or_insert(minibuffer);
+                                },
+                                None => commands::execute(&mut global, &mut buffer, Repeatable::Command(command), context),
+                            }
+                        },
+                        Binding::MinibufferCommand(name) => {
+                            pending_keys.clear();
+                            prefix_arg = None;
+                            prefix_digits = None;
+                            match command_registry::command(name) {
+                                Ok(command) => command(&mut global, &mut buffer),
+                                Err(e) => global.message(e),
+                            }
+                            global.buffers.apply_request(&mut buffer);
+                        },
+                        Binding::PopupCommand(name) => {
+                            pending_keys.clear();
+                            prefix_arg = None;
+                            prefix_digits = None;
+                            match command_registry::command(name) {
+                                Ok(command) => commands::execute(&mut global, &mut buffer, Repeatable::Command(command), CommandContext::default()),
+                                Err(e) => global.message(e),
+                            }
+                        },
+                        // Plain keys go on to be typed with the argument
+                        Binding::Unbound if plain && pending_keys.len() == 1 => pending_keys.clear(),
+                        Binding::Unbound => {
+                            global.message(format!("{} is undefined", describe_keys(&pending_keys)));
+                            pending_keys.clear();
+                            prefix_arg = None;
+                            prefix_digits = None;
+                        },
+                    }
+                },
+                _ => {}
+            }
+            if global.quit {
+                break 'mainloop;
+            }
+        }
 
-        let mut ts_id_to_face_id = HashMap::<usize, usize>::new();
+        if prefix_since.is_some_and(|since| since.elapsed() >= WHICH_KEY_DELAY) {
+            prefix_since = None;
+            if !pending_keys.is_empty() {
+                let next = global.keymaps.continuations(&buffer, global.minibuffer.is_some(), &pending_keys);
+                global.popups.open(Popup::info(buffer.content.cursor, which_key_lines(&next)));
+            }
+        }
 
-        for (id, name) in highlight_query.capture_names().iter().enumerate() {
-            let maybe_face_id = match name.as_str() {
-                "keyword"         => global.faces.get_face_id(&"keyword".to_string()),
-                "function"        => global.faces.get_face_id(&"function".to_string()),
-                "function.method" => global.faces.get_face_id(&"function".to_string()),
-                "function.macro"  => global.faces.get_face_id(&"function".to_string()),
-                "comment"         => global.faces.get_face_id(&"comment".to_string()),
-                _ => None,
-            };
-            // 0 is magic number for default font face
-            let face_id = maybe_face_id.unwrap_or(0);
-            ts_id_to_face_id.insert(id, face_id);
+        if last_auto_save.elapsed() >= auto_save::AUTO_SAVE_INTERVAL {
+            last_auto_save = Instant::now();
+            for auto_saving in std::iter::once(&mut buffer).chain(global.buffers.iter_mut()) {
+                if let Err(e) = auto_saving.auto_save() {
+                    global.message = Some(format!("Auto-saving failed: {}", e));
+                }
+            }
+        }
+
+        if last_disk_check.elapsed() >= DISK_CHECK_INTERVAL {
+            last_disk_check = Instant::now();
+            for other in global.buffers.iter_mut() {
+                other.check_disk();
+            }
+            if buffer.check_disk() && global.minibuffer.is_none() {
+                commands::offer_revert(&mut global);
+            }
+        }
+
+        buffer.refresh_faces(&mut global);
+        buffer.refresh_brackets();
 
-            println!("id: {}, name: {}", id, name)
+        // Character sizes change with the font, which redraws everything
+        let wanted_size = font::point_size(global.font.size, display_scale, global.text_scale);
+        if wanted_size != font_size || global.font != loaded_font.0 {
+            let current = scaled_fonts.as_ref().unwrap_or(context.fonts);
+            let reloaded = if global.font == loaded_font.0 {
+                current.at_size(context.ttf, wanted_size)
+            } else {
+                Fonts::load(context.ttf, &global.font, wanted_size)
+            };
+            match reloaded {
+                Ok(fonts) => {
+                    scaled_fonts = Some(fonts);
+                    font_size = wanted_size;
+                    loaded_font = (global.font.clone(), global.text_scale);
+                },
+                Err(e) => {
+                    global.message(format!("Loading fonts failed: {}", e));
+                    global.font = loaded_font.0.clone();
+                    global.text_scale = loaded_font.1;
+                },
+            }
+        }
+        let fonts = scaled_fonts.as_ref().unwrap_or(context.fonts);
+
+        let (char_width, char_height) = fonts.cell_size()?;
+        let (window_width, window_height) = context.canvas.output_size()?;
+        let window_rows = (window_height / char_height) as usize;
+        // The bottom rows are kept for the minibuffer and the top one for the
+        // tab bar, the windows share the rest
+        if let Some(minibuffer) = &mut global.minibuffer {
+            minibuffer.update_completions();
+        }
+        let minibuffer_top = window_rows.saturating_sub(minibuffer_rows(global.minibuffer.as_ref()));
+        let tab_bar_rows = (global.tab_bar.enabled as usize).min(minibuffer_top.saturating_sub(1));
+        let tab_bar_area = Area { x: 0, y: 0, width: window_width, height: tab_bar_rows as u32 * char_height };
+        let windows_area = Area { y: tab_bar_area.height, height: (minibuffer_top - tab_bar_rows) as u32 * char_height, ..tab_bar_area };
+        let minibuffer_area = Area { y: minibuffer_top as u32 * char_height, height: window_height - minibuffer_top as u32 * char_height, ..tab_bar_area };
+
+        // Windows and the buffers they show are set aside while drawing, so
+        // each can be borrowed alongside the rest of Global
+        let mut windows = std::mem::take(&mut global.windows);
+        let mut others = std::mem::take(&mut global.buffers);
+        windows.sync(&mut buffer, &others);
+        global.tab_bar.sync(&buffer.name, &others);
+        for window in windows.leaves() {
+            if let Some(shown) = others.find_mut(&window.buffer) {
+                shown.refresh_faces(&mut global);
+            }
         }
 
-        for m in cursor.matches(&highlight_query, tree.root_node(), text_callback) {
-            for capture in m.captures {
-                let ts_id = capture.index as usize;
-                let face_id = *ts_id_to_face_id.get(&ts_id).unwrap();
+        let areas = windows.layout(windows_area, (char_width, char_height));
+        let selected = windows.selected();
+        let ids: Vec<usize> = windows.leaves().iter().map(|window| window.id).collect();
+        window_frames.retain(|id, _| ids.contains(id));
+
+        let mut frame_context = RenderContext { sdl: context.sdl, canvas: &mut *context.canvas, ttf: context.ttf, fonts };
+        let mut scrolling = false;
+        for (i, (window, &area)) in windows.leaves_mut().into_iter().zip(&areas).enumerate() {
+            let frame = window_frames.entry(window.id).or_default();
+            let shown = match others.find_mut(&window.buffer) {
+                Some(shown) if i != selected => shown,
+                _ => &mut buffer,
+            };
+            // Other windows show their buffer with their own view and cursor
+            if i != selected {
+                window.swap(shown);
+            }
+            let drawn = draw_window(&mut frame_context, &global, shown, i == selected, area, frame, &texture_creator);
+            scrolling |= shown.view.is_scrolling();
+            if i != selected {
+                window.swap(shown);
+            }
+            drawn?;
+        }
+        global.windows = windows;
+        global.buffers = others;
+
+        let layout = (minibuffer_area, char_width, char_height, global.faces.generation(), global.cursor.style);
+        let fresh = damage.begin(layout, (minibuffer_area.height / char_height) as usize + 1);
+        if fresh {
+            minibuffer_frame = Some(texture_creator
+                .create_texture_target(None, window_width, minibuffer_area.height.max(1))
+                .map_err(|e| e.to_string())?);
+        }
+        let minibuffer_texture = minibuffer_frame.as_mut().ok_or("No minibuffer texture")?;
+
+        let (sdl, ttf) = (context.sdl, context.ttf);
+        let background = global.faces.get_face_by_id(0).and_then(|face| face.bg).unwrap_or(FaceColor::Rgb(0, 0, 0));
+        let mut drawn = Ok(());
+        context.canvas.with_texture_canvas(minibuffer_texture, |canvas| {
+            if fresh {
+                canvas.set_draw_color(background);
+                canvas.clear();
+            }
+            let mut target = RenderContext { sdl, canvas, ttf, fonts };
+            drawn = draw_minibuffer(&mut target, &global, global.minibuffer.as_ref(), 0, &mut damage);
+        }).map_err(|e| e.to_string())?;
+        drawn?;
+
+        if tab_bar_rows > 0 {
+            let layout = (tab_bar_area, char_width, char_height, global.faces.generation());
+            let fresh = tab_bar_damage.begin(layout, 1);
+            if fresh {
+                tab_bar_frame = Some(texture_creator
+                    .create_texture_target(None, window_width, char_height)
+                    .map_err(|e| e.to_string())?);
+            }
+            let tab_bar_texture = tab_bar_frame.as_mut().ok_or("No tab bar texture")?;
+            let mut drawn = Ok(());
+            context.canvas.with_texture_canvas(tab_bar_texture, |canvas| {
+                let mut target = RenderContext { sdl, canvas, ttf, fonts };
+                drawn = draw_tab_bar(&mut target, &global, &buffer.name, window_width, &mut tab_bar_damage);
+            }).map_err(|e| e.to_string())?;
+            drawn?;
+        }
 
-                let node = capture.node;
-                let start_pos = node.start_position();
-                let end_pos = node.end_position();
+        // The input method puts its candidates by the cursor
+        let input_rect = match &global.minibuffer {
+            Some(minibuffer) => {
+                let content = &minibuffer.input.content;
+                let cell = display_width(&minibuffer.prompt) + display_width(&content.line(0)[..content.cursor.col]);
+                let row = minibuffer_rows(Some(minibuffer)) - 1;
+                rect!(cell as u32 * char_width, minibuffer_area.y + row as u32 * char_height, char_width, char_height)
+            },
+            None => {
+                let area = areas[selected];
+                let (row, cell) = buffer.view.position_cell(&buffer.content, buffer.content.cursor);
+                let cell = buffer.view.gutter_width(&buffer.content) + cell;
+                rect!(area.x + cell as u32 * char_width, area.y + row as u32 * char_height, char_width, char_height)
+            },
+        };
+        if shown_input_rect != Some(input_rect) {
+            context.sdl.video()?.text_input().set_rect(pixels_to_window(context, input_rect)?);
+            shown_input_rect = Some(input_rect);
+        }
 
-                let row = start_pos.row;
+        let title = window_title(&buffer);
+        if context.canvas.window().title() != title {
+            context.canvas.window_mut().set_title(&title).map_err(|e| e.to_string())?;
+        }
 
-                for col in start_pos.column..end_pos.column {
-                    content.faces[row][col] = face_id;
+        let changed = window_frames.values().any(|frame| frame.damage.any() || frame.shift != frame.shown_shift);
+        let popups_changed = global.popups.generation() != shown_popups_generation;
+        if changed || damage.any() || (tab_bar_rows > 0 && tab_bar_damage.any()) || popups_changed || areas != shown_areas {
+            context.canvas.set_draw_color(background);
+            context.canvas.clear();
+            for (id, &area) in ids.iter().zip(&areas) {
+                if let Some(frame) = window_frames.get_mut(id) {
+                    copy_window(context, frame, area, char_height)?;
                 }
             }
+            // Side by side windows have a line between them
+            let border = global.faces.get_face_by_name("vertical-border").and_then(|face| face.fg).unwrap_or(FaceColor::Rgb(70, 70, 80));
+            context.canvas.set_draw_color(border);
+            for area in areas.iter().filter(|area| area.x > 0) {
+                context.canvas.fill_rect(rect!(area.x, area.y, 1, area.height))?;
+            }
+            // Popups go over the windows, and are drawn again every time
+            let mut target = RenderContext { sdl: context.sdl, canvas: &mut *context.canvas, ttf: context.ttf, fonts };
+            shown_popups = draw_popups(&mut target, &global, &buffer, areas[selected])?;
+            shown_popups_generation = global.popups.generation();
+            if let (true, Some(tab_bar_texture)) = (tab_bar_rows > 0, &tab_bar_frame) {
+                context.canvas.copy(tab_bar_texture, None, rect!(0, 0, tab_bar_area.width, tab_bar_area.height))?;
+            }
+            let minibuffer_texture = minibuffer_frame.as_ref().ok_or("No minibuffer texture")?;
+            context.canvas.copy(
+                minibuffer_texture,
+                rect!(0, 0, minibuffer_area.width, minibuffer_area.height),
+                rect!(minibuffer_area.x, minibuffer_area.y, minibuffer_area.width, minibuffer_area.height))?;
+            context.canvas.present();
+            shown_areas = areas;
+            last_frame = Instant::now();
+        } else if !scrolling && script.is_none() {
+            // Nothing changed, so sleep until something happens or a timer is due
+            let wait = if buffer.is_busy() { BUSY_WAIT_MS } else { IDLE_WAIT_MS };
+            waited_event = event_pump.wait_event_timeout(wait);
+        }
+
+        // Mid animation, wait for the next frame while still taking events
+        if scrolling && waited_event.is_none() {
+            let wait = frame_rate.until_next_frame(last_frame).as_millis() as u32;
+            if wait > 0 {
+                waited_event = event_pump.wait_event_timeout(wait);
+            }
         }
     }
+
+    // Where the bookmarks were moved to by editing their files
+    if let Err(e) = global.bookmarks.save() {
+        eprintln!("Saving bookmarks failed: {}", e);
+    }
+    Ok(())
+}
+
+pub struct TextMode {
+}
+
+impl MajorMode for TextMode  {
+    fn draw(&mut self, context: &mut RenderContext) -> Result<(), String> {
+        run(context)
+    }
+}
+
+// Loads a file into a new buffer with the modes for its language. A file
+// that doesn't exist yet gets an empty buffer, created on the first save,
+// and a directory a listing of it.
+pub fn visit_file(path: &Path, global: &Global) -> Result<Buffer, String> {
+    if path.is_dir() {
+        return dired::open(path);
+    }
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("{}: {}", path.display(), e)),
+    };
+    let name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+    let first_line = text.lines().next().unwrap_or("");
+
+    let (line_ending, mixed) = LineEnding::detect(&text);
+    let modes = auto_mode::modes_for_file(path, first_line);
+    let mut buffer = Buffer::new(&name, TextContent::new(&line_ending.decode(&text)), modes);
+    buffer.path = Some(path.to_path_buf());
+    buffer.set_file_line_ending(line_ending, mixed);
+    buffer.record_disk_state();
+    if global.undo_files {
+        undo_file::read(path, &mut buffer.content);
+    }
+    // Bookmarks in the file follow its edits
+    if global.bookmarks.in_file(path) {
+        buffer.minor_modes.push(Box::new(BookmarkMode::new(path)));
+    }
+    // Files we can't write to can still be read
+    buffer.read_only = path.exists() && std::fs::OpenOptions::new().write(true).open(path).is_err();
+    Ok(buffer)
 }
 
 /*
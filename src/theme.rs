@@ -0,0 +1,216 @@
+use std::fs;
+use std::path::Path;
+
+use crate::text_mode::Face;
+use crate::text_mode::FaceColor;
+use crate::text_mode::Faces;
+
+// Where load-theme starts looking
+pub const THEME_DIRECTORY: &str = "assets/themes/";
+
+fn face(fg: Option<FaceColor>, bg: Option<FaceColor>) -> Face {
+    Face { bg, fg, bold: false, underline: false }
+}
+
+// Faces the editor draws with itself. A theme can restyle them, and they go
+// back to these when the next theme leaves them out.
+fn base_faces() -> Vec<(String, Face)> {
+    vec!(
+        ("default".to_string(), face(Some(FaceColor::Rgb(255, 255, 255)), Some(FaceColor::Rgb(0, 0, 0)))),
+        ("cursor".to_string(), face(Some(FaceColor::Rgb(255, 255, 255)), None)),
+        ("region".to_string(), face(None, Some(FaceColor::Rgba(90, 90, 180, 170)))),
+        ("line-number".to_string(), face(Some(FaceColor::Rgb(100, 100, 100)), None)),
+        ("line-number-current-line".to_string(), face(Some(FaceColor::Rgb(200, 200, 200)), None)),
+        ("continuation".to_string(), face(Some(FaceColor::Rgb(120, 120, 120)), None)),
+        ("comint-prompt".to_string(), Face { bold: true, ..face(Some(FaceColor::Rgb(150, 200, 255)), None) }),
+        ("ansi-black".to_string(), face(Some(FaceColor::Rgb(100, 100, 110)), None)),
+        ("ansi-red".to_string(), face(Some(FaceColor::Rgb(240, 100, 100)), None)),
+        ("ansi-green".to_string(), face(Some(FaceColor::Rgb(140, 210, 120)), None)),
+        ("ansi-yellow".to_string(), face(Some(FaceColor::Rgb(230, 200, 110)), None)),
+        ("ansi-blue".to_string(), face(Some(FaceColor::Rgb(100, 160, 250)), None)),
+        ("ansi-magenta".to_string(), face(Some(FaceColor::Rgb(200, 130, 230)), None)),
+        ("ansi-cyan".to_string(), face(Some(FaceColor::Rgb(100, 210, 220)), None)),
+        ("ansi-white".to_string(), face(Some(FaceColor::Rgb(220, 220, 225)), None)),
+        ("git-blame".to_string(), face(Some(FaceColor::Rgb(110, 110, 120)), None)),
+        ("git-gutter-added".to_string(), face(Some(FaceColor::Rgb(120, 190, 110)), None)),
+        ("git-gutter-modified".to_string(), face(Some(FaceColor::Rgb(100, 150, 230)), None)),
+        ("git-gutter-deleted".to_string(), face(Some(FaceColor::Rgb(220, 100, 100)), None)),
+        ("git-status-heading".to_string(), Face { bold: true, ..face(Some(FaceColor::Rgb(150, 200, 255)), None) }),
+        ("git-status-file".to_string(), face(Some(FaceColor::Rgb(230, 230, 230)), None)),
+        ("diff-file-header".to_string(), Face { bold: true, ..face(Some(FaceColor::Rgb(230, 200, 120)), None) }),
+        ("diff-hunk-header".to_string(), face(Some(FaceColor::Rgb(200, 160, 240)), Some(FaceColor::Rgb(45, 40, 60)))),
+        ("diff-added".to_string(), face(Some(FaceColor::Rgb(140, 210, 130)), Some(FaceColor::Rgb(30, 50, 30)))),
+        ("diff-removed".to_string(), face(Some(FaceColor::Rgb(240, 130, 130)), Some(FaceColor::Rgb(60, 30, 30)))),
+        ("spell-error".to_string(), Face { underline: true, ..face(Some(FaceColor::Rgb(240, 120, 120)), None) }),
+        ("compilation-error".to_string(), Face { underline: true, ..face(Some(FaceColor::Rgb(240, 100, 100)), None) }),
+        ("compilation-warning".to_string(), Face { underline: true, ..face(Some(FaceColor::Rgb(230, 180, 90)), None) }),
+        ("compilation-info".to_string(), Face { underline: true, ..face(Some(FaceColor::Rgb(120, 200, 120)), None) }),
+        ("dired-header".to_string(), Face { bold: true, ..face(Some(FaceColor::Rgb(150, 200, 255)), None) }),
+        ("dired-directory".to_string(), face(Some(FaceColor::Rgb(100, 180, 255)), None)),
+        ("dired-symlink".to_string(), face(Some(FaceColor::Rgb(120, 220, 220)), None)),
+        ("error".to_string(), Face { underline: true, ..face(Some(FaceColor::Rgb(255, 90, 90)), None) }),
+        ("fold".to_string(), face(Some(FaceColor::Rgb(150, 150, 150)), Some(FaceColor::Rgb(45, 45, 55)))),
+        ("minibuffer-prompt".to_string(), face(Some(FaceColor::Rgb(0, 200, 255)), None)),
+        ("completions-common-part".to_string(), face(Some(FaceColor::Rgb(255, 170, 60)), None)),
+        ("completions-highlight".to_string(), face(None, Some(FaceColor::Rgb(45, 60, 90)))),
+        ("hl-line".to_string(), face(None, Some(FaceColor::Rgba(255, 255, 255, 24)))),
+        ("hl-todo-todo".to_string(), Face { bold: true, ..face(Some(FaceColor::Rgb(240, 180, 80)), None) }),
+        ("hl-todo-fixme".to_string(), Face { bold: true, ..face(Some(FaceColor::Rgb(250, 100, 100)), None) }),
+        ("hl-todo-hack".to_string(), Face { bold: true, ..face(Some(FaceColor::Rgb(210, 130, 230)), None) }),
+        ("hl-todo-note".to_string(), Face { bold: true, ..face(Some(FaceColor::Rgb(120, 200, 140)), None) }),
+        ("query-replace".to_string(), face(Some(FaceColor::Rgb(20, 20, 20)), Some(FaceColor::Rgb(240, 170, 80)))),
+        ("link".to_string(), Face { underline: true, ..face(Some(FaceColor::Rgb(100, 170, 255)), None) }),
+        ("fill-column-indicator".to_string(), face(Some(FaceColor::Rgb(70, 70, 70)), None)),
+        ("indent-guide".to_string(), face(Some(FaceColor::Rgb(55, 55, 55)), None)),
+        ("show-paren-match".to_string(), face(None, Some(FaceColor::Rgba(70, 130, 180, 170)))),
+        ("show-paren-mismatch".to_string(), face(Some(FaceColor::Rgb(255, 255, 255)), Some(FaceColor::Rgb(160, 32, 240)))),
+        ("mode-line".to_string(), face(Some(FaceColor::Rgb(220, 220, 220)), Some(FaceColor::Rgb(50, 50, 60)))),
+        ("mode-line-inactive".to_string(), face(Some(FaceColor::Rgb(150, 150, 150)), Some(FaceColor::Rgb(30, 30, 36)))),
+        ("vertical-border".to_string(), face(Some(FaceColor::Rgb(70, 70, 80)), None)),
+        ("mode-line-buffer-id".to_string(), face(Some(FaceColor::Rgb(255, 200, 100)), None)),
+        ("mode-line-mode".to_string(), face(Some(FaceColor::Rgb(150, 200, 255)), None)),
+        ("mode-line-emphasis".to_string(), face(Some(FaceColor::Rgb(255, 120, 120)), None)),
+        ("tab-bar".to_string(), face(Some(FaceColor::Rgb(200, 200, 200)), Some(FaceColor::Rgb(30, 30, 36)))),
+        ("tab-bar-tab".to_string(), face(Some(FaceColor::Rgb(255, 255, 255)), Some(FaceColor::Rgb(60, 60, 75)))),
+        ("tab-bar-tab-inactive".to_string(), face(Some(FaceColor::Rgb(150, 150, 150)), Some(FaceColor::Rgb(40, 40, 48)))),
+        ("popup".to_string(), face(Some(FaceColor::Rgb(220, 220, 220)), Some(FaceColor::Rgb(40, 40, 50)))),
+        ("popup-selected".to_string(), face(Some(FaceColor::Rgb(255, 255, 255)), Some(FaceColor::Rgb(60, 90, 140)))),
+        ("popup-border".to_string(), face(Some(FaceColor::Rgb(90, 90, 110)), None)),
+        ("scroll-bar".to_string(), face(Some(FaceColor::Rgb(100, 100, 110)), Some(FaceColor::Rgb(25, 25, 30)))),
+        ("preedit".to_string(), face(Some(FaceColor::Rgb(230, 230, 230)), Some(FaceColor::Rgb(50, 50, 60)))),
+        ("minimap-viewport".to_string(), face(None, Some(FaceColor::Rgba(255, 255, 255, 40)))),
+        ("outline-1".to_string(), Face { bold: true, ..face(Some(FaceColor::Rgb(100, 180, 255)), None) }),
+        ("outline-2".to_string(), Face { bold: true, ..face(Some(FaceColor::Rgb(240, 180, 80)), None) }),
+        ("outline-3".to_string(), Face { bold: true, ..face(Some(FaceColor::Rgb(180, 140, 240)), None) }),
+        ("outline-4".to_string(), Face { bold: true, ..face(Some(FaceColor::Rgb(120, 210, 160)), None) }),
+        ("outline-todo".to_string(), Face { bold: true, ..face(Some(FaceColor::Rgb(255, 100, 100)), None) }),
+        ("outline-done".to_string(), Face { bold: true, ..face(Some(FaceColor::Rgb(120, 220, 120)), None) }),
+        ("rainbow-delimiters-depth-1".to_string(), face(Some(FaceColor::Rgb(255, 215, 0)), None)),
+        ("rainbow-delimiters-depth-2".to_string(), face(Some(FaceColor::Rgb(218, 112, 214)), None)),
+        ("rainbow-delimiters-depth-3".to_string(), face(Some(FaceColor::Rgb(23, 159, 255)), None)),
+        ("rainbow-delimiters-depth-4".to_string(), face(Some(FaceColor::Rgb(120, 220, 120)), None)),
+        ("rainbow-delimiters-depth-5".to_string(), face(Some(FaceColor::Rgb(255, 140, 90)), None)),
+        ("rainbow-delimiters-depth-6".to_string(), face(Some(FaceColor::Rgb(150, 200, 255)), None)),
+    )
+}
+
+// Syntax faces used until a theme file is loaded. They're named after the
+// tree-sitter captures they show, see Faces::get_capture_face_id.
+fn default_theme() -> Vec<(String, Face)> {
+    vec!(
+        ("keyword".to_string(), face(Some(FaceColor::Rgb(255, 0, 0)), None)),
+        ("function".to_string(), face(Some(FaceColor::Rgb(0, 255, 0)), None)),
+        ("comment".to_string(), face(Some(FaceColor::Rgb(150, 150, 150)), None)),
+        ("string".to_string(), face(Some(FaceColor::Rgb(230, 200, 110)), None)),
+        ("type".to_string(), face(Some(FaceColor::Rgb(100, 200, 230)), None)),
+        ("constant".to_string(), face(Some(FaceColor::Rgb(220, 130, 230)), None)),
+        ("number".to_string(), face(Some(FaceColor::Rgb(180, 220, 120)), None)),
+        ("operator".to_string(), face(Some(FaceColor::Rgb(200, 200, 200)), None)),
+        ("punctuation".to_string(), face(Some(FaceColor::Rgb(170, 170, 170)), None)),
+        ("variable".to_string(), face(Some(FaceColor::Rgb(235, 235, 235)), None)),
+        ("property".to_string(), face(Some(FaceColor::Rgb(160, 190, 255)), None)),
+        ("text.title".to_string(), Face { bold: true, ..face(Some(FaceColor::Rgb(255, 120, 80)), None) }),
+        ("text.title.1".to_string(), Face { bold: true, underline: true, ..face(Some(FaceColor::Rgb(255, 150, 100)), None) }),
+        ("text.strong".to_string(), Face { bold: true, ..face(None, None) }),
+        ("text.literal".to_string(), face(Some(FaceColor::Rgb(230, 200, 110)), Some(FaceColor::Rgb(40, 40, 48)))),
+        ("text.reference".to_string(), Face { underline: true, ..face(Some(FaceColor::Rgb(160, 190, 255)), None) }),
+        ("text.uri".to_string(), Face { underline: true, ..face(Some(FaceColor::Rgb(100, 180, 255)), None) }),
+    )
+}
+
+// The theme file named by EDIT0R_THEME, or the built-in theme without one
+pub fn from_env() -> Vec<(String, Face)> {
+    let path = match std::env::var("EDIT0R_THEME") {
+        Ok(path) if !path.is_empty() => path,
+        _ => return default_theme(),
+    };
+    read(Path::new(&path)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        default_theme()
+    })
+}
+
+pub fn read(path: &Path) -> Result<Vec<(String, Face)>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Parses a theme file, which has a section for each face setting its fg and
+// bg to "#rrggbb" colors, or "#rrggbbaa" to blend with the face underneath,
+// and bold and underline to true or false, in the style of TOML:
+//
+//     # Comments start with a hash
+//     [comment]
+//     fg = "#969696"
+//     bold = true
+pub fn parse(text: &str) -> Result<Vec<(String, Face)>, String> {
+    let mut theme: Vec<(String, Face)> = vec!();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: &str| format!("line {}: {}", i + 1, message);
+
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            theme.push((name.trim().to_string(), face(None, None)));
+            continue;
+        }
+
+        let (key, value) = line.split_once('=').ok_or_else(|| error("Expected a [face] or key = value"))?;
+        let (_, face) = theme.last_mut().ok_or_else(|| error("Setting outside a [face]"))?;
+        match (key.trim(), value.trim()) {
+            ("bold", value) => {
+                face.bold = parse_bool(value).ok_or_else(|| error("Expected true or false"))?;
+                continue;
+            },
+            ("underline", value) => {
+                face.underline = parse_bool(value).ok_or_else(|| error("Expected true or false"))?;
+                continue;
+            },
+            _ => {},
+        }
+        let color = parse_color(value.trim()).ok_or_else(|| error("Expected a color like \"#rrggbb\" or \"#rrggbbaa\""))?;
+        match key.trim() {
+            "fg" => face.fg = Some(color),
+            "bg" => face.bg = Some(color),
+            key => return Err(error(&format!("Unknown setting {}", key))),
+        }
+    }
+    Ok(theme)
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_color(value: &str) -> Option<FaceColor> {
+    let hex = value.strip_prefix('"')?.strip_suffix('"')?.strip_prefix('#')?;
+    if !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    match hex.len() {
+        6 => Some(FaceColor::Rgb(channel(0)?, channel(2)?, channel(4)?)),
+        8 => Some(FaceColor::Rgba(channel(0)?, channel(2)?, channel(4)?, channel(6)?)),
+        _ => None,
+    }
+}
+
+// Switches to `theme`, with the base faces it leaves out going back to how
+// they started
+pub fn apply(faces: &mut Faces, theme: Vec<(String, Face)>) {
+    let base = base_faces();
+    let (overrides, syntax): (Vec<_>, Vec<_>) = theme
+        .into_iter()
+        .partition(|(name, _)| base.iter().any(|(base_name, _)| base_name == name));
+
+    for (name, face) in base.into_iter().chain(overrides) {
+        faces.put_face(name, face);
+    }
+    faces.load_theme_faces(syntax);
+}
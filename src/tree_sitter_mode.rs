@@ -0,0 +1,1073 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use tree_sitter::InputEdit;
+use tree_sitter::Node;
+use tree_sitter::Parser;
+use tree_sitter::Point;
+use tree_sitter::Query;
+use tree_sitter::QueryCursor;
+use tree_sitter::Range;
+use tree_sitter::Tree;
+
+use crate::auto_mode;
+use crate::buffer_local::INDENT_WIDTH;
+use crate::buffer_local::SYNTAX_ERRORS;
+use crate::context_menu::MenuItem;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+use crate::undo;
+use crate::undo::Edit;
+
+// The language mode every tree-sitter grammar shares, and the structural
+// editing on top of it. Language modes hand over their syntax tree through
+// TextMinorMode::syntax_tree and the commands here work on any grammar.
+
+// Where the parser of a language comes from
+#[derive(Clone, Copy)]
+pub enum Grammar {
+    Builtin(fn() -> tree_sitter::Language),
+    // From a shared library loaded at startup, see grammars.rs
+    Loaded(tree_sitter::Language),
+}
+
+// What a language brings to its TreeSitterMode
+pub struct Language {
+    // The mode's name, which its keymap section goes by
+    pub name: &'static str,
+    pub grammar: Grammar,
+    // Joined into one query, for grammars built on another like TypeScript
+    pub highlight_queries: &'static [&'static str],
+    // Nodes captured as @indent indent their contents one level, and
+    // @indent.open indents everything after it. Without one, new lines copy
+    // the indentation of the line above.
+    pub indent_query: Option<&'static str>,
+    pub comment_start: Option<&'static str>,
+    pub context_menu: &'static [MenuItem],
+    // Finds text in other languages, like code in a Markdown fence, as
+    // @injection.content. The language comes from an @injection.language
+    // capture or an injection.language property, named like a mode or by
+    // its extension.
+    pub injection_query: Option<&'static str>,
+    // Nodes captured as @fold can be folded, besides bracketed lists and
+    // comments spanning more than one line
+    pub fold_query: Option<&'static str>,
+    // Joined into one query like the highlight queries. Definitions are
+    // captured as @definition.KIND with their name as @name, as in
+    // tree-sitter's tags queries.
+    pub tags_queries: &'static [&'static str],
+}
+
+impl Language {
+    pub fn grammar(&self) -> tree_sitter::Language {
+        match self.grammar {
+            Grammar::Builtin(language) => language(),
+            Grammar::Loaded(language) => language,
+        }
+    }
+}
+
+// Something defined in the content, like a function or a type
+pub struct Definition {
+    // With the names of the definitions it's inside, e.g. "Point.new"
+    pub name: String,
+    // From the capture, e.g. "function" or "class"
+    pub kind: String,
+    pub start: Position,
+}
+
+// Context menu items for working on the syntax tree
+pub const SYNTAX_ITEMS: &[MenuItem] = &[
+    MenuItem { label: "Select Node", command: "select-enclosing-node" },
+    MenuItem { label: "Describe Syntax", command: "describe-syntax-at-point" },
+];
+
+// A span of highlighted text and the index of its capture
+type Highlight = (Position, Position, usize);
+
+// Work for the parsing thread: the text as it was at change `change_count`,
+// the last tree edited to match it and the rows to highlight again
+struct Job {
+    text: String,
+    tree: Option<Tree>,
+    change_count: usize,
+    rows: Vec<(usize, usize)>,
+    visible: (Position, Position),
+}
+
+// What the parsing thread sends back for a job, with the rows the
+// highlights are for. The tree is None if it couldn't be parsed.
+struct Done {
+    tree: Option<Tree>,
+    change_count: usize,
+    rows: Vec<(usize, usize)>,
+    highlights: Vec<Highlight>,
+    visible: (Position, Position),
+}
+
+pub struct TreeSitterMode {
+    language: &'static Language,
+    // For parsing right away when something needs the tree up to date, like
+    // indenting. Highlighting is left to the parsing thread.
+    parser: Parser,
+    // From the last parse, which was at TextContent::change_count() parsed_at.
+    // Edits up to followed_at are fed to it so the next parse only redoes
+    // what changed.
+    tree: Option<Tree>,
+    parsed_at: usize,
+    followed_at: usize,
+    capture_names: Vec<String>,
+    indent_query: Option<Query>,
+    injection_query: Option<Query>,
+    fold_query: Option<Query>,
+    tags_query: Option<Query>,
+    // A parser and highlight query for each language injected, made the
+    // first time it's needed
+    injected: HashMap<&'static str, (Parser, Query)>,
+    // Kept from one modify to the next, following the edits. Until the
+    // parsing thread catches up they're shown as they were.
+    highlights: Vec<Highlight>,
+    injected_highlights: Vec<(Position, Position, usize)>,
+    // Where the tree has ERROR and MISSING nodes, kept like the highlights
+    errors: Vec<(Position, Position)>,
+    // The part of the content the highlights cover, None when they have to
+    // be worked out all over again
+    highlighted: Option<(Position, Position)>,
+    // Text changed or parsed differently since it was highlighted
+    dirty: Vec<(Position, Position)>,
+    jobs: Sender<Job>,
+    results: Receiver<Done>,
+    // Set to stop the parsing thread's current job once a newer one is sent
+    cancelled: Arc<AtomicUsize>,
+    // Set by the parsing thread when it has sent something back
+    ready: Arc<AtomicBool>,
+    // The change count and visible range of the last job sent, until it's done
+    sent: Option<(usize, (Position, Position))>,
+}
+
+impl TreeSitterMode {
+    pub fn new(language: &'static Language) -> TreeSitterMode {
+        let grammar = language.grammar();
+        let mut parser = Parser::new();
+        parser.set_language(grammar).expect("Error loading grammar");
+        let highlight_query = Query::new(grammar, &language.highlight_queries.concat()).unwrap();
+        let capture_names = highlight_query.capture_names().to_vec();
+        let indent_query = language.indent_query.map(|query| Query::new(grammar, query).unwrap());
+        let injection_query = language.injection_query.map(|query| Query::new(grammar, query).unwrap());
+        let fold_query = language.fold_query.map(|query| Query::new(grammar, query).unwrap());
+        let tags_query = (!language.tags_queries.is_empty())
+            .then(|| Query::new(grammar, &language.tags_queries.concat()).unwrap());
+
+        let (jobs, job_receiver) = mpsc::channel();
+        let (result_sender, results) = mpsc::channel();
+        let cancelled = Arc::new(AtomicUsize::new(0));
+        let ready = Arc::new(AtomicBool::new(false));
+        let (thread_cancelled, thread_ready) = (cancelled.clone(), ready.clone());
+        thread::Builder::new()
+            .name(format!("{}-parser", language.name))
+            .spawn(move || work(language, highlight_query, job_receiver, result_sender, thread_cancelled, thread_ready))
+            .expect("Error starting parsing thread");
+
+        TreeSitterMode {
+            language,
+            parser,
+            tree: None,
+            parsed_at: 0,
+            followed_at: 0,
+            capture_names,
+            indent_query,
+            injection_query,
+            fold_query,
+            tags_query,
+            injected: HashMap::new(),
+            highlights: vec!(),
+            injected_highlights: vec!(),
+            errors: vec!(),
+            highlighted: None,
+            dirty: vec!(),
+            jobs,
+            results,
+            cancelled,
+            ready,
+            sent: None,
+        }
+    }
+
+    fn is_parsed(&self, content: &TextContent) -> bool {
+        self.tree.is_some() && self.parsed_at == content.change_count()
+    }
+
+    // Feeds the edits made since the last time to the tree and highlights
+    fn catch_up(&mut self, content: &TextContent) {
+        if self.followed_at == content.change_count() {
+            return;
+        }
+        match content.changes_with_offsets_since(self.followed_at) {
+            Some(changes) => {
+                for (edit, offset) in changes {
+                    if let Some(tree) = &mut self.tree {
+                        tree.edit(&input_edit(edit, offset));
+                    }
+                    self.follow(edit);
+                }
+            },
+            None => {
+                self.tree = None;
+                self.highlighted = None;
+            },
+        }
+        self.followed_at = content.change_count();
+    }
+
+    // Brings the tree up to date on this thread, reusing the last one for
+    // the parts the edits since didn't touch
+    fn parse(&mut self, content: &TextContent) -> Option<Tree> {
+        self.catch_up(content);
+        if self.is_parsed(content) {
+            return self.tree.clone();
+        }
+
+        let old = self.tree.take();
+        self.tree = parse_with(&mut self.parser, content, old.as_ref());
+        self.parsed_at = content.change_count();
+        if let (Some(old), Some(tree)) = (&old, &self.tree) {
+            for range in old.changed_ranges(tree) {
+                self.dirty.push((position(content, range.start_point), position(content, range.end_point)));
+            }
+        }
+        self.tree.clone()
+    }
+
+    // Moves the highlights along with `edit`, marking the text it touched
+    fn follow(&mut self, edit: &Edit) {
+        for highlight in self.highlights.iter_mut().chain(self.injected_highlights.iter_mut()) {
+            highlight.0 = edit.shift(highlight.0, false);
+            highlight.1 = edit.shift(highlight.1, true);
+        }
+        for error in &mut self.errors {
+            *error = (edit.shift(error.0, false), edit.shift(error.1, true));
+        }
+        self.highlights.retain(|&(start, end, _)| start < end);
+        self.injected_highlights.retain(|&(start, end, _)| start < end);
+        self.errors.retain(|&(start, end)| start < end);
+        for dirty in &mut self.dirty {
+            *dirty = (edit.shift(dirty.0, true), edit.shift(dirty.1, false));
+        }
+        self.highlighted = self.highlighted.map(|(start, end)| (edit.shift(start, true), edit.shift(end, false)));
+        match edit {
+            Edit::Insert { at, text } => self.dirty.push((*at, undo::end_of(*at, text))),
+            Edit::Delete { at, .. } => self.dirty.push((*at, *at)),
+        }
+    }
+
+    // Takes what the parsing thread sent back, as long as nothing was edited
+    // since the job was sent
+    fn receive(&mut self, content: &TextContent) {
+        self.ready.store(false, Ordering::SeqCst);
+        while let Ok(done) = self.results.try_recv() {
+            if self.sent.is_some_and(|(change_count, _)| change_count == done.change_count) {
+                self.sent = None;
+            }
+            if done.change_count != content.change_count() || self.highlighted != Some(done.visible) {
+                continue;
+            }
+            let tree = match done.tree {
+                Some(tree) => tree,
+                None => continue,
+            };
+            self.tree = Some(tree);
+            self.parsed_at = done.change_count;
+            for &(first, last) in &done.rows {
+                self.highlights.retain(|&(start, end, _)| end.row < first || start.row > last);
+            }
+            self.highlights.extend(done.highlights);
+            // In the order the query gives them, for captures of the same node
+            self.highlights.sort_by_key(|&(start, _, _)| start);
+            self.dirty.clear();
+        }
+    }
+
+    // Sends the parsing thread the content to parse and highlight, giving up
+    // on the job before
+    fn request(&mut self, content: &TextContent, visible: (Position, Position)) {
+        let change_count = content.change_count();
+        if self.sent == Some((change_count, visible)) {
+            return;
+        }
+        self.cancelled.store(1, Ordering::SeqCst);
+        let job = Job {
+            text: content.text(),
+            tree: self.tree.clone(),
+            change_count,
+            rows: self.dirty_rows(content),
+            visible,
+        };
+        if self.jobs.send(job).is_ok() {
+            self.sent = Some((change_count, visible));
+        }
+    }
+
+    // The ranges of `visible` in other languages, by language
+    fn injections(&self, content: &TextContent, tree: &Tree, visible: (Position, Position)) -> Vec<(&'static Language, Vec<Range>)> {
+        let query = match &self.injection_query {
+            Some(query) => query,
+            None => return vec!(),
+        };
+        let text_callback = |node: Node| content.slice(node.byte_range()).into_owned();
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(content.offset(visible.0), content.offset(visible.1));
+
+        let mut injections: Vec<(&'static Language, Vec<Range>)> = vec!();
+        for m in cursor.matches(query, tree.root_node(), text_callback) {
+            let mut name = query.property_settings(m.pattern_index)
+                .iter()
+                .find(|property| &*property.key == "injection.language")
+                .and_then(|property| property.value.as_deref().map(String::from));
+            let mut ranges = vec!();
+            for capture in m.captures {
+                match query.capture_names()[capture.index as usize].as_str() {
+                    "injection.language" => name = Some(content.slice(capture.node.byte_range()).into_owned()),
+                    "injection.content" => ranges.push(capture.node.range()),
+                    _ => {},
+                }
+            }
+            // Fences can say more after the language, e.g. "rust,ignore"
+            let language = name.as_deref()
+                .and_then(|name| name.split(|c: char| c == ',' || c.is_whitespace()).next())
+                .and_then(auto_mode::language_named);
+            let language = match language {
+                Some(language) => language,
+                None => continue,
+            };
+            match injections.iter_mut().find(|(other, _)| other.name == language.name) {
+                Some((_, existing)) => existing.extend(ranges),
+                None => injections.push((language, ranges)),
+            }
+        }
+        injections
+    }
+
+    // Highlights for the text in other languages, each parsed on its own
+    // from just the ranges in that language
+    fn highlight_injections(&mut self, global: &Global, content: &TextContent, tree: &Tree, visible: (Position, Position)) -> Vec<(Position, Position, usize)> {
+        let mut highlights = vec!();
+        for (language, mut ranges) in self.injections(content, tree, visible) {
+            // Included ranges have to be in order without overlapping
+            ranges.sort_by_key(|range| range.start_byte);
+            ranges.dedup_by(|range, previous| range.start_byte < previous.end_byte);
+
+            let (parser, query) = self.injected.entry(language.name).or_insert_with(|| {
+                let grammar = language.grammar();
+                let mut parser = Parser::new();
+                parser.set_language(grammar).expect("Error loading grammar");
+                (parser, Query::new(grammar, &language.highlight_queries.concat()).unwrap())
+            });
+            if parser.set_included_ranges(&ranges).is_err() {
+                continue;
+            }
+            let injected_tree = match parse_with(parser, content, None) {
+                Some(tree) => tree,
+                None => continue,
+            };
+
+            let text_callback = |node: Node| content.slice(node.byte_range()).into_owned();
+            for range in &ranges {
+                let mut cursor = QueryCursor::new();
+                cursor.set_byte_range(range.start_byte, range.end_byte);
+                for m in cursor.matches(query, injected_tree.root_node(), text_callback) {
+                    for capture in m.captures {
+                        let name = &query.capture_names()[capture.index as usize];
+                        let face_id = global.faces.get_capture_face_id(name).unwrap_or(0);
+                        let bytes = capture.node.byte_range();
+                        highlights.push((content.position_at(bytes.start), content.position_at(bytes.end), face_id));
+                    }
+                }
+            }
+        }
+        highlights
+    }
+
+    // The rows to highlight again, as merged first and last rows
+    fn dirty_rows(&self, content: &TextContent) -> Vec<(usize, usize)> {
+        let last_row = content.line_count() - 1;
+        merge_rows(self.dirty.iter().map(|(start, end)| (start.row.min(last_row), end.row.min(last_row))).collect())
+    }
+}
+
+// Sorts first and last rows, joining the ones that overlap or touch
+fn merge_rows(mut rows: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    rows.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = vec!();
+    for (first, last) in rows {
+        match merged.last_mut() {
+            Some(previous) if first <= previous.1 + 1 => previous.1 = previous.1.max(last),
+            _ => merged.push((first, last)),
+        }
+    }
+    merged
+}
+
+// The parsing thread, which works through the jobs sent until the mode is
+// dropped. Only the latest job is worked on, and it's dropped halfway when
+// another one comes in.
+fn work(language: &'static Language, query: Query, jobs: Receiver<Job>, results: Sender<Done>, cancelled: Arc<AtomicUsize>, ready: Arc<AtomicBool>) {
+    let mut parser = Parser::new();
+    parser.set_language(language.grammar()).expect("Error loading grammar");
+    // The flag outlives the parser, being dropped after it
+    unsafe { parser.set_cancellation_flag(Some(&cancelled)) };
+
+    'jobs: while let Ok(mut job) = jobs.recv() {
+        while let Ok(newer) = jobs.try_recv() {
+            job = newer;
+        }
+        cancelled.store(0, Ordering::SeqCst);
+        // Otherwise a cancelled parse would carry on where it stopped
+        parser.reset();
+
+        let text = &job.text;
+        let tree = parser.parse(text, job.tree.as_ref());
+        if tree.is_none() && cancelled.load(Ordering::SeqCst) != 0 {
+            continue;
+        }
+
+        let mut rows = job.rows;
+        let mut highlights = vec!();
+        if let Some(tree) = &tree {
+            if let Some(old) = &job.tree {
+                rows.extend(old.changed_ranges(tree).map(|range| (range.start_point.row, range.end_point.row)));
+            }
+            let (visible_start, visible_end) = job.visible;
+            let rows_in_view = rows.iter()
+                .map(|&(first, last)| (first.max(visible_start.row), last.min(visible_end.row)))
+                .filter(|(first, last)| first <= last)
+                .collect();
+            rows = merge_rows(rows_in_view);
+
+            let line_starts: Vec<usize> = std::iter::once(0)
+                .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+                .collect();
+            let offset = |pos: Position| (line_starts[pos.row] + pos.col).min(text.len());
+            let line_len = |row: usize| match line_starts.get(row + 1) {
+                Some(next) => next - 1 - line_starts[row],
+                None => text.len() - line_starts[row],
+            };
+            for &(first, last) in &rows {
+                if cancelled.load(Ordering::SeqCst) != 0 {
+                    continue 'jobs;
+                }
+                let start = Position::new(first, 0).max(visible_start);
+                let end = Position::new(last, line_len(last)).min(visible_end);
+                highlights.extend(captures(&query, tree, text, offset(start), offset(end)));
+            }
+        }
+
+        let done = Done { tree, change_count: job.change_count, rows, highlights, visible: job.visible };
+        if results.send(done).is_err() {
+            break;
+        }
+        ready.store(true, Ordering::SeqCst);
+    }
+}
+
+// What `query` captures in `text` between bytes `start` and `end`, by
+// capture index. Captures like block comments run over several rows.
+fn captures(query: &Query, tree: &Tree, text: &str, start: usize, end: usize) -> Vec<(Position, Position, usize)> {
+    let mut cursor = QueryCursor::new();
+    cursor.set_byte_range(start, end);
+    let mut highlights = vec!();
+    for m in cursor.matches(query, tree.root_node(), |node: Node| &text[node.byte_range()]) {
+        for capture in m.captures {
+            let (start, end) = (capture.node.start_position(), capture.node.end_position());
+            highlights.push((Position::new(start.row, start.column), Position::new(end.row, end.column), capture.index as usize));
+        }
+    }
+    highlights
+}
+
+// The edit as tree-sitter takes it, made at byte `offset`
+fn input_edit(edit: &Edit, offset: usize) -> InputEdit {
+    let (at, text) = match edit {
+        Edit::Insert { at, text } | Edit::Delete { at, text } => (*at, text),
+    };
+    let end = point(undo::end_of(at, text));
+    let (old_end_byte, new_end_byte, old_end_position, new_end_position) = match edit {
+        Edit::Insert { .. } => (offset, offset + text.len(), point(at), end),
+        Edit::Delete { .. } => (offset + text.len(), offset, end, point(at)),
+    };
+    InputEdit {
+        start_byte: offset,
+        old_end_byte,
+        new_end_byte,
+        start_position: point(at),
+        old_end_position,
+        new_end_position,
+    }
+}
+
+impl TextMinorMode for TreeSitterMode {
+    fn name(&self) -> &'static str {
+        self.language.name
+    }
+
+    fn comment_syntax(&self) -> Option<&'static str> {
+        self.language.comment_start
+    }
+
+    fn is_major(&self) -> bool {
+        true
+    }
+
+    fn context_menu(&self) -> &'static [MenuItem] {
+        self.language.context_menu
+    }
+
+    fn syntax_tree(&mut self, content: &TextContent) -> Option<Tree> {
+        self.parse(content)
+    }
+
+    // The parsing thread highlights again only the rows that changed since
+    // the last time. Until it's done the old highlights are shown, moved
+    // along with the edits.
+    fn modify(&mut self, global: &mut Global, content: &mut TextContent) {
+        self.catch_up(content);
+        self.receive(content);
+
+        // Nothing outside the narrowed part is shown
+        let visible = content.visible_range();
+        if self.highlighted != Some(visible) {
+            self.highlights.clear();
+            self.dirty.clear();
+            self.dirty.push(visible);
+            self.highlighted = Some(visible);
+        }
+        if !self.is_parsed(content) || !self.dirty.is_empty() {
+            self.request(content, visible);
+        }
+
+        // 0 is magic number for default font face
+        let face_ids: Vec<usize> = self.capture_names
+            .iter()
+            .map(|name| global.faces.get_capture_face_id(name).unwrap_or(0))
+            .collect();
+        for &(start, end, capture) in &self.highlights {
+            content.faces.add(start, end, face_ids[capture]);
+        }
+
+        // Injected languages go on top, worked out again whenever the tree
+        // is up to date since they're usually a small part of the content.
+        // So are syntax errors, only looking inside nodes that have some.
+        if let (true, Some(tree)) = (self.is_parsed(content), self.tree.clone()) {
+            self.injected_highlights = self.highlight_injections(global, content, &tree, visible);
+            self.errors = syntax_errors(content, &tree);
+        }
+        for &(start, end, face_id) in &self.injected_highlights {
+            content.faces.add(start, end, face_id);
+        }
+        if let (true, Some(face_id)) = (content.locals.get(&SYNTAX_ERRORS), global.faces.get_face_id("error")) {
+            for &(start, end) in &self.errors {
+                content.faces.add(start, end, face_id);
+            }
+        }
+    }
+
+    fn has_results(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    fn is_busy(&self) -> bool {
+        self.sent.is_some()
+    }
+
+    fn fold_ranges(&mut self, content: &TextContent) -> Option<Vec<(Position, Position)>> {
+        let tree = self.parse(content)?;
+        let mut ranges = vec!();
+        let mut nodes = vec!(tree.root_node());
+        while let Some(node) = nodes.pop() {
+            let spans_rows = node.start_position().row < node.end_position().row;
+            if spans_rows && node.parent().is_some() && (delimiters(node).is_some() || node.kind().contains("comment")) {
+                ranges.push(node_range(content, node));
+            }
+            let mut walk = node.walk();
+            nodes.extend(node.children(&mut walk));
+        }
+
+        if let Some(query) = &self.fold_query {
+            let text_callback = |node: Node| content.slice(node.byte_range()).into_owned();
+            let mut cursor = QueryCursor::new();
+            for m in cursor.matches(query, tree.root_node(), text_callback) {
+                ranges.extend(m.captures.iter().map(|capture| node_range(content, capture.node)));
+            }
+        }
+        ranges.retain(|(start, end)| start.row < end.row);
+        ranges.sort_unstable();
+        ranges.dedup();
+        Some(ranges)
+    }
+
+    fn definitions(&mut self, content: &TextContent) -> Option<Vec<Definition>> {
+        self.tags_query.as_ref()?;
+        let tree = self.parse(content)?;
+        let query = self.tags_query.as_ref()?;
+
+        let text_callback = |node: Node| content.slice(node.byte_range()).into_owned();
+        let mut found = vec!();
+        let mut cursor = QueryCursor::new();
+        for m in cursor.matches(query, tree.root_node(), text_callback) {
+            let mut name = None;
+            let mut definition = None;
+            for capture in m.captures {
+                let capture_name = &query.capture_names()[capture.index as usize];
+                if capture_name == "name" {
+                    name = Some(capture.node);
+                } else if let Some(kind) = capture_name.strip_prefix("definition.") {
+                    definition = Some((capture.node, kind));
+                }
+            }
+            if let (Some(name), Some((node, kind))) = (name, definition) {
+                // Names can be whole types, spread over lines
+                let name = content.slice(name.byte_range()).split_whitespace().collect::<Vec<_>>().join(" ");
+                let (start, end) = node_range(content, node);
+                found.push((start, end, name, kind.to_string()));
+            }
+        }
+        found.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+        found.dedup_by(|a, b| a.0 == b.0 && a.2 == b.2);
+
+        // Each is named after the ones around it, which come before it
+        let mut definitions = vec!();
+        let mut around: Vec<(Position, String)> = vec!();
+        for (start, end, name, kind) in found {
+            while around.last().is_some_and(|&(around_end, _)| around_end <= start) {
+                around.pop();
+            }
+            let name = match around.last() {
+                Some((_, outer)) => format!("{}.{}", outer, name),
+                None => name,
+            };
+            around.push((end, name.clone()));
+            definitions.push(Definition { name, kind, start });
+        }
+        Some(definitions)
+    }
+
+    // Indents one level per line that opened a node still enclosing `row`
+    fn indent_level(&mut self, content: &TextContent, row: usize) -> Option<usize> {
+        self.indent_query.as_ref()?;
+        let tree = self.parse(content)?;
+        let indent_query = self.indent_query.as_ref()?;
+
+        let text_callback = |node: Node| content.slice(node.byte_range()).into_owned();
+
+        // A line starting with a closer lines up with the line that opened it
+        let closes = content.line(row)
+            .trim_start()
+            .starts_with(['}', ')', ']']);
+
+        let mut opening_rows = HashSet::new();
+        let mut cursor = QueryCursor::new();
+        for m in cursor.matches(indent_query, tree.root_node(), text_callback) {
+            for capture in m.captures {
+                let name = &indent_query.capture_names()[capture.index as usize];
+                let start = capture.node.start_position();
+                let end = capture.node.end_position();
+
+                let encloses = name == "indent.open"
+                    || end.row > row
+                    || (end.row == row && !closes);
+                if start.row < row && encloses {
+                    opening_rows.insert(start.row);
+                }
+            }
+        }
+
+        Some(opening_rows.len() * content.locals.get(&INDENT_WIDTH))
+    }
+}
+
+// The text of ERROR nodes, up to the end of their first line so a missing
+// bracket doesn't take over everything after it, and the character before
+// MISSING ones, which take up no text
+fn syntax_errors(content: &TextContent, tree: &Tree) -> Vec<(Position, Position)> {
+    let mut errors = vec!();
+    let mut nodes = vec!(tree.root_node());
+    while let Some(node) = nodes.pop() {
+        if !node.has_error() {
+            continue;
+        }
+        if node.is_error() {
+            let (start, end) = node_range(content, node);
+            let end = if end.row > start.row { Position::new(start.row, content.line_len(start.row)) } else { end };
+            errors.push((start, end));
+            continue;
+        }
+        if node.is_missing() {
+            let at = position(content, node.start_position());
+            let before = content.prev_position(at).filter(|before| before.row == at.row);
+            let after = content.next_position(at).filter(|after| after.row == at.row);
+            match (before, after) {
+                (Some(before), _) => errors.push((before, at)),
+                (None, Some(after)) => errors.push((at, after)),
+                (None, None) => {},
+            }
+            continue;
+        }
+        let mut walk = node.walk();
+        nodes.extend(node.children(&mut walk));
+    }
+    errors.retain(|(start, end)| start < end);
+    errors.sort_unstable();
+    errors
+}
+
+// Brackets that make a node a list whose contents can be spliced out
+const DELIMITERS: &[(&str, &str)] = &[
+    ("(", ")"),
+    ("[", "]"),
+    ("{", "}"),
+    ("<", ">"),
+];
+
+// Parses the content a chunk at a time, without joining it into one string
+// first. With `old`, edited to match the content, unchanged parts are reused.
+fn parse_with(parser: &mut Parser, content: &TextContent, old: Option<&Tree>) -> Option<Tree> {
+    parser.parse_with(&mut |byte: usize, _position: Point| -> &[u8] {
+        content.chunk_at(byte)
+    }, old)
+}
+
+fn point(pos: Position) -> Point {
+    Point::new(pos.row, pos.col)
+}
+
+// The buffer position of a tree point, which can be past the final newline
+fn position(content: &TextContent, point: Point) -> Position {
+    if point.row >= content.line_count() {
+        return content.end_of_buffer();
+    }
+    Position::new(point.row, point.column.min(content.line_len(point.row)))
+}
+
+fn node_range(content: &TextContent, node: Node) -> (Position, Position) {
+    (position(content, node.start_position()), position(content, node.end_position()))
+}
+
+// The region, or just the cursor when there isn't one
+fn selection(content: &TextContent) -> (Position, Position) {
+    content.region().unwrap_or((content.cursor, content.cursor))
+}
+
+fn select(content: &mut TextContent, node: Node) {
+    let (start, end) = node_range(content, node);
+    content.mark = Some(start);
+    content.cursor = end;
+}
+
+// The smallest named node covering `start` to `end`
+fn node_for_range(tree: &Tree, start: Position, end: Position) -> Option<Node<'_>> {
+    tree.root_node().named_descendant_for_point_range(point(start), point(end))
+}
+
+// The largest named node starting at the same place as `node`
+fn outermost_at_start(node: Node) -> Node {
+    let mut node = node;
+    while let Some(parent) = node.parent() {
+        if parent.parent().is_none() || parent.start_position() != node.start_position() {
+            break;
+        }
+        node = parent;
+    }
+    node
+}
+
+// The nearest named ancestor covering more text than `node`
+fn larger_ancestor(node: Node) -> Option<Node> {
+    let mut parent = node.parent()?;
+    while parent.range() == node.range() || !parent.is_named() {
+        parent = parent.parent()?;
+    }
+    Some(parent)
+}
+
+// The opening and closing brackets of a bracketed list
+pub fn delimiters(node: Node) -> Option<(Node, Node)> {
+    let count = node.child_count();
+    if count < 2 {
+        return None;
+    }
+
+    let open = node.child(0)?;
+    let close = node.child(count - 1)?;
+    DELIMITERS
+        .iter()
+        .any(|&(o, c)| open.kind() == o && close.kind() == c)
+        .then_some((open, close))
+}
+
+// The bracket matching the one at `at`, which is None when the tree has it
+// unclosed. None overall when the character at `at` isn't a bracket token,
+// e.g. in a string or comment.
+pub fn matching_bracket(content: &TextContent, tree: &Tree, at: Position) -> Option<Option<Position>> {
+    let byte = content.offset(at);
+    let node = tree.root_node().descendant_for_byte_range(byte, byte + 1)?;
+    if node.start_byte() != byte || node.child_count() > 0 || !DELIMITERS.iter().any(|&(o, c)| node.kind() == o || node.kind() == c) {
+        return None;
+    }
+
+    let matching = node.parent().and_then(delimiters).and_then(|(open, close)| {
+        if open == node {
+            Some(close)
+        } else if close == node {
+            Some(open)
+        } else {
+            None
+        }
+    });
+    Some(matching.filter(|other| !other.is_missing()).map(|other| position(content, other.start_position())))
+}
+
+// Where the nodes under `node` on the rows from `first` to `last` are whose
+// kind is `wanted`, without looking inside them
+pub fn find_nodes(node: Node, first: usize, last: usize, wanted: fn(&str) -> bool, ranges: &mut Vec<(Position, Position)>) {
+    if node.end_position().row < first || node.start_position().row > last {
+        return;
+    }
+    if wanted(node.kind()) {
+        let (start, end) = (node.start_position(), node.end_position());
+        ranges.push((Position::new(start.row, start.column), Position::new(end.row, end.column)));
+        return;
+    }
+    let mut walk = node.walk();
+    for child in node.children(&mut walk) {
+        find_nodes(child, first, last, wanted, ranges);
+    }
+}
+
+// The kinds of the named nodes around `at`, innermost first
+pub fn node_path(tree: &Tree, at: Position) -> Vec<String> {
+    let mut path = vec!();
+    let mut node = node_for_range(tree, at, at);
+    while let Some(current) = node {
+        if current.is_named() {
+            path.push(current.kind().to_string());
+        }
+        node = current.parent();
+    }
+    path
+}
+
+// Selects the smallest node around the cursor
+pub fn select_enclosing_node(content: &mut TextContent, tree: &Tree) {
+    let cursor = content.cursor;
+    if let Some(node) = node_for_range(tree, cursor, cursor) {
+        select(content, node);
+    }
+}
+
+// Grows the selection to the next larger node around it
+pub fn expand_selection(content: &mut TextContent, tree: &Tree) {
+    let (start, end) = selection(content);
+    let mut node = match node_for_range(tree, start, end) {
+        Some(node) => node,
+        None => return,
+    };
+
+    if content.mark.is_some() && node_range(content, node) == (start, end) {
+        node = match larger_ancestor(node) {
+            Some(parent) => parent,
+            None => return,
+        };
+    }
+    select(content, node);
+}
+
+// Shrinks a selected node down to its first named child
+pub fn shrink_selection(content: &mut TextContent, tree: &Tree) {
+    let (start, end) = match content.region() {
+        Some(region) => region,
+        None => return,
+    };
+    let mut node = match node_for_range(tree, start, end) {
+        Some(node) => node,
+        None => return,
+    };
+    if node_range(content, node) != (start, end) {
+        return;
+    }
+
+    loop {
+        match node.named_child(0) {
+            Some(child) if child.range() == node.range() => node = child,
+            Some(child) => return select(content, child),
+            None => {
+                content.cursor = start;
+                content.deactivate_mark();
+                return;
+            },
+        }
+    }
+}
+
+// Moves to the start of the next node at the same level as the one at the cursor
+pub fn forward_sibling(content: &mut TextContent, tree: &Tree) {
+    let cursor = content.cursor;
+    let node = match node_for_range(tree, cursor, cursor) {
+        Some(node) => node,
+        None => return,
+    };
+
+    let target = if position(content, node.start_position()) == cursor {
+        outermost_at_start(node).next_named_sibling()
+    } else {
+        // Between the children of a node, or partway into a leaf
+        let mut walk = node.walk();
+        let next_child = node
+            .named_children(&mut walk)
+            .find(|child| position(content, child.start_position()) > cursor);
+        next_child.or_else(|| node.next_named_sibling())
+    };
+
+    if let Some(target) = target {
+        content.cursor = position(content, target.start_position());
+    }
+}
+
+// Moves to the start of the node at the cursor, or the one before it when
+// already there
+pub fn backward_sibling(content: &mut TextContent, tree: &Tree) {
+    let cursor = content.cursor;
+    let node = match node_for_range(tree, cursor, cursor) {
+        Some(node) => node,
+        None => return,
+    };
+
+    let start = position(content, node.start_position());
+    let target = if start == cursor {
+        outermost_at_start(node).prev_named_sibling()
+    } else {
+        let mut walk = node.walk();
+        let previous_child = node
+            .named_children(&mut walk)
+            .filter(|child| position(content, child.end_position()) <= cursor)
+            .last();
+        previous_child.or(Some(node))
+    };
+
+    if let Some(target) = target {
+        content.cursor = position(content, target.start_position());
+    }
+}
+
+// The selected node, or the largest one starting at the cursor
+fn node_at_point<'tree>(content: &TextContent, tree: &'tree Tree) -> Option<Node<'tree>> {
+    let (start, end) = selection(content);
+    let node = node_for_range(tree, start, end)?;
+    if content.mark.is_none() && position(content, node.start_position()) == start {
+        return Some(outermost_at_start(node));
+    }
+    Some(node)
+}
+
+// Replaces the node around the one at point with it, looking past the
+// bracketed lists in between
+pub fn raise_node(content: &mut TextContent, tree: &Tree) {
+    let node = match node_at_point(content, tree) {
+        Some(node) => node,
+        None => return,
+    };
+
+    let mut parent = match larger_ancestor(node) {
+        Some(parent) => parent,
+        None => return,
+    };
+    while delimiters(parent).is_some() {
+        parent = match larger_ancestor(parent) {
+            Some(parent) => parent,
+            None => return,
+        };
+    }
+    if parent.parent().is_none() {
+        return;
+    }
+
+    let (start, end) = node_range(content, node);
+    let text = content.text_range(start, end);
+    let (parent_start, parent_end) = node_range(content, parent);
+    content.replace_range(parent_start, parent_end, &text);
+    content.cursor = parent_start;
+    content.deactivate_mark();
+}
+
+// Removes the brackets of the innermost bracketed list around the cursor
+pub fn splice_node(content: &mut TextContent, tree: &Tree) {
+    let cursor = content.cursor;
+    let mut node = match node_for_range(tree, cursor, cursor) {
+        Some(node) => node,
+        None => return,
+    };
+
+    let (open, close) = loop {
+        if let Some(brackets) = delimiters(node) {
+            break brackets;
+        }
+        node = match node.parent() {
+            Some(parent) => parent,
+            None => return,
+        };
+    };
+
+    // The closer goes first so the opener's position stays valid
+    let (close_start, close_end) = node_range(content, close);
+    let (open_start, open_end) = node_range(content, open);
+    content.delete_range(close_start, close_end);
+    content.delete_range(open_start, open_end);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::face_spans::FaceSpans;
+    use crate::rust_mode::RUST;
+
+    // Highlights `text` as Rust into faces numbered by capture index, with
+    // the index of the comment capture
+    fn rust_faces(text: &str) -> (FaceSpans, usize) {
+        let mut parser = Parser::new();
+        parser.set_language(RUST.grammar()).unwrap();
+        let tree = parser.parse(text, None).unwrap();
+        let query = Query::new(RUST.grammar(), &RUST.highlight_queries.concat()).unwrap();
+        let comment = query.capture_names().iter().position(|name| name == "comment").unwrap();
+        let mut faces = FaceSpans::default();
+        for (start, end, capture) in captures(&query, &tree, text, 0, text.len()) {
+            faces.add(start, end, capture);
+        }
+        (faces, comment)
+    }
+
+    #[test]
+    fn block_comment_faces_every_line() {
+        let text = "/* one\ntwo\nthree */\nfn main() {}";
+        let (faces, comment) = rust_faces(text);
+        assert_eq!(faces.line_spans(0, 6), vec!((0..6, comment)));
+        assert_eq!(faces.line_spans(1, 3), vec!((0..3, comment)));
+        assert_eq!(faces.line_spans(2, 8), vec!((0..8, comment)));
+        assert!(faces.line_spans(3, 12).iter().all(|&(_, face_id)| face_id != comment));
+    }
+
+    #[test]
+    fn closed_block_comment_leaves_code_after_it() {
+        let text = "/* one\ntwo\n*/three */";
+        let (faces, comment) = rust_faces(text);
+        assert_eq!(faces.line_spans(1, 3), vec!((0..3, comment)));
+        assert!(faces.line_spans(2, 10).iter().all(|(range, face_id)| *face_id != comment || range.end <= 2));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::text_content::Position;
+
+#[derive(Clone, Debug)]
+pub enum Edit {
+    Insert { at: Position, text: String },
+    Delete { at: Position, text: String },
+}
+
+impl Edit {
+    pub fn inverse(&self) -> Edit {
+        match self {
+            Edit::Insert { at, text } => Edit::Delete { at: *at, text: text.clone() },
+            Edit::Delete { at, text } => Edit::Insert { at: *at, text: text.clone() },
+        }
+    }
+
+    // The text it covers before it's made, empty for an insertion
+    pub fn range(&self) -> (Position, Position) {
+        match self {
+            Edit::Insert { at, .. } => (*at, *at),
+            Edit::Delete { at, text } => (*at, end_of(*at, text)),
+        }
+    }
+
+    // Where `pos` ends up after this edit. With `stick_left` a position right
+    // at an insertion stays put instead of moving past the new text.
+    pub fn shift(&self, pos: Position, stick_left: bool) -> Position {
+        match self {
+            Edit::Insert { at, .. } if stick_left && pos == *at => pos,
+            Edit::Insert { at, text } => pos.after_insert(*at, end_of(*at, text)),
+            Edit::Delete { at, text } => pos.after_delete(*at, end_of(*at, text)),
+        }
+    }
+}
+
+// The position right after `text` once it is inserted at `at`
+pub fn end_of(at: Position, text: &str) -> Position {
+    match text.rfind('\n') {
+        Some(i) => Position::new(at.row + text.matches('\n').count(), text.len() - i - 1),
+        None    => Position::new(at.row, at.col + text.len()),
+    }
+}
+
+// Edits that are undone and redone as one unit
+#[derive(Clone)]
+pub struct UndoGroup {
+    pub edits: Vec<Edit>,
+    pub cursor_before: Position,
+    pub cursor_after: Position,
+}
+
+// Typing bursts longer than this are split into several undo groups
+const MAX_AMALGAMATED_INSERTS: usize = 20;
+
+#[derive(Clone)]
+pub struct UndoHistory {
+    undo_stack: Vec<UndoGroup>,
+    redo_stack: Vec<UndoGroup>,
+    // The next recorded edit starts a fresh group
+    boundary: bool,
+    // Number of self inserts in the current group
+    amalgamated: usize,
+    // Boundaries are ignored while a transaction is open
+    open_transactions: usize,
+}
+
+impl Default for UndoHistory {
+    fn default() -> UndoHistory {
+        UndoHistory {
+            undo_stack: vec!(),
+            redo_stack: vec!(),
+            boundary: true,
+            amalgamated: 0,
+            open_transactions: 0,
+        }
+    }
+}
+
+impl UndoHistory {
+    pub fn boundary(&mut self) {
+        if self.open_transactions > 0 {
+            return;
+        }
+        self.boundary = true;
+        self.amalgamated = 0;
+    }
+
+    pub fn begin_transaction(&mut self) {
+        self.open_transactions += 1;
+    }
+
+    pub fn commit_transaction(&mut self) {
+        self.open_transactions = self.open_transactions.saturating_sub(1);
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.open_transactions > 0
+    }
+
+    // Called before each typed insertion so a burst of typing undoes together
+    pub fn amalgamate_insert(&mut self) {
+        if self.amalgamated >= MAX_AMALGAMATED_INSERTS {
+            self.boundary();
+        }
+        self.amalgamated += 1;
+    }
+
+    pub fn record(&mut self, edit: Edit, cursor_before: Position, cursor_after: Position) {
+        self.redo_stack.clear();
+
+        if self.boundary || self.undo_stack.is_empty() {
+            self.boundary = false;
+            self.undo_stack.push(UndoGroup {
+                edits: vec!(edit),
+                cursor_before,
+                cursor_after,
+            });
+            return;
+        }
+
+        let group = self.undo_stack.last_mut().unwrap();
+        group.cursor_after = cursor_after;
+
+        // Merge contiguous insertions so typing doesn't keep one edit per key
+        if let (Some(Edit::Insert { at, text }), Edit::Insert { at: new_at, text: new_text }) =
+            (group.edits.last_mut(), &edit)
+        {
+            if end_of(*at, text) == *new_at {
+                text.push_str(new_text);
+                return;
+            }
+        }
+        group.edits.push(edit);
+    }
+
+    pub fn pop_undo(&mut self) -> Option<UndoGroup> {
+        self.boundary();
+        self.undo_stack.pop()
+    }
+
+    pub fn push_redo(&mut self, group: UndoGroup) {
+        self.redo_stack.push(group);
+    }
+
+    pub fn pop_redo(&mut self) -> Option<UndoGroup> {
+        self.boundary();
+        self.redo_stack.pop()
+    }
+
+    pub fn push_undo(&mut self, group: UndoGroup) {
+        self.undo_stack.push(group);
+    }
+}
+
+// Undo files hold a history as text:
+//
+//   edit0r-undo 1
+//   <undo group count> <redo group count>
+//   <cursor before row> <col> <cursor after row> <col> <edit count>   per group
+//   <i or d> <row> <col> <byte length> <text>                          per edit
+//
+// with the text taking exactly its byte length, newlines and all.
+const UNDO_FILE_HEADER: &str = "edit0r-undo 1\n";
+
+impl UndoHistory {
+    pub fn encode(&self) -> String {
+        let mut out = String::from(UNDO_FILE_HEADER);
+        out.push_str(&format!("{} {}\n", self.undo_stack.len(), self.redo_stack.len()));
+        for group in self.undo_stack.iter().chain(&self.redo_stack) {
+            let (before, after) = (group.cursor_before, group.cursor_after);
+            out.push_str(&format!("{} {} {} {} {}\n", before.row, before.col, after.row, after.col, group.edits.len()));
+            for edit in &group.edits {
+                let (kind, at, text) = match edit {
+                    Edit::Insert { at, text } => ('i', at, text),
+                    Edit::Delete { at, text } => ('d', at, text),
+                };
+                out.push_str(&format!("{} {} {} {} {}\n", kind, at.row, at.col, text.len(), text));
+            }
+        }
+        out
+    }
+
+    // None when the text isn't a history written by encode
+    pub fn decode(text: &str) -> Option<UndoHistory> {
+        let mut rest = text.strip_prefix(UNDO_FILE_HEADER)?;
+        let undo_count = next_number(&mut rest)?;
+        let redo_count = next_number(&mut rest)?;
+
+        let mut groups = vec!();
+        for _ in 0..undo_count + redo_count {
+            let cursor_before = Position::new(next_number(&mut rest)?, next_number(&mut rest)?);
+            let cursor_after = Position::new(next_number(&mut rest)?, next_number(&mut rest)?);
+            let edit_count = next_number(&mut rest)?;
+
+            let mut edits = vec!();
+            for _ in 0..edit_count {
+                let kind = next_field(&mut rest)?;
+                let at = Position::new(next_number(&mut rest)?, next_number(&mut rest)?);
+                let len = next_number(&mut rest)?;
+                let text = rest.get(..len)?.to_string();
+                rest = rest.get(len..)?.strip_prefix('\n')?;
+                edits.push(match kind {
+                    "i" => Edit::Insert { at, text },
+                    "d" => Edit::Delete { at, text },
+                    _ => return None,
+                });
+            }
+            groups.push(UndoGroup { edits, cursor_before, cursor_after });
+        }
+
+        let redo_stack = groups.split_off(undo_count);
+        Some(UndoHistory {
+            undo_stack: groups,
+            redo_stack,
+            ..UndoHistory::default()
+        })
+    }
+}
+
+// Takes the next space or newline separated field off the front of `rest`
+fn next_field<'a>(rest: &mut &'a str) -> Option<&'a str> {
+    let end = rest.find([' ', '\n'])?;
+    let field = &rest[..end];
+    *rest = &rest[end + 1..];
+    Some(field)
+}
+
+fn next_number(rest: &mut &str) -> Option<usize> {
+    next_field(rest)?.parse().ok()
+}
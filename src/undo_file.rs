@@ -0,0 +1,51 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::buffer::write_atomically;
+use crate::text_content::TextContent;
+use crate::undo::UndoHistory;
+
+// Undo histories are kept next to their files, e.g. "src/.main.rs.~undo~",
+// along with a fingerprint of the text they apply to
+
+// Whether EDIT0R_UNDO_FILES asks for histories to be kept between sessions
+pub fn enabled_from_env() -> bool {
+    std::env::var("EDIT0R_UNDO_FILES").is_ok_and(|setting| !setting.is_empty() && setting != "off")
+}
+
+fn undo_file_path(path: &Path) -> Option<PathBuf> {
+    let file_name = path.file_name()?.to_string_lossy();
+    Some(path.with_file_name(format!(".{}.~undo~", file_name)))
+}
+
+// FNV-1a, which unlike the standard hasher gives the same answer in every build
+fn fingerprint(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+// Saves the history of the content just written to `path`
+pub fn write(path: &Path, content: &TextContent) -> Result<(), String> {
+    let undo_path = undo_file_path(path).ok_or("Not a file path")?;
+    let text = format!("{:016x}\n{}", fingerprint(&content.text()), content.history().encode());
+    write_atomically(&undo_path, &text)
+}
+
+// Brings back the history saved with the file at `path`, as long as the
+// file hasn't been changed by anything else since
+pub fn read(path: &Path, content: &mut TextContent) {
+    let text = match undo_file_path(path).and_then(|undo_path| fs::read_to_string(undo_path).ok()) {
+        Some(text) => text,
+        None => return,
+    };
+    let (saved_fingerprint, history) = match text.split_once('\n') {
+        Some(parts) => parts,
+        None => return,
+    };
+    if saved_fingerprint != format!("{:016x}", fingerprint(&content.text())) {
+        return;
+    }
+    if let Some(history) = UndoHistory::decode(history) {
+        content.set_history(history);
+    }
+}
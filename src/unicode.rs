@@ -0,0 +1,151 @@
+// Grapheme clusters and how many character cells they take up on screen.
+// This covers the common cases of UAX #29 and East Asian Width rather than
+// the full tables: combining marks, variation selectors, emoji joined with
+// ZWJ or skin tone modifiers, flags made of regional indicator pairs and CRLF.
+
+const ZWJ: char = '\u{200D}';
+const EMOJI_PRESENTATION: char = '\u{FE0F}';
+
+// Marks that attach to the character before them
+const EXTENDING: &[(u32, u32)] = &[
+    (0x0300, 0x036F), (0x0483, 0x0489), (0x0591, 0x05BD), (0x05BF, 0x05BF),
+    (0x05C1, 0x05C2), (0x05C4, 0x05C5), (0x05C7, 0x05C7), (0x0610, 0x061A),
+    (0x064B, 0x065F), (0x0670, 0x0670), (0x06D6, 0x06DC), (0x06DF, 0x06E4),
+    (0x06E7, 0x06E8), (0x06EA, 0x06ED), (0x0900, 0x0903), (0x093A, 0x094F),
+    (0x0951, 0x0957), (0x0962, 0x0963), (0x0981, 0x0983), (0x09BC, 0x09BC),
+    (0x09BE, 0x09CD), (0x09D7, 0x09D7), (0x0E31, 0x0E31), (0x0E34, 0x0E3A),
+    (0x0E47, 0x0E4E), (0x0EB1, 0x0EB1), (0x0EB4, 0x0EBC), (0x0EC8, 0x0ECD),
+    (0x1AB0, 0x1AFF), (0x1DC0, 0x1DFF), (0x200C, 0x200D), (0x20D0, 0x20FF),
+    (0x302A, 0x302F), (0x3099, 0x309A), (0xFE00, 0xFE0F), (0xFE20, 0xFE2F),
+    (0x1F3FB, 0x1F3FF), (0xE0020, 0xE007F), (0xE0100, 0xE01EF),
+];
+
+// Characters taking up two cells: CJK, Hangul, fullwidth forms and emoji
+const WIDE: &[(u32, u32)] = &[
+    (0x1100, 0x115F), (0x231A, 0x231B), (0x2329, 0x232A), (0x23E9, 0x23EC),
+    (0x23F0, 0x23F0), (0x23F3, 0x23F3), (0x25FD, 0x25FE), (0x2614, 0x2615),
+    (0x2648, 0x2653), (0x267F, 0x267F), (0x2693, 0x2693), (0x26A1, 0x26A1),
+    (0x26AA, 0x26AB), (0x26BD, 0x26BE), (0x26C4, 0x26C5), (0x26CE, 0x26CE),
+    (0x26D4, 0x26D4), (0x26EA, 0x26EA), (0x26F2, 0x26F3), (0x26F5, 0x26F5),
+    (0x26FA, 0x26FA), (0x26FD, 0x26FD), (0x2705, 0x2705), (0x270A, 0x270B),
+    (0x2728, 0x2728), (0x274C, 0x274C), (0x274E, 0x274E), (0x2753, 0x2755),
+    (0x2757, 0x2757), (0x2795, 0x2797), (0x27B0, 0x27B0), (0x27BF, 0x27BF),
+    (0x2B1B, 0x2B1C), (0x2B50, 0x2B50), (0x2B55, 0x2B55), (0x2E80, 0x303E),
+    (0x3041, 0x33FF), (0x3400, 0x4DBF), (0x4E00, 0x9FFF), (0xA000, 0xA4CF),
+    (0xA960, 0xA97F), (0xAC00, 0xD7A3), (0xF900, 0xFAFF), (0xFE10, 0xFE19),
+    (0xFE30, 0xFE6F), (0xFF00, 0xFF60), (0xFFE0, 0xFFE6), (0x16FE0, 0x18AFF),
+    (0x1B000, 0x1B2FF), (0x1F004, 0x1F004), (0x1F0CF, 0x1F0CF), (0x1F18E, 0x1F18E),
+    (0x1F191, 0x1F19A), (0x1F200, 0x1F251), (0x1F300, 0x1F64F), (0x1F680, 0x1F6FF),
+    (0x1F7E0, 0x1F7EB), (0x1F90C, 0x1F9FF), (0x1FA70, 0x1FAFF), (0x20000, 0x3FFFD),
+];
+
+fn in_table(table: &[(u32, u32)], c: char) -> bool {
+    let c = c as u32;
+    table
+        .binary_search_by(|&(start, end)| {
+            if end < c {
+                std::cmp::Ordering::Less
+            } else if start > c {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+fn is_extending(c: char) -> bool {
+    in_table(EXTENDING, c)
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+fn is_pictographic(c: char) -> bool {
+    in_table(WIDE, c) || ('\u{2600}'..='\u{27BF}').contains(&c) || ('\u{1F000}'..='\u{1FAFF}').contains(&c)
+}
+
+// Control characters (apart from tabs) show in caret notation, e.g. ^A
+pub fn is_escaped(c: char) -> bool {
+    c.is_ascii_control() && c != '\t'
+}
+
+// The grapheme clusters of a string with their byte offsets
+pub struct Graphemes<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+pub fn graphemes(text: &str) -> Graphemes<'_> {
+    Graphemes { text, offset: 0 }
+}
+
+impl<'a> Iterator for Graphemes<'a> {
+    type Item = (usize, &'a str);
+
+    fn next(&mut self) -> Option<(usize, &'a str)> {
+        let start = self.offset;
+        let rest = &self.text[start..];
+        let mut chars = rest.char_indices();
+        let (_, first) = chars.next()?;
+
+        let mut prev = first;
+        let mut regional_indicators = is_regional_indicator(first) as usize;
+        let mut len = rest.len();
+        for (i, c) in chars {
+            let joins = match (prev, c) {
+                ('\r', '\n') => true,
+                _ if prev.is_control() => false,
+                _ if is_extending(c) => true,
+                (ZWJ, _) => is_pictographic(c),
+                _ if is_regional_indicator(c) => regional_indicators % 2 == 1,
+                _ => false,
+            };
+            if !joins {
+                len = i;
+                break;
+            }
+            regional_indicators += is_regional_indicator(c) as usize;
+            prev = c;
+        }
+
+        self.offset += len;
+        Some((start, &rest[..len]))
+    }
+}
+
+// Number of character cells a grapheme cluster takes up on screen
+pub fn cluster_cells(cluster: &str) -> usize {
+    let mut chars = cluster.chars();
+    match chars.next() {
+        Some(c) if is_escaped(c) => 2,
+        Some(c) if in_table(WIDE, c) || is_regional_indicator(c) => 2,
+        Some(_) if chars.any(|c| c == EMOJI_PRESENTATION) => 2,
+        Some(_) => 1,
+        None => 0,
+    }
+}
+
+// Number of character cells `text` takes up on screen
+pub fn display_width(text: &str) -> usize {
+    graphemes(text).map(|(_, cluster)| cluster_cells(cluster)).sum()
+}
+
+// The byte offset of the cluster covering cell `cell` of `text`, or its end
+// when it's narrower
+pub fn col_at_cell(text: &str, cell: usize) -> usize {
+    let mut used = 0;
+    for (i, cluster) in graphemes(text) {
+        used += cluster_cells(cluster);
+        if used > cell {
+            return i;
+        }
+    }
+    text.len()
+}
+
+// Whether byte offset `col` of `text` is between two clusters
+pub fn is_cluster_boundary(text: &str, col: usize) -> bool {
+    col == text.len() || graphemes(text).any(|(i, _)| i == col)
+}
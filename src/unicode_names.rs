@@ -0,0 +1,58 @@
+use std::sync::OnceLock;
+
+// The names of characters, like LATIN SMALL LETTER E WITH ACUTE, from a
+// list read the first time one is looked up. Ideographs are named after
+// their code point, so those names are worked out rather than listed.
+
+const NAMES_PATH: &str = "assets/unicode-names.txt";
+
+// Names whose ending is the code point in hex
+const NAMED_BY_CODE_POINT: &[&str] = &[
+    "CJK UNIFIED IDEOGRAPH-",
+    "CJK COMPATIBILITY IDEOGRAPH-",
+    "TANGUT IDEOGRAPH-",
+    "KHITAN SMALL SCRIPT CHARACTER-",
+    "NUSHU CHARACTER-",
+];
+
+static NAMES: OnceLock<Vec<(String, char)>> = OnceLock::new();
+
+// Every listed name with its character, or none if the list can't be read
+fn names() -> &'static [(String, char)] {
+    NAMES.get_or_init(|| {
+        let text = std::fs::read_to_string(NAMES_PATH).unwrap_or_default();
+        text.lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| {
+                let (code_point, name) = line.split_once(';')?;
+                let c = u32::from_str_radix(code_point, 16).ok().and_then(char::from_u32)?;
+                Some((name.to_string(), c))
+            })
+            .collect()
+    })
+}
+
+// The character called `name`, in any case
+pub fn lookup(name: &str) -> Option<char> {
+    let name = name.trim().to_uppercase();
+    let by_code_point = NAMED_BY_CODE_POINT.iter().find_map(|prefix| name.strip_prefix(prefix));
+    if let Some(digits) = by_code_point {
+        return u32::from_str_radix(digits, 16).ok().and_then(char::from_u32);
+    }
+    names().iter().find(|(listed, _)| *listed == name).map(|&(_, c)| c)
+}
+
+// The names with every word of `input` in them, for the minibuffer to
+// narrow down. Fewer than two letters would match nearly all of them.
+pub fn complete(input: &str) -> Vec<String> {
+    let input = input.to_uppercase();
+    let words: Vec<&str> = input.split_whitespace().collect();
+    if input.trim().len() < 2 {
+        return vec!();
+    }
+    names()
+        .iter()
+        .filter(|(name, _)| words.iter().all(|word| name.contains(word)))
+        .map(|(name, _)| name.clone())
+        .collect()
+}
@@ -0,0 +1,384 @@
+use std::ops::Range;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::unicode::cluster_cells;
+use crate::unicode::col_at_cell;
+use crate::unicode::display_width;
+use crate::unicode::graphemes;
+
+// Rows of context kept on screen when paging through a buffer
+const PAGE_OVERLAP: usize = 2;
+
+// How long a smooth scroll takes to play out
+const SCROLL_DURATION: Duration = Duration::from_millis(150);
+
+// Scrolls further than this many pages skip straight to the last of them,
+// so jumping across a file doesn't race through all of it
+const MAX_SCROLL_PAGES: f32 = 2.0;
+
+// The top row sliding towards where the view is scrolled to
+#[derive(Clone)]
+struct Scroll {
+    from: f32,
+    start: Instant,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineNumbers {
+    Off,
+    #[default]
+    Absolute,
+    // Distance from the cursor's line, which keeps its own number
+    Relative,
+}
+
+impl LineNumbers {
+    pub fn next(self) -> LineNumbers {
+        match self {
+            LineNumbers::Off => LineNumbers::Absolute,
+            LineNumbers::Absolute => LineNumbers::Relative,
+            LineNumbers::Relative => LineNumbers::Off,
+        }
+    }
+}
+
+// Which part of a buffer is on screen. Long lines are either cut off at the
+// window edge and scrolled sideways to keep the cursor in view, or wrapped
+// onto more screen rows.
+#[derive(Clone, Default)]
+pub struct View {
+    pub top_row: usize,
+    // Character cells scrolled off the left edge, when not wrapping
+    pub left_col: usize,
+    // How many rows and columns of text fit beside the gutter, set from the
+    // window size before each frame
+    pub rows: usize,
+    pub cols: usize,
+    pub wrap: bool,
+    pub line_numbers: LineNumbers,
+    // A column of the gutter left of the line numbers, for modes to mark
+    // lines in
+    pub line_marks: bool,
+    // The top row as of the last frame, which is where a scroll starts from
+    shown_top: usize,
+    scroll: Option<Scroll>,
+}
+
+impl View {
+    // Cells of text per screen row, leaving the last for the continuation mark
+    pub fn text_cols(&self) -> usize {
+        self.cols.saturating_sub(1).max(1)
+    }
+
+    // Cells taken up by line marks and line numbers
+    pub fn gutter_width(&self, content: &TextContent) -> usize {
+        self.line_marks as usize + self.line_number_width(content)
+    }
+
+    // Cells taken up by line numbers, wide enough for the last one and a space
+    pub fn line_number_width(&self, content: &TextContent) -> usize {
+        if self.line_numbers == LineNumbers::Off {
+            return 0;
+        }
+        let (start, end) = content.visible_range();
+        (end.row - start.row + 1).to_string().len() + 1
+    }
+
+    // The number shown beside line `row`, counting from the start of the
+    // narrowed part
+    pub fn line_number_label(&self, content: &TextContent, row: usize) -> Option<String> {
+        let first = content.visible_range().0.row;
+        let cursor_row = content.cursor.row;
+        match self.line_numbers {
+            LineNumbers::Off => None,
+            LineNumbers::Relative if row != cursor_row => Some(row.abs_diff(cursor_row).to_string()),
+            _ => Some((row - first + 1).to_string()),
+        }
+    }
+
+    // How far one page scrolls
+    pub fn page(&self) -> usize {
+        self.rows.saturating_sub(PAGE_OVERLAP).max(1)
+    }
+
+    // The byte ranges of `line` within `cols` that go on screen, one per
+    // screen row
+    pub fn segments(&self, line: &str, cols: Range<usize>) -> Vec<Range<usize>> {
+        if self.wrap {
+            return wrap(line, cols, self.text_cols());
+        }
+        let start = cols.start + fit(&line[cols.clone()], self.left_col);
+        let end = start + fit(&line[start..cols.end], self.text_cols());
+        let segment = start..end;
+        vec!(segment)
+    }
+
+    // Screen rows taken up by `row`, which is none when it's folded away
+    fn height(&self, content: &TextContent, row: usize) -> usize {
+        if content.is_hidden(row) {
+            return 0;
+        }
+        if !self.wrap {
+            return 1;
+        }
+        self.segments(&content.line(row), content.visible_cols(row)).len()
+    }
+
+    // The last row starting on screen
+    pub fn bottom_row(&self, content: &TextContent) -> usize {
+        let last = content.visible_range().1.row;
+        let mut screen_rows = 0;
+        let mut row = self.top_row;
+        while row < last {
+            screen_rows += self.height(content, row);
+            let next = content.next_shown_row(row);
+            if screen_rows >= self.rows.max(1) || next > last {
+                break;
+            }
+            row = next;
+        }
+        row
+    }
+
+    // The screen row, counting from the top of the view, and the cell the
+    // cursor is in
+    pub fn cursor_cell(&self, content: &TextContent) -> (usize, usize) {
+        self.cursor_cell_from(content, self.top_row)
+    }
+
+    // The same with `top_row` at the top, for drawing part way through a
+    // scroll
+    pub fn cursor_cell_from(&self, content: &TextContent, top_row: usize) -> (usize, usize) {
+        self.cell_from(content, content.cursor, top_row)
+    }
+
+    // The screen row and cell `position` is in, when on a row at or below
+    // the top of the view
+    pub fn position_cell(&self, content: &TextContent, position: Position) -> (usize, usize) {
+        self.cell_from(content, position, self.top_row)
+    }
+
+    // The position shown at `cell` of screen row `screen_row`, counting from
+    // the top of the view. Cells past the end of a row go to its end, and
+    // rows past the end of the content to the end of the content.
+    pub fn position_at_cell(&self, content: &TextContent, screen_row: usize, cell: usize) -> Position {
+        let last = content.visible_range().1.row;
+        let mut row = self.top_row;
+        let mut above = 0;
+        loop {
+            let line = content.line(row);
+            let cols = content.visible_cols(row);
+            let segments = self.segments(&line, cols.clone());
+            if screen_row < above + segments.len() {
+                // Hidden text takes no cells to click on
+                let invisible = content.invisible_cols(row);
+                if !invisible.is_empty() {
+                    let segment = segments[screen_row - above].clone();
+                    return Position::new(row, shown_col_at_cell(&line, segment, &invisible, cell));
+                }
+                if !self.wrap {
+                    return Position::new(row, cols.start + col_at_cell(&line[cols], cell + self.left_col));
+                }
+                let segment = segments[screen_row - above].clone();
+                return Position::new(row, segment.start + col_at_cell(&line[segment], cell));
+            }
+            let next = content.next_shown_row(row);
+            if next > last {
+                return Position::new(row, cols.end);
+            }
+            above += segments.len();
+            row = next;
+        }
+    }
+
+    fn cell_from(&self, content: &TextContent, position: Position, top_row: usize) -> (usize, usize) {
+        let Position { row, col } = position;
+        let line = content.line(row);
+        let segments = self.segments(&line, content.visible_cols(row));
+
+        let above: usize = (top_row..row).map(|row| self.height(content, row)).sum();
+        if !self.wrap {
+            let cells = display_width(&line[content.visible_cols(row).start..col]);
+            return (above, cells.saturating_sub(self.left_col));
+        }
+
+        let index = segments.iter().rposition(|segment| segment.start <= col).unwrap_or(0);
+        (above + index, display_width(&line[segments[index].start..col]))
+    }
+
+    // Scrolls just far enough to bring the cursor on screen
+    pub fn follow(&mut self, content: &TextContent) {
+        self.clamp(content);
+        let Position { row, col } = content.cursor;
+
+        if row < self.top_row {
+            self.top_row = row;
+        } else {
+            let rows = self.rows.max(1);
+            // Every row takes at least one screen row, unless folded away
+            if content.folds().is_empty() {
+                self.top_row = self.top_row.max((row + 1).saturating_sub(rows));
+            }
+            if self.cursor_cell(content).0 >= rows {
+                // Fills the screen up from the cursor's row
+                let mut top = row;
+                let mut used = self.cursor_cell_from(content, row).0 + 1;
+                while top > self.top_row {
+                    let above = content.shown_row(top - 1);
+                    used += self.height(content, above);
+                    if used > rows {
+                        break;
+                    }
+                    top = above;
+                }
+                self.top_row = top;
+            }
+        }
+
+        if self.wrap {
+            self.left_col = 0;
+            return;
+        }
+        let cells = display_width(&content.line(row)[content.visible_cols(row).start..col]);
+        if cells < self.left_col {
+            self.left_col = cells;
+        } else if cells >= self.left_col + self.text_cols() {
+            self.left_col = cells + 1 - self.text_cols();
+        }
+    }
+
+    // Scrolls by `delta` rows (down the buffer when positive), taking the
+    // cursor along when it would go off screen
+    pub fn scroll_by(&mut self, content: &mut TextContent, delta: isize) {
+        if content.folds().is_empty() {
+            self.top_row = self.top_row.saturating_add_signed(delta);
+        } else {
+            // Folded rows don't count
+            for _ in 0..delta.unsigned_abs() {
+                self.top_row = match delta > 0 {
+                    true => content.next_shown_row(self.top_row),
+                    false => content.shown_row(self.top_row.saturating_sub(1)),
+                };
+            }
+        }
+        self.clamp(content);
+
+        let Position { row, col } = content.cursor;
+        let row = row.clamp(self.top_row, self.bottom_row(content));
+        if row != content.cursor.row {
+            content.cursor = Position::new(row, content.clamp_col(row, col));
+            content.clamp_to_narrowing();
+        }
+    }
+
+    // Starts sliding over from where the view was shown last frame when the
+    // top row moved since, or jumps straight there when `smooth` is off
+    pub fn animate_scroll(&mut self, smooth: bool) {
+        if self.top_row != self.shown_top && smooth {
+            let target = self.top_row as f32;
+            let furthest = self.rows.max(1) as f32 * MAX_SCROLL_PAGES;
+            let from = self.visual_top().clamp(target - furthest, target + furthest);
+            self.scroll = Some(Scroll { from, start: Instant::now() });
+        } else if !smooth || !self.is_scrolling() {
+            self.scroll = None;
+        }
+        self.shown_top = self.top_row;
+    }
+
+    pub fn is_scrolling(&self) -> bool {
+        self.scroll.as_ref().is_some_and(|scroll| scroll.start.elapsed() < SCROLL_DURATION)
+    }
+
+    // The row at the top of the screen, part way between two rows while a
+    // scroll plays out
+    fn visual_top(&self) -> f32 {
+        let target = self.shown_top as f32;
+        match &self.scroll {
+            Some(scroll) => {
+                let t = (scroll.start.elapsed().as_secs_f32() / SCROLL_DURATION.as_secs_f32()).min(1.0);
+                // Eases out, slowing down as it arrives
+                let eased = 1.0 - (1.0 - t).powi(3);
+                scroll.from + (target - scroll.from) * eased
+            },
+            None => target,
+        }
+    }
+
+    // The first row to draw, and how many screen rows of it are above the
+    // top edge while scrolling
+    pub fn first_shown_row(&self, content: &TextContent) -> (usize, f32) {
+        let (start, end) = content.visible_range();
+        let top = self.visual_top().max(start.row as f32);
+        let row = (top.floor() as usize).min(end.row);
+        let shown = content.shown_row(row);
+        if shown != row {
+            return (shown, 0.0);
+        }
+        (row, (top - row as f32) * self.height(content, row) as f32)
+    }
+
+    // Keeps the top row within the visible part of the content
+    fn clamp(&mut self, content: &TextContent) {
+        let (start, end) = content.visible_range();
+        self.top_row = content.shown_row(self.top_row.clamp(start.row, end.row));
+    }
+}
+
+// Bytes of the longest start of `text` that fits in `cells`
+fn fit(text: &str, cells: usize) -> usize {
+    let mut used = 0;
+    for (i, cluster) in graphemes(text) {
+        used += cluster_cells(cluster);
+        if used > cells {
+            return i;
+        }
+    }
+    text.len()
+}
+
+// Splits `cols` of `line` into screen rows `width` cells wide, breaking after
+// a space where there is one
+fn wrap(line: &str, cols: Range<usize>, width: usize) -> Vec<Range<usize>> {
+    let mut segments = vec!();
+    let mut start = cols.start;
+    let mut used = 0;
+    let mut after_space = None;
+
+    for (i, cluster) in graphemes(&line[cols.clone()]) {
+        let i = cols.start + i;
+        let cells = cluster_cells(cluster);
+        if used + cells > width && i > start {
+            let end = after_space.filter(|&end| end > start).unwrap_or(i);
+            segments.push(start..end);
+            used = display_width(&line[end..i]);
+            start = end;
+            after_space = None;
+        }
+        used += cells;
+        if cluster == " " {
+            after_space = Some(i + 1);
+        }
+    }
+    segments.push(start..cols.end);
+    segments
+}
+
+// Like col_at_cell for the `segment` of `line`, counting only the cells of
+// clusters outside `hidden`
+fn shown_col_at_cell(line: &str, segment: Range<usize>, hidden: &[Range<usize>], cell: usize) -> usize {
+    let mut used = 0;
+    for (i, cluster) in graphemes(&line[segment.clone()]) {
+        let col = segment.start + i;
+        if hidden.iter().any(|range| range.contains(&col)) {
+            continue;
+        }
+        used += cluster_cells(cluster);
+        if used > cell {
+            return col;
+        }
+    }
+    segment.end
+}
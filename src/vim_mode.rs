@@ -0,0 +1,260 @@
+use crate::buffer::Buffer;
+use crate::buffer_local::Variable;
+use crate::command_registry;
+use crate::commands;
+use crate::commands::CommandContext;
+use crate::commands::Repeatable;
+use crate::modeline::Segment;
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::Global;
+use crate::text_mode::TextMinorMode;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Normal,
+    // Typing goes into the buffer as usual
+    Insert,
+    // Motions grow the region from where v was pressed
+    Visual,
+}
+
+// Where a buffer is in vim's modal editing, kept with the buffer
+#[derive(Clone)]
+pub struct Vim {
+    state: State,
+    // Digits typed before a command
+    count: Option<usize>,
+    // d, y or c waiting for the motion it works up to
+    operator: Option<char>,
+    // g waiting for the key after it
+    g: bool,
+}
+
+const VIM: Variable<Vim> = Variable::new("vim", Vim { state: State::Normal, count: None, operator: None, g: false });
+
+// Modal editing like vim's. Outside of insert state, typed characters run
+// commands from the registry rather than going into the buffer, while keys
+// with modifiers keep their usual bindings.
+pub struct VimMode {
+}
+
+impl TextMinorMode for VimMode {
+    fn name(&self) -> &'static str {
+        "vim"
+    }
+
+    fn modeline_segment(&self, content: &TextContent) -> Option<Segment> {
+        let label = match content.locals.get(&VIM).state {
+            State::Normal => "<N>",
+            State::Insert => "<I>",
+            State::Visual => "<V>",
+        };
+        Some(Segment::new(label.to_string(), "mode-line-emphasis"))
+    }
+}
+
+// From EDIT0R_VIM, which is "on" to start every buffer in vim mode
+pub fn enabled_from_env() -> bool {
+    std::env::var("EDIT0R_VIM").is_ok_and(|setting| setting == "on")
+}
+
+pub fn vim_mode(_: &mut Global, buffer: &mut Buffer) {
+    buffer.toggle_minor_mode("vim", || Box::new(VimMode {}));
+    set_state(buffer, State::Normal);
+}
+
+// Escape: back to normal state, dropping a half typed command
+pub fn vim_normal_state(global: &mut Global, buffer: &mut Buffer) {
+    match buffer.content.locals.get(&VIM).state {
+        // Like vim, the cursor ends up on the last character typed
+        State::Insert if buffer.content.cursor.col > 0 => run(global, buffer, "backward-char", 1),
+        State::Visual => buffer.content.deactivate_mark(),
+        _ => {},
+    }
+    set_state(buffer, State::Normal);
+}
+
+fn set_state(buffer: &mut Buffer, state: State) {
+    buffer.content.locals.set(&VIM, Vim { state, count: None, operator: None, g: false });
+}
+
+// Whether typing in `buffer` runs vim commands rather than inserting
+pub fn takes_text(buffer: &Buffer) -> bool {
+    buffer.has_minor_mode("vim") && buffer.content.locals.get(&VIM).state != State::Insert
+}
+
+// Runs the command called `name` like a key bound to it would
+fn run(global: &mut Global, buffer: &mut Buffer, name: &str, count: usize) {
+    match command_registry::command(name) {
+        Ok(command) => commands::execute(global, buffer, Repeatable::Command(command), CommandContext::count(count)),
+        Err(e) => global.message(e),
+    }
+}
+
+// The command a motion key runs, with `g` for the keys after g
+fn motion(key: char, g: bool) -> Option<&'static str> {
+    let name = match (g, key) {
+        (false, 'h') => "backward-char",
+        (false, 'l') | (false, ' ') => "forward-char",
+        (false, 'j') => "next-line",
+        (false, 'k') => "previous-line",
+        (false, 'w') | (false, 'e') => "forward-word",
+        (false, 'b') => "backward-word",
+        (false, '0') | (false, '^') => "beginning-of-line",
+        (false, '$') => "end-of-line",
+        (false, '}') => "forward-paragraph",
+        (false, '{') => "backward-paragraph",
+        (false, '%') => "jump-to-matching-bracket",
+        (false, 'G') => "end-of-buffer",
+        (true, 'g') => "beginning-of-buffer",
+        _ => return None,
+    };
+    Some(name)
+}
+
+// Runs `operator` on the text between `start` and `end`, by way of the
+// region: d kills it, y copies it and c kills it to type a replacement
+fn operate(global: &mut Global, buffer: &mut Buffer, operator: char, start: Position, end: Position) {
+    buffer.content.mark = Some(start);
+    buffer.content.cursor = end;
+    match operator {
+        'y' => {
+            run(global, buffer, "copy-region", 1);
+            buffer.content.deactivate_mark();
+            buffer.content.cursor = start;
+        },
+        _ => run(global, buffer, "kill-region", 1),
+    }
+    let state = if operator == 'c' { State::Insert } else { State::Normal };
+    set_state(buffer, state);
+}
+
+// The start and end of `count` lines from `row`, with the line break that
+// goes with them
+fn lines(content: &TextContent, row: usize, count: usize) -> (Position, Position) {
+    let end_row = row + count;
+    if end_row < content.line_count() {
+        return (Position::new(row, 0), Position::new(end_row, 0));
+    }
+    let last = content.line_count() - 1;
+    let end = Position::new(last, content.line_len(last));
+    match row {
+        0 => (Position::new(0, 0), end),
+        _ => (Position::new(row - 1, content.line_len(row - 1)), end),
+    }
+}
+
+// Handles text typed in normal or visual state
+pub fn text_input(global: &mut Global, buffer: &mut Buffer, text: &str) {
+    for key in text.chars() {
+        if takes_text(buffer) {
+            key_typed(global, buffer, key);
+        } else {
+            // What comes after e.g. "i" in one go is typed in as usual
+            commands::execute(global, buffer, Repeatable::SelfInsert(key.to_string()), CommandContext::default());
+        }
+    }
+}
+
+fn key_typed(global: &mut Global, buffer: &mut Buffer, key: char) {
+    let mut vim = buffer.content.locals.get(&VIM);
+    if let Some(digit) = key.to_digit(10).filter(|&digit| digit > 0 || vim.count.is_some()) {
+        vim.count = Some(vim.count.unwrap_or(0) * 10 + digit as usize);
+        buffer.content.locals.set(&VIM, vim);
+        return;
+    }
+    let count = vim.count.take().unwrap_or(1);
+    let operator = vim.operator.take();
+    let g = std::mem::take(&mut vim.g);
+    let state = vim.state;
+    buffer.content.locals.set(&VIM, vim.clone());
+
+    if let Some(name) = motion(key, g) {
+        match operator {
+            Some(operator) => {
+                let start = buffer.content.cursor;
+                run(global, buffer, name, count);
+                let end = buffer.content.cursor;
+                operate(global, buffer, operator, start.min(end), start.max(end));
+            },
+            None => run(global, buffer, name, count),
+        }
+        return;
+    }
+
+    match key {
+        'g' if !g => {
+            vim.g = true;
+            vim.count = Some(count).filter(|&count| count > 1);
+            vim.operator = operator;
+            buffer.content.locals.set(&VIM, vim);
+        },
+        // In visual state operators work on the region, including the
+        // character under the cursor
+        'd' | 'y' | 'c' | 'x' if state == State::Visual => {
+            let (start, end) = buffer.content.region().unwrap_or((buffer.content.cursor, buffer.content.cursor));
+            let end = buffer.content.next_position(end).unwrap_or(end);
+            let operator = if key == 'x' { 'd' } else { key };
+            operate(global, buffer, operator, start, end);
+        },
+        // Doubled, they work on whole lines
+        'd' | 'y' | 'c' if operator == Some(key) => {
+            let (start, end) = lines(&buffer.content, buffer.content.cursor.row, count);
+            operate(global, buffer, key, start, end);
+        },
+        'd' | 'y' | 'c' => {
+            vim.operator = Some(key);
+            vim.count = Some(count).filter(|&count| count > 1);
+            buffer.content.locals.set(&VIM, vim);
+        },
+        'v' if state == State::Visual => {
+            buffer.content.deactivate_mark();
+            set_state(buffer, State::Normal);
+        },
+        'v' => {
+            buffer.content.set_mark();
+            set_state(buffer, State::Visual);
+        },
+        'i' => set_state(buffer, State::Insert),
+        'a' => {
+            if buffer.content.cursor.col < buffer.content.line_len(buffer.content.cursor.row) {
+                run(global, buffer, "forward-char", 1);
+            }
+            set_state(buffer, State::Insert);
+        },
+        'I' => {
+            run(global, buffer, "beginning-of-line", 1);
+            set_state(buffer, State::Insert);
+        },
+        'A' => {
+            run(global, buffer, "end-of-line", 1);
+            set_state(buffer, State::Insert);
+        },
+        'o' => {
+            run(global, buffer, "end-of-line", 1);
+            run(global, buffer, "newline-and-indent", 1);
+            set_state(buffer, State::Insert);
+        },
+        'O' => {
+            run(global, buffer, "beginning-of-line", 1);
+            run(global, buffer, "newline", 1);
+            run(global, buffer, "previous-line", 1);
+            run(global, buffer, "indent-for-tab", 1);
+            set_state(buffer, State::Insert);
+        },
+        'x' => run(global, buffer, "delete-char", count),
+        'X' => run(global, buffer, "delete-backward-char", count),
+        'D' => run(global, buffer, "kill-line", 1),
+        'J' => run(global, buffer, "join-line", count),
+        // Yank takes an argument as leaving the cursor before the text
+        'p' | 'P' => {
+            for _ in 0..count {
+                run(global, buffer, "yank", 1);
+            }
+        },
+        'u' => run(global, buffer, "undo", count),
+        ':' => run(global, buffer, "execute-extended-command", 1),
+        _ => {},
+    }
+}
@@ -0,0 +1,53 @@
+use crate::text_content::Position;
+use crate::text_content::TextContent;
+use crate::text_mode::TextMinorMode;
+
+// Tidies up whitespace whenever the buffer is saved
+pub struct WhitespaceCleanupMode {
+    trim_trailing: bool,
+    final_newline: bool,
+}
+
+impl WhitespaceCleanupMode {
+    pub fn new(trim_trailing: bool, final_newline: bool) -> WhitespaceCleanupMode {
+        WhitespaceCleanupMode {
+            trim_trailing,
+            final_newline,
+        }
+    }
+
+    // The cleanup suiting files with extension `extension`
+    pub fn for_extension(extension: &str) -> WhitespaceCleanupMode {
+        match extension {
+            // Two trailing spaces are a line break in Markdown
+            "md" | "markdown" => WhitespaceCleanupMode::new(false, true),
+            // Context lines of a patch keep their exact whitespace
+            "diff" | "patch"  => WhitespaceCleanupMode::new(false, false),
+            _                 => WhitespaceCleanupMode::new(true, true),
+        }
+    }
+}
+
+impl TextMinorMode for WhitespaceCleanupMode {
+    fn name(&self) -> &'static str {
+        "whitespace-cleanup"
+    }
+
+    fn before_save(&mut self, content: &mut TextContent) {
+        if self.trim_trailing {
+            for row in 0..content.line_count() {
+                let line = content.line(row);
+                let trimmed = line.trim_end_matches([' ', '\t']).len();
+                if trimmed < line.len() {
+                    let end = line.len();
+                    content.delete_range(Position::new(row, trimmed), Position::new(row, end));
+                }
+            }
+        }
+
+        if self.final_newline && content.end_of_buffer().col > 0 {
+            let end = content.end_of_buffer();
+            content.insert(end, "\n");
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use crate::buffer::Buffer;
+use crate::buffer_list::BufferList;
+use crate::show_paren::BracketMatch;
+use crate::text_content::Position;
+use crate::view::View;
+
+// Where a window goes on screen, in pixels
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Area {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// One pane of the frame showing a buffer. The selected window's view and
+// cursor are the ones in the current buffer, the others keep their own.
+pub struct Window {
+    // Stays the same while the window is open, unlike its place in the tree
+    pub id: usize,
+    pub buffer: String,
+    view: View,
+    cursor: Position,
+    // Always None outside of swap, so unselected windows show no region or
+    // bracket match
+    mark: Option<Position>,
+    brackets: Option<BracketMatch>,
+}
+
+// Keeps the cursor inside the content, which could have been edited in
+// another window since it was put there
+fn clamp_cursor(buffer: &mut Buffer) {
+    let content = &mut buffer.content;
+    let row = content.cursor.row.min(content.line_count() - 1);
+    content.cursor = Position::new(row, content.clamp_col(row, content.cursor.col));
+}
+
+impl Window {
+    // A window showing `buffer` the way it is shown now
+    fn new(id: usize, buffer: &Buffer) -> Window {
+        Window {
+            id,
+            buffer: buffer.name.clone(),
+            view: buffer.view.clone(),
+            cursor: buffer.content.cursor,
+            mark: None,
+            brackets: None,
+        }
+    }
+
+    // A stand-in while the tree is being rebuilt
+    fn placeholder() -> Window {
+        Window {
+            id: 0,
+            buffer: String::new(),
+            view: View::default(),
+            cursor: Position::default(),
+            mark: None,
+            brackets: None,
+        }
+    }
+
+    // Swaps the window's view and cursor with those of `buffer`, so it shows
+    // the way the window does. Doing it again swaps them back.
+    pub fn swap(&mut self, buffer: &mut Buffer) {
+        std::mem::swap(&mut self.view, &mut buffer.view);
+        std::mem::swap(&mut self.cursor, &mut buffer.content.cursor);
+        std::mem::swap(&mut self.mark, &mut buffer.content.mark);
+        std::mem::swap(&mut self.brackets, &mut buffer.brackets);
+        clamp_cursor(buffer);
+    }
+}
+
+enum Tree {
+    Leaf(Window),
+    // Two windows or groups of them sharing an area, one above the other when
+    // `stacked` and side by side otherwise. The first gets `ratio` of it.
+    Split {
+        stacked: bool,
+        first: Box<Tree>,
+        second: Box<Tree>,
+        ratio: f32,
+    },
+}
+
+impl Tree {
+    fn leaves<'a>(&'a self, leaves: &mut Vec<&'a Window>) {
+        match self {
+            Tree::Leaf(window) => leaves.push(window),
+            Tree::Split { first, second, .. } => {
+                first.leaves(leaves);
+                second.leaves(leaves);
+            },
+        }
+    }
+
+    fn leaves_mut<'a>(&'a mut self, leaves: &mut Vec<&'a mut Window>) {
+        match self {
+            Tree::Leaf(window) => leaves.push(window),
+            Tree::Split { first, second, .. } => {
+                first.leaves_mut(leaves);
+                second.leaves_mut(leaves);
+            },
+        }
+    }
+
+    // Splits the window numbered `target` counting from `count`, with
+    // `new_window` taking the second half
+    fn split(self, target: usize, count: &mut usize, stacked: bool, new_window: &mut Option<Window>) -> Tree {
+        match self {
+            Tree::Leaf(window) => {
+                *count += 1;
+                match (*count - 1 == target, new_window.take()) {
+                    (true, Some(new_window)) => Tree::Split {
+                        stacked,
+                        first: Box::new(Tree::Leaf(window)),
+                        second: Box::new(Tree::Leaf(new_window)),
+                        ratio: 0.5,
+                    },
+                    (_, taken) => {
+                        *new_window = taken;
+                        Tree::Leaf(window)
+                    },
+                }
+            },
+            Tree::Split { stacked: split_stacked, first, second, ratio } => Tree::Split {
+                first: Box::new(first.split(target, count, stacked, new_window)),
+                second: Box::new(second.split(target, count, stacked, new_window)),
+                stacked: split_stacked,
+                ratio,
+            },
+        }
+    }
+
+    // The tree without the window numbered `target` counting from `count`,
+    // its sibling taking over its space
+    fn without(self, target: usize, count: &mut usize) -> Option<Tree> {
+        match self {
+            Tree::Leaf(window) => {
+                *count += 1;
+                (*count - 1 != target).then_some(Tree::Leaf(window))
+            },
+            Tree::Split { stacked, first, second, ratio } => {
+                let first = first.without(target, count);
+                let second = second.without(target, count);
+                match (first, second) {
+                    (Some(first), Some(second)) => Some(Tree::Split {
+                        stacked,
+                        first: Box::new(first),
+                        second: Box::new(second),
+                        ratio,
+                    }),
+                    (Some(tree), None) | (None, Some(tree)) => Some(tree),
+                    (None, None) => None,
+                }
+            },
+        }
+    }
+
+    // The window numbered `target` counting from `count`, dropping the rest
+    fn take_leaf(self, target: usize, count: &mut usize) -> Option<Window> {
+        match self {
+            Tree::Leaf(window) => {
+                *count += 1;
+                (*count - 1 == target).then_some(window)
+            },
+            Tree::Split { first, second, .. } => first.take_leaf(target, count).or_else(|| second.take_leaf(target, count)),
+        }
+    }
+
+    // How many windows share the space along a run of splits going the way
+    // `stacked` says
+    fn units(&self, stacked: bool) -> usize {
+        match self {
+            Tree::Split { stacked: split_stacked, first, second, .. } if *split_stacked == stacked => {
+                first.units(stacked) + second.units(stacked)
+            },
+            _ => 1,
+        }
+    }
+
+    fn balance(&mut self) {
+        if let Tree::Split { stacked, first, second, ratio } = self {
+            let first_units = first.units(*stacked);
+            *ratio = first_units as f32 / (first_units + second.units(*stacked)) as f32;
+            first.balance();
+            second.balance();
+        }
+    }
+
+    // Splits up `area` between the windows, rounding to whole character
+    // cells of `cell_size`
+    fn layout(&self, area: Area, cell_size: (u32, u32), areas: &mut Vec<Area>) {
+        let (first, second, ratio, stacked) = match self {
+            Tree::Leaf(_) => return areas.push(area),
+            Tree::Split { first, second, ratio, stacked } => (first, second, *ratio, *stacked),
+        };
+
+        let (length, cell) = if stacked { (area.height, cell_size.1) } else { (area.width, cell_size.0) };
+        let cells = length / cell;
+        // Each side keeps at least a cell, the first also its modeline
+        let first_cells = ((cells as f32 * ratio).round() as u32).min(cells.saturating_sub(2)).max(2.min(cells));
+        let first_length = (first_cells * cell).min(length);
+
+        let (first_area, second_area) = if stacked {
+            (
+                Area { height: first_length, ..area },
+                Area { y: area.y + first_length, height: area.height - first_length, ..area },
+            )
+        } else {
+            (
+                Area { width: first_length, ..area },
+                Area { x: area.x + first_length, width: area.width - first_length, ..area },
+            )
+        };
+        first.layout(first_area, cell_size, areas);
+        second.layout(second_area, cell_size, areas);
+    }
+}
+
+// The windows the frame is split into
+pub struct Windows {
+    tree: Tree,
+    // Which window has the focus, counting in order across the tree
+    selected: usize,
+    next_id: usize,
+    // The selected window changed, so its view and cursor go to the current
+    // buffer once it has been switched to
+    restore: bool,
+}
+
+impl Default for Windows {
+    fn default() -> Windows {
+        Windows {
+            tree: Tree::Leaf(Window::placeholder()),
+            selected: 0,
+            next_id: 1,
+            restore: false,
+        }
+    }
+}
+
+impl Windows {
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn leaves(&self) -> Vec<&Window> {
+        let mut leaves = vec!();
+        self.tree.leaves(&mut leaves);
+        leaves
+    }
+
+    pub fn leaves_mut(&mut self) -> Vec<&mut Window> {
+        let mut leaves = vec!();
+        self.tree.leaves_mut(&mut leaves);
+        leaves
+    }
+
+    // Where each window goes in `area`, in the same order as leaves
+    pub fn layout(&self, area: Area, cell_size: (u32, u32)) -> Vec<Area> {
+        let mut areas = vec!();
+        self.tree.layout(area, cell_size, &mut areas);
+        areas
+    }
+
+    // Splits the selected window in two showing the same part of the
+    // current buffer, which stays selected
+    pub fn split(&mut self, buffer: &Buffer, stacked: bool) {
+        self.split_showing(buffer, stacked);
+    }
+
+    // Splits the selected window in two, the new one showing `other`
+    pub fn split_showing(&mut self, other: &Buffer, stacked: bool) {
+        let mut new_window = Some(Window::new(self.next_id, other));
+        self.next_id += 1;
+        let tree = std::mem::replace(&mut self.tree, Tree::Leaf(Window::placeholder()));
+        self.tree = tree.split(self.selected, &mut 0, stacked, &mut new_window);
+    }
+
+    // Moves the focus to window `index`, switching to its buffer
+    pub fn select(&mut self, index: usize, buffer: &Buffer, buffers: &mut BufferList) {
+        if index == self.selected || index >= self.leaves().len() {
+            return;
+        }
+
+        // Until the last selected window was caught up with, the buffer still
+        // has the view of the one before
+        let (selected, restore) = (self.selected, self.restore);
+        let mut leaves = self.leaves_mut();
+        if !restore {
+            let old = &mut leaves[selected];
+            old.buffer = buffer.name.clone();
+            old.view = buffer.view.clone();
+            old.cursor = buffer.content.cursor;
+        }
+
+        let new = &leaves[index];
+        if new.buffer != buffer.name {
+            buffers.switch_to(&new.buffer);
+        }
+        self.selected = index;
+        self.restore = true;
+    }
+
+    // Closes the selected window, giving its space to its neighbour
+    pub fn delete_selected(&mut self, buffer: &Buffer, buffers: &mut BufferList) -> Result<(), String> {
+        if matches!(self.tree, Tree::Leaf(_)) {
+            return Err("Attempt to delete the only window".to_string());
+        }
+
+        let deleted = self.selected;
+        let tree = std::mem::replace(&mut self.tree, Tree::Leaf(Window::placeholder()));
+        self.tree = tree.without(deleted, &mut 0).ok_or("No windows left")?;
+
+        // The focus goes to the window that took the space, with nothing to
+        // save from the one that's gone
+        let next = deleted.min(self.leaves().len() - 1);
+        let new = &self.leaves()[next];
+        if new.buffer != buffer.name {
+            buffers.switch_to(&new.buffer);
+        }
+        self.selected = next;
+        self.restore = true;
+        Ok(())
+    }
+
+    // Moves the cursor of the other windows showing the buffer `name`, which
+    // scroll to show it
+    pub fn move_cursors(&mut self, name: &str, cursor: Position) {
+        let selected = self.selected;
+        for (i, window) in self.leaves_mut().into_iter().enumerate() {
+            if i != selected && window.buffer == name {
+                window.cursor = cursor;
+            }
+        }
+    }
+
+    // Makes the selected window fill the frame
+    pub fn delete_others(&mut self) {
+        let tree = std::mem::replace(&mut self.tree, Tree::Leaf(Window::placeholder()));
+        let kept = tree.take_leaf(self.selected, &mut 0).unwrap_or_else(Window::placeholder);
+        self.tree = Tree::Leaf(kept);
+        self.selected = 0;
+    }
+
+    // Gives every window the same share of the space along each split
+    pub fn balance(&mut self) {
+        self.tree.balance();
+    }
+
+    // Catches the windows up with what commands did to the buffers: the
+    // selected window shows the current buffer, with the view and cursor it
+    // had when it was selected, and windows on killed buffers switch over to
+    // the current one
+    pub fn sync(&mut self, buffer: &mut Buffer, buffers: &BufferList) {
+        let (selected, restore) = (self.selected, std::mem::take(&mut self.restore));
+        for (i, window) in self.leaves_mut().into_iter().enumerate() {
+            if i == selected {
+                if restore && window.buffer == buffer.name {
+                    buffer.view = window.view.clone();
+                    buffer.content.cursor = window.cursor;
+                    clamp_cursor(buffer);
+                }
+                window.buffer = buffer.name.clone();
+            } else if window.buffer != buffer.name && buffers.find(&window.buffer).is_none() {
+                window.buffer = buffer.name.clone();
+            }
+        }
+    }
+}